@@ -0,0 +1,287 @@
+//! Console on the first serial port, started instead of init with
+//! `console=serial`. It asks for a login from `/SYS/USERS.DB`, then runs a
+//! copy of itself as that user for the shell and asks again once it exits.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use core::panic::PanicInfo;
+
+use bafioDb::Value;
+use libk::io;
+
+#[global_allocator]
+static ALLOC: libk::heap::Allocator = libk::heap::Allocator::new();
+
+const USERS: &str = "/SYS/USERS.DB";
+
+/// Argument the login side starts the shell side with.
+const SHELL: &str = "SHELL";
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start(arg_ptr: u32, arg_len: u32) -> ! {
+    ALLOC.init(0x10_0000);
+    ALLOC.first_free.load(core::sync::atomic::Ordering::Relaxed);
+
+    let arg = unsafe { core::slice::from_raw_parts(arg_ptr as *const u8, arg_len as usize) };
+    if arg == SHELL.as_bytes() {
+        shell();
+    }
+
+    loop {
+        print("\nbafiOS serial console\nlogin: ");
+        let name = read_line(true);
+        print("password: ");
+        let password = read_line(false);
+        print("\n");
+
+        let Some(uid) = authenticate(&name, &password) else {
+            print("Login incorrect\n");
+            continue;
+        };
+
+        let args = [SHELL.as_ptr() as u32, SHELL.len() as u32, 0, 0];
+        match libk::elf::load_elf_as("/USER/CONSOLE.ELF", Some(&args), Some(uid)) {
+            Ok(pid) => wait_for(pid),
+            Err(e) => print(&format!("Could not start the shell: {}\n", e)),
+        }
+    }
+}
+
+/// The uid of `name` if `password` is theirs, checked like the login screen.
+fn authenticate(name: &str, password: &str) -> Option<u16> {
+    let users = bafioDb::load(USERS);
+    let hash = libk::hash::hash_to_hex(&libk::hash::hash_128bit(password.as_bytes()));
+
+    users.data.iter().find_map(|user| {
+        let (Some(Value::String(user_name)), Some(Value::String(user_hash))) =
+            (user.values.get("USER"), user.values.get("USERPSW"))
+        else {
+            return None;
+        };
+
+        if user_name != name || user_hash.as_bytes() != hash {
+            return None;
+        }
+
+        // accounts without a UID never get admin rights
+        match user.values.get("UID") {
+            Some(Value::Number(n)) => Some(*n as u16),
+            _ => Some(1000),
+        }
+    })
+}
+
+fn wait_for(pid: u32) {
+    loop {
+        match libk::task::wait() {
+            Some(exit) if exit.pid == pid => return,
+            Some(_) => {}
+            None => libk::task::yield_now(),
+        }
+    }
+}
+
+fn shell() -> ! {
+    let mut cwd = String::from("/");
+    let prompt = if libk::perm::uid() == libk::perm::ADMIN { '#' } else { '$' };
+
+    loop {
+        // programs started with `run` are children of the shell
+        while let Some(exit) = libk::task::wait() {
+            print(&format!("[{}] exited with status {}\n", exit.pid, exit.status));
+        }
+
+        print(&format!("{} {} ", cwd, prompt));
+        let line = read_line(true);
+        let words: alloc::vec::Vec<&str> = line.split_ascii_whitespace().collect();
+        let Some(&command) = words.first() else {
+            continue;
+        };
+        let arg = words.get(1).map(|a| full_path(&cwd, a));
+
+        match (command, arg) {
+            ("help", _) => print(
+                "ls [DIR]  cd DIR  pwd  cat FILE  echo TEXT  mkdir DIR  touch FILE  rm PATH\n\
+                 run PROGRAM  cmdline  sync  logout  reboot  shutdown\n",
+            ),
+            ("ls", arg) => list(arg.as_deref().unwrap_or(&cwd)),
+            ("cd", Some(dir)) => match lookup(&dir) {
+                _ if dir == "/" => cwd = dir,
+                Some(entry) if entry.is_dir() => cwd = dir,
+                Some(_) => print("Not a directory\n"),
+                None => print("No such directory\n"),
+            },
+            ("pwd", _) => print(&format!("{}\n", cwd)),
+            ("cat", Some(file)) => cat(&file),
+            ("echo", _) => print(&format!("{}\n", words[1..].join(" "))),
+            ("mkdir", Some(dir)) => report(io::make_dir(&dir), "Could not create the directory"),
+            ("touch", Some(file)) => {
+                if !io::exists(&file) {
+                    report(io::make_file(&file), "Could not create the file");
+                }
+            }
+            ("rm", Some(path)) => report(io::remove(&path), "Could not remove it"),
+            ("run", Some(program)) => match libk::elf::load_elf(&program, None) {
+                Ok(pid) => print(&format!("[{}] started\n", pid)),
+                Err(e) => print(&format!("{}\n", e)),
+            },
+            ("cmdline", _) => print(&format!("{}\n", libk::cmdline::line())),
+            ("sync", _) => report(io::sync(), "Could not write the cache back"),
+            ("logout" | "exit", _) => libk::task::exit(0),
+            ("reboot", _) => {
+                libk::syscall::reboot();
+                print("Only the admin may\n");
+            }
+            ("shutdown", _) => {
+                libk::syscall::shutdown();
+                print("Only the admin may\n");
+            }
+            ("cd" | "cat" | "mkdir" | "touch" | "rm" | "run", None) => {
+                print(&format!("Usage: {} PATH\n", command))
+            }
+            _ => print(&format!("Unknown command {}, try help\n", command)),
+        }
+    }
+}
+
+fn report(ok: bool, error: &str) {
+    if !ok {
+        print(&format!("{}\n", error));
+    }
+}
+
+/// `path` below `cwd` unless it is absolute, with `.` and `..` resolved.
+fn full_path(cwd: &str, path: &str) -> String {
+    let mut full = String::new();
+    let start = if path.starts_with('/') { "" } else { cwd };
+
+    for part in start.split('/').chain(path.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => full.truncate(full.rfind('/').unwrap_or(0)),
+            part => {
+                full.push('/');
+                full.push_str(&part.to_ascii_uppercase());
+            }
+        }
+    }
+
+    if full.is_empty() { String::from("/") } else { full }
+}
+
+/// `NAME    EXT` as `NAME.EXT`.
+fn display_name(entry: &io::Entry) -> String {
+    let name = core::str::from_utf8(&entry.name).unwrap_or("?");
+    let (base, ext) = (name[..8].trim_end(), name[8..].trim_end());
+
+    if ext.is_empty() { String::from(base) } else { format!("{}.{}", base, ext) }
+}
+
+/// The entry `path` names, looked up in its parent.
+fn lookup(path: &str) -> Option<io::Entry> {
+    let (parent, name) = path.rsplit_once('/')?;
+    let parent = if parent.is_empty() { "/" } else { parent };
+
+    (0..io::dir_entries(parent))
+        .filter_map(|i| io::get_entry(parent, i))
+        .find(|e| display_name(e).eq_ignore_ascii_case(name))
+}
+
+fn list(dir: &str) {
+    let count = io::dir_entries(dir);
+    if count == 0 && lookup(dir).is_none_or(|e| !e.is_dir()) && dir != "/" {
+        print("No such directory\n");
+        return;
+    }
+
+    for entry in (0..count).filter_map(|i| io::get_entry(dir, i)) {
+        let size = if entry.is_dir() { String::from("<DIR>") } else { format!("{}", { entry.size }) };
+        print(&format!("{:>10}  {}  {}\n", size, entry.modified(), display_name(&entry)));
+    }
+}
+
+fn cat(path: &str) {
+    let mut buffer = [0u8; 512];
+    let mut offset = 0;
+
+    loop {
+        match io::read_at(path, offset, &mut buffer) {
+            Some(0) => break,
+            Some(count) => {
+                write_text(&buffer[..count]);
+                offset += count as u32;
+            }
+            None => {
+                print("No such file\n");
+                return;
+            }
+        }
+    }
+
+    print("\n");
+}
+
+fn print(text: &str) {
+    write_text(text.as_bytes());
+}
+
+/// Line ends as a terminal on the other end expects them.
+fn write_text(data: &[u8]) {
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        match line.strip_suffix(b"\n") {
+            Some(line) => {
+                libk::serial::write(line);
+                libk::serial::write(b"\r\n");
+            }
+            None => libk::serial::write(line),
+        }
+    }
+}
+
+/// Waits for a line, echoing it unless it is a password.
+fn read_line(echo: bool) -> String {
+    let mut line = String::new();
+    let mut byte = [0u8];
+
+    loop {
+        if libk::serial::read(&mut byte) == 0 {
+            libk::task::yield_now();
+            continue;
+        }
+
+        match byte[0] {
+            b'\r' | b'\n' => {
+                if echo {
+                    print("\n");
+                }
+                return line;
+            }
+            BACKSPACE | DELETE => {
+                if line.pop().is_some() && echo {
+                    libk::serial::write(b"\x08 \x08");
+                }
+            }
+            b @ 0x20..=0x7E => {
+                line.push(b as char);
+                if echo {
+                    libk::serial::write(&byte);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    libk::println!("[console] PANIC {}", info);
+    libk::task::exit(libk::task::FAULT);
+}
@@ -0,0 +1,190 @@
+//! Display settings. Lists the usual resolutions and switches to the one
+//! clicked through `libk::syscall::set_video_mode`, at the depth picked
+//! below them. Only the admin may change the mode.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use core::panic::PanicInfo;
+use kui::widgets::*;
+
+#[global_allocator]
+static ALLOC: libk::heap::Allocator = libk::heap::Allocator::new();
+
+const MODES: [(u16, u16); 7] = [
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 720),
+    (1280, 1024),
+    (1600, 900),
+    (1920, 1080),
+];
+
+const DEPTHS: [u8; 2] = [24, 32];
+
+/// Depth the next mode is set with, the current one at first.
+pub static mut DEPTH: u8 = 32;
+
+/// Shown in the header instead of the current mode after a change failed.
+pub static mut STATUS: Option<String> = None;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    ALLOC.init(0x10_0000);
+    ALLOC.first_free.load(core::sync::atomic::Ordering::Relaxed);
+
+    let mut main = Window::new()
+        .name("Display")
+        .width(Size::new("300"))
+        .height(Size::new("400"))
+        .display(Display::None);
+
+    unsafe {
+        let depth = (*(&raw const SCREEN)).depth as u8;
+        if DEPTHS.contains(&depth) {
+            DEPTH = depth;
+        }
+    }
+
+    let mut frame1 = Widget::Frame(
+        Frame::new()
+            .width(Size::new("100%"))
+            .height(Size::new("100%"))
+            .color(Color::rgb(18, 52, 88))
+            .display(Display::Grid(Grid::new(1, MODES.len() + 2))),
+    );
+
+    let frame = frame1.get_id().unwrap() as u32;
+    let window = unsafe { (*(&raw mut WINDOWS)).len() as u32 };
+
+    if let Widget::Frame(f) = &mut frame1 {
+        show_modes(f, frame, window);
+    }
+
+    main.add(frame1);
+
+    kui::draw::init(main);
+
+    loop {}
+}
+
+/// Fills the frame with the header, a button per mode and the depth row.
+fn show_modes(f: &mut Frame, a1: u32, a2: u32) {
+    f.children.clear();
+
+    let screen = unsafe { *(&raw const SCREEN) };
+    let current = (
+        screen.width.absolute.unwrap_or(0) as u16,
+        screen.height.absolute.unwrap_or(0) as u16,
+    );
+
+    let status = unsafe { (*(&raw const STATUS)).clone() };
+    let header = status.unwrap_or_else(|| format!("Screen {}x{}x{}", current.0, current.1, screen.depth));
+
+    f.add(Widget::Label(
+        Label::new()
+            .text(&header)
+            .color(Color::rgb(18, 52, 88))
+            .width(Size::new("100%"))
+            .height(Size::new("100%"))
+            .text_align(Align::Center),
+    ));
+
+    for (i, &(width, height)) in MODES.iter().enumerate() {
+        let color = if (width, height) == current {
+            Color::rgb(229, 80, 80)
+        } else {
+            Color::rgb(61, 139, 221)
+        };
+
+        f.add(Widget::Button(
+            Button::new()
+                .label(&format!("{}x{}", width, height))
+                .color(color)
+                .width(Size::new("100%"))
+                .height(Size::new("100%"))
+                .event(set_mode)
+                .set_args([a1, a2, i as u32]),
+        ));
+    }
+
+    let mut depths = Widget::Frame(
+        Frame::new()
+            .width(Size::new("100%"))
+            .height(Size::new("100%"))
+            .color(Color::rgb(18, 52, 88))
+            .display(Display::Grid(Grid::new(DEPTHS.len(), 1))),
+    );
+
+    for bpp in DEPTHS {
+        let color = if bpp == unsafe { DEPTH } {
+            Color::rgb(229, 80, 80)
+        } else {
+            Color::rgb(61, 139, 221)
+        };
+
+        depths.add(Widget::Button(
+            Button::new()
+                .label(&format!("{} bpp", bpp))
+                .color(color)
+                .width(Size::new("100%"))
+                .height(Size::new("100%"))
+                .event(set_depth)
+                .set_args([a1, a2, bpp as u32]),
+        ));
+    }
+
+    f.add(depths);
+}
+
+/// Lists the modes again, after the screen or the chosen depth changed.
+fn refresh(a1: u32, a2: u32) {
+    let w = unsafe { &mut (*(&raw mut WINDOWS))[a2 as usize] };
+
+    for f in w.children.iter_mut() {
+        if f.get_id().unwrap() == a1 as u16 {
+            if let Widget::Frame(f) = f {
+                show_modes(f, a1, a2);
+            }
+        }
+    }
+
+    kui::draw::draw(w);
+    libk::syscall::syscall(41, w.id as u32, 0, 0);
+}
+
+pub fn set_mode(_w: &mut Widget, a1: u32, a2: u32, a3: u32) {
+    let (width, height) = MODES[a3 as usize];
+    let bpp = unsafe { DEPTH };
+
+    let status = if libk::syscall::set_video_mode(width, height, bpp) {
+        unsafe { (*(&raw mut SCREEN)).init() };
+        None
+    } else if libk::perm::uid() != libk::perm::ADMIN {
+        Some(String::from("Only the admin may"))
+    } else {
+        Some(format!("{}x{}x{} not available", width, height, bpp))
+    };
+
+    unsafe { STATUS = status };
+    refresh(a1, a2);
+}
+
+pub fn set_depth(_w: &mut Widget, a1: u32, a2: u32, a3: u32) {
+    unsafe {
+        DEPTH = a3 as u8;
+        STATUS = None;
+    }
+
+    refresh(a1, a2);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,472 @@
+//! Consistency checker for FAT16 volumes. It only needs sector access, so the
+//! kernel runs it over its block cache and the host tool over an image file.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::device::{BlockDevice, Error, SECTOR};
+use crate::entry::{ATTR_DIRECTORY, ATTR_LONG_NAME, ATTR_VOLUME_ID, DELETED};
+use crate::geometry::*;
+
+/// Bit 15 of the second FAT entry is set while the volume is cleanly unmounted.
+const CLEAN_SHUTDOWN: u16 = 0x8000;
+
+/// Deeper trees are almost certainly a directory loop.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Default, Debug)]
+pub struct Report {
+    pub files: u32,
+    pub directories: u32,
+    pub lost_clusters: u32,
+    pub cross_links: u32,
+    pub broken_chains: u32,
+    pub size_mismatches: u32,
+    pub invalid_names: u32,
+    pub fat_mismatches: u32,
+    pub repaired: bool,
+    pub messages: Vec<String>,
+}
+
+impl Report {
+    pub fn problems(&self) -> u32 {
+        self.lost_clusters
+            + self.cross_links
+            + self.broken_chains
+            + self.size_mismatches
+            + self.invalid_names
+            + self.fat_mismatches
+    }
+}
+
+/// Where a directory lives: the fixed root area or a cluster chain.
+enum Dir {
+    Root,
+    Chain(Vec<u32>),
+}
+
+struct Checker<'a, D: BlockDevice> {
+    disk: &'a D,
+    lba: u64,
+    geometry: Geometry,
+    fat: Vec<u16>,
+    used: Vec<bool>,
+    fat_changed: bool,
+    repair: bool,
+    report: Report,
+}
+
+/// Walks every directory and cluster chain on the FAT16 volume starting at `lba`.
+/// With `repair` set, problems are fixed on disk as they are found: broken or
+/// cross-linked chains are cut short, sizes are fitted to their chains, bad
+/// characters in names become `_`, and lost clusters are freed.
+pub fn check<D: BlockDevice>(disk: &D, lba: u64, repair: bool) -> Result<Report, Error> {
+    let mut boot = [0u8; SECTOR];
+    disk.read(lba, &mut boot)?;
+    let geometry = Geometry::parse(&boot)?;
+
+    let mut checker = Checker {
+        disk,
+        lba,
+        geometry,
+        fat: Vec::new(),
+        used: vec![false; geometry.clusters_end as usize],
+        fat_changed: false,
+        repair,
+        report: Report::default(),
+    };
+
+    checker.load_fat()?;
+    checker.walk()?;
+    checker.find_lost();
+    checker.store_fat()?;
+
+    checker.report.repaired = repair && checker.report.problems() > 0;
+    Ok(checker.report)
+}
+
+/// Whether the volume was cleanly unmounted the last time it was used.
+pub fn is_clean<D: BlockDevice>(disk: &D, lba: u64) -> Result<bool, Error> {
+    let mut sector = [0u8; SECTOR];
+    disk.read(lba, &mut sector)?;
+    let geometry = Geometry::parse(&sector)?;
+
+    disk.read(lba + geometry.reserved as u64, &mut sector)?;
+    Ok(u16::from_le_bytes([sector[2], sector[3]]) & CLEAN_SHUTDOWN != 0)
+}
+
+/// Clears the clean bit on mount and sets it again on a clean unmount.
+pub fn set_clean<D: BlockDevice>(disk: &D, lba: u64, clean: bool) -> Result<(), Error> {
+    let mut sector = [0u8; SECTOR];
+    disk.read(lba, &mut sector)?;
+    let geometry = Geometry::parse(&sector)?;
+
+    for copy in 0..geometry.fat_count {
+        let fat_lba = lba + geometry.fat_lba(copy);
+        disk.read(fat_lba, &mut sector)?;
+
+        let mut value = u16::from_le_bytes([sector[2], sector[3]]);
+        if clean {
+            value |= CLEAN_SHUTDOWN;
+        } else {
+            value &= !CLEAN_SHUTDOWN;
+        }
+        sector[2..4].copy_from_slice(&value.to_le_bytes());
+
+        disk.write(fat_lba, &sector)?;
+    }
+
+    Ok(())
+}
+
+impl<D: BlockDevice> Checker<'_, D> {
+    fn note(&mut self, message: String) {
+        self.report.messages.push(message);
+    }
+
+    fn load_fat(&mut self) -> Result<(), Error> {
+        let g = self.geometry;
+        let bytes = g.sectors_per_fat as usize * SECTOR;
+        let mut first = vec![0u8; bytes];
+        let mut other = vec![0u8; bytes];
+
+        self.read_fat_copy(0, &mut first)?;
+
+        for copy in 1..g.fat_count {
+            self.read_fat_copy(copy, &mut other)?;
+
+            let differing = first
+                .chunks(SECTOR)
+                .zip(other.chunks(SECTOR))
+                .filter(|(a, b)| a != b)
+                .count() as u32;
+
+            if differing > 0 {
+                self.report.fat_mismatches += differing;
+                self.fat_changed = true;
+                self.note(format!("FAT copy {} differs from the first in {} sectors", copy + 1, differing));
+            }
+        }
+
+        self.fat = first
+            .chunks(2)
+            .take(g.clusters_end as usize)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        Ok(())
+    }
+
+    fn read_fat_copy(&mut self, copy: u32, target: &mut [u8]) -> Result<(), Error> {
+        let g = self.geometry;
+        let start = self.lba + g.fat_lba(copy);
+
+        for (i, sector) in target.chunks_mut(SECTOR).enumerate() {
+            self.disk.read(start + i as u64, sector)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the first FAT back to every copy if anything was changed.
+    fn store_fat(&mut self) -> Result<(), Error> {
+        if !self.repair || !self.fat_changed {
+            return Ok(());
+        }
+
+        let g = self.geometry;
+        let mut bytes = vec![0u8; g.sectors_per_fat as usize * SECTOR];
+
+        // entries past the last cluster are kept as they are
+        self.read_fat_copy(0, &mut bytes)?;
+        for (i, value) in self.fat.iter().enumerate() {
+            bytes[i * 2..i * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        for copy in 0..g.fat_count {
+            let start = self.lba + g.fat_lba(copy);
+
+            for (i, sector) in bytes.chunks(SECTOR).enumerate() {
+                self.disk.write(start + i as u64, sector)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u16) {
+        if self.repair {
+            self.fat[cluster as usize] = value;
+            self.fat_changed = true;
+        }
+    }
+
+    /// Follows a chain, marking its clusters as used. The chain is cut at the
+    /// first cluster that is out of range, free, bad or already used.
+    fn follow(&mut self, path: &str, start: u32) -> Vec<u32> {
+        let mut chain: Vec<u32> = Vec::new();
+        let mut cluster = start;
+
+        loop {
+            if cluster < 2 || cluster >= self.geometry.clusters_end {
+                self.report.broken_chains += 1;
+                self.note(format!("{}: chain points outside the volume ({})", path, cluster));
+                break;
+            }
+
+            if self.used[cluster as usize] {
+                self.report.cross_links += 1;
+                self.note(format!("{}: cluster {} is cross-linked", path, cluster));
+                break;
+            }
+
+            self.used[cluster as usize] = true;
+            chain.push(cluster);
+
+            match self.fat[cluster as usize] {
+                FIRST_END..=END => return chain,
+                next @ 2..FIRST_RESERVED => cluster = next as u32,
+                value => {
+                    self.report.broken_chains += 1;
+                    self.note(format!("{}: chain ends in cluster {} marked {:#06x}", path, cluster, value));
+                    self.set_fat(cluster, END);
+                    return chain;
+                }
+            }
+        }
+
+        if let Some(&last) = chain.last() {
+            self.set_fat(last, END);
+        }
+
+        chain
+    }
+
+    /// Frees the tail of a chain beyond its first `keep` clusters.
+    fn truncate(&mut self, chain: &mut Vec<u32>, keep: usize) {
+        if !self.repair || chain.len() <= keep {
+            return;
+        }
+
+        if keep > 0 {
+            self.set_fat(chain[keep - 1], END);
+        }
+
+        for &cluster in &chain[keep..] {
+            self.set_fat(cluster, FREE);
+            self.used[cluster as usize] = false;
+        }
+
+        chain.truncate(keep);
+    }
+
+    fn walk(&mut self) -> Result<(), Error> {
+        let mut pending = vec![(Dir::Root, String::new(), 0usize)];
+
+        while let Some((dir, path, depth)) = pending.pop() {
+            let sectors = self.dir_sectors(&dir);
+
+            'sectors: for lba in sectors {
+                let mut sector = [0u8; SECTOR];
+                self.disk.read(self.lba + lba, &mut sector)?;
+                let mut changed = false;
+
+                for offset in (0..SECTOR).step_by(ENTRY) {
+                    let entry = &mut sector[offset..offset + ENTRY];
+
+                    match entry[0] {
+                        0x00 => {
+                            self.flush(lba, &sector, changed)?;
+                            break 'sectors;
+                        }
+                        DELETED => continue,
+                        _ => {}
+                    }
+
+                    let attributes = entry[11];
+                    if attributes == ATTR_LONG_NAME || attributes & ATTR_VOLUME_ID != 0 {
+                        continue;
+                    }
+
+                    if entry[0] == b'.' && (entry[1] == b' ' || entry[1] == b'.') {
+                        continue;
+                    }
+
+                    let child = format!("{}/{}", path, display_name(entry));
+                    changed |= self.check_name(&child, entry);
+
+                    let (sub, entry_changed) = self.check_entry(&child, entry);
+                    changed |= entry_changed;
+
+                    if let Some(chain) = sub {
+                        if depth + 1 >= MAX_DEPTH {
+                            self.note(format!("{}: directories nested too deep, not checked", child));
+                        } else {
+                            pending.push((Dir::Chain(chain), child, depth + 1));
+                        }
+                    }
+                }
+
+                self.flush(lba, &sector, changed)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, lba: u64, sector: &[u8], changed: bool) -> Result<(), Error> {
+        if changed && self.repair {
+            self.disk.write(self.lba + lba, sector)?;
+        }
+
+        Ok(())
+    }
+
+    fn dir_sectors(&self, dir: &Dir) -> Vec<u64> {
+        let g = self.geometry;
+
+        match dir {
+            Dir::Root => (0..g.root_sectors as u64).map(|s| g.root_lba + s).collect(),
+            Dir::Chain(chain) => chain
+                .iter()
+                .flat_map(|&c| (0..g.sectors_per_cluster as u64).map(move |s| g.cluster_lba(c) + s))
+                .collect(),
+        }
+    }
+
+    /// Returns true if the name was rewritten.
+    fn check_name(&mut self, path: &str, entry: &mut [u8]) -> bool {
+        let bad = |i: usize, c: u8| {
+            (c < 0x20 && !(i == 0 && c == 0x05)) || b"\"*+,./:;<=>?[\\]|\x7F".contains(&c)
+        };
+
+        let invalid = entry[0] == b' ' || entry[..11].iter().enumerate().any(|(i, &c)| bad(i, c));
+        if !invalid {
+            return false;
+        }
+
+        self.report.invalid_names += 1;
+        self.note(format!("{}: invalid characters in name", path));
+
+        if entry[0] == b' ' {
+            entry[0] = b'_';
+        }
+        for i in 0..11 {
+            if bad(i, entry[i]) {
+                entry[i] = b'_';
+            }
+        }
+
+        true
+    }
+
+    /// Checks the chain and size of one entry. Returns the chain of a directory
+    /// so it can be walked, and whether the entry was changed.
+    fn check_entry(&mut self, path: &str, entry: &mut [u8]) -> (Option<Vec<u32>>, bool) {
+        let dir = entry[11] & ATTR_DIRECTORY != 0;
+        // the high word holds the owner on FAT16, see the kernel's directory entry
+        let start = u16::from_le_bytes([entry[26], entry[27]]) as u32;
+        let size = u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]);
+        let mut changed = false;
+
+        if dir {
+            self.report.directories += 1;
+        } else {
+            self.report.files += 1;
+        }
+
+        if start == 0 {
+            if dir {
+                // cluster 0 would make it an alias of the root directory
+                self.report.broken_chains += 1;
+                self.note(format!("{}: directory has no clusters, removing it", path));
+                entry[0] = DELETED;
+                changed = true;
+            } else if size != 0 {
+                self.report.size_mismatches += 1;
+                self.note(format!("{}: size {} but no clusters", path, size));
+                entry[28..32].copy_from_slice(&0u32.to_le_bytes());
+                changed = true;
+            }
+
+            return (None, changed);
+        }
+
+        let mut chain = self.follow(path, start);
+
+        if chain.is_empty() {
+            // even the first cluster was unusable
+            if dir {
+                entry[0] = DELETED;
+            } else {
+                entry[26..28].copy_from_slice(&0u16.to_le_bytes());
+                entry[28..32].copy_from_slice(&0u32.to_le_bytes());
+            }
+            return (None, true);
+        }
+
+        if dir {
+            if size != 0 {
+                self.report.size_mismatches += 1;
+                self.note(format!("{}: directory has size {}", path, size));
+                entry[28..32].copy_from_slice(&0u32.to_le_bytes());
+                changed = true;
+            }
+
+            return (Some(chain), changed);
+        }
+
+        let cluster_bytes = self.geometry.cluster_bytes();
+        let needed = size.div_ceil(cluster_bytes) as usize;
+        let capacity = chain.len() as u32 * cluster_bytes;
+
+        if chain.len() < needed {
+            self.report.size_mismatches += 1;
+            self.note(format!("{}: size {} but its chain holds {} bytes", path, size, capacity));
+            entry[28..32].copy_from_slice(&capacity.to_le_bytes());
+            changed = true;
+        } else if chain.len() > needed.max(1) {
+            // empty files keep the one cluster they are created with
+            self.report.size_mismatches += 1;
+            self.note(format!(
+                "{}: {} clusters allocated for {} bytes",
+                path,
+                chain.len(),
+                size
+            ));
+            self.truncate(&mut chain, needed.max(1));
+        }
+
+        (None, changed)
+    }
+
+    fn find_lost(&mut self) {
+        let mut lost = 0;
+
+        for cluster in 2..self.geometry.clusters_end {
+            let value = self.fat[cluster as usize];
+
+            if value != FREE && value != BAD && !self.used[cluster as usize] {
+                lost += 1;
+                self.set_fat(cluster, FREE);
+            }
+        }
+
+        if lost > 0 {
+            self.report.lost_clusters = lost;
+            self.note(format!("{} lost clusters not owned by any file", lost));
+        }
+    }
+}
+
+/// `NAME    EXT` as `NAME.EXT`.
+fn display_name(entry: &[u8]) -> String {
+    let mut name = [0u8; 11];
+    name.copy_from_slice(&entry[..11]);
+
+    let mut shown = [0u8; 12];
+    let length = crate::entry::display_name(&name, &mut shown);
+    String::from_utf8_lossy(&shown[..length]).into_owned()
+}
@@ -0,0 +1,58 @@
+use core::fmt;
+
+pub const SECTOR: usize = 512;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    Io,
+    NotFat16,
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    Exists,
+    NotEmpty,
+    NoSpace,
+    InvalidName,
+    /// A cluster chain is shorter than its entry says; fsck can fix it.
+    Corrupt,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Io => "I/O error",
+            Error::NotFat16 => "Not a FAT16 volume",
+            Error::NotFound => "No such file or directory",
+            Error::NotADirectory => "Not a directory",
+            Error::IsADirectory => "Is a directory",
+            Error::Exists => "File exists",
+            Error::NotEmpty => "Directory not empty",
+            Error::NoSpace => "No space left on device",
+            Error::InvalidName => "Invalid name",
+            Error::Corrupt => "Filesystem is corrupt",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Sector access to the disk holding a volume. Buffers are a whole number of
+/// 512-byte sectors and `lba` counts from the start of the disk.
+pub trait BlockDevice {
+    fn read(&self, lba: u64, buffer: &mut [u8]) -> Result<(), Error>;
+    fn write(&self, lba: u64, buffer: &[u8]) -> Result<(), Error>;
+}
+
+impl<D: BlockDevice + ?Sized> BlockDevice for &D {
+    fn read(&self, lba: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        (**self).read(lba, buffer)
+    }
+
+    fn write(&self, lba: u64, buffer: &[u8]) -> Result<(), Error> {
+        (**self).write(lba, buffer)
+    }
+}
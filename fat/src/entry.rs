@@ -0,0 +1,210 @@
+use crate::device::Error;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+pub const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// First name byte of a deleted entry.
+pub const DELETED: u8 = 0xE5;
+
+/// A date and time in the packed form FAT stores them in.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Timestamp {
+    pub date: u16,
+    pub time: u16,
+    /// Hundredths of a second on top of the two second resolution of `time`.
+    pub tenths: u8,
+}
+
+/// Owner, permission bits and creation time given to a new entry.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Meta {
+    pub owner: u16,
+    pub mode: u8,
+    pub time: Timestamp,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(C, packed)]
+pub struct Entry {
+    pub name: [u8; 11],
+    pub attributes: u8,
+    /// Reserved by FAT; bafiOS keeps permission bits here.
+    mode: u8,
+    created_time_tenths: u8,
+    created_time: u16,
+    created_date: u16,
+    accessed_date: u16,
+    /// FAT16 clusters fit in the low word, so the high one holds the owner's uid.
+    owner: u16,
+    modified_time: u16,
+    modified_date: u16,
+    first_cluster_low: u16,
+    pub size: u32,
+}
+
+impl Entry {
+    pub const EMPTY: Entry = Entry {
+        name: [0; 11],
+        attributes: 0,
+        mode: 0,
+        created_time_tenths: 0,
+        created_time: 0,
+        created_date: 0,
+        accessed_date: 0,
+        owner: 0,
+        modified_time: 0,
+        modified_date: 0,
+        first_cluster_low: 0,
+        size: 0,
+    };
+
+    pub fn new(name: [u8; 11], attributes: u8, cluster: u32, size: u32) -> Entry {
+        Entry {
+            name,
+            attributes,
+            first_cluster_low: cluster as u16,
+            size,
+            ..Entry::EMPTY
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Entry {
+        assert!(bytes.len() >= 32);
+        unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const Entry) }
+    }
+
+    pub(crate) fn write_to(&self, bytes: &mut [u8]) {
+        assert!(bytes.len() >= 32);
+        unsafe { core::ptr::write_unaligned(bytes.as_mut_ptr() as *mut Entry, *self) }
+    }
+
+    pub fn cluster(&self) -> u32 {
+        self.first_cluster_low as u32
+    }
+
+    pub(crate) fn set_cluster(&mut self, cluster: u32) {
+        self.first_cluster_low = cluster as u16;
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+
+    /// `.` and `..`, which every directory but the root starts with.
+    pub fn is_dot(&self) -> bool {
+        self.name[0] == b'.' && (self.name[1] == b' ' || self.name[1] == b'.')
+    }
+
+    /// Long name fragments and volume labels are not files.
+    pub(crate) fn is_visible(&self) -> bool {
+        self.name[0] != DELETED
+            && self.attributes != ATTR_LONG_NAME
+            && self.attributes & ATTR_VOLUME_ID == 0
+    }
+
+    pub fn owner(&self) -> u16 {
+        self.owner
+    }
+
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+
+    pub fn set_permissions(&mut self, owner: u16, mode: u8) {
+        self.owner = owner;
+        self.mode = mode;
+    }
+
+    pub fn created(&self) -> Timestamp {
+        Timestamp {
+            date: self.created_date,
+            time: self.created_time,
+            tenths: self.created_time_tenths,
+        }
+    }
+
+    pub fn modified(&self) -> Timestamp {
+        Timestamp {
+            date: self.modified_date,
+            time: self.modified_time,
+            tenths: 0,
+        }
+    }
+
+    pub fn accessed_date(&self) -> u16 {
+        self.accessed_date
+    }
+
+    /// Sets every timestamp, as for a freshly created entry.
+    pub fn stamp_created(&mut self, now: Timestamp) {
+        self.created_date = now.date;
+        self.created_time = now.time;
+        self.created_time_tenths = now.tenths;
+        self.stamp_modified(now);
+    }
+
+    pub fn stamp_modified(&mut self, now: Timestamp) {
+        self.modified_date = now.date;
+        self.modified_time = now.time;
+        self.accessed_date = now.date;
+    }
+
+    /// Returns false if the entry was already accessed that day and nothing changed.
+    pub fn stamp_accessed(&mut self, now: Timestamp) -> bool {
+        let changed = self.accessed_date != now.date;
+        self.accessed_date = now.date;
+
+        changed
+    }
+}
+
+/// Space padded 8.3 name as stored in a directory entry, in upper case.
+/// Names already in that 11 byte form are taken as they are.
+pub fn short_name(name: &str) -> Result<[u8; 11], Error> {
+    let mut short = [b' '; 11];
+
+    if name.len() == 11 && !name.contains('.') {
+        short.copy_from_slice(name.as_bytes());
+    } else {
+        let (base, ext) = match name.rfind('.') {
+            Some(0) | None => (name, ""),
+            Some(dot) => (&name[..dot], &name[dot + 1..]),
+        };
+
+        if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+            return Err(Error::InvalidName);
+        }
+
+        short[..base.len()].copy_from_slice(base.as_bytes());
+        short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    }
+
+    short.make_ascii_uppercase();
+
+    let bad = |c: &u8| *c < 0x20 || b"\"*+,./:;<=>?[\\]|\x7F".contains(c);
+    if short[0] == b' ' || short[0] == DELETED || short.iter().any(bad) {
+        return Err(Error::InvalidName);
+    }
+
+    Ok(short)
+}
+
+/// `NAME    EXT` as `NAME.EXT`.
+pub fn display_name(name: &[u8; 11], target: &mut [u8; 12]) -> usize {
+    let base = name[..8].iter().rposition(|c| *c != b' ').map_or(0, |p| p + 1);
+    let ext = name[8..].iter().rposition(|c| *c != b' ').map_or(0, |p| p + 1);
+
+    target[..base].copy_from_slice(&name[..base]);
+    if ext == 0 {
+        return base;
+    }
+
+    target[base] = b'.';
+    target[base + 1..base + 1 + ext].copy_from_slice(&name[8..8 + ext]);
+    base + 1 + ext
+}
@@ -0,0 +1,98 @@
+use crate::device::{BlockDevice, Error, SECTOR};
+use crate::geometry::*;
+
+const RESERVED: u32 = 1;
+const FAT_COUNT: u32 = 2;
+const ROOT_ENTRIES: u32 = 512;
+const MEDIA: u8 = 0xF8;
+
+/// Picks the smallest cluster size that keeps the cluster count within what
+/// FAT16 can describe. Returns sectors per cluster and sectors per FAT.
+fn layout(sectors: u32) -> Result<(u32, u32), Error> {
+    let root_sectors = ROOT_ENTRIES * ENTRY as u32 / SECTOR as u32;
+
+    for shift in 0..7 {
+        let per_cluster = 1 << shift;
+        let mut per_fat = 1;
+
+        // the FAT shrinks the data area it describes, so settle on a size
+        let clusters = loop {
+            let overhead = RESERVED + FAT_COUNT * per_fat + root_sectors;
+            let clusters = sectors.checked_sub(overhead).ok_or(Error::NotFat16)? / per_cluster;
+            let needed = ((clusters + 2) * 2).div_ceil(SECTOR as u32);
+
+            if needed <= per_fat {
+                break clusters;
+            }
+            per_fat = needed;
+        };
+
+        if clusters < MIN_CLUSTERS {
+            return Err(Error::NotFat16);
+        }
+
+        if clusters <= MAX_CLUSTERS {
+            return Ok((per_cluster, per_fat));
+        }
+    }
+
+    Err(Error::NotFat16)
+}
+
+/// Writes an empty FAT16 volume of `sectors` sectors starting at `lba`.
+pub fn format<D: BlockDevice>(device: &D, lba: u64, sectors: u32, label: &str) -> Result<(), Error> {
+    let (per_cluster, per_fat) = layout(sectors)?;
+    let root_sectors = ROOT_ENTRIES * ENTRY as u32 / SECTOR as u32;
+
+    let mut boot = [0u8; SECTOR];
+    boot[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    boot[3..11].copy_from_slice(b"BAFIOS  ");
+    boot[11..13].copy_from_slice(&(SECTOR as u16).to_le_bytes());
+    boot[13] = per_cluster as u8;
+    boot[14..16].copy_from_slice(&(RESERVED as u16).to_le_bytes());
+    boot[16] = FAT_COUNT as u8;
+    boot[17..19].copy_from_slice(&(ROOT_ENTRIES as u16).to_le_bytes());
+    if sectors <= u16::MAX as u32 {
+        boot[19..21].copy_from_slice(&(sectors as u16).to_le_bytes());
+    } else {
+        boot[32..36].copy_from_slice(&sectors.to_le_bytes());
+    }
+    boot[21] = MEDIA;
+    boot[22..24].copy_from_slice(&(per_fat as u16).to_le_bytes());
+    boot[24..26].copy_from_slice(&63u16.to_le_bytes());
+    boot[26..28].copy_from_slice(&255u16.to_le_bytes());
+    boot[28..32].copy_from_slice(&(lba as u32).to_le_bytes());
+    boot[36] = 0x80;
+    boot[38] = 0x29;
+    boot[39..43].copy_from_slice(&(sectors ^ 0xBAF1_05FA).to_le_bytes());
+
+    let mut name = [b' '; 11];
+    let label = if label.is_empty() { "NO NAME" } else { label };
+    for (c, b) in name.iter_mut().zip(label.bytes()) {
+        *c = b.to_ascii_uppercase();
+    }
+    boot[43..54].copy_from_slice(&name);
+    boot[54..62].copy_from_slice(b"FAT16   ");
+
+    // not bootable: hand over to the next boot device
+    boot[62..66].copy_from_slice(&[0xCD, 0x18, 0xEB, 0xFE]);
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+
+    device.write(lba, &boot)?;
+
+    let zero = [0u8; SECTOR];
+    for sector in RESERVED..RESERVED + FAT_COUNT * per_fat + root_sectors {
+        device.write(lba + sector as u64, &zero)?;
+    }
+
+    let mut first = [0u8; SECTOR];
+    first[0..2].copy_from_slice(&(0xFF00 | MEDIA as u16).to_le_bytes());
+    first[2..4].copy_from_slice(&END.to_le_bytes());
+
+    for copy in 0..FAT_COUNT {
+        device.write(lba + (RESERVED + copy * per_fat) as u64, &first)?;
+    }
+
+    Ok(())
+}
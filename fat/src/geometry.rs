@@ -0,0 +1,97 @@
+use crate::device::{Error, SECTOR};
+
+pub(crate) const ENTRY: usize = 32;
+
+pub(crate) const FREE: u16 = 0x0000;
+pub(crate) const BAD: u16 = 0xFFF7;
+pub(crate) const END: u16 = 0xFFFF;
+pub(crate) const FIRST_END: u16 = 0xFFF8;
+pub(crate) const FIRST_RESERVED: u16 = 0xFFF0;
+
+/// Fewer clusters than this and other systems take the volume for FAT12.
+pub(crate) const MIN_CLUSTERS: u32 = 4085;
+pub(crate) const MAX_CLUSTERS: u32 = 0xFFF4;
+
+/// Where the parts of a volume are, in sectors from its boot sector.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Geometry {
+    pub sectors_per_cluster: u32,
+    pub reserved: u32,
+    pub fat_count: u32,
+    pub sectors_per_fat: u32,
+    pub root_lba: u64,
+    pub root_sectors: u32,
+    pub data_lba: u64,
+    /// One past the highest valid cluster number.
+    pub clusters_end: u32,
+}
+
+impl Geometry {
+    pub fn parse(boot: &[u8]) -> Result<Geometry, Error> {
+        let u16_at = |o: usize| u16::from_le_bytes([boot[o], boot[o + 1]]) as u32;
+        let u32_at = |o: usize| u32::from_le_bytes([boot[o], boot[o + 1], boot[o + 2], boot[o + 3]]);
+
+        let bytes_per_sector = u16_at(11);
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved = u16_at(14);
+        let fat_count = boot[16] as u32;
+        let root_entries = u16_at(17);
+        let sectors_per_fat = u16_at(22);
+        let total = match u16_at(19) {
+            0 => u32_at(32),
+            n => n,
+        };
+
+        if boot[510] != 0x55
+            || boot[511] != 0xAA
+            || bytes_per_sector as usize != SECTOR
+            || sectors_per_cluster == 0
+            || fat_count == 0
+            || sectors_per_fat == 0
+        {
+            return Err(Error::NotFat16);
+        }
+
+        let root_lba = (reserved + fat_count * sectors_per_fat) as u64;
+        let root_sectors = (root_entries * ENTRY as u32).div_ceil(SECTOR as u32);
+        let data_lba = root_lba + root_sectors as u64;
+
+        let data_sectors = total.checked_sub(data_lba as u32).ok_or(Error::NotFat16)?;
+        let clusters = data_sectors / sectors_per_cluster;
+
+        // the FAT has to be able to describe every cluster
+        if clusters == 0
+            || clusters > MAX_CLUSTERS
+            || clusters + 2 > sectors_per_fat * (SECTOR as u32 / 2)
+        {
+            return Err(Error::NotFat16);
+        }
+
+        Ok(Geometry {
+            sectors_per_cluster,
+            reserved,
+            fat_count,
+            sectors_per_fat,
+            root_lba,
+            root_sectors,
+            data_lba,
+            clusters_end: clusters + 2,
+        })
+    }
+
+    pub fn cluster_bytes(&self) -> u32 {
+        self.sectors_per_cluster * SECTOR as u32
+    }
+
+    pub fn cluster_lba(&self, cluster: u32) -> u64 {
+        self.data_lba + (cluster as u64 - 2) * self.sectors_per_cluster as u64
+    }
+
+    pub fn fat_lba(&self, copy: u32) -> u64 {
+        (self.reserved + copy * self.sectors_per_fat) as u64
+    }
+
+    pub fn is_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.clusters_end
+    }
+}
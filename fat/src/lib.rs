@@ -0,0 +1,24 @@
+#![no_std]
+
+//! FAT16 as bafiOS uses it. All disk access goes through a [`BlockDevice`], so
+//! the same code runs in the kernel over its block cache and on the build
+//! machine over an image file.
+
+extern crate alloc;
+
+mod device;
+mod entry;
+mod format;
+mod geometry;
+mod volume;
+
+pub mod check;
+
+pub use check::{Report, check, is_clean, set_clean};
+pub use device::{BlockDevice, Error, SECTOR};
+pub use entry::{
+    ATTR_ARCHIVE, ATTR_DIRECTORY, ATTR_HIDDEN, ATTR_READ_ONLY, ATTR_SYSTEM, Entry, Meta, Timestamp,
+    display_name, short_name,
+};
+pub use format::format;
+pub use volume::Volume;
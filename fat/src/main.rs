@@ -0,0 +1,253 @@
+//! Works on bafiOS disk images from the build machine:
+//!
+//!     fat format IMAGE SECTORS [LABEL]
+//!     fat ls IMAGE [PATH]
+//!     fat mkdir IMAGE PATH
+//!     fat put IMAGE FILE PATH
+//!     fat get IMAGE PATH FILE
+//!     fat rm IMAGE PATH
+//!     fat fsck [-r] IMAGE
+//!
+//! A whole disk image is used at its first FAT16 partition.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fat::{BlockDevice, Error, Meta, SECTOR, Timestamp, Volume};
+
+const USAGE: &str = "usage: fat format IMAGE SECTORS [LABEL]
+       fat ls IMAGE [PATH]
+       fat mkdir IMAGE PATH
+       fat put IMAGE FILE PATH
+       fat get IMAGE PATH FILE
+       fat rm IMAGE PATH
+       fat fsck [-r] IMAGE";
+
+struct Image(File);
+
+impl BlockDevice for Image {
+    fn read(&self, lba: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        let mut file = &self.0;
+        file.seek(SeekFrom::Start(lba * SECTOR as u64)).map_err(|_| Error::Io)?;
+        file.read_exact(buffer).map_err(|_| Error::Io)
+    }
+
+    fn write(&self, lba: u64, buffer: &[u8]) -> Result<(), Error> {
+        let mut file = &self.0;
+        file.seek(SeekFrom::Start(lba * SECTOR as u64)).map_err(|_| Error::Io)?;
+        file.write_all(buffer).map_err(|_| Error::Io)
+    }
+}
+
+const FAT16_TYPES: [u8; 3] = [0x04, 0x06, 0x0E];
+
+/// Start of the first FAT16 partition, or 0 if the image is a bare volume.
+fn volume_start(image: &Image) -> Result<u64, Error> {
+    let mut mbr = [0u8; SECTOR];
+    image.read(0, &mut mbr)?;
+
+    // a FAT boot sector starts with a jump, an MBR usually does not
+    if mbr[0] == 0xEB || mbr[0] == 0xE9 || mbr[510..] != [0x55, 0xAA] {
+        return Ok(0);
+    }
+
+    for entry in mbr[446..510].chunks(16) {
+        if FAT16_TYPES.contains(&entry[4]) {
+            return Ok(u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64);
+        }
+    }
+
+    Ok(0)
+}
+
+/// The current UTC time as FAT stores it.
+fn now() -> Timestamp {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // days since 1970 to a civil date, after Howard Hinnant
+    let z = days as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let (hour, minute, second) = (time / 3600, time / 60 % 60, time % 60);
+
+    Timestamp {
+        date: (((year - 1980).clamp(0, 127) as u16) << 9) | ((month as u16) << 5) | day as u16,
+        time: ((hour as u16) << 11) | ((minute as u16) << 5) | (second as u16 / 2),
+        tenths: (second % 2) as u8 * 100,
+    }
+}
+
+/// Files copied in belong to the admin and are open to everyone, like the
+/// rest of a freshly built image.
+fn meta() -> Meta {
+    Meta {
+        owner: 0,
+        mode: 0,
+        time: now(),
+    }
+}
+
+fn open(path: &str, write: bool) -> Result<Volume<Image>, String> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(write)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+
+    let image = Image(file);
+    volume_start(&image)
+        .and_then(|lba| Volume::open(image, lba))
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+fn format(image: &str, sectors: &str, label: &str) -> Result<(), String> {
+    let sectors: u32 = sectors.parse().map_err(|_| format!("{}: not a sector count", sectors))?;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(image)
+        .map_err(|e| format!("{}: {}", image, e))?;
+    file.set_len(sectors as u64 * SECTOR as u64).map_err(|e| format!("{}: {}", image, e))?;
+
+    fat::format(&Image(file), 0, sectors, label).map_err(|e| format!("{}: {}", image, e))
+}
+
+fn ls(image: &str, path: &str) -> Result<(), String> {
+    let volume = open(image, false)?;
+
+    volume
+        .list(path, |entry| {
+            let mut name = [0u8; 12];
+            let length = fat::display_name(&entry.name, &mut name);
+            let name = String::from_utf8_lossy(&name[..length]);
+            let size = entry.size;
+
+            if entry.is_dir() {
+                println!("{:<12} {:>10}", name, "<DIR>");
+            } else {
+                println!("{:<12} {:>10}", name, size);
+            }
+        })
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+fn mkdir(image: &str, path: &str) -> Result<(), String> {
+    let mut volume = open(image, true)?;
+
+    volume.create_dir(path, &meta()).map(|_| ()).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn put(image: &str, source: &str, path: &str) -> Result<(), String> {
+    let data = std::fs::read(source).map_err(|e| format!("{}: {}", source, e))?;
+    let mut volume = open(image, true)?;
+
+    match volume.create_file(path, &meta()) {
+        Ok(_) | Err(Error::Exists) => {}
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    }
+
+    volume.write(path, &data, now()).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn get(image: &str, path: &str, target: &str) -> Result<(), String> {
+    let volume = open(image, false)?;
+
+    let entry = volume.find(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut data = vec![0u8; entry.size as usize];
+    volume.read(&entry, &mut data).map_err(|e| format!("{}: {}", path, e))?;
+
+    std::fs::write(target, &data).map_err(|e| format!("{}: {}", target, e))
+}
+
+fn rm(image: &str, path: &str) -> Result<(), String> {
+    let mut volume = open(image, true)?;
+
+    volume.remove(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn fsck(args: &[String]) -> Result<ExitCode, String> {
+    let repair = args.iter().any(|a| a == "-r");
+    let Some(path) = args.iter().find(|a| !a.starts_with('-')) else {
+        return Ok(usage());
+    };
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(repair)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let image = Image(file);
+
+    let report = volume_start(&image)
+        .and_then(|lba| fat::check(&image, lba, repair))
+        .map_err(|e| format!("{}: {}", path, e))?;
+
+    for message in &report.messages {
+        println!("{}", message);
+    }
+
+    println!(
+        "{}: {} files, {} directories, {} problems{}",
+        path,
+        report.files,
+        report.directories,
+        report.problems(),
+        if report.repaired { " (repaired)" } else { "" }
+    );
+
+    if report.problems() == 0 || report.repaired {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    let result = match args.as_slice() {
+        ["format", image, sectors] => format(image, sectors, ""),
+        ["format", image, sectors, label] => format(image, sectors, label),
+        ["ls", image] => ls(image, "/"),
+        ["ls", image, path] => ls(image, path),
+        ["mkdir", image, path] => mkdir(image, path),
+        ["put", image, source, path] => put(image, source, path),
+        ["get", image, path, target] => get(image, path, target),
+        ["rm", image, path] => rm(image, path),
+        ["fsck", rest @ ..] => {
+            let rest: Vec<String> = rest.iter().map(|a| a.to_string()).collect();
+            return fsck(&rest).unwrap_or_else(|e| {
+                eprintln!("fat: {}", e);
+                ExitCode::from(2)
+            });
+        }
+        _ => return usage(),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("fat: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
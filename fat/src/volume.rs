@@ -0,0 +1,654 @@
+use alloc::vec;
+
+use crate::device::{BlockDevice, Error, SECTOR};
+use crate::entry::*;
+use crate::geometry::*;
+
+/// Where a directory lives: the fixed root area or a cluster chain.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Dir {
+    Root,
+    Chain(u32),
+}
+
+/// Position of a directory entry, in sectors from the start of the volume.
+#[derive(Copy, Clone, Debug)]
+struct Slot {
+    lba: u64,
+    offset: usize,
+}
+
+/// A mounted FAT16 volume. Paths are `/` separated and relative to its root;
+/// every component is an 8.3 name, either as `NAME.EXT` or in the padded
+/// 11 byte form, and is matched without regard to case.
+pub struct Volume<D: BlockDevice> {
+    device: D,
+    lba: u64,
+    geometry: Geometry,
+    /// Where the search for a free cluster starts.
+    next_free: u32,
+}
+
+impl<D: BlockDevice> Volume<D> {
+    /// Opens the volume whose boot sector is at `lba` on the device.
+    pub fn open(device: D, lba: u64) -> Result<Volume<D>, Error> {
+        let mut boot = [0u8; SECTOR];
+        device.read(lba, &mut boot)?;
+        let geometry = Geometry::parse(&boot)?;
+
+        Ok(Volume {
+            device,
+            lba,
+            geometry,
+            next_free: 2,
+        })
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    pub fn lba(&self) -> u64 {
+        self.lba
+    }
+
+    pub fn cluster_bytes(&self) -> u32 {
+        self.geometry.cluster_bytes()
+    }
+
+    /// Reads as many sectors from `lba` on as `buffer` holds, in one transfer.
+    fn read_sectors(&self, lba: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        self.device.read(self.lba + lba, buffer)
+    }
+
+    fn write_sectors(&self, lba: u64, buffer: &[u8]) -> Result<(), Error> {
+        self.device.write(self.lba + lba, buffer)
+    }
+
+    fn fat(&self, cluster: u32) -> Result<u16, Error> {
+        let byte = cluster as usize * 2;
+        let mut sector = [0u8; SECTOR];
+        self.read_sectors(self.geometry.fat_lba(0) + (byte / SECTOR) as u64, &mut sector)?;
+
+        let offset = byte % SECTOR;
+        Ok(u16::from_le_bytes([sector[offset], sector[offset + 1]]))
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u16) -> Result<(), Error> {
+        let byte = cluster as usize * 2;
+        let index = (byte / SECTOR) as u64;
+        let offset = byte % SECTOR;
+
+        let mut sector = [0u8; SECTOR];
+        self.read_sectors(self.geometry.fat_lba(0) + index, &mut sector)?;
+        sector[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+
+        // keep every copy of the FAT identical
+        for copy in 0..self.geometry.fat_count {
+            self.write_sectors(self.geometry.fat_lba(copy) + index, &sector)?;
+        }
+
+        Ok(())
+    }
+
+    /// Cluster after `cluster` in its chain, None at the end of the chain.
+    fn next(&self, cluster: u32) -> Result<Option<u32>, Error> {
+        match self.fat(cluster)? {
+            FIRST_END..=END => Ok(None),
+            next if self.geometry.is_cluster(next as u32) => Ok(Some(next as u32)),
+            _ => Err(Error::Corrupt),
+        }
+    }
+
+    /// Claims a free cluster and marks it as the end of a chain.
+    fn allocate(&mut self) -> Result<u32, Error> {
+        let g = self.geometry;
+        let per_sector = (SECTOR / 2) as u32;
+        let total = g.clusters_end - 2;
+
+        let mut sector = [0u8; SECTOR];
+        let mut loaded = u32::MAX;
+
+        for step in 0..total {
+            let cluster = 2 + (self.next_free - 2 + step) % total;
+
+            if cluster / per_sector != loaded {
+                loaded = cluster / per_sector;
+                self.read_sectors(g.fat_lba(0) + loaded as u64, &mut sector)?;
+            }
+
+            let offset = (cluster % per_sector) as usize * 2;
+            if u16::from_le_bytes([sector[offset], sector[offset + 1]]) == FREE {
+                self.set_fat(cluster, END)?;
+                self.next_free = if cluster + 1 < g.clusters_end { cluster + 1 } else { 2 };
+
+                return Ok(cluster);
+            }
+        }
+
+        Err(Error::NoSpace)
+    }
+
+    fn free_chain(&mut self, start: u32) -> Result<(), Error> {
+        let mut cluster = start;
+
+        // the bound stops a looping chain from hanging us
+        for _ in 0..self.geometry.clusters_end {
+            if !self.geometry.is_cluster(cluster) {
+                break;
+            }
+
+            let next = self.fat(cluster)?;
+            self.set_fat(cluster, FREE)?;
+            cluster = next as u32;
+        }
+
+        Ok(())
+    }
+
+    /// A new directory cluster must not show whatever was stored there before.
+    fn clear_cluster(&mut self, cluster: u32) -> Result<(), Error> {
+        let zero = vec![0u8; self.geometry.cluster_bytes() as usize];
+        self.write_sectors(self.geometry.cluster_lba(cluster), &zero)
+    }
+
+    /// Calls `f` with every sector of a directory until it returns true.
+    fn sectors(&self, dir: Dir, mut f: impl FnMut(u64) -> Result<bool, Error>) -> Result<(), Error> {
+        let g = self.geometry;
+
+        match dir {
+            Dir::Root => {
+                for sector in 0..g.root_sectors as u64 {
+                    if f(g.root_lba + sector)? {
+                        return Ok(());
+                    }
+                }
+            }
+            Dir::Chain(start) => {
+                let mut cluster = start;
+
+                for _ in 0..g.clusters_end {
+                    if !g.is_cluster(cluster) {
+                        return Err(Error::Corrupt);
+                    }
+
+                    for sector in 0..g.sectors_per_cluster as u64 {
+                        if f(g.cluster_lba(cluster) + sector)? {
+                            return Ok(());
+                        }
+                    }
+
+                    match self.next(cluster)? {
+                        Some(next) => cluster = next,
+                        None => return Ok(()),
+                    }
+                }
+
+                return Err(Error::Corrupt);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hands every entry of a directory to `f`, up to and including the one
+    /// marking its end, and stops early at the first `Some`.
+    fn scan<R>(&self, dir: Dir, mut f: impl FnMut(Slot, &Entry) -> Option<R>) -> Result<Option<R>, Error> {
+        let mut result = None;
+
+        self.sectors(dir, |lba| {
+            let mut sector = [0u8; SECTOR];
+            self.read_sectors(lba, &mut sector)?;
+
+            for offset in (0..SECTOR).step_by(ENTRY) {
+                let entry = Entry::from_bytes(&sector[offset..]);
+                result = f(Slot { lba, offset }, &entry);
+
+                if result.is_some() || entry.name[0] == 0 {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
+        })?;
+
+        Ok(result)
+    }
+
+    fn find_in(&self, dir: Dir, name: &[u8; 11]) -> Result<Option<(Slot, Entry)>, Error> {
+        self.scan(dir, |slot, entry| {
+            let matches =
+                entry.name[0] != 0 && entry.is_visible() && entry.name.eq_ignore_ascii_case(name);
+            matches.then_some((slot, *entry))
+        })
+    }
+
+    fn dir_of(entry: &Entry) -> Result<Dir, Error> {
+        if !entry.is_dir() {
+            return Err(Error::NotADirectory);
+        }
+
+        // `..` in a directory just below the root points at cluster 0
+        match entry.cluster() {
+            0 => Ok(Dir::Root),
+            cluster => Ok(Dir::Chain(cluster)),
+        }
+    }
+
+    fn dir(&self, path: &str) -> Result<Dir, Error> {
+        let mut dir = Dir::Root;
+
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            let (_, entry) = self.find_in(dir, &short_name(part)?)?.ok_or(Error::NotFound)?;
+            dir = Self::dir_of(&entry)?;
+        }
+
+        Ok(dir)
+    }
+
+    /// Directory holding `path` and the name of its last component.
+    fn parent(&self, path: &str) -> Result<(Dir, [u8; 11]), Error> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = match path.rfind('/') {
+            Some(pos) => (&path[..pos], &path[pos + 1..]),
+            None => ("", path),
+        };
+
+        if name.is_empty() {
+            return Err(Error::InvalidName);
+        }
+
+        Ok((self.dir(dir)?, short_name(name)?))
+    }
+
+    fn lookup(&self, path: &str) -> Result<(Slot, Entry), Error> {
+        let (dir, name) = self.parent(path)?;
+        self.find_in(dir, &name)?.ok_or(Error::NotFound)
+    }
+
+    fn store(&mut self, slot: Slot, entry: &Entry) -> Result<(), Error> {
+        let mut sector = [0u8; SECTOR];
+        self.read_sectors(slot.lba, &mut sector)?;
+        entry.write_to(&mut sector[slot.offset..]);
+
+        self.write_sectors(slot.lba, &sector)
+    }
+
+    /// First unused entry of a directory. Full subdirectories grow by a
+    /// cluster, the root directory has a fixed size.
+    fn free_slot(&mut self, dir: Dir) -> Result<Slot, Error> {
+        let free = self.scan(dir, |slot, entry| {
+            (entry.name[0] == 0 || entry.name[0] == DELETED).then_some(slot)
+        })?;
+
+        if let Some(slot) = free {
+            return Ok(slot);
+        }
+
+        let Dir::Chain(start) = dir else {
+            return Err(Error::NoSpace);
+        };
+
+        let mut last = start;
+        while let Some(next) = self.next(last)? {
+            last = next;
+        }
+
+        let cluster = self.allocate()?;
+        self.clear_cluster(cluster)?;
+        self.set_fat(last, cluster as u16)?;
+
+        Ok(Slot {
+            lba: self.geometry.cluster_lba(cluster),
+            offset: 0,
+        })
+    }
+
+    fn create(&mut self, path: &str, attributes: u8, cluster: u32, meta: &Meta) -> Result<Entry, Error> {
+        let (dir, name) = self.parent(path)?;
+
+        if self.find_in(dir, &name)?.is_some() {
+            return Err(Error::Exists);
+        }
+
+        let slot = self.free_slot(dir)?;
+
+        let mut entry = Entry::new(name, attributes, cluster, 0);
+        entry.set_permissions(meta.owner, meta.mode);
+        entry.stamp_created(meta.time);
+
+        self.store(slot, &entry)?;
+        Ok(entry)
+    }
+
+    /// Writes `data` after the current end of a file, growing its chain as needed.
+    /// The size is kept up to date, so the entry stays valid if this fails halfway.
+    fn fill(&mut self, entry: &mut Entry, data: &[u8]) -> Result<(), Error> {
+        let g = self.geometry;
+        let cluster_bytes = g.cluster_bytes() as usize;
+
+        if (entry.size as usize).checked_add(data.len()).is_none_or(|s| s > u32::MAX as usize) {
+            return Err(Error::NoSpace);
+        }
+
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        // find the cluster holding the first byte to write
+        let position = entry.size as usize;
+        let mut offset = position % cluster_bytes;
+        let steps = position / cluster_bytes;
+
+        let mut cluster = if g.is_cluster(entry.cluster()) {
+            let mut cluster = entry.cluster();
+
+            for step in 0..steps {
+                cluster = match self.next(cluster)? {
+                    Some(next) => next,
+                    // the chain may end exactly where the data does
+                    None if step + 1 == steps && offset == 0 => {
+                        let new = self.allocate()?;
+                        self.set_fat(cluster, new as u16)?;
+                        new
+                    }
+                    None => return Err(Error::Corrupt),
+                };
+            }
+
+            cluster
+        } else if position == 0 {
+            let cluster = self.allocate()?;
+            entry.set_cluster(cluster);
+            offset = 0;
+            cluster
+        } else {
+            return Err(Error::Corrupt);
+        };
+
+        let mut written = 0;
+        let mut sector = [0u8; SECTOR];
+
+        loop {
+            while offset < cluster_bytes && written < data.len() {
+                let lba = g.cluster_lba(cluster) + (offset / SECTOR) as u64;
+                let within = offset % SECTOR;
+                let count = if within == 0 && data.len() - written >= SECTOR {
+                    // whole sectors up to the end of the cluster go in one transfer
+                    core::cmp::min(cluster_bytes - offset, (data.len() - written) / SECTOR * SECTOR)
+                } else {
+                    core::cmp::min(SECTOR - within, data.len() - written)
+                };
+
+                if count % SECTOR == 0 {
+                    self.write_sectors(lba, &data[written..written + count])?;
+                } else {
+                    self.read_sectors(lba, &mut sector)?;
+                    sector[within..within + count].copy_from_slice(&data[written..written + count]);
+                    self.write_sectors(lba, &sector)?;
+                }
+
+                offset += count;
+                written += count;
+                entry.size += count as u32;
+            }
+
+            if written == data.len() {
+                return Ok(());
+            }
+
+            cluster = match self.next(cluster)? {
+                Some(next) => next,
+                None => {
+                    let new = self.allocate()?;
+                    self.set_fat(cluster, new as u16)?;
+                    new
+                }
+            };
+            offset = 0;
+        }
+    }
+
+    pub fn find(&self, path: &str) -> Result<Entry, Error> {
+        Ok(self.lookup(path)?.1)
+    }
+
+    /// Whether `path` names a directory; the root counts as one.
+    pub fn is_dir(&self, path: &str) -> Result<bool, Error> {
+        match self.dir(path) {
+            Ok(_) => Ok(true),
+            Err(Error::NotADirectory) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads a file from its start into `buffer` and returns how many bytes were read.
+    pub fn read(&self, entry: &Entry, buffer: &mut [u8]) -> Result<usize, Error> {
+        self.read_at(entry, 0, buffer)
+    }
+
+    /// Reads from `offset` bytes into a file, stopping at its end.
+    pub fn read_at(&self, entry: &Entry, offset: u32, buffer: &mut [u8]) -> Result<usize, Error> {
+        if entry.is_dir() {
+            return Err(Error::IsADirectory);
+        }
+
+        let g = self.geometry;
+        let cluster_bytes = g.cluster_bytes() as usize;
+        let offset = offset as usize;
+
+        if offset >= entry.size as usize {
+            return Ok(0);
+        }
+
+        let length = core::cmp::min(entry.size as usize - offset, buffer.len());
+        let mut cluster = entry.cluster();
+
+        for _ in 0..offset / cluster_bytes {
+            if !g.is_cluster(cluster) {
+                return Err(Error::Corrupt);
+            }
+            cluster = self.next(cluster)?.ok_or(Error::Corrupt)?;
+        }
+
+        let mut position = offset % cluster_bytes;
+        let mut done = 0;
+        let mut sector = [0u8; SECTOR];
+
+        while done < length {
+            if !g.is_cluster(cluster) {
+                return Err(Error::Corrupt);
+            }
+
+            while position < cluster_bytes && done < length {
+                let lba = g.cluster_lba(cluster) + (position / SECTOR) as u64;
+                let within = position % SECTOR;
+                let count = if within == 0 && length - done >= SECTOR {
+                    // whole sectors up to the end of the cluster come in one transfer
+                    core::cmp::min(cluster_bytes - position, (length - done) / SECTOR * SECTOR)
+                } else {
+                    core::cmp::min(SECTOR - within, length - done)
+                };
+
+                if count % SECTOR == 0 {
+                    self.read_sectors(lba, &mut buffer[done..done + count])?;
+                } else {
+                    self.read_sectors(lba, &mut sector)?;
+                    buffer[done..done + count].copy_from_slice(&sector[within..within + count]);
+                }
+
+                position += count;
+                done += count;
+            }
+
+            if done < length {
+                cluster = self.next(cluster)?.ok_or(Error::Corrupt)?;
+                position = 0;
+            }
+        }
+
+        Ok(length)
+    }
+
+    /// Calls `f` with every file and directory in a directory, in disk order.
+    pub fn list(&self, path: &str, mut f: impl FnMut(&Entry)) -> Result<(), Error> {
+        let dir = self.dir(path)?;
+
+        self.scan(dir, |_, entry| {
+            if entry.name[0] != 0 && entry.is_visible() && !entry.is_dot() {
+                f(entry);
+            }
+            None::<()>
+        })?;
+
+        Ok(())
+    }
+
+    pub fn count(&self, path: &str) -> Result<usize, Error> {
+        let mut count = 0;
+        self.list(path, |_| count += 1)?;
+
+        Ok(count)
+    }
+
+    /// The `index`th entry `list` would hand out.
+    pub fn entry_at(&self, path: &str, index: usize) -> Result<Option<Entry>, Error> {
+        let dir = self.dir(path)?;
+        let mut seen = 0;
+
+        self.scan(dir, |_, entry| {
+            if entry.name[0] == 0 || !entry.is_visible() || entry.is_dot() {
+                return None;
+            }
+
+            seen += 1;
+            (seen == index + 1).then_some(*entry)
+        })
+    }
+
+    pub fn create_file(&mut self, path: &str, meta: &Meta) -> Result<Entry, Error> {
+        self.create(path, ATTR_ARCHIVE, 0, meta)
+    }
+
+    pub fn create_dir(&mut self, path: &str, meta: &Meta) -> Result<Entry, Error> {
+        let (dir, name) = self.parent(path)?;
+        if self.find_in(dir, &name)?.is_some() {
+            return Err(Error::Exists);
+        }
+
+        let cluster = self.allocate()?;
+
+        let entry = match self
+            .clear_cluster(cluster)
+            .and_then(|_| self.create(path, ATTR_DIRECTORY, cluster, meta))
+        {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.set_fat(cluster, FREE)?;
+                return Err(e);
+            }
+        };
+
+        let parent_cluster = match dir {
+            Dir::Root => 0,
+            Dir::Chain(c) => c,
+        };
+
+        let mut dot = entry;
+        dot.name = *b".          ";
+
+        let mut dot_dot = entry;
+        dot_dot.name = *b"..         ";
+        dot_dot.set_cluster(parent_cluster);
+
+        let mut sector = [0u8; SECTOR];
+        dot.write_to(&mut sector[0..]);
+        dot_dot.write_to(&mut sector[ENTRY..]);
+        self.write_sectors(self.geometry.cluster_lba(cluster), &sector)?;
+
+        Ok(entry)
+    }
+
+    /// Replaces the contents of a file.
+    pub fn write(&mut self, path: &str, data: &[u8], now: Timestamp) -> Result<(), Error> {
+        let (slot, mut entry) = self.lookup(path)?;
+        if entry.is_dir() {
+            return Err(Error::IsADirectory);
+        }
+
+        let old = entry.cluster();
+        entry.set_cluster(0);
+        entry.size = 0;
+
+        if self.geometry.is_cluster(old) {
+            self.free_chain(old)?;
+        }
+
+        let result = self.fill(&mut entry, data);
+        entry.stamp_modified(now);
+        self.store(slot, &entry)?;
+
+        result
+    }
+
+    pub fn append(&mut self, path: &str, data: &[u8], now: Timestamp) -> Result<(), Error> {
+        let (slot, mut entry) = self.lookup(path)?;
+        if entry.is_dir() {
+            return Err(Error::IsADirectory);
+        }
+
+        let result = self.fill(&mut entry, data);
+        entry.stamp_modified(now);
+        self.store(slot, &entry)?;
+
+        result
+    }
+
+    /// Deletes a file or an empty directory.
+    pub fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let (slot, mut entry) = self.lookup(path)?;
+
+        if entry.is_dir() && entry.cluster() != 0 {
+            let contents = self.scan(Dir::Chain(entry.cluster()), |_, e| {
+                (e.name[0] != 0 && e.is_visible() && !e.is_dot()).then_some(())
+            })?;
+
+            if contents.is_some() {
+                return Err(Error::NotEmpty);
+            }
+        }
+
+        if self.geometry.is_cluster(entry.cluster()) {
+            self.free_chain(entry.cluster())?;
+        }
+
+        entry.name[0] = DELETED;
+        self.store(slot, &entry)
+    }
+
+    /// Gives an entry a new name in the directory it is already in.
+    pub fn rename(&mut self, path: &str, name: &str) -> Result<(), Error> {
+        let (dir, old) = self.parent(path)?;
+        let new = short_name(name)?;
+
+        let (slot, mut entry) = self.find_in(dir, &old)?.ok_or(Error::NotFound)?;
+
+        if !new.eq_ignore_ascii_case(&old) && self.find_in(dir, &new)?.is_some() {
+            return Err(Error::Exists);
+        }
+
+        entry.name = new;
+        self.store(slot, &entry)
+    }
+
+    /// Lets `f` change an entry in place; it is written back if `f` returns true.
+    pub fn update(&mut self, path: &str, f: impl FnOnce(&mut Entry) -> bool) -> Result<(), Error> {
+        let (slot, mut entry) = self.lookup(path)?;
+
+        if f(&mut entry) {
+            self.store(slot, &entry)?;
+        }
+
+        Ok(())
+    }
+}
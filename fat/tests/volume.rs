@@ -0,0 +1,364 @@
+use std::cell::{Cell, RefCell};
+
+use fat::{BlockDevice, Error, Meta, SECTOR, Timestamp, Volume};
+
+/// 32 MiB is the smallest round size that still formats as FAT16.
+const SECTORS: u32 = 65536;
+
+struct RamDisk {
+    data: RefCell<Vec<u8>>,
+    /// Size of the largest read so far, in bytes.
+    largest_read: Cell<usize>,
+}
+
+impl RamDisk {
+    fn new(sectors: u32) -> RamDisk {
+        RamDisk {
+            data: RefCell::new(vec![0; sectors as usize * SECTOR]),
+            largest_read: Cell::new(0),
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read(&self, lba: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        let start = lba as usize * SECTOR;
+        let data = self.data.borrow();
+        self.largest_read.set(self.largest_read.get().max(buffer.len()));
+        let source = data.get(start..start + buffer.len()).ok_or(Error::Io)?;
+
+        buffer.copy_from_slice(source);
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buffer: &[u8]) -> Result<(), Error> {
+        let start = lba as usize * SECTOR;
+        let mut data = self.data.borrow_mut();
+        let target = data.get_mut(start..start + buffer.len()).ok_or(Error::Io)?;
+
+        target.copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+const NOW: Timestamp = Timestamp {
+    date: (45 << 9) | (3 << 5) | 14,
+    time: (12 << 11) | (30 << 5),
+    tenths: 0,
+};
+
+const META: Meta = Meta {
+    owner: 1000,
+    mode: 0o22,
+    time: NOW,
+};
+
+fn volume() -> Volume<RamDisk> {
+    let disk = RamDisk::new(SECTORS);
+    fat::format(&disk, 0, SECTORS, "TEST").unwrap();
+
+    Volume::open(disk, 0).unwrap()
+}
+
+fn pattern(length: usize) -> Vec<u8> {
+    (0..length).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+fn contents(volume: &Volume<RamDisk>, path: &str) -> Vec<u8> {
+    let entry = volume.find(path).unwrap();
+    let mut data = vec![0; entry.size as usize];
+    assert_eq!(volume.read(&entry, &mut data).unwrap(), data.len());
+
+    data
+}
+
+fn assert_clean(volume: &Volume<RamDisk>) {
+    let report = fat::check(volume.device(), volume.lba(), false).unwrap();
+    assert_eq!(report.problems(), 0, "{:?}", report.messages);
+}
+
+#[test]
+fn formats_an_empty_volume() {
+    let volume = volume();
+
+    assert_eq!(volume.count("/").unwrap(), 0);
+    assert!(volume.is_dir("/").unwrap());
+    assert!(fat::is_clean(volume.device(), 0).unwrap());
+    assert_clean(&volume);
+}
+
+#[test]
+fn refuses_volumes_too_small_for_fat16() {
+    let disk = RamDisk::new(4096);
+    assert_eq!(fat::format(&disk, 0, 4096, ""), Err(Error::NotFat16));
+}
+
+#[test]
+fn writes_and_reads_across_clusters() {
+    let mut volume = volume();
+    let data = pattern(volume.cluster_bytes() as usize * 3 + 100);
+
+    volume.create_file("/DATA.BIN", &META).unwrap();
+    volume.write("/DATA.BIN", &data, NOW).unwrap();
+
+    assert_eq!(contents(&volume, "/DATA.BIN"), data);
+    assert_clean(&volume);
+}
+
+#[test]
+fn reads_whole_clusters_in_one_transfer() {
+    let mut volume = volume();
+    let cluster = volume.cluster_bytes() as usize;
+    let data = pattern(cluster * 2);
+
+    volume.create_file("/DATA.BIN", &META).unwrap();
+    volume.write("/DATA.BIN", &data, NOW).unwrap();
+    volume.device().largest_read.set(0);
+
+    assert_eq!(contents(&volume, "/DATA.BIN"), data);
+    assert_eq!(volume.device().largest_read.get(), cluster);
+}
+
+#[test]
+fn reads_from_an_offset() {
+    let mut volume = volume();
+    let cluster = volume.cluster_bytes() as usize;
+    let data = pattern(cluster * 2 + 700);
+
+    volume.create_file("/DATA.BIN", &META).unwrap();
+    volume.write("/DATA.BIN", &data, NOW).unwrap();
+    let entry = volume.find("/DATA.BIN").unwrap();
+
+    // a window straddling a sector and a cluster boundary
+    let mut window = vec![0; 1000];
+    let start = cluster - 300;
+    assert_eq!(volume.read_at(&entry, start as u32, &mut window).unwrap(), 1000);
+    assert_eq!(window, &data[start..start + 1000]);
+
+    // reads stop at the end of the file
+    let end = data.len() - 10;
+    assert_eq!(volume.read_at(&entry, end as u32, &mut window).unwrap(), 10);
+    assert_eq!(&window[..10], &data[end..]);
+    assert_eq!(volume.read_at(&entry, data.len() as u32, &mut window).unwrap(), 0);
+}
+
+#[test]
+fn keeps_owner_mode_and_times() {
+    let mut volume = volume();
+    let entry = volume.create_file("/A.TXT", &META).unwrap();
+
+    assert_eq!(entry.owner(), 1000);
+    assert_eq!(entry.mode(), 0o22);
+    assert_eq!(entry.created(), NOW);
+    assert_eq!(volume.find("/A.TXT").unwrap(), entry);
+}
+
+#[test]
+fn appends_to_a_file() {
+    let mut volume = volume();
+    let cluster = volume.cluster_bytes() as usize;
+    let first = pattern(cluster - 10);
+    let second = pattern(cluster + 30);
+
+    volume.create_file("/LOG.TXT", &META).unwrap();
+    volume.append("/LOG.TXT", &first, NOW).unwrap();
+    volume.append("/LOG.TXT", &second, NOW).unwrap();
+
+    let mut expected = first.clone();
+    expected.extend_from_slice(&second);
+    assert_eq!(contents(&volume, "/LOG.TXT"), expected);
+    assert_clean(&volume);
+}
+
+#[test]
+fn appends_at_a_cluster_boundary() {
+    let mut volume = volume();
+    let cluster = volume.cluster_bytes() as usize;
+
+    volume.create_file("/LOG.TXT", &META).unwrap();
+    volume.append("/LOG.TXT", &pattern(cluster), NOW).unwrap();
+    volume.append("/LOG.TXT", b"tail", NOW).unwrap();
+
+    let data = contents(&volume, "/LOG.TXT");
+    assert_eq!(data.len(), cluster + 4);
+    assert_eq!(&data[cluster..], b"tail");
+    assert_clean(&volume);
+}
+
+#[test]
+fn overwriting_frees_the_old_chain() {
+    let mut volume = volume();
+
+    volume.create_file("/BIG.BIN", &META).unwrap();
+    volume.write("/BIG.BIN", &pattern(100_000), NOW).unwrap();
+    volume.write("/BIG.BIN", b"small", NOW).unwrap();
+
+    assert_eq!(contents(&volume, "/BIG.BIN"), b"small");
+    assert_clean(&volume);
+}
+
+#[test]
+fn creates_nested_directories() {
+    let mut volume = volume();
+
+    volume.create_dir("/USER", &META).unwrap();
+    volume.create_dir("/USER/DESKTOP", &META).unwrap();
+    volume.create_file("/USER/DESKTOP/NOTE.TXT", &META).unwrap();
+
+    assert_eq!(volume.count("/USER").unwrap(), 1);
+    assert_eq!(volume.count("/USER/DESKTOP").unwrap(), 1);
+
+    let entry = volume.entry_at("/USER/DESKTOP", 0).unwrap().unwrap();
+    assert_eq!(&entry.name, b"NOTE    TXT");
+    assert!(volume.entry_at("/USER/DESKTOP", 1).unwrap().is_none());
+
+    assert!(volume.is_dir("/USER/DESKTOP").unwrap());
+    assert!(!volume.is_dir("/USER/DESKTOP/NOTE.TXT").unwrap());
+    assert_clean(&volume);
+}
+
+#[test]
+fn matches_names_without_case() {
+    let mut volume = volume();
+
+    volume.create_dir("/user", &META).unwrap();
+    volume.create_file("/user/readme.txt", &META).unwrap();
+
+    assert!(volume.find("/USER/README.TXT").is_ok());
+    assert!(volume.find("/User/README  TXT").is_ok());
+    assert_eq!(volume.create_file("/USER/README.TXT", &META), Err(Error::Exists));
+}
+
+#[test]
+fn grows_full_directories() {
+    let mut volume = volume();
+    let per_cluster = volume.cluster_bytes() as usize / 32;
+
+    volume.create_dir("/MANY", &META).unwrap();
+    for i in 0..per_cluster * 2 {
+        volume.create_file(&format!("/MANY/F{}.TXT", i), &META).unwrap();
+    }
+
+    assert_eq!(volume.count("/MANY").unwrap(), per_cluster * 2);
+    assert!(volume.find(&format!("/MANY/F{}.TXT", per_cluster * 2 - 1)).is_ok());
+    assert_clean(&volume);
+}
+
+#[test]
+fn root_directory_has_a_fixed_size() {
+    let mut volume = volume();
+
+    for i in 0..512 {
+        volume.create_file(&format!("/F{}", i), &META).unwrap();
+    }
+
+    assert_eq!(volume.create_file("/ONEMORE", &META), Err(Error::NoSpace));
+}
+
+#[test]
+fn removes_files_and_empty_directories() {
+    let mut volume = volume();
+
+    volume.create_dir("/DIR", &META).unwrap();
+    volume.create_file("/DIR/A.TXT", &META).unwrap();
+    volume.write("/DIR/A.TXT", &pattern(5000), NOW).unwrap();
+
+    assert_eq!(volume.remove("/DIR"), Err(Error::NotEmpty));
+
+    volume.remove("/DIR/A.TXT").unwrap();
+    volume.remove("/DIR").unwrap();
+
+    assert_eq!(volume.find("/DIR"), Err(Error::NotFound));
+    assert_eq!(volume.count("/").unwrap(), 0);
+    assert_clean(&volume);
+}
+
+#[test]
+fn reuses_deleted_entries() {
+    let mut volume = volume();
+
+    volume.create_file("/A", &META).unwrap();
+    volume.create_file("/B", &META).unwrap();
+    volume.remove("/A").unwrap();
+    volume.create_file("/C", &META).unwrap();
+
+    let first = volume.entry_at("/", 0).unwrap().unwrap();
+    assert_eq!(&first.name, b"C          ");
+}
+
+#[test]
+fn renames_in_place() {
+    let mut volume = volume();
+
+    volume.create_file("/OLD.TXT", &META).unwrap();
+    volume.create_file("/OTHER.TXT", &META).unwrap();
+    volume.write("/OLD.TXT", b"hello", NOW).unwrap();
+
+    assert_eq!(volume.rename("/OLD.TXT", "OTHER.TXT"), Err(Error::Exists));
+
+    volume.rename("/OLD.TXT", "new.txt").unwrap();
+    assert_eq!(volume.find("/OLD.TXT"), Err(Error::NotFound));
+    assert_eq!(contents(&volume, "/NEW.TXT"), b"hello");
+
+    // only the case differs, so it is not a clash with itself
+    volume.rename("/NEW.TXT", "New.Txt").unwrap();
+}
+
+#[test]
+fn rejects_bad_names() {
+    assert_eq!(fat::short_name("readme.txt"), Ok(*b"README  TXT"));
+    assert_eq!(fat::short_name("KERNEL"), Ok(*b"KERNEL     "));
+    assert_eq!(fat::short_name("TOOLONGNAME.TXT"), Err(Error::InvalidName));
+    assert_eq!(fat::short_name("A.LONG"), Err(Error::InvalidName));
+    assert_eq!(fat::short_name("A*B"), Err(Error::InvalidName));
+    assert_eq!(fat::short_name(""), Err(Error::InvalidName));
+}
+
+#[test]
+fn works_inside_a_partition() {
+    let disk = RamDisk::new(SECTORS + 2048);
+    fat::format(&disk, 2048, SECTORS, "").unwrap();
+
+    let mut volume = Volume::open(&disk, 2048).unwrap();
+    volume.create_file("/A.TXT", &META).unwrap();
+    volume.write("/A.TXT", b"data", NOW).unwrap();
+
+    // nothing may land in front of the partition
+    assert!(disk.data.borrow()[..2048 * SECTOR].iter().all(|b| *b == 0));
+
+    let report = fat::check(&disk, 2048, false).unwrap();
+    assert_eq!(report.files, 1);
+    assert_eq!(report.problems(), 0);
+}
+
+#[test]
+fn check_repairs_a_lost_cluster() {
+    let mut volume = volume();
+    volume.create_file("/A.TXT", &META).unwrap();
+    volume.write("/A.TXT", &pattern(3000), NOW).unwrap();
+
+    // point the entry elsewhere, orphaning its chain
+    volume
+        .update("/A.TXT", |entry| {
+            *entry = fat::Entry::new(entry.name, fat::ATTR_ARCHIVE, 0, 0);
+            true
+        })
+        .unwrap();
+
+    let report = fat::check(volume.device(), 0, true).unwrap();
+    assert!(report.lost_clusters > 0);
+    assert!(report.repaired);
+
+    assert_clean(&volume);
+}
+
+#[test]
+fn clean_flag_round_trips() {
+    let volume = volume();
+
+    fat::set_clean(volume.device(), 0, false).unwrap();
+    assert!(!fat::is_clean(volume.device(), 0).unwrap());
+
+    fat::set_clean(volume.device(), 0, true).unwrap();
+    assert!(fat::is_clean(volume.device(), 0).unwrap());
+}
@@ -43,6 +43,128 @@ pub extern "C" fn _start() -> ! {
             .display(Display::Grid(Grid::new(cols, rows))),
     );
 
+    let frame = frame1.get_id().unwrap() as u32;
+    let window = unsafe { (*(&raw mut kui::widgets::WINDOWS)).len() as u32 };
+
+    if let Widget::Frame(f) = &mut frame1 {
+        show_dir(f, folder, frame, window);
+    }
+
+    main.add(frame1);
+
+    kui::draw::init(main);
+
+    unsafe { *(*(&raw mut CURRENT_DIR)).lock() = alloc::string::String::from(folder) }
+
+    let mut watched = alloc::string::String::new();
+    let mut watch = None;
+
+    loop {
+        // the buttons run as their own tasks, so follow whatever they navigated to
+        let dir = unsafe { (*(&raw mut CURRENT_DIR)).lock().clone() };
+        if dir != watched {
+            if let Some(id) = watch {
+                libk::io::unwatch(id);
+            }
+            watch = libk::io::watch(&dir);
+            watched = dir;
+        }
+
+        let mut changed = false;
+        while let Some(_event) = watch.and_then(libk::io::poll_watch) {
+            changed = true;
+        }
+
+        if changed {
+            refresh(frame, window);
+        }
+
+        for _ in 0..100_000 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Lists the current directory again after it changed under us.
+fn refresh(a1: u32, a2: u32) {
+    let w = unsafe { &mut (*(&raw mut kui::widgets::WINDOWS))[a2 as usize] };
+    let dir_str = unsafe { (*(&raw mut CURRENT_DIR)).lock().clone() };
+
+    for f in w.children.iter_mut() {
+        if f.get_id().unwrap() == a1 as u16 {
+            if let kui::widgets::Widget::Frame(f) = f {
+                show_dir(f, &dir_str, a1, a2);
+            }
+        }
+    }
+
+    kui::draw::draw(w);
+    libk::syscall::syscall(41, w.id as u32, 0, 0);
+}
+
+pub fn back(_w: &mut Widget, a1: u32, a2: u32, _a3: u32) {
+    let w = unsafe { &mut (*(&raw mut kui::widgets::WINDOWS))[a2 as usize] };
+
+    for f in w.children.iter_mut() {
+        if f.get_id().unwrap() == a1 as u16 {
+            match f {
+                kui::widgets::Widget::Frame(f) => {
+                    // the header label may hold a status message instead of the path
+                    let dir_str = unsafe { (*(&raw mut CURRENT_DIR)).lock().clone() };
+                    let dir_str = alloc::string::String::from(remove_last_component(&dir_str));
+                    unsafe { STATUS = None };
+
+                    let entries = list_entries(&dir_str) as usize;
+                    let rows = 8;
+                    let cols = (entries + rows - 1) / rows;
+
+                    f.display = kui::widgets::Display::Grid(Grid::new(cols, rows));
+
+                    show_dir(f, &dir_str, a1, a2);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    kui::draw::draw(w);
+    libk::syscall::syscall(41, w.id as u32, 0, 0);
+}
+
+pub fn new_folder(w: &mut Widget, a1: u32, a2: u32, a3: u32) {
+    let old_value = core::mem::replace(w, kui::widgets::Widget::Label(Label::new()));
+
+    let w = unsafe { &mut (*(&raw mut kui::widgets::WINDOWS))[a2 as usize] };
+
+    for f in w.children.iter_mut() {
+        if f.get_id().unwrap() == a1 as u16 {
+            match f {
+                kui::widgets::Widget::Frame(f) => {
+                    let ndir_str = unsafe { (*(&raw mut PROGRAMS))[a3 as usize].clone() };
+                    let dir_str = ndir_str.trim_end();
+                    unsafe { STATUS = None };
+
+                    let entries = list_entries(dir_str) as usize;
+                    let rows = 9;
+                    let cols = 1;
+
+                    f.display = kui::widgets::Display::Grid(Grid::new(cols, rows));
+
+                    show_dir(f, dir_str, a1, a2);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    kui::draw::draw(w);
+    libk::syscall::syscall(41, w.id as u32, 0, 0);
+}
+
+/// Lists `dir_str` into the file frame, under a header with the back button.
+fn show_dir(f: &mut kui::widgets::Frame, dir_str: &str, a1: u32, a2: u32) {
+    f.children.clear();
+
     let mut frame10 = Widget::Frame(
         kui::widgets::Frame::new()
             .width(Size::new("100%"))
@@ -58,35 +180,55 @@ pub extern "C" fn _start() -> ! {
             .width(Size::new("20%"))
             .height(Size::new("100%"))
             .event(back)
-            .set_args([
-                frame1.get_id().unwrap() as u32,
-                unsafe { (*(&raw mut kui::widgets::WINDOWS)).len() as u32 },
-                0,
-            ]),
+            .set_args([a1, a2, 0]),
     );
 
+    let status = unsafe { (*(&raw const STATUS)).clone() };
     let l = Widget::Label(
         Label::new()
-            .text(folder)
+            .text(status.as_deref().unwrap_or(dir_str))
             .color(Color::rgb(255, 255, 255))
             .x(Size::new("20%"))
-            .width(Size::new("80%"))
+            .width(Size::new("60%"))
             .height(Size::new("100%"))
             .text_align(Align::Center),
     );
 
+    let btn_sort = Widget::Button(
+        Button::new()
+            .label(if unsafe { SORT_BY_DATE } { "Date" } else { "Name" })
+            .color(Color::rgb(61, 139, 221))
+            .x(Size::new("80%"))
+            .width(Size::new("20%"))
+            .height(Size::new("100%"))
+            .event(toggle_sort)
+            .set_args([a1, a2, 0]),
+    );
+
     frame10.add(btn_back);
     frame10.add(l);
-    frame1.add(frame10);
+    frame10.add(btn_sort);
+    f.add(frame10);
+
+    unsafe { (*(&raw mut PROGRAMS)).clear() }
 
     let f_ile = bafioDb::load("/SYS/ICONS.DB");
 
-    for i in 0..list_entries(folder) {
-        let var = libk::io::get_entry(folder, i).unwrap().name;
-        let fname = alloc::string::String::from(unsafe { core::str::from_utf8_unchecked(&var) });
-        let fake_name =
-            unsafe { core::str::from_utf8_unchecked(libk::io::expand_path_8_3(&fname)) };
-        let func = alloc::string::String::from(folder) + &fname;
+    for var in sorted_entries(dir_str) {
+        let fname = alloc::string::String::from(unsafe {
+            core::str::from_utf8_unchecked(&var.name)
+        });
+        let fake_name = unsafe {
+            core::str::from_utf8_unchecked(libk::io::expand_path_8_3(&fname))
+        };
+        let func = alloc::format!("{}/{}", dir_str.trim_end_matches('/'), fname);
+
+        let modified = var.modified();
+        let text = if modified.is_set() {
+            alloc::format!("{:<12} {}", fake_name.trim_end(), modified)
+        } else {
+            alloc::string::String::from(fake_name)
+        };
 
         let color = Color::rgb(255, 255, 255);
 
@@ -100,7 +242,7 @@ pub extern "C" fn _start() -> ! {
 
         let l = Widget::Label(
             Label::new()
-                .text(fake_name)
+                .text(&text)
                 .color(color)
                 .width(Size::new("80%"))
                 .height(Size::new("100%"))
@@ -118,7 +260,9 @@ pub extern "C" fn _start() -> ! {
                 match icon_path.unwrap() {
                     crate::bafioDb::Value::String(s) => {
                         let owned_file_icon = s.clone();
-                        file_icon = alloc::boxed::Box::leak(owned_file_icon.into_boxed_str());
+                        file_icon = alloc::boxed::Box::leak(
+                            owned_file_icon.into_boxed_str(),
+                        );
                     }
                     _ => {}
                 }
@@ -130,7 +274,7 @@ pub extern "C" fn _start() -> ! {
                 .width(Size::new("32"))
                 .height(Size::new("32"))
                 .event(executor)
-                .set_args([0, 0, unsafe { (*(&raw mut PROGRAMS)).len() as u32 }]),
+                .set_args([a1, a2, unsafe { (*(&raw mut PROGRAMS)).len() as u32 }]),
         );
         if file_icon == "ICONS/FOLDER2.TGA" {
             i = Widget::Image(
@@ -138,11 +282,9 @@ pub extern "C" fn _start() -> ! {
                     .width(Size::new("32"))
                     .height(Size::new("32"))
                     .event(new_folder)
-                    .set_args([
-                        frame1.get_id().unwrap() as u32,
-                        unsafe { (*(&raw mut kui::widgets::WINDOWS)).len() as u32 },
-                        unsafe { (*(&raw mut PROGRAMS)).len() as u32 },
-                    ]),
+                    .set_args([a1 as u32, a2 as u32, unsafe {
+                        (*(&raw mut PROGRAMS)).len() as u32
+                    }]),
             );
         }
 
@@ -152,325 +294,27 @@ pub extern "C" fn _start() -> ! {
 
         frame2.add(i);
         frame2.add(l);
-        frame1.add(frame2);
+        f.add(frame2);
     }
 
-    main.add(frame1);
-
-    kui::draw::init(main);
-
-    loop {}
+    unsafe { *(*(&raw mut CURRENT_DIR)).lock() = alloc::string::String::from(dir_str) }
 }
 
-pub fn back(_w: &mut Widget, a1: u32, a2: u32, _a3: u32) {
-    let w = unsafe { &mut (*(&raw mut kui::widgets::WINDOWS))[a2 as usize] };
-
-    for f in w.children.iter_mut() {
-        if f.get_id().unwrap() == a1 as u16 {
-            match f {
-                kui::widgets::Widget::Frame(f) => {
-                    let dir = &f.children[0];
-
-                    let mut dir_str = alloc::string::String::from("/");
-
-                    match dir {
-                        kui::widgets::Widget::Frame(f) => match &f.children[1] {
-                            kui::widgets::Widget::Label(l) => {
-                                dir_str = alloc::string::String::from(&l.label);
-                                dir_str =
-                                    alloc::string::String::from(remove_last_component(&dir_str));
-                            }
-                            _ => {
-                                return;
-                            }
-                        },
-                        _ => {
-                            return;
-                        }
-                    }
-
-                    f.children.clear();
-
-                    let mut frame10 = Widget::Frame(
-                        kui::widgets::Frame::new()
-                            .width(Size::new("100%"))
-                            .height(Size::new("100%"))
-                            .color(Color::rgb(18, 52, 88))
-                            .display(Display::None),
-                    );
-
-                    let btn_back = Widget::Button(
-                        Button::new()
-                            .label("<")
-                            .color(Color::rgb(61, 139, 221))
-                            .width(Size::new("20%"))
-                            .height(Size::new("100%"))
-                            .event(back)
-                            .set_args([a1, a2, 0]),
-                    );
-
-                    let l = Widget::Label(
-                        Label::new()
-                            .text(&dir_str)
-                            .color(Color::rgb(255, 255, 255))
-                            .x(Size::new("20%"))
-                            .width(Size::new("80%"))
-                            .height(Size::new("100%"))
-                            .text_align(Align::Center),
-                    );
-
-                    frame10.add(btn_back);
-                    frame10.add(l);
-                    f.add(frame10);
-
-                    let entries = list_entries(&dir_str) as usize;
-                    let rows = 8;
-                    let cols = (entries + rows - 1) / rows;
-
-                    f.display = kui::widgets::Display::Grid(Grid::new(cols, rows));
-
-                    unsafe { (*(&raw mut PROGRAMS)).clear() }
-
-                    let f_ile = bafioDb::load("/SYS/ICONS.DB");
-
-                    let str_len = list_entries(&dir_str);
-                    for i in 0..str_len {
-                        let var = libk::io::get_entry(&dir_str, i).unwrap();
-                        let fname = alloc::string::String::from(unsafe {
-                            core::str::from_utf8_unchecked(&var.name)
-                        });
-                        let fake_name = unsafe {
-                            core::str::from_utf8_unchecked(libk::io::expand_path_8_3(&fname))
-                        };
-                        let func = alloc::string::String::from(&dir_str) + "/" + &fname;
-
-                        let color = Color::rgb(255, 255, 255);
-
-                        let mut frame2 = Widget::Frame(
-                            kui::widgets::Frame::new()
-                                .width(Size::new("100%"))
-                                .height(Size::new("100%"))
-                                .color(color)
-                                .display(Display::Flex),
-                        );
-
-                        let l = Widget::Label(
-                            Label::new()
-                                .text(fake_name)
-                                .color(color)
-                                .width(Size::new("80%"))
-                                .height(Size::new("100%"))
-                                .text_align(Align::Center),
-                        );
-
-                        let mut file_icon = "ICONS/FILE.TGA";
-                        let f3 = libk::io::File::new(&func);
-                        if f3.is_dir() {
-                            file_icon = "ICONS/FOLDER2.TGA";
-                        } else {
-                            let icon_path = f_ile.get(f3.get_file_extention());
-
-                            if icon_path.is_some() {
-                                match icon_path.unwrap() {
-                                    crate::bafioDb::Value::String(s) => {
-                                        let owned_file_icon = s.clone();
-                                        file_icon = alloc::boxed::Box::leak(
-                                            owned_file_icon.into_boxed_str(),
-                                        );
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-
-                        let mut i = Widget::Image(
-                            Image::new(file_icon)
-                                .width(Size::new("32"))
-                                .height(Size::new("32"))
-                                .event(executor)
-                                .set_args([0, 0, unsafe { (*(&raw mut PROGRAMS)).len() as u32 }]),
-                        );
-                        if file_icon == "ICONS/FOLDER2.TGA" {
-                            i = Widget::Image(
-                                Image::new(file_icon)
-                                    .width(Size::new("32"))
-                                    .height(Size::new("32"))
-                                    .event(new_folder)
-                                    .set_args([a1 as u32, a2 as u32, unsafe {
-                                        (*(&raw mut PROGRAMS)).len() as u32
-                                    }]),
-                            );
-                        }
-
-                        unsafe {
-                            (*(&raw mut PROGRAMS)).push(func);
-                        }
-
-                        frame2.add(i);
-                        frame2.add(l);
-                        f.add(frame2);
-                    }
-                }
-                _ => {}
-            }
-        }
-    }
-
-    kui::draw::draw(w);
-    libk::syscall::syscall(41, w.id as u32, 0, 0);
-}
-
-pub fn new_folder(w: &mut Widget, a1: u32, a2: u32, a3: u32) {
-    let old_value = core::mem::replace(w, kui::widgets::Widget::Label(Label::new()));
-
-    let w = unsafe { &mut (*(&raw mut kui::widgets::WINDOWS))[a2 as usize] };
-
-    for f in w.children.iter_mut() {
-        if f.get_id().unwrap() == a1 as u16 {
-            match f {
-                kui::widgets::Widget::Frame(f) => {
-                    f.children.clear();
-
-                    let ndir_str = unsafe { (*(&raw mut PROGRAMS))[a3 as usize].clone() };
-                    let dir_str = ndir_str.trim_end();
-
-                    let mut frame10 = Widget::Frame(
-                        kui::widgets::Frame::new()
-                            .width(Size::new("100%"))
-                            .height(Size::new("100%"))
-                            .color(Color::rgb(18, 52, 88))
-                            .display(Display::None),
-                    );
-
-                    let btn_back = Widget::Button(
-                        Button::new()
-                            .label("<")
-                            .color(Color::rgb(61, 139, 221))
-                            .width(Size::new("20%"))
-                            .height(Size::new("100%"))
-                            .event(back)
-                            .set_args([a1, a2, 0]),
-                    );
-
-                    let l = Widget::Label(
-                        Label::new()
-                            .text(dir_str)
-                            .color(Color::rgb(255, 255, 255))
-                            .x(Size::new("20%"))
-                            .width(Size::new("80%"))
-                            .height(Size::new("100%"))
-                            .text_align(Align::Center),
-                    );
-
-                    frame10.add(btn_back);
-                    frame10.add(l);
-                    f.add(frame10);
-
-                    let entries = list_entries(dir_str) as usize;
-                    let rows = 9;
-                    let cols = 1;
-
-                    f.display = kui::widgets::Display::Grid(Grid::new(cols, rows));
-
-                    unsafe { (*(&raw mut PROGRAMS)).clear() }
-
-                    let f_ile = bafioDb::load("/SYS/ICONS.DB");
-
-                    for i in 0..list_entries(&dir_str) {
-                        let var = libk::io::get_entry(&dir_str, i).unwrap().name;
-                        let fname = alloc::string::String::from(unsafe {
-                            core::str::from_utf8_unchecked(&var)
-                        });
-                        let fake_name = unsafe {
-                            core::str::from_utf8_unchecked(libk::io::expand_path_8_3(&fname))
-                        };
-                        let func = alloc::string::String::from(dir_str) + "/" + &fname;
+pub static mut PROGRAMS: Vec<alloc::string::String> = Vec::new();
 
-                        let color = Color::rgb(255, 255, 255);
+/// Shown in the header instead of the path until the next navigation.
+pub static mut STATUS: Option<alloc::string::String> = None;
 
-                        let mut frame2 = Widget::Frame(
-                            kui::widgets::Frame::new()
-                                .width(Size::new("100%"))
-                                .height(Size::new("100%"))
-                                .color(color)
-                                .display(Display::Flex),
-                        );
+/// Newest first when set, by name otherwise.
+pub static mut SORT_BY_DATE: bool = false;
 
-                        let l = Widget::Label(
-                            Label::new()
-                                .text(fake_name)
-                                .color(color)
-                                .width(Size::new("80%"))
-                                .height(Size::new("100%"))
-                                .text_align(Align::Center),
-                        );
+/// Directory on screen, kept so the main task can watch it.
+pub static mut CURRENT_DIR: Mutex<alloc::string::String> = Mutex::new(alloc::string::String::new());
 
-                        let mut file_icon = "ICONS/FILE.TGA";
-                        let f3 = libk::io::File::new(&func);
-                        if f3.is_dir() {
-                            file_icon = "ICONS/FOLDER2.TGA";
-                        } else {
-                            let icon_path = f_ile.get(f3.get_file_extention());
-
-                            if icon_path.is_some() {
-                                match icon_path.unwrap() {
-                                    crate::bafioDb::Value::String(s) => {
-                                        let owned_file_icon = s.clone();
-                                        file_icon = alloc::boxed::Box::leak(
-                                            owned_file_icon.into_boxed_str(),
-                                        );
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-
-                        let mut i = Widget::Image(
-                            Image::new(file_icon)
-                                .width(Size::new("32"))
-                                .height(Size::new("32"))
-                                .event(executor)
-                                .set_args([0, 0, unsafe { (*(&raw mut PROGRAMS)).len() as u32 }]),
-                        );
-                        if file_icon == "ICONS/FOLDER2.TGA" {
-                            i = Widget::Image(
-                                Image::new(file_icon)
-                                    .width(Size::new("32"))
-                                    .height(Size::new("32"))
-                                    .event(new_folder)
-                                    .set_args([a1 as u32, a2 as u32, unsafe {
-                                        (*(&raw mut PROGRAMS)).len() as u32
-                                    }]),
-                            );
-                        }
-
-                        unsafe {
-                            (*(&raw mut PROGRAMS)).push(func);
-                        }
-
-                        frame2.add(i);
-                        frame2.add(l);
-                        f.add(frame2);
-                    }
-                }
-                _ => {}
-            }
-        }
-    }
-
-    kui::draw::draw(w);
-    libk::syscall::syscall(41, w.id as u32, 0, 0);
-}
-
-pub static mut PROGRAMS: Vec<alloc::string::String> = Vec::new();
-
-pub fn list_entries(dir: &str) -> u8 {
+pub fn list_entries(dir: &str) -> u32 {
     let mut count = 0;
 
     loop {
-        if count == 255 {
-            return count;
-        }
         let e = libk::io::get_entry(dir, count);
         if e.is_none() {
             return count;
@@ -480,6 +324,26 @@ pub fn list_entries(dir: &str) -> u8 {
     }
 }
 
+/// The entries of `dir`, directories first, then by name or by date.
+fn sorted_entries(dir: &str) -> Vec<libk::io::Entry> {
+    let mut entries: Vec<libk::io::Entry> = (0..list_entries(dir))
+        .filter_map(|i| libk::io::get_entry(dir, i))
+        .collect();
+
+    if unsafe { SORT_BY_DATE } {
+        entries.sort_by_key(|e| (!e.is_dir(), core::cmp::Reverse(e.modified().to_unix())));
+    } else {
+        entries.sort_by_key(|e| (!e.is_dir(), e.name));
+    }
+
+    entries
+}
+
+pub fn toggle_sort(_w: &mut Widget, a1: u32, a2: u32, _a3: u32) {
+    unsafe { SORT_BY_DATE = !SORT_BY_DATE };
+    refresh(a1, a2);
+}
+
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
@@ -494,11 +358,27 @@ fn remove_last_component(path: &str) -> &str {
     }
 }
 
-pub fn executor(_wid: &mut Widget, _a1: u32, _a2: u32, a3: u32) {
+pub fn executor(_wid: &mut Widget, a1: u32, a2: u32, a3: u32) {
     let file2 = bafioDb::load("/SYS/EXEC.DB");
 
     let func = unsafe { (*(&raw mut PROGRAMS))[a3 as usize].clone() };
 
+    // archives unpack next to themselves, the watch refreshes the view
+    if func.ends_with("TAR") {
+        let mut failed = None;
+        let result = libk::tar::extract(&func, remove_last_component(&func), |name, result| {
+            if let Err(e) = result {
+                failed = Some(alloc::format!("{}: {}", name, e));
+            }
+        });
+
+        if let Some(message) = result.err().map(alloc::string::String::from).or(failed) {
+            unsafe { STATUS = Some(message) };
+            refresh(a1, a2);
+        }
+        return;
+    }
+
     let f3 = libk::io::File::new(&func);
     let default_executor_app = "/USER/DESKTOP/IDE.ELF";
 
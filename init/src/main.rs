@@ -0,0 +1,209 @@
+//! First user process. Starts the programs listed in `/SYS/INIT.DB` in
+//! order, restarts them by their policy and collects orphaned tasks.
+//!
+//! Each entry takes
+//!
+//!     EXEC=str:/USER/LOGIN.ELF   program to run
+//!     ORDER=num:1                lower starts first, ties keep file order
+//!     RESTART=str:never          never, always or on-failure
+//!     UID=num:0                  user to run as, the admin by default
+//!     WAIT=bool:false            finish before the next entry starts
+//!     ARG=str:...                handed to the program as (pointer, length)
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bafioDb::Value;
+use libk::println;
+use libk::task::Exit;
+
+#[global_allocator]
+static ALLOC: libk::heap::Allocator = libk::heap::Allocator::new();
+
+const CONFIG: &str = "/SYS/INIT.DB";
+const FALLBACK: &str = "/USER/LOGIN.ELF";
+
+/// A service that keeps failing is given up on after this many tries in a row.
+const MAX_FAILURES: u32 = 5;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Restart {
+    Never,
+    Always,
+    OnFailure,
+}
+
+struct Service {
+    exec: String,
+    order: f64,
+    restart: Restart,
+    uid: u16,
+    wait: bool,
+    arg: Option<String>,
+
+    pid: u32,
+    failures: u32,
+}
+
+impl Service {
+    fn new(exec: &str) -> Service {
+        Service {
+            exec: String::from(exec),
+            order: 0.0,
+            restart: Restart::Never,
+            uid: libk::perm::ADMIN,
+            wait: false,
+            arg: None,
+            pid: 0,
+            failures: 0,
+        }
+    }
+
+    fn start(&mut self) -> bool {
+        let args = self.arg.as_ref().map(|a| [a.as_ptr() as u32, a.len() as u32, 0, 0]);
+
+        match libk::elf::load_elf_as(&self.exec, args.as_ref().map(|a| &a[..]), Some(self.uid)) {
+            Ok(pid) => {
+                println!("[init] Started {} as pid {} (uid {})", self.exec, pid, self.uid);
+                self.pid = pid;
+                true
+            }
+            Err(e) => {
+                println!("[init] Could not start {}: {}", self.exec, e);
+                self.pid = 0;
+                false
+            }
+        }
+    }
+
+    /// Whether the policy asks for another run after `exit`.
+    fn should_restart(&mut self, exit: &Exit) -> bool {
+        if exit.failed() {
+            self.failures += 1;
+        } else {
+            self.failures = 0;
+        }
+
+        if self.failures >= MAX_FAILURES {
+            println!("[init] {} failed {} times in a row, giving up", self.exec, self.failures);
+            return false;
+        }
+
+        match self.restart {
+            Restart::Never => false,
+            Restart::Always => true,
+            Restart::OnFailure => exit.failed(),
+        }
+    }
+}
+
+fn load() -> Vec<Service> {
+    if !libk::io::exists(CONFIG) {
+        println!("[init] No {}, starting {}", CONFIG, FALLBACK);
+        return alloc::vec![Service::new(FALLBACK)];
+    }
+
+    let config = bafioDb::load(CONFIG);
+    let mut services = Vec::new();
+
+    for entry in config.data.iter() {
+        let Some(Value::String(exec)) = entry.values.get("EXEC") else {
+            println!("[init] Entry {} has no EXEC, skipped", entry.id);
+            continue;
+        };
+
+        let mut service = Service::new(exec);
+
+        if let Some(Value::Number(n)) = entry.values.get("ORDER") {
+            service.order = *n;
+        }
+        if let Some(Value::Number(n)) = entry.values.get("UID") {
+            service.uid = *n as u16;
+        }
+        if let Some(Value::Boolean(b)) = entry.values.get("WAIT") {
+            service.wait = *b;
+        }
+        if let Some(Value::String(s)) = entry.values.get("ARG") {
+            service.arg = Some(s.clone());
+        }
+        if let Some(Value::String(s)) = entry.values.get("RESTART") {
+            service.restart = match s.as_str() {
+                "always" => Restart::Always,
+                "on-failure" => Restart::OnFailure,
+                "never" => Restart::Never,
+                _ => {
+                    println!("[init] Unknown restart policy {} for {}", s, exec);
+                    Restart::Never
+                }
+            };
+        }
+
+        services.push(service);
+    }
+
+    // stable, so entries with the same order start in file order
+    services.sort_by(|a, b| a.order.total_cmp(&b.order));
+    services
+}
+
+/// Deals with one exited task: a service is restarted if its policy says so,
+/// anything else is an orphan and only logged.
+fn collect(services: &mut [Service], exit: Exit) {
+    let Some(service) = services.iter_mut().find(|s| s.pid == exit.pid) else {
+        println!("[init] Reaped pid {} (status {})", exit.pid, exit.status);
+        return;
+    };
+
+    println!("[init] {} exited with status {}", service.exec, exit.status);
+    service.pid = 0;
+
+    if service.should_restart(&exit) {
+        service.start();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    ALLOC.init(0x10_0000);
+    ALLOC.first_free.load(core::sync::atomic::Ordering::Relaxed);
+
+    if !libk::task::become_reaper() {
+        println!("[init] Not running as admin, orphans will not be collected");
+    }
+
+    let mut services = load();
+
+    for i in 0..services.len() {
+        if !services[i].start() || !services[i].wait {
+            continue;
+        }
+
+        // later entries may depend on this one having finished
+        let pid = services[i].pid;
+        while pid != 0 && services[i].pid == pid {
+            match libk::task::wait() {
+                Some(exit) => collect(&mut services, exit),
+                None => libk::task::yield_now(),
+            }
+        }
+    }
+
+    loop {
+        match libk::task::wait() {
+            Some(exit) => collect(&mut services, exit),
+            None => libk::task::yield_now(),
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    println!("[init] PANIC {}", info);
+    libk::task::exit(libk::task::FAULT);
+}
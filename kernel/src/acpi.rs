@@ -1 +1,317 @@
+//! ACPI tables, found through the RSDP in `BOOTINFO` and parsed once at boot.
+//! Tables with a bad checksum are skipped; of the rest the MADT, FADT and
+//! HPET are kept for the drivers that need them.
 
+use core::ptr::read_unaligned;
+
+use libk::acpi::{
+    ACPI_NULL, Acpi, Cpu, Fadt, GenericAddress, Hpet, IoApic, MADT_NULL, MAX_CPUS, MAX_IOAPICS,
+    MAX_NMIS, MAX_OVERRIDES, MAX_TABLES, Madt, Nmi, Override, SPACE_IO,
+};
+use libk::boot::Rsdp;
+use libk::println;
+
+pub static mut ACPI: Option<Acpi> = None;
+
+const HEADER_LEN: u32 = 36;
+
+pub fn init() {
+    let rsdp = unsafe { crate::BOOTINFO.rsdp };
+
+    let acpi = match parse(&rsdp) {
+        Ok(acpi) => acpi,
+        Err(err) => {
+            println!("[x] ACPI: {}", err);
+            return;
+        }
+    };
+
+    let root = if acpi.xsdt { "XSDT" } else { "RSDT" };
+    println!("[!] ACPI: {} tables in the {}", acpi.table_count, root);
+
+    if let Some(madt) = &acpi.madt {
+        let lapic = madt.lapic_address;
+        println!(
+            "[!] ACPI: {} CPUs, {} IOAPICs, LAPIC at {:x}",
+            madt.cpu_count, madt.ioapic_count, lapic
+        );
+    }
+
+    if let Some(fadt) = &acpi.fadt {
+        unsafe { crate::rtc::CENTURY = fadt.century };
+    }
+
+    if let Some(hpet) = &acpi.hpet {
+        let address = hpet.address;
+        println!("[!] ACPI: HPET at {:x}", address);
+    }
+
+    unsafe { ACPI = Some(acpi) };
+}
+
+pub fn get() -> Option<&'static Acpi> {
+    unsafe { (*(&raw const ACPI)).as_ref() }
+}
+
+fn parse(rsdp: &Rsdp) -> Result<Acpi, &'static str> {
+    if rsdp.signature != *b"RSD PTR " {
+        return Err("No RSDP");
+    }
+
+    let bytes = unsafe {
+        core::slice::from_raw_parts(rsdp as *const Rsdp as *const u8, size_of::<Rsdp>())
+    };
+    if checksum(&bytes[..20]) != 0 {
+        return Err("Bad RSDP checksum");
+    }
+
+    // the XSDT has 64 bit entries, but also has to be below 4 GiB for us
+    let xsdt_address = rsdp.xsdt_address;
+    let xsdt = rsdp.revision >= 2
+        && rsdp.length as usize >= bytes.len()
+        && checksum(bytes) == 0
+        && xsdt_address != 0
+        && xsdt_address <= u32::MAX as u64;
+
+    let (root, entry_size) = if xsdt {
+        (table(xsdt_address as u32, b"XSDT")?, 8)
+    } else {
+        (table(rsdp.rsdt_address, b"RSDT")?, 4)
+    };
+
+    let mut acpi = ACPI_NULL;
+    acpi.revision = rsdp.revision;
+    acpi.oem = rsdp.oem_id;
+    acpi.xsdt = xsdt;
+
+    let count = (read32(root + 4) - HEADER_LEN) / entry_size;
+    for i in 0..count {
+        let at = root + HEADER_LEN + i * entry_size;
+        let address = if xsdt { read64(at) } else { read32(at) as u64 };
+        if address == 0 || address > u32::MAX as u64 {
+            continue;
+        }
+
+        let address = address as u32;
+        let signature: [u8; 4] = read(address);
+        if table(address, &signature).is_err() {
+            println!("[x] ACPI: skipping {}", name(&signature));
+            continue;
+        }
+
+        if acpi.table_count < MAX_TABLES {
+            acpi.tables[acpi.table_count] = signature;
+            acpi.table_count += 1;
+        }
+
+        let length = read32(address + 4);
+        match &signature {
+            b"APIC" => acpi.madt = madt(address, length),
+            b"FACP" => acpi.fadt = fadt(address, length),
+            b"HPET" => acpi.hpet = hpet(address, length),
+            _ => {}
+        }
+    }
+
+    Ok(acpi)
+}
+
+/// Checks the signature and checksum of the table at `address`.
+fn table(address: u32, signature: &[u8; 4]) -> Result<u32, &'static str> {
+    if read::<[u8; 4]>(address) != *signature {
+        return Err("Table not where the RSDP says");
+    }
+
+    let length = read32(address + 4);
+    if length < HEADER_LEN {
+        return Err("Table too short");
+    }
+
+    let bytes = unsafe { core::slice::from_raw_parts(address as *const u8, length as usize) };
+    if checksum(bytes) != 0 {
+        return Err("Bad table checksum");
+    }
+
+    Ok(address)
+}
+
+fn madt(address: u32, length: u32) -> Option<Madt> {
+    if length < 44 {
+        return None;
+    }
+
+    let mut madt = MADT_NULL;
+    madt.lapic_address = read32(address + 36) as u64;
+    madt.legacy_pic = read32(address + 40) & 1 != 0;
+
+    let end = address + length;
+    let mut entry = address + 44;
+
+    while entry + 2 <= end {
+        let kind = read::<u8>(entry);
+        let len = read::<u8>(entry + 1) as u32;
+        if len < 2 || entry + len > end {
+            break;
+        }
+
+        match kind {
+            // processor local APIC
+            0 if len >= 8 => add_cpu(
+                &mut madt,
+                read::<u8>(entry + 2) as u32,
+                read::<u8>(entry + 3) as u32,
+                read32(entry + 4),
+            ),
+            1 if len >= 12 && madt.ioapic_count < MAX_IOAPICS => {
+                madt.ioapics[madt.ioapic_count] = IoApic {
+                    id: read(entry + 2),
+                    address: read32(entry + 4),
+                    gsi_base: read32(entry + 8),
+                };
+                madt.ioapic_count += 1;
+            }
+            2 if len >= 10 && madt.override_count < MAX_OVERRIDES => {
+                madt.overrides[madt.override_count] = Override {
+                    irq: read(entry + 3),
+                    gsi: read32(entry + 4),
+                    flags: read(entry + 8),
+                };
+                madt.override_count += 1;
+            }
+            4 if len >= 6 && madt.nmi_count < MAX_NMIS => {
+                madt.nmis[madt.nmi_count] = Nmi {
+                    processor_id: read(entry + 2),
+                    flags: read(entry + 3),
+                    lint: read(entry + 5),
+                };
+                madt.nmi_count += 1;
+            }
+            // 64 bit local APIC address
+            5 if len >= 12 => madt.lapic_address = read64(entry + 4),
+            // processor local x2APIC
+            9 if len >= 16 => add_cpu(
+                &mut madt,
+                read32(entry + 12),
+                read32(entry + 4),
+                read32(entry + 8),
+            ),
+            _ => {}
+        }
+
+        entry += len;
+    }
+
+    Some(madt)
+}
+
+fn add_cpu(madt: &mut Madt, processor_id: u32, apic_id: u32, flags: u32) {
+    if madt.cpu_count < MAX_CPUS {
+        madt.cpus[madt.cpu_count] = Cpu {
+            processor_id,
+            apic_id,
+            enabled: flags & 1 != 0,
+            online_capable: flags & 2 != 0,
+        };
+        madt.cpu_count += 1;
+    }
+}
+
+/// Takes the ACPI 1.0 fields, then the later ones if the table is long
+/// enough to have them.
+fn fadt(address: u32, length: u32) -> Option<Fadt> {
+    if length < 116 {
+        return None;
+    }
+
+    let mut fadt = Fadt {
+        dsdt: read32(address + 40) as u64,
+        sci: read(address + 46),
+        smi_command: read32(address + 48),
+        acpi_enable: read(address + 52),
+        acpi_disable: read(address + 53),
+        pm1a_event: read32(address + 56),
+        pm1b_event: read32(address + 60),
+        pm1a_control: read32(address + 64),
+        pm1b_control: read32(address + 68),
+        pm_timer: read32(address + 76),
+        century: read(address + 108),
+        boot_flags: read(address + 109),
+        flags: read32(address + 112),
+        reset: GenericAddress {
+            space: 0,
+            bit_width: 0,
+            bit_offset: 0,
+            access_size: 0,
+            address: 0,
+        },
+        reset_value: 0,
+    };
+
+    if length >= 129 {
+        fadt.reset = read(address + 116);
+        fadt.reset_value = read(address + 128);
+    } else {
+        fadt.flags &= !libk::acpi::FADT_RESET_SUPPORTED;
+    }
+
+    if length >= 148 {
+        let x_dsdt = read64(address + 140);
+        if x_dsdt != 0 {
+            fadt.dsdt = x_dsdt;
+        }
+    }
+
+    // firmware may only fill in the extended blocks
+    if length >= 184 {
+        let x_pm1a_event: GenericAddress = read(address + 148);
+        let x_pm1a_control: GenericAddress = read(address + 172);
+
+        if fadt.pm1a_event == 0 && x_pm1a_event.space == SPACE_IO {
+            fadt.pm1a_event = x_pm1a_event.address as u32;
+        }
+        if fadt.pm1a_control == 0 && x_pm1a_control.space == SPACE_IO {
+            fadt.pm1a_control = x_pm1a_control.address as u32;
+        }
+    }
+
+    Some(fadt)
+}
+
+fn hpet(address: u32, length: u32) -> Option<Hpet> {
+    if length < 56 {
+        return None;
+    }
+
+    let id = read32(address + 36);
+    let base: GenericAddress = read(address + 40);
+
+    Some(Hpet {
+        address: base.address,
+        number: read(address + 52),
+        comparators: ((id >> 8) & 0x1F) as u8 + 1,
+        counter_64: id & (1 << 13) != 0,
+        legacy_replacement: id & (1 << 15) != 0,
+        vendor: (id >> 16) as u16,
+        min_tick: read(address + 53),
+    })
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn name(signature: &[u8; 4]) -> &str {
+    core::str::from_utf8(signature).unwrap_or("????")
+}
+
+fn read<T>(address: u32) -> T {
+    unsafe { read_unaligned(address as *const T) }
+}
+
+fn read32(address: u32) -> u32 {
+    read(address)
+}
+
+fn read64(address: u32) -> u64 {
+    read(address)
+}
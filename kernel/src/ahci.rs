@@ -0,0 +1,443 @@
+use crate::block::{BlockDevice, BlockError};
+use crate::disk::Identity;
+use crate::pci;
+use alloc::boxed::Box;
+
+const HBA_GHC: u32 = 0x04;
+const HBA_IS: u32 = 0x08;
+const HBA_PI: u32 = 0x0C;
+
+const GHC_AE: u32 = 1 << 31;
+const GHC_IE: u32 = 1 << 1;
+
+const PORT_BASE: u32 = 0x100;
+const PORT_SIZE: u32 = 0x80;
+
+const PX_CLB: u32 = 0x00;
+const PX_CLBU: u32 = 0x04;
+const PX_FB: u32 = 0x08;
+const PX_FBU: u32 = 0x0C;
+const PX_IS: u32 = 0x10;
+const PX_IE: u32 = 0x14;
+const PX_CMD: u32 = 0x18;
+const PX_TFD: u32 = 0x20;
+const PX_SIG: u32 = 0x24;
+const PX_SSTS: u32 = 0x28;
+const PX_SERR: u32 = 0x30;
+const PX_CI: u32 = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+
+const IS_DHRS: u32 = 1 << 0;
+const IS_TFES: u32 = 1 << 30;
+
+const TFD_ERR: u32 = 0x01;
+const TFD_DRQ: u32 = 0x08;
+const TFD_BSY: u32 = 0x80;
+
+const SIG_SATA: u32 = 0x0000_0101;
+const DET_PRESENT: u32 = 3;
+const IPM_ACTIVE: u32 = 1;
+
+const FIS_H2D: u8 = 0x27;
+
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_FLUSH_EXT: u8 = 0xEA;
+const ATA_IDENTIFY: u8 = 0xEC;
+
+/* offsets inside the page each port gets */
+const CMD_LIST: u32 = 0x000;
+const RX_FIS: u32 = 0x400;
+const CMD_TABLE: u32 = 0x800;
+
+const PRDT_ENTRIES: u32 = 8;
+/// A PRD moves at most 4 MiB.
+const PRD_MAX: u32 = 4 * 1024 * 1024;
+/// Largest single command; bigger requests are split.
+const MAX_SECTORS: u32 = 8192;
+
+const TIMEOUT: u32 = 10_000_000;
+/// Sleeps allowed while waiting for the completion interrupt before falling back to polling.
+const IRQ_WAITS: u32 = 1000;
+
+static mut ABAR: u32 = 0;
+static mut DEVICE: Option<pci::PciDevice> = None;
+static mut IRQ_LINE: u8 = 0xFF;
+static mut IRQ_ENABLED: bool = false;
+/// The processor the IOAPIC delivers the interrupt to.
+static mut IRQ_CPU: usize = 0;
+/// Ports whose last command completed or failed, as seen by the interrupt handler.
+static mut COMPLETED: u32 = 0;
+static mut FAILED: u32 = 0;
+
+fn read_reg(offset: u32) -> u32 {
+    unsafe { core::ptr::read_volatile((ABAR + offset) as *const u32) }
+}
+
+fn write_reg(offset: u32, value: u32) {
+    unsafe { core::ptr::write_volatile((ABAR + offset) as *mut u32, value) }
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+struct CommandHeader {
+    flags: u16,
+    prdt_length: u16,
+    transferred: u32,
+    table: u32,
+    table_upper: u32,
+    reserved: [u32; 4],
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+struct Prd {
+    address: u32,
+    address_upper: u32,
+    reserved: u32,
+    count: u32,
+}
+
+pub struct Port {
+    number: u8,
+    memory: u32,
+    identity: Identity,
+}
+
+impl Port {
+    fn reg(&self, offset: u32) -> u32 {
+        PORT_BASE + self.number as u32 * PORT_SIZE + offset
+    }
+
+    fn stop(&self) -> Result<(), BlockError> {
+        write_reg(self.reg(PX_CMD), read_reg(self.reg(PX_CMD)) & !CMD_ST);
+        write_reg(self.reg(PX_CMD), read_reg(self.reg(PX_CMD)) & !CMD_FRE);
+
+        for _ in 0..TIMEOUT {
+            if read_reg(self.reg(PX_CMD)) & (CMD_CR | CMD_FR) == 0 {
+                return Ok(());
+            }
+        }
+
+        Err(BlockError::Timeout)
+    }
+
+    fn start(&self) {
+        write_reg(self.reg(PX_SERR), 0xFFFF_FFFF);
+        write_reg(self.reg(PX_IS), 0xFFFF_FFFF);
+
+        write_reg(self.reg(PX_CMD), read_reg(self.reg(PX_CMD)) | CMD_FRE);
+        write_reg(self.reg(PX_CMD), read_reg(self.reg(PX_CMD)) | CMD_ST);
+    }
+
+    fn init(&mut self) -> Result<(), BlockError> {
+        self.stop()?;
+
+        unsafe {
+            core::ptr::write_bytes(self.memory as *mut u8, 0, 4096);
+        }
+
+        write_reg(self.reg(PX_CLB), self.memory + CMD_LIST);
+        write_reg(self.reg(PX_CLBU), 0);
+        write_reg(self.reg(PX_FB), self.memory + RX_FIS);
+        write_reg(self.reg(PX_FBU), 0);
+
+        self.start();
+
+        let mut words = [0u16; 256];
+        self.command(ATA_IDENTIFY, 0, 0, words.as_mut_ptr() as u32, 512, false)?;
+        self.identity = Identity::parse(&words);
+
+        Ok(())
+    }
+
+    /// Issues a single command through slot 0 and waits for it to finish.
+    fn command(
+        &mut self,
+        command: u8,
+        lba: u64,
+        sectors: u32,
+        buffer: u32,
+        bytes: u32,
+        write: bool,
+    ) -> Result<(), BlockError> {
+        let mut idle = false;
+        for _ in 0..TIMEOUT {
+            if read_reg(self.reg(PX_TFD)) & (TFD_BSY | TFD_DRQ) == 0 {
+                idle = true;
+                break;
+            }
+        }
+
+        if !idle {
+            return Err(BlockError::Timeout);
+        }
+
+        let header = (self.memory + CMD_LIST) as *mut CommandHeader;
+        let table = (self.memory + CMD_TABLE) as *mut u8;
+        let prdt = (self.memory + CMD_TABLE + 0x80) as *mut Prd;
+
+        let prds = bytes.div_ceil(PRD_MAX);
+        if prds > PRDT_ENTRIES {
+            return Err(BlockError::OutOfRange);
+        }
+
+        unsafe {
+            core::ptr::write_bytes(table, 0, 0x80);
+
+            let mut left = bytes;
+            for i in 0..prds {
+                let size = core::cmp::min(left, PRD_MAX);
+
+                *prdt.add(i as usize) = Prd {
+                    address: buffer + i * PRD_MAX,
+                    address_upper: 0,
+                    reserved: 0,
+                    // the last entry raises an interrupt when done
+                    count: (size - 1) | if i == prds - 1 { 1 << 31 } else { 0 },
+                };
+
+                left -= size;
+            }
+
+            let fis = table;
+            *fis.add(0) = FIS_H2D;
+            *fis.add(1) = 0x80;
+            *fis.add(2) = command;
+            *fis.add(4) = lba as u8;
+            *fis.add(5) = (lba >> 8) as u8;
+            *fis.add(6) = (lba >> 16) as u8;
+            *fis.add(7) = if command == ATA_IDENTIFY { 0 } else { 1 << 6 };
+            *fis.add(8) = (lba >> 24) as u8;
+            *fis.add(9) = (lba >> 32) as u8;
+            *fis.add(10) = (lba >> 40) as u8;
+            *fis.add(12) = sectors as u8;
+            *fis.add(13) = (sectors >> 8) as u8;
+
+            *header = CommandHeader {
+                // FIS length in dwords, plus the write bit
+                flags: 5 | if write { 1 << 6 } else { 0 },
+                prdt_length: prds as u16,
+                transferred: 0,
+                table: self.memory + CMD_TABLE,
+                table_upper: 0,
+                reserved: [0; 4],
+            };
+
+            COMPLETED &= !(1 << self.number);
+            FAILED &= !(1 << self.number);
+        }
+
+        write_reg(self.reg(PX_IS), 0xFFFF_FFFF);
+        write_reg(self.reg(PX_CI), 1);
+
+        let wait_irq = unsafe { IRQ_ENABLED } && crate::block::can_wait_irq(unsafe { IRQ_CPU });
+        let mut result = Err(BlockError::Timeout);
+
+        for i in 0..TIMEOUT {
+            if wait_irq && i < IRQ_WAITS && unsafe { COMPLETED } & (1 << self.number) == 0 {
+                crate::block::wait_irq();
+            }
+
+            let failed = unsafe { FAILED } & (1 << self.number) != 0;
+            if failed || read_reg(self.reg(PX_IS)) & IS_TFES != 0 {
+                result = Err(BlockError::Error((read_reg(self.reg(PX_TFD)) >> 8) as u8));
+                break;
+            }
+
+            if read_reg(self.reg(PX_CI)) & 1 == 0 {
+                result = Ok(());
+                break;
+            }
+        }
+
+        let tfd = read_reg(self.reg(PX_TFD));
+        if result.is_ok() && tfd & TFD_ERR != 0 {
+            result = Err(BlockError::Error((tfd >> 8) as u8));
+        }
+
+        if result.is_err() {
+            // a failed command leaves the port stopped until it is restarted
+            let _ = self.stop();
+            self.start();
+        }
+
+        result
+    }
+
+    fn transfer(&mut self, lba: u64, sectors: u32, buffer: u32, write: bool) -> Result<(), BlockError> {
+        if lba + sectors as u64 > self.identity.sectors {
+            return Err(BlockError::OutOfRange);
+        }
+
+        let command = if write { ATA_WRITE_DMA_EXT } else { ATA_READ_DMA_EXT };
+        let mut done = 0;
+
+        while done < sectors {
+            let count = core::cmp::min(sectors - done, MAX_SECTORS);
+
+            self.command(
+                command,
+                lba + done as u64,
+                count,
+                buffer + done * 512,
+                count * 512,
+                write,
+            )?;
+
+            done += count;
+        }
+
+        if write {
+            self.command(ATA_FLUSH_EXT, 0, 0, 0, 0, false)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl BlockDevice for Port {
+    fn driver(&self) -> &'static str {
+        "AHCI"
+    }
+
+    fn model(&self) -> &str {
+        self.identity.model()
+    }
+
+    fn sectors(&self) -> u64 {
+        self.identity.sectors
+    }
+
+    fn read(&mut self, lba: u64, sectors: u32, target: *mut u8) -> Result<(), BlockError> {
+        self.transfer(lba, sectors, target as u32, false)
+    }
+
+    fn write(&mut self, lba: u64, sectors: u32, buffer: *const u8) -> Result<(), BlockError> {
+        self.transfer(lba, sectors, buffer as u32, true)
+    }
+}
+
+pub fn init() {
+    let Some(dev) = pci::find_class(0x01, 0x06, 0x01) else {
+        return;
+    };
+
+    dev.enable_bus_mastering();
+
+    unsafe {
+        ABAR = dev.get_bar(5).unwrap_or(0);
+        DEVICE = Some(dev);
+    }
+
+    if unsafe { ABAR } == 0 {
+        return;
+    }
+
+    libk::println!("[+] AHCI controller at {:#X}", unsafe { ABAR });
+
+    write_reg(HBA_GHC, read_reg(HBA_GHC) | GHC_AE);
+
+    let implemented = read_reg(HBA_PI);
+
+    for number in 0..32u8 {
+        if implemented & (1 << number) == 0 {
+            continue;
+        }
+
+        let base = PORT_BASE + number as u32 * PORT_SIZE;
+        let status = read_reg(base + PX_SSTS);
+
+        if status & 0x0F != DET_PRESENT || (status >> 8) & 0x0F != IPM_ACTIVE {
+            continue;
+        }
+
+        // ATAPI, port multipliers and bridges are not handled
+        if read_reg(base + PX_SIG) != SIG_SATA {
+            continue;
+        }
+
+        let Some(memory) = (unsafe { (*(&raw mut crate::pmm::PADDR)).malloc(4096) }) else {
+            libk::println!("[x] AHCI port {}: no memory for its command list", number);
+            continue;
+        };
+
+        let mut port = Port {
+            number,
+            memory,
+            identity: Identity::parse(&[0; 256]),
+        };
+
+        match port.init() {
+            Ok(()) => {
+                write_reg(base + PX_IE, IS_DHRS | IS_TFES);
+                crate::block::register(Box::new(port));
+            }
+
+            Err(e) => {
+                libk::println!("[x] AHCI port {} failed: {:?}", number, e);
+                unsafe { (*(&raw mut crate::pmm::PADDR)).dealloc(memory) };
+            }
+        }
+    }
+}
+
+/// Routes the controller interrupt to its PCI IRQ unless that is already
+/// taken by another driver, in which case commands keep being polled.
+pub fn install_irq() {
+    unsafe {
+        let Some(line) = (*(&raw const DEVICE)).as_ref().and_then(crate::apic::pci_irq) else {
+            return;
+        };
+
+        if ABAR == 0 {
+            return;
+        }
+
+        IRQ_LINE = line;
+
+        let vector = 32 + IRQ_LINE as usize;
+        if !(*(&raw const crate::idt::IDT)).is_free(vector) {
+            return;
+        }
+
+        (*(&raw mut crate::idt::IDT)).add(vector, irq as u32);
+        crate::apic::enable_irq(IRQ_LINE, crate::apic::Bus::Pci);
+
+        write_reg(HBA_GHC, read_reg(HBA_GHC) | GHC_IE);
+        IRQ_CPU = crate::smp::cpu();
+        IRQ_ENABLED = true;
+    }
+}
+
+pub extern "x86-interrupt" fn irq() {
+    let pending = read_reg(HBA_IS);
+
+    for number in 0..32u32 {
+        if pending & (1 << number) == 0 {
+            continue;
+        }
+
+        let offset = PORT_BASE + number * PORT_SIZE + PX_IS;
+        let status = read_reg(offset);
+        write_reg(offset, status);
+
+        unsafe {
+            COMPLETED |= 1 << number;
+
+            if status & IS_TFES != 0 {
+                FAILED |= 1 << number;
+            }
+        }
+    }
+
+    write_reg(HBA_IS, pending);
+
+    unsafe {
+        crate::apic::end_interrupt(32 + IRQ_LINE);
+    }
+}
@@ -0,0 +1,344 @@
+//! Local APIC and IOAPIC, used instead of the 8259 PICs when the MADT lists
+//! them. IRQs keep the vectors the PICs gave them, 32 + IRQ, and the local
+//! APIC timer takes over the scheduler tick from the PIT.
+
+use core::arch::asm;
+use core::arch::x86::__cpuid;
+
+use crate::pci::PciDevice;
+use libk::acpi::{IoApic, Madt};
+use libk::mmio::{read_32, write_32};
+use libk::port::{inb, outb};
+use libk::println;
+
+pub static mut ENABLED: bool = false;
+
+static mut LAPIC: u32 = 0;
+/// Initial count of the periodic timer, measured once against the PIT.
+static mut TIMER_COUNT: u32 = 0;
+
+const ID: u32 = 0x20;
+const TPR: u32 = 0x80;
+const EOI: u32 = 0xB0;
+const SVR: u32 = 0xF0;
+const LVT_TIMER: u32 = 0x320;
+const LVT_LINT0: u32 = 0x350;
+const LVT_LINT1: u32 = 0x360;
+const ICR_LOW: u32 = 0x300;
+const ICR_HIGH: u32 = 0x310;
+const TIMER_INITIAL: u32 = 0x380;
+const TIMER_CURRENT: u32 = 0x390;
+const TIMER_DIVIDE: u32 = 0x3E0;
+
+const SVR_ENABLE: u32 = 1 << 8;
+const DIVIDE_16: u32 = 0x3;
+
+const ICR_INIT: u32 = 0x5 << 8;
+const ICR_STARTUP: u32 = 0x6 << 8;
+const ICR_PENDING: u32 = 1 << 12;
+const ICR_ASSERT: u32 = 1 << 14;
+
+// local vector table and redirection entries share these bits
+const NMI: u32 = 0x4 << 8;
+const ACTIVE_LOW: u32 = 1 << 13;
+const LEVEL: u32 = 1 << 15;
+const MASKED: u32 = 1 << 16;
+const PERIODIC: u32 = 1 << 17;
+
+pub const SPURIOUS: u8 = 0xFF;
+
+const IOREGSEL: u32 = 0x00;
+const IOWIN: u32 = 0x10;
+const IOAPICVER: u32 = 0x01;
+const REDIRECTION: u32 = 0x10;
+
+const APIC_BASE_MSR: u32 = 0x1B;
+const APIC_BASE_ENABLE: u32 = 1 << 11;
+
+/// The PIT runs at 1193182 Hz and, never reprogrammed, divides by 65536 for
+/// 18.2 ticks a second. Everything counting `task::TICKS` expects that rate,
+/// so the local APIC timer is set to match.
+const PIT_DIVISOR: u64 = 65536;
+const PIT_HZ: u32 = 1193182;
+/// PIT counts in the 10 ms calibration window.
+const CALIBRATION: u16 = 11932;
+
+const PIT_CHANNEL_2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_GATE: u16 = 0x61;
+
+/// Which defaults apply to an IRQ without an interrupt source override.
+pub enum Bus {
+    /// Edge triggered, active high.
+    Isa,
+    /// Level triggered, active low.
+    Pci,
+}
+
+/// Switches to the APICs if there are any and neither `noapic` nor `safe` is
+/// set, returning whether it did. All IRQs start masked, see `enable_irq`.
+pub fn init() -> bool {
+    if crate::cmdline::flag("noapic") || crate::cmdline::flag("safe") {
+        return false;
+    }
+
+    let Some(madt) = madt() else {
+        return false;
+    };
+
+    let has_apic = unsafe { __cpuid(1) }.edx & (1 << 9) != 0;
+    if !has_apic || madt.ioapic_count == 0 || madt.lapic_address > u32::MAX as u64 {
+        return false;
+    }
+
+    unsafe {
+        LAPIC = madt.lapic_address as u32;
+
+        // the PICs stay remapped, so anything still pending lands on IRQ vectors
+        let pics = &*(&raw const crate::pic::PICS);
+        pics.master.write_data(0xFF);
+        pics.slave.write_data(0xFF);
+    }
+
+    for ioapic in &madt.ioapics[..madt.ioapic_count] {
+        for index in 0..entries(ioapic) {
+            write_ioapic(ioapic.address, REDIRECTION + index * 2, MASKED);
+        }
+    }
+
+    init_local(&madt);
+    unsafe { TIMER_COUNT = calibrate() };
+    start_timer();
+
+    unsafe { ENABLED = true };
+
+    println!(
+        "[+] APIC: LAPIC {} at {:x}, {} IOAPICs, timer count {}",
+        lapic_id(),
+        unsafe { LAPIC },
+        madt.ioapic_count,
+        unsafe { TIMER_COUNT }
+    );
+
+    true
+}
+
+/// Sets up the local APIC of the processor running this.
+pub fn init_local(madt: &Madt) {
+    unsafe {
+        let (low, high) = rdmsr(APIC_BASE_MSR);
+        wrmsr(APIC_BASE_MSR, low | APIC_BASE_ENABLE, high);
+    }
+
+    write(TPR, 0);
+    write(SVR, SVR_ENABLE | SPURIOUS as u32);
+    write(LVT_TIMER, MASKED);
+
+    // the MADT says which LINT pin carries the NMI, by ACPI processor id
+    let id = lapic_id();
+    let processor = madt.cpus[..madt.cpu_count]
+        .iter()
+        .find(|c| c.apic_id == id)
+        .map(|c| c.processor_id);
+
+    for nmi in &madt.nmis[..madt.nmi_count] {
+        if nmi.processor_id != 0xFF && Some(nmi.processor_id as u32) != processor {
+            continue;
+        }
+
+        let register = if nmi.lint == 0 { LVT_LINT0 } else { LVT_LINT1 };
+        write(register, NMI | polarity_and_trigger(nmi.flags, false));
+    }
+}
+
+/// Starts the periodic scheduler tick on this processor.
+pub fn start_timer() {
+    write(TIMER_DIVIDE, DIVIDE_16);
+    write(LVT_TIMER, crate::exceptions::TIMER_INT as u32 | PERIODIC);
+    write(TIMER_INITIAL, unsafe { TIMER_COUNT });
+}
+
+/// Counts local APIC timer ticks over 10 ms of PIT channel 2, scaled up to
+/// one PIT period.
+fn calibrate() -> u32 {
+    write(TIMER_DIVIDE, DIVIDE_16);
+    write(TIMER_INITIAL, u32::MAX);
+    pit_wait(CALIBRATION);
+
+    let elapsed = u32::MAX - read(TIMER_CURRENT);
+    write(TIMER_INITIAL, 0);
+
+    (elapsed as u64 * PIT_DIVISOR / CALIBRATION as u64) as u32
+}
+
+/// Busy waits, for the delays starting a processor needs.
+pub fn delay(micros: u32) {
+    let mut counts = (micros as u64 * PIT_HZ as u64 / 1_000_000) as u32;
+
+    while counts > 0 {
+        let chunk = counts.min(u16::MAX as u32);
+        pit_wait(chunk as u16);
+        counts -= chunk;
+    }
+}
+
+/// Runs PIT channel 2 once for `counts` and waits for it, leaving the
+/// speaker off.
+fn pit_wait(counts: u16) {
+    let gate = inb(PIT_GATE) & !0x03;
+    outb(PIT_GATE, gate | 0x01);
+    outb(PIT_COMMAND, 0b1011_0010);
+    outb(PIT_CHANNEL_2, counts as u8);
+    outb(PIT_CHANNEL_2, (counts >> 8) as u8);
+
+    // a rising edge on the gate starts it
+    outb(PIT_GATE, gate);
+    outb(PIT_GATE, gate | 0x01);
+
+    while inb(PIT_GATE) & 0x20 == 0 {}
+
+    outb(PIT_GATE, gate);
+}
+
+/// The IRQ a PCI device interrupts on, None if it has none. Through the PICs
+/// that is the ISA line the firmware routed it to. An ICH9 (Q35) chipset
+/// wires PIRQ A-H straight to IOAPIC inputs 16-23, so with the IOAPIC the
+/// device gets the one past the ISA range its pin is routed to.
+pub fn pci_irq(dev: &PciDevice) -> Option<u8> {
+    if unsafe { ENABLED } {
+        if let Some(pirq) = dev.ich9_pirq() {
+            return Some(16 + pirq);
+        }
+    }
+
+    Some(dev.interrupt_line()).filter(|&line| line < 16)
+}
+
+/// Delivers `irq` on vector 32 + `irq`, through the IOAPIC input the MADT
+/// maps it to or by unmasking it on the PICs. IRQs past 15 only exist on
+/// the IOAPIC.
+pub fn enable_irq(irq: u8, bus: Bus) {
+    if !unsafe { ENABLED } {
+        let pics = unsafe { &*(&raw const crate::pic::PICS) };
+        if irq < 8 {
+            pics.master.unmask_irq(irq);
+        } else if irq < 16 {
+            pics.master.unmask_irq(2);
+            pics.slave.unmask_irq(irq - 8);
+        }
+        return;
+    }
+
+    let Some(madt) = madt() else {
+        return;
+    };
+
+    let (gsi, flags) = madt.overrides[..madt.override_count]
+        .iter()
+        .find(|o| o.irq == irq)
+        .map_or((irq as u32, 0), |o| (o.gsi, o.flags));
+
+    let Some(ioapic) = madt.ioapics[..madt.ioapic_count]
+        .iter()
+        .find(|io| io.gsi_base <= gsi && gsi < io.gsi_base + entries(io))
+    else {
+        println!("[x] APIC: no IOAPIC has GSI {}", gsi);
+        return;
+    };
+
+    let index = gsi - ioapic.gsi_base;
+    let low = (32 + irq) as u32 | polarity_and_trigger(flags, matches!(bus, Bus::Pci));
+
+    write_ioapic(ioapic.address, REDIRECTION + index * 2 + 1, lapic_id() << 24);
+    write_ioapic(ioapic.address, REDIRECTION + index * 2, low);
+}
+
+pub fn end_interrupt(vector: u8) {
+    if unsafe { ENABLED } {
+        write(EOI, 0);
+    } else {
+        unsafe { (*(&raw mut crate::pic::PICS)).end_interrupt(vector) };
+    }
+}
+
+pub fn send_ipi(apic_id: u32, vector: u8) {
+    send(apic_id, vector as u32);
+}
+
+pub fn send_init(apic_id: u32) {
+    send(apic_id, ICR_INIT | ICR_ASSERT);
+}
+
+/// Starts a processor after INIT at `page` * 4 KiB, in real mode.
+pub fn send_startup(apic_id: u32, page: u8) {
+    send(apic_id, ICR_STARTUP | ICR_ASSERT | page as u32);
+}
+
+fn send(apic_id: u32, command: u32) {
+    write(ICR_HIGH, apic_id << 24);
+    write(ICR_LOW, command);
+
+    while read(ICR_LOW) & ICR_PENDING != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Needs no end of interrupt.
+pub extern "x86-interrupt" fn spurious() {}
+
+pub fn lapic_id() -> u32 {
+    read(ID) >> 24
+}
+
+/// MPS INTI flags: 01 and 11 pick the polarity and trigger mode, 00 leaves
+/// the bus default.
+fn polarity_and_trigger(flags: u16, pci: bool) -> u32 {
+    let low = match flags & 0x3 {
+        0x1 => false,
+        0x3 => true,
+        _ => pci,
+    };
+    let level = match (flags >> 2) & 0x3 {
+        0x1 => false,
+        0x3 => true,
+        _ => pci,
+    };
+
+    (if low { ACTIVE_LOW } else { 0 }) | (if level { LEVEL } else { 0 })
+}
+
+fn madt() -> Option<Madt> {
+    crate::acpi::get().and_then(|a| a.madt)
+}
+
+fn entries(ioapic: &IoApic) -> u32 {
+    ((read_ioapic(ioapic.address, IOAPICVER) >> 16) & 0xFF) + 1
+}
+
+fn read(register: u32) -> u32 {
+    read_32(unsafe { LAPIC } + register)
+}
+
+fn write(register: u32, value: u32) {
+    write_32(unsafe { LAPIC } + register, value);
+}
+
+fn read_ioapic(base: u32, register: u32) -> u32 {
+    write_32(base + IOREGSEL, register);
+    read_32(base + IOWIN)
+}
+
+fn write_ioapic(base: u32, register: u32, value: u32) {
+    write_32(base + IOREGSEL, register);
+    write_32(base + IOWIN, value);
+}
+
+unsafe fn rdmsr(msr: u32) -> (u32, u32) {
+    let (low, high): (u32, u32);
+    unsafe { asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high) };
+    (low, high)
+}
+
+unsafe fn wrmsr(msr: u32, low: u32, high: u32) {
+    unsafe { asm!("wrmsr", in("ecx") msr, in("eax") low, in("edx") high) };
+}
@@ -0,0 +1,147 @@
+//! Bochs graphics adapter, the display card of Bochs and QEMU's `-vga std`.
+//! Its DISPI registers change the mode at any time, without the BIOS calls
+//! stage 2 uses.
+
+use libk::port::{inw, outw};
+use libk::println;
+
+const VENDOR: u32 = 0x1234;
+const DEVICE: u32 = 0x1111;
+
+const INDEX: u16 = 0x01CE;
+const DATA: u16 = 0x01CF;
+
+const REG_ID: u16 = 0x00;
+const REG_XRES: u16 = 0x01;
+const REG_YRES: u16 = 0x02;
+const REG_BPP: u16 = 0x03;
+const REG_ENABLE: u16 = 0x04;
+const REG_X_OFFSET: u16 = 0x08;
+const REG_Y_OFFSET: u16 = 0x09;
+const REG_VIDEO_MEMORY: u16 = 0x0A;
+
+const ENABLED: u16 = 0x01;
+const GETCAPS: u16 = 0x02;
+const LFB_ENABLED: u16 = 0x40;
+
+/// 32 bpp and the GETCAPS bit came with this version.
+const ID2: u16 = 0xB0C2;
+/// Reports the video memory size, in 64 KiB.
+const ID5: u16 = 0xB0C5;
+
+pub struct Bga {
+    id: u16,
+    framebuffer: u32,
+    max_width: u16,
+    max_height: u16,
+}
+
+pub static mut BGA: Option<Bga> = None;
+
+pub fn init() {
+    let Some(device) = crate::pci::find_device(VENDOR, DEVICE) else {
+        return;
+    };
+
+    let id = read(REG_ID);
+    if !(ID2..=ID5).contains(&id) {
+        println!("[x] BGA: unsupported version {:x}", id);
+        return;
+    }
+
+    let Some(framebuffer) = device.get_bar(0) else {
+        println!("[x] BGA: no framebuffer");
+        return;
+    };
+
+    // keeping the other bits leaves the current mode alone
+    let enable = read(REG_ENABLE);
+    write(REG_ENABLE, enable | GETCAPS);
+    let max_width = read(REG_XRES);
+    let max_height = read(REG_YRES);
+    write(REG_ENABLE, enable);
+
+    println!("[!] BGA: up to {}x{}", max_width, max_height);
+
+    unsafe {
+        BGA = Some(Bga {
+            id,
+            framebuffer,
+            max_width,
+            max_height,
+        });
+    }
+}
+
+/// Whether the card can show `width`x`height` at `bpp` bits per pixel,
+/// returning the pitch it would have.
+pub fn check_mode(width: u16, height: u16, bpp: u8) -> Result<u16, &'static str> {
+    let bga = unsafe { (*(&raw const BGA)).as_ref() }.ok_or("No BGA display")?;
+
+    if bpp != 24 && bpp != 32 {
+        return Err("Only 24 and 32 bpp can be drawn");
+    }
+
+    if width == 0 || height == 0 || width > bga.max_width || height > bga.max_height {
+        return Err("Resolution out of range");
+    }
+
+    let pitch = width as u32 * (bpp as u32 / 8);
+    if pitch > u16::MAX as u32 {
+        return Err("Resolution out of range");
+    }
+
+    if bga.id >= ID5 && pitch * height as u32 > read(REG_VIDEO_MEMORY) as u32 * 0x10000 {
+        return Err("Not enough video memory");
+    }
+
+    Ok(pitch as u16)
+}
+
+/// Switches to `width`x`height` at `bpp` bits per pixel and records the new
+/// mode in `BOOTINFO`, where the display server and programs read it. A mode
+/// the card refuses leaves the previous one in place.
+pub fn set_mode(width: u16, height: u16, bpp: u8) -> Result<(), &'static str> {
+    let pitch = check_mode(width, height, bpp)?;
+    let framebuffer = unsafe { (*(&raw const BGA)).as_ref() }.map_or(0, |b| b.framebuffer);
+
+    let old = (read(REG_XRES), read(REG_YRES), read(REG_BPP), read(REG_ENABLE));
+
+    program(width, height, bpp as u16, ENABLED | LFB_ENABLED);
+
+    if read(REG_XRES) != width || read(REG_YRES) != height || read(REG_BPP) != bpp as u16 {
+        program(old.0, old.1, old.2, old.3);
+        return Err("Mode refused by the card");
+    }
+
+    unsafe {
+        let mode = &mut (*(&raw mut crate::BOOTINFO)).mode;
+        mode.width = width;
+        mode.height = height;
+        mode.bpp = bpp;
+        mode.pitch = pitch;
+        mode.framebuffer = framebuffer;
+    }
+
+    Ok(())
+}
+
+fn program(width: u16, height: u16, bpp: u16, enable: u16) {
+    write(REG_ENABLE, 0);
+    write(REG_XRES, width);
+    write(REG_YRES, height);
+    write(REG_BPP, bpp);
+    write(REG_ENABLE, enable);
+    write(REG_X_OFFSET, 0);
+    write(REG_Y_OFFSET, 0);
+}
+
+fn read(register: u16) -> u16 {
+    outw(INDEX, register);
+    inw(DATA)
+}
+
+fn write(register: u16, value: u16) {
+    outw(INDEX, register);
+    outw(DATA, value);
+}
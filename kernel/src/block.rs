@@ -0,0 +1,90 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BlockError {
+    NoDevice,
+    Timeout,
+    DeviceFault,
+    Error(u8),
+    OutOfRange,
+}
+
+/// A disk addressed in 512-byte sectors. Buffers are physical addresses.
+pub trait BlockDevice {
+    fn driver(&self) -> &'static str;
+    fn model(&self) -> &str;
+    fn sectors(&self) -> u64;
+    fn read(&mut self, lba: u64, sectors: u32, target: *mut u8) -> Result<(), BlockError>;
+    fn write(&mut self, lba: u64, sectors: u32, buffer: *const u8) -> Result<(), BlockError>;
+}
+
+pub static mut DEVICES: Vec<Box<dyn BlockDevice>> = Vec::new();
+
+/// Whether a driver whose IRQ is delivered to `cpu` can sleep until it
+/// instead of polling. Syscalls and the writeback task get to the drivers
+/// holding the kernel lock with interrupts off: holding it, the timer will
+/// not switch tasks and other handlers re-enter it, so interrupts can be
+/// let in for the wait. Anywhere else, or on another processor, they can't.
+pub fn can_wait_irq(cpu: usize) -> bool {
+    cpu == crate::smp::cpu() && crate::smp::holds_kernel()
+}
+
+/// Halts until the next interrupt, letting interrupts in for it if they are
+/// off. sti only takes effect after hlt, so one already pending wakes it.
+pub fn wait_irq() {
+    let flags: u32;
+    unsafe {
+        core::arch::asm!("pushfd", "pop {0}", out(reg) flags);
+
+        if flags & (1 << 9) != 0 {
+            core::arch::asm!("hlt");
+        } else {
+            core::arch::asm!("sti", "hlt", "cli");
+        }
+    }
+}
+
+/// Adds a device and returns the index used to address it from now on.
+pub fn register(device: Box<dyn BlockDevice>) -> u8 {
+    unsafe {
+        let devices = &mut *(&raw mut DEVICES);
+
+        libk::println!(
+            "[+] Block device {}: {} {} ({} MiB)",
+            devices.len(),
+            device.driver(),
+            device.model(),
+            device.sectors() / 2048
+        );
+
+        devices.push(device);
+        (devices.len() - 1) as u8
+    }
+}
+
+pub fn count() -> u8 {
+    unsafe { (*(&raw const DEVICES)).len() as u8 }
+}
+
+pub fn sectors(device: u8) -> Option<u64> {
+    unsafe { (*(&raw const DEVICES)).get(device as usize).map(|d| d.sectors()) }
+}
+
+pub fn read<T>(lba: u64, sectors: u32, device: u8, target: *mut T) -> Result<(), BlockError> {
+    unsafe {
+        match (*(&raw mut DEVICES)).get_mut(device as usize) {
+            Some(d) => d.read(lba, sectors, target as *mut u8),
+            None => Err(BlockError::NoDevice),
+        }
+    }
+}
+
+pub fn write<T>(lba: u64, sectors: u32, device: u8, buffer: *const T) -> Result<(), BlockError> {
+    unsafe {
+        match (*(&raw mut DEVICES)).get_mut(device as usize) {
+            Some(d) => d.write(lba, sectors, buffer as *const u8),
+            None => Err(BlockError::NoDevice),
+        }
+    }
+}
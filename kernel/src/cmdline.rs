@@ -0,0 +1,27 @@
+use libk::cmdline::MAX_LEN;
+
+/// The command line stage 2 left in the boot info, up to the first zero.
+pub fn line() -> &'static str {
+    let raw: &'static [u8; MAX_LEN] = unsafe { &(*(&raw const crate::BOOTINFO)).cmdline };
+    let length = raw.iter().position(|&b| b == 0).unwrap_or(MAX_LEN);
+
+    core::str::from_utf8(&raw[..length]).unwrap_or("")
+}
+
+pub fn get(key: &str) -> Option<&'static str> {
+    libk::cmdline::find(line(), key)
+}
+
+pub fn flag(key: &str) -> bool {
+    libk::cmdline::is_set(get(key))
+}
+
+pub fn number(key: &str) -> Option<u32> {
+    get(key).and_then(|v| v.parse().ok())
+}
+
+/// `video=WIDTHxHEIGHT`.
+pub fn video() -> Option<(u16, u16)> {
+    let (width, height) = get("video")?.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
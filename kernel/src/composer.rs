@@ -42,7 +42,24 @@ const MOUSE_CURSOR: [u32; 96] = [
 ];
 
 impl DisplayServer {
-    pub fn init(&mut self) {
+    /// Takes the mode from `BOOTINFO`. Without memory for the double buffer
+    /// the screen stays 0x0, so nothing is drawn to it.
+    pub fn init(&mut self) -> Result<(), &'static str> {
+        let vbe = unsafe { crate::BOOTINFO.mode };
+
+        let Some(double_buffer) =
+            (unsafe { (*(&raw mut crate::pmm::PADDR)).malloc(vbe.pitch as u32 * vbe.height as u32) })
+        else {
+            self.width = 0;
+            self.height = 0;
+            return Err("No memory for the double buffer");
+        };
+
+        self.use_mode(double_buffer);
+        Ok(())
+    }
+
+    fn use_mode(&mut self, double_buffer: u32) {
         let vbe = unsafe { crate::BOOTINFO.mode };
         self.width = vbe.width as u64;
         self.pitch = vbe.pitch as u64;
@@ -54,14 +71,52 @@ impl DisplayServer {
         }
 
         self.framebuffer = vbe.framebuffer;
+        self.double_buffer = double_buffer;
         unsafe {
             (*(&raw mut crate::pmm::PADDR))
                 .add_fb(self.framebuffer, self.pitch as u32 * self.height as u32);
+        }
+    }
+
+    /// Changes the resolution through the BGA, then lays the windows out on
+    /// the new screen. Everything the new mode needs is allocated first, so
+    /// running out of memory leaves the old mode as it was.
+    pub fn set_mode(&mut self, width: u16, height: u16, bpp: u8) -> Result<(), &'static str> {
+        let old = (self.width as u16, self.height as u16, self.depth);
+
+        let pitch = crate::bga::check_mode(width, height, bpp)?;
+        let paddr = unsafe { &mut *(&raw mut crate::pmm::PADDR) };
+
+        let double_buffer = paddr
+            .malloc(pitch as u32 * height as u32)
+            .ok_or("No memory for the double buffer")?;
+
+        let composer = unsafe { &mut *(&raw mut COMPOSER) };
+        let buffers = match composer.buffers_for(old, (width, height, bpp as usize)) {
+            Ok(buffers) => buffers,
+            Err(e) => {
+                paddr.dealloc(double_buffer);
+                return Err(e);
+            }
+        };
 
-            self.double_buffer = (*(&raw mut crate::pmm::PADDR))
-                .malloc(self.pitch as u32 * self.height as u32)
-                .unwrap();
+        if let Err(e) = crate::bga::set_mode(width, height, bpp) {
+            paddr.dealloc(double_buffer);
+            buffers.iter().filter(|&&b| b != 0).for_each(|&b| paddr.dealloc(b));
+            return Err(e);
         }
+
+        paddr.dealloc(self.framebuffer);
+        paddr.dealloc(self.double_buffer);
+        self.use_mode(double_buffer);
+
+        unsafe {
+            MOUSE.x = core::cmp::min(MOUSE.x, width - 1);
+            MOUSE.y = core::cmp::min(MOUSE.y, height - 1);
+        }
+        composer.screen_changed(old, buffers);
+
+        Ok(())
     }
 
     pub fn copy(&self) {
@@ -295,9 +350,10 @@ impl Mouse {
 
                     (*(&raw mut crate::task::TASK_MANAGER))
                         .lock()
-                        .add_user_task(
+                        .add_user_task_as(
                             w.resize,
                             Some(&[w.wid as u32, w.width as u32, w.height as u32, w.buffer]),
+                            w.uid,
                         );
 
                 } else if (*(&raw mut DRAGGING_WINDOW)).load(Ordering::Relaxed) != 0 {
@@ -496,17 +552,19 @@ impl Mouse {
                             let yc = ws.y;
                             let id = ws.wid;
                             let mouse = ws.mouse;
+                            let uid = ws.uid;
 
                             unsafe {
                                 (*(&raw mut crate::task::TASK_MANAGER))
                                     .lock()
-                                    .add_user_task(
+                                    .add_user_task_as(
                                         mouse,
                                         Some(&[
                                             id as u32,
                                             (self.x - xc) as u32,
                                             (self.y - yc) as u32,
                                         ]),
+                                        uid,
                                     );
                             }
                         }
@@ -529,17 +587,19 @@ impl Mouse {
                             let yc = ws.y;
                             let id = ws.wid;
                             let mouse = ws.mouse;
+                            let uid = ws.uid;
 
                             unsafe {
                                 (*(&raw mut crate::task::TASK_MANAGER))
                                     .lock()
-                                    .add_user_task(
+                                    .add_user_task_as(
                                         mouse,
                                         Some(&[
                                             id as u32,
                                             (self.x - xc) as u32,
                                             (self.y - yc) as u32,
                                         ]),
+                                        uid,
                                     );
                             };
                         }
@@ -660,6 +720,8 @@ pub struct Window {
     movable: bool,
     pub buffer: u32,
     pub wtype: Items,
+    /// Set by the kernel; callbacks run as the user that opened the window.
+    pub uid: u16,
 }
 
 pub static NULL_WINDOW: Window = Window {
@@ -676,6 +738,7 @@ pub static NULL_WINDOW: Window = Window {
     movable: false,
     buffer: 0,
     wtype: Items::Null,
+    uid: 0,
 };
 
 #[derive(Debug, Clone)]
@@ -796,6 +859,7 @@ impl Composer {
 
         let rng = libk::rng::LcgRng::new(w.buffer as u64);
         w.wid = self.check_id(rng);
+        w.uid = crate::task::current_uid();
 
         for i in 0..self.windows.len() {
             match self.windows[i].wtype {
@@ -824,6 +888,134 @@ impl Composer {
         (w.wid as u32, w.buffer)
     }
 
+    /// Size a window takes on a `new` screen that was `old`, width, height and
+    /// depth. Sides as long as the old screen follow the new one, the rest
+    /// shrink if they no longer fit. None if its buffer can stay.
+    fn fitted(w: &Window, old: (u16, u16, usize), new: (u16, u16, usize)) -> Option<(u16, u16)> {
+        let width = if w.width == old.0 { new.0 } else { core::cmp::min(w.width, new.0) };
+        let height = if w.height == old.1 { new.1 } else { core::cmp::min(w.height, new.1) };
+
+        if width == w.width && height == w.height && new.2 == old.2 {
+            None
+        } else {
+            Some((width, height))
+        }
+    }
+
+    /// New buffers for the windows `fitted` resizes, 0 for the rest. Either
+    /// all of them are allocated or none.
+    pub fn buffers_for(
+        &self,
+        old: (u16, u16, usize),
+        new: (u16, u16, usize),
+    ) -> Result<[u32; 16], &'static str> {
+        let paddr = unsafe { &mut *(&raw mut crate::pmm::PADDR) };
+        let mut buffers = [0; 16];
+
+        for (i, w) in self.windows.iter().enumerate() {
+            let wtype = w.wtype;
+            if wtype == Items::Null {
+                continue;
+            }
+
+            let Some((width, height)) = Self::fitted(w, old, new) else {
+                continue;
+            };
+
+            match paddr.malloc(width as u32 * height as u32 * (new.2 / 4) as u32) {
+                Some(buffer) => buffers[i] = buffer,
+                None => {
+                    buffers.iter().filter(|&&b| b != 0).for_each(|&b| paddr.dealloc(b));
+                    return Err("No memory for the window buffers");
+                }
+            }
+        }
+
+        Ok(buffers)
+    }
+
+    /// Fits the windows into the new screen, which was `old`, using the
+    /// buffers `buffers_for` gave. Windows that changed size, or all of them
+    /// if the depth changed, get their new buffer and their resize callback.
+    pub fn screen_changed(&mut self, old: (u16, u16, usize), buffers: [u32; 16]) {
+        let screen = unsafe { &*(&raw const DISPLAY_SERVER) };
+        let (width, height) = (screen.width as u16, screen.height as u16);
+        let new = (width, height, screen.depth);
+
+        unsafe {
+            (*(&raw mut DRAGGING_WINDOW)).store(0, Ordering::Relaxed);
+            (*(&raw mut RESIZING_WINDOW)).store(0, Ordering::Relaxed);
+            W_WIDTH = 0;
+            W_HEIGHT = 0;
+        }
+
+        for i in 0..self.windows.len() {
+            let w = &mut self.windows[i];
+            let wtype = w.wtype;
+            if wtype == Items::Null {
+                continue;
+            }
+
+            let (new_width, new_height) = Self::fitted(w, old, new).unwrap_or((w.width, w.height));
+
+            w.x = core::cmp::min(w.x, width - new_width);
+            w.y = core::cmp::min(w.y, height - new_height);
+
+            if buffers[i] == 0 {
+                continue;
+            }
+
+            w.width = new_width;
+            w.height = new_height;
+
+            unsafe {
+                (*(&raw mut crate::pmm::PADDR)).dealloc(w.buffer);
+                w.buffer = buffers[i];
+                core::ptr::write_bytes(
+                    w.buffer as *mut u8,
+                    0,
+                    new_width as usize * new_height as usize * (screen.depth / 8),
+                );
+            }
+
+            if w.resize != 0 {
+                unsafe {
+                    (*(&raw mut crate::task::TASK_MANAGER))
+                        .lock()
+                        .add_user_task_as(
+                            w.resize,
+                            Some(&[w.wid as u32, new_width as u32, new_height as u32, w.buffer]),
+                            w.uid,
+                        );
+                }
+            }
+        }
+
+        // what the resize callbacks have not redrawn yet shows up black
+        unsafe {
+            core::ptr::write_bytes(
+                screen.double_buffer as *mut u8,
+                0,
+                (screen.pitch * screen.height) as usize,
+            );
+        }
+
+        for j in (0..self.windows.len()).rev() {
+            let wtype = self.windows[j].wtype;
+            if wtype != Items::Null {
+                screen.copy_to_db(
+                    self.windows[j].width as u32,
+                    self.windows[j].height as u32,
+                    self.windows[j].buffer,
+                    self.windows[j].x as u32,
+                    self.windows[j].y as u32,
+                );
+            }
+        }
+
+        screen.copy();
+    }
+
     pub fn write_kb(&mut self, char: char) {
         for i in 0..self.windows.len() {
             let y = self.windows[i].wtype;
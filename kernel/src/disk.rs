@@ -1,149 +1,402 @@
-use crate::dma::*;
+use crate::block::{BlockDevice, BlockError};
+use alloc::boxed::Box;
 use core::arch::asm;
-use libk::port::{inb, inw, outb, outl, outw};
+use libk::port::{inb, inw, outb, outw};
+
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_DF: u8 = 0x20;
+const STATUS_RDY: u8 = 0x40;
+const STATUS_BSY: u8 = 0x80;
+
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIG: u16 = 5;
+const REG_DRIVE: u16 = 6;
+pub const REG_COMMAND: u16 = 7;
+
+const CMD_READ_PIO: u8 = 0x20;
+const CMD_READ_PIO_EXT: u8 = 0x24;
+const CMD_WRITE_PIO: u8 = 0x30;
+const CMD_WRITE_PIO_EXT: u8 = 0x34;
+const CMD_FLUSH: u8 = 0xE7;
+const CMD_FLUSH_EXT: u8 = 0xEA;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+/// Polls of the status register before a command is considered lost.
+const TIMEOUT: u32 = 1_000_000;
+
+/// Sectors addressable with 28-bit LBA.
+const LBA28_LIMIT: u64 = 1 << 28;
+
+pub const MAX_DRIVES: usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Channel {
+    pub io: u16,
+    pub ctrl: u16,
+}
 
-pub fn read<T>(lba: u64, sectors: u16, disk: u8, target: *mut T) {
-    while is_busy() {}
+pub const CHANNELS: [Channel; 2] = [
+    Channel {
+        io: 0x1F0,
+        ctrl: 0x3F6,
+    },
+    Channel {
+        io: 0x170,
+        ctrl: 0x376,
+    },
+];
+
+/// What a device reports about itself in response to IDENTIFY DEVICE.
+#[derive(Copy, Clone, Debug)]
+pub struct Identity {
+    pub model: [u8; 40],
+    pub sectors: u64,
+    pub lba48: bool,
+    pub dma: bool,
+    pub udma_modes: u8,
+    pub mwdma_modes: u8,
+}
 
-    outb(0x3f6, 0b00000010);
+impl Identity {
+    pub fn parse(words: &[u16; 256]) -> Identity {
+        let mut model = [0u8; 40];
+        for i in 0..20 {
+            model[i * 2] = (words[27 + i] >> 8) as u8;
+            model[i * 2 + 1] = words[27 + i] as u8;
+        }
 
-    let disk = disk;
+        let lba48 = words[83] & (1 << 10) != 0;
+        let sectors = if lba48 {
+            (words[100] as u64)
+                | (words[101] as u64) << 16
+                | (words[102] as u64) << 32
+                | (words[103] as u64) << 48
+        } else {
+            (words[60] as u64) | (words[61] as u64) << 16
+        };
 
-    outb(0x1F1, 0x00);
-    outb(0x1F2, sectors as u8);
-    outb(0x1F3, lba as u8);
-    outb(0x1F4, (lba >> 8) as u8);
-    outb(0x1F5, (lba >> 16) as u8);
+        Identity {
+            model,
+            sectors,
+            lba48,
+            dma: words[49] & (1 << 8) != 0,
+            // word 88 is only valid when bit 2 of word 53 is set
+            udma_modes: if words[53] & 0x04 != 0 { words[88] as u8 } else { 0 },
+            mwdma_modes: (words[63] & 0x07) as u8,
+        }
+    }
 
-    outb(0x1F6, (disk as u64 | ((lba >> 24) & 0x0F)) as u8);
+    pub fn model(&self) -> &str {
+        core::str::from_utf8(&self.model).unwrap_or("?").trim()
+    }
 
-    outb(0x1F7, 0x20);
+    /// Fastest supported DMA mode, as a name and mode number.
+    pub fn dma_mode(&self) -> Option<(&'static str, u8)> {
+        if self.udma_modes != 0 {
+            Some(("UDMA", 7 - self.udma_modes.leading_zeros() as u8))
+        } else if self.mwdma_modes != 0 {
+            Some(("MWDMA", 7 - self.mwdma_modes.leading_zeros() as u8))
+        } else {
+            None
+        }
+    }
 
-    let mut sectors_left = sectors;
-    let mut target_pointer = target;
+}
 
-    while sectors_left > 0 {
-        for _ in 0..256 {
-            while is_busy() {}
-            while !is_ready() {}
+#[derive(Copy, Clone, Debug)]
+pub struct Drive {
+    pub index: u8,
+    pub channel: u8,
+    pub slave: bool,
+    pub identity: Identity,
+}
 
-            let bytes_16 = inw(0x1F0) as u16;
+impl Drive {
+    pub fn io(&self) -> u16 {
+        CHANNELS[self.channel as usize].io
+    }
 
-            unsafe {
-                core::ptr::write(target_pointer as *mut u16, bytes_16);
-                target_pointer = target_pointer.byte_add(2);
-            }
-        }
-        sectors_left -= 1;
+    /// Drive/head register value with the LBA bit set.
+    pub fn select_bits(&self) -> u8 {
+        if self.slave { 0xF0 } else { 0xE0 }
     }
 
-    reset();
+    pub fn needs_lba48(&self, lba: u64, sectors: u32) -> bool {
+        lba + sectors as u64 > LBA28_LIMIT || sectors > 256
+    }
 }
 
-pub fn write<T>(lba: u64, content: *const T, length_bytes: usize) {
+impl BlockDevice for Drive {
+    fn driver(&self) -> &'static str {
+        "ATA"
+    }
 
-    let total_sectors = (length_bytes + 511) / 512;
-    let mut offset: usize = 0;
+    fn model(&self) -> &str {
+        self.identity.model()
+    }
 
-    while offset < total_sectors {
+    fn sectors(&self) -> u64 {
+        self.identity.sectors
+    }
 
-        let sectors_to_write = if (total_sectors - offset) > 255 {
-            255
-        } else {
-            (total_sectors - offset) as u16
-        };
+    fn read(&mut self, lba: u64, sectors: u32, target: *mut u8) -> Result<(), BlockError> {
+        crate::dma::read(lba, sectors, self.index, target)
+    }
 
-        while is_busy() {}
+    fn write(&mut self, lba: u64, sectors: u32, buffer: *const u8) -> Result<(), BlockError> {
+        crate::dma::write(lba, sectors, self.index, buffer)
+    }
+}
 
-        outb(0x3f6, 0b00000010);
-        outb(0x1F1, 0x00);
-        outb(0x1F2, sectors_to_write as u8);
-        outb(0x1F3, (lba + offset as u64) as u8);
-        outb(0x1F4, ((lba + offset as u64) >> 8) as u8);
-        outb(0x1F5, ((lba + offset as u64) >> 16) as u8);
-        outb(0x1F6, (0xE0 | (((lba + offset as u64) >> 24) & 0x0F)) as u8);
-        outb(0x1F7, 0x30);
+pub static mut DRIVES: [Option<Drive>; MAX_DRIVES] = [None; MAX_DRIVES];
 
-        let content_ptr = content as *const u8;
-        let mut sectors_left = sectors_to_write;
+/// Primary master, primary slave, secondary master, secondary slave.
+pub fn drive(index: u8) -> Result<Drive, BlockError> {
+    unsafe {
+        (*(&raw const DRIVES))
+            .get(index as usize)
+            .copied()
+            .flatten()
+            .ok_or(BlockError::NoDevice)
+    }
+}
 
-        while sectors_left > 0 {
-            let bytes_remaining = length_bytes.saturating_sub(offset * 512);
+pub fn init() {
+    for channel in CHANNELS.iter() {
+        outb(channel.ctrl, 0b00000110);
+        delay();
+        outb(channel.ctrl, 0b00000010);
+    }
 
-            for i in 0..256 {
-                while is_busy() {}
-                while !is_ready() {}
+    for index in 0..MAX_DRIVES as u8 {
+        let found = identify(index / 2, index % 2 == 1).map(|identity| Drive {
+            index,
+            channel: index / 2,
+            slave: index % 2 == 1,
+            identity,
+        });
 
-                let byte_offset = offset * 512 + i * 2;
-                let mut b_2_w: u16 = 0;
+        unsafe {
+            DRIVES[index as usize] = found;
+        }
 
-                if byte_offset < length_bytes {
-                    b_2_w = unsafe { *content_ptr.add(byte_offset) as u16 };
-                }
+        if let Some(d) = found {
+            let (mode, level) = d.identity.dma_mode().unwrap_or(("PIO", 0));
 
-                if byte_offset + 1 < length_bytes {
-                    b_2_w |= (unsafe { *content_ptr.add(byte_offset + 1) as u16 }) << 8;
-                }
+            libk::println!(
+                "[+] ATA {} uses {}{}{}",
+                index,
+                mode,
+                level,
+                if d.identity.lba48 { ", LBA48" } else { "" }
+            );
 
-                outw(0x1F0, b_2_w);
-            }
-            sectors_left -= 1;
-            offset += 1;
+            crate::block::register(Box::new(d));
         }
-
-        reset();
-        outb(0x1F7, 0xE7);
     }
 }
 
-pub fn reset() {
-    outb(0x3f6, 0b00000110);
-    outb(0x3f6, 0b00000010);
+fn identify(channel: u8, slave: bool) -> Option<Identity> {
+    let io = CHANNELS[channel as usize].io;
+
+    // a floating bus reads back 0xFF
+    if inb(io + REG_COMMAND) == 0xFF {
+        return None;
+    }
+
+    outb(io + REG_DRIVE, if slave { 0xB0 } else { 0xA0 });
+    delay();
+
+    outb(io + REG_COUNT, 0);
+    outb(io + REG_LBA_LOW, 0);
+    outb(io + REG_LBA_MID, 0);
+    outb(io + REG_LBA_HIG, 0);
+    outb(io + REG_COMMAND, CMD_IDENTIFY);
+    delay();
+
+    if inb(io + REG_COMMAND) == 0 {
+        return None;
+    }
+
+    wait_busy(io).ok()?;
+
+    // ATAPI and SATA devices abort IDENTIFY and leave a signature behind
+    if inb(io + REG_LBA_MID) != 0 || inb(io + REG_LBA_HIG) != 0 {
+        return None;
+    }
+
+    wait_drq(io).ok()?;
+
+    let mut words = [0u16; 256];
+    for w in words.iter_mut() {
+        *w = inw(io + REG_DATA);
+    }
+
+    Some(Identity::parse(&words))
 }
 
-pub fn is_ready() -> bool {
-    let status: u8 = inb(0x1F7);
+/// Selects the drive and programs the task file for a transfer, using LBA48 when needed.
+/// Returns whether the extended command set has to be used.
+pub fn setup(d: &Drive, lba: u64, sectors: u32) -> Result<bool, BlockError> {
+    if sectors == 0 || lba + sectors as u64 > d.identity.sectors {
+        return Err(BlockError::OutOfRange);
+    }
+
+    let io = d.io();
+    let ext = d.needs_lba48(lba, sectors);
+
+    if ext && (!d.identity.lba48 || sectors > 65536) {
+        return Err(BlockError::OutOfRange);
+    }
+
+    wait_busy(io)?;
 
-    (status & 0b01000000) != 0
+    if ext {
+        outb(io + REG_DRIVE, d.select_bits() & 0xF0);
+        delay();
+
+        outb(io + REG_COUNT, (sectors >> 8) as u8);
+        outb(io + REG_LBA_LOW, (lba >> 24) as u8);
+        outb(io + REG_LBA_MID, (lba >> 32) as u8);
+        outb(io + REG_LBA_HIG, (lba >> 40) as u8);
+    } else {
+        outb(io + REG_DRIVE, d.select_bits() | ((lba >> 24) & 0x0F) as u8);
+        delay();
+    }
+
+    outb(io + REG_COUNT, sectors as u8);
+    outb(io + REG_LBA_LOW, lba as u8);
+    outb(io + REG_LBA_MID, (lba >> 8) as u8);
+    outb(io + REG_LBA_HIG, (lba >> 16) as u8);
+
+    Ok(ext)
 }
 
-pub fn is_busy() -> bool {
-    let status: u8 = inb(0x1F7);
+pub fn read<T>(lba: u64, sectors: u32, drive_index: u8, target: *mut T) -> Result<(), BlockError> {
+    let d = drive(drive_index)?;
+    let io = d.io();
+
+    let ext = setup(&d, lba, sectors)?;
+    outb(io + REG_COMMAND, if ext { CMD_READ_PIO_EXT } else { CMD_READ_PIO });
+
+    let mut target_pointer = target as *mut u16;
+
+    for _ in 0..sectors {
+        wait_drq(io)?;
 
-    (status & 0b10000000) != 0
+        for _ in 0..256 {
+            unsafe {
+                core::ptr::write_unaligned(target_pointer, inw(io + REG_DATA));
+                target_pointer = target_pointer.add(1);
+            }
+        }
+    }
+
+    Ok(())
 }
 
-fn delay() {
-    for _ in 0..10000 {
-        unsafe { asm!("nop") };
+pub fn write<T>(lba: u64, sectors: u32, drive_index: u8, buffer: *const T) -> Result<(), BlockError> {
+    let d = drive(drive_index)?;
+    let io = d.io();
+
+    let ext = setup(&d, lba, sectors)?;
+    outb(io + REG_COMMAND, if ext { CMD_WRITE_PIO_EXT } else { CMD_WRITE_PIO });
+
+    let mut source = buffer as *const u16;
+
+    for _ in 0..sectors {
+        wait_drq(io)?;
+
+        for _ in 0..256 {
+            unsafe {
+                outw(io + REG_DATA, core::ptr::read_unaligned(source));
+                source = source.add(1);
+            }
+        }
     }
+
+    flush(&d, ext)
 }
 
-pub fn check_disk() -> [bool; 2] {
-    let mut master = false;
-    let mut slave = false;
+pub fn flush(d: &Drive, ext: bool) -> Result<(), BlockError> {
+    outb(d.io() + REG_COMMAND, if ext { CMD_FLUSH_EXT } else { CMD_FLUSH });
+    wait_busy(d.io())?;
+    check(d.io())
+}
 
-    outb(0x1F6, 0xF0);
-    outb(0x1F7, 0xEC);
+pub fn reset(channel: u8) {
+    let ctrl = CHANNELS[channel as usize].ctrl;
 
+    outb(ctrl, 0b00000110);
     delay();
+    outb(ctrl, 0b00000010);
+}
 
-    let status = inb(0x1F7);
-    if status != 0 {
-        slave = true;
+/// Waits for BSY to clear, failing on timeout.
+pub fn wait_busy(io: u16) -> Result<u8, BlockError> {
+    for _ in 0..TIMEOUT {
+        let status = inb(io + REG_COMMAND);
+
+        if status & STATUS_BSY == 0 {
+            return Ok(status);
+        }
     }
 
-    delay();
+    Err(BlockError::Timeout)
+}
 
-    outb(0x1F6, 0xE0);
-    outb(0x1F7, 0xEC);
+/// Waits until the drive is ready to move a sector of data.
+pub fn wait_drq(io: u16) -> Result<(), BlockError> {
+    for _ in 0..TIMEOUT {
+        let status = inb(io + REG_COMMAND);
 
-    delay();
+        if status & STATUS_BSY != 0 {
+            continue;
+        }
+
+        if status & STATUS_ERR != 0 {
+            return Err(BlockError::Error(inb(io + REG_ERROR)));
+        }
+
+        if status & STATUS_DF != 0 {
+            return Err(BlockError::DeviceFault);
+        }
+
+        if status & STATUS_DRQ != 0 {
+            return Ok(());
+        }
+    }
 
-    let status = inb(0x1F7);
-    if status != 0 {
-        master = true;
+    Err(BlockError::Timeout)
+}
+
+/// Reports the outcome of the last command once the drive went idle.
+pub fn check(io: u16) -> Result<(), BlockError> {
+    let status = wait_busy(io)?;
+
+    if status & STATUS_ERR != 0 {
+        return Err(BlockError::Error(inb(io + REG_ERROR)));
+    }
+
+    if status & STATUS_DF != 0 {
+        return Err(BlockError::DeviceFault);
+    }
+
+    if status & STATUS_RDY == 0 {
+        return Err(BlockError::NoDevice);
     }
 
-    [master, slave]
+    Ok(())
+}
+
+/// Gives the drive well over the 400ns it needs to put a valid status on the bus.
+fn delay() {
+    for _ in 0..10000 {
+        unsafe { asm!("nop") };
+    }
 }
@@ -1,3 +1,5 @@
+use crate::block::BlockError;
+use crate::disk;
 use crate::pci::*;
 use libk::port::*;
 use libk::println;
@@ -7,128 +9,176 @@ fn test_pci_detection() {
         if let Some(dev) = find_device(0x8086, 0x7010) {
             println!("IDE controller found!");
 
-            BM_BASE = dev.get_bar(4).unwrap() as u16;
-            BMR_COMMAND = BM_BASE + 0;
-            BMR_STATUS = BM_BASE + 2;
-            BMR_PRDT = BM_BASE + 4;
+            BM_BASE = dev.get_bar(4).unwrap_or(0) as u16;
 
             dev.enable_bus_mastering();
         } else {
-            panic!("PIIX4 controller not found!");
+            println!("[!] PIIX IDE controller not found, falling back to PIO");
         }
     }
 }
 
+#[derive(Copy, Clone)]
+#[repr(C)]
 struct PrdtEntry {
     buffer_phys: u32,
     transfer_size: u16,
     flags: u16,
 }
 
-static mut PRDT: PrdtEntry = PrdtEntry {
+const PRDT_ENTRIES: usize = 16;
+const PRDT_LAST: u16 = 0x8000;
+
+/// One page-aligned table per channel so neither crosses a 64 KiB boundary.
+#[repr(C, align(4096))]
+struct Prdt([PrdtEntry; PRDT_ENTRIES]);
+
+const NULL_PRD: PrdtEntry = PrdtEntry {
     buffer_phys: 0,
     transfer_size: 0,
     flags: 0,
 };
 
+static mut PRDT: [Prdt; 2] = [Prdt([NULL_PRD; PRDT_ENTRIES]), Prdt([NULL_PRD; PRDT_ENTRIES])];
+
 static mut BM_BASE: u16 = 0;
-static mut BMR_COMMAND: u16 = unsafe { BM_BASE + 0 };
-static mut BMR_STATUS: u16 = unsafe { BM_BASE + 2 };
-static mut BMR_PRDT: u16 = unsafe { BM_BASE + 4 };
-
-const ATA: u16 = 0x1F0;
-const ATA_DISK: u16 = ATA + 6;
-const ATA_SECTOR: u16 = ATA + 2;
-const ATA_LBA_LOW: u16 = ATA + 3;
-const ATA_LBA_MID: u16 = ATA + 4;
-const ATA_LBA_HIG: u16 = ATA + 5;
-const ATA_COMMAND: u16 = ATA + 7;
+
+const BMR_COMMAND: u16 = 0;
+const BMR_STATUS: u16 = 2;
+const BMR_PRDT: u16 = 4;
+
+const BM_START: u8 = 0x01;
+const BM_READ: u8 = 0x08;
+const BM_ACTIVE: u8 = 0x01;
+const BM_ERROR: u8 = 0x02;
+const BM_IRQ: u8 = 0x04;
 
 const ATA_READ_DMA: u8 = 0xC8;
+const ATA_READ_DMA_EXT: u8 = 0x25;
 const ATA_WRITE_DMA: u8 = 0xCA;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
 
-pub fn read<T>(lba: u64, sectors: u8, disk: u8, target: *mut T) {
-    unsafe {
-        PRDT.buffer_phys = target as u32;
-        PRDT.transfer_size = 512 * sectors as u16;
-        PRDT.flags = 0x8000;
+/// Largest single command; bigger requests are split.
+pub const MAX_SECTORS: u32 = 256;
 
-        outb(BMR_COMMAND, 0);
+const TIMEOUT: u32 = 10_000_000;
 
-        outl(BMR_PRDT, core::ptr::addr_of!(PRDT) as u32);
+pub fn read<T>(lba: u64, sectors: u32, drive: u8, target: *mut T) -> Result<(), BlockError> {
+    let mut done = 0;
+
+    while done < sectors {
+        let count = core::cmp::min(sectors - done, MAX_SECTORS);
+        let buffer = (target as u32) + done * 512;
+
+        transfer(lba + done as u64, count, drive, buffer, false)?;
+        done += count;
+    }
+
+    Ok(())
+}
 
-        outb(ATA_DISK, (disk as u64 | ((lba >> 24) & 0x0F)) as u8);
+pub fn write<T>(lba: u64, sectors: u32, drive: u8, buffer: *const T) -> Result<(), BlockError> {
+    let mut done = 0;
 
-        outb(ATA_SECTOR, sectors as u8);
-        outb(ATA_LBA_LOW, lba as u8);
-        outb(ATA_LBA_MID, (lba >> 8) as u8);
-        outb(ATA_LBA_HIG, (lba >> 16) as u8);
+    while done < sectors {
+        let count = core::cmp::min(sectors - done, MAX_SECTORS);
+        let source = (buffer as u32) + done * 512;
 
-        outb(ATA_COMMAND, ATA_READ_DMA);
+        transfer(lba + done as u64, count, drive, source, true)?;
+        done += count;
+    }
 
-        outb(BMR_COMMAND, 0x8 | 0x1);
+    Ok(())
+}
 
-        loop {
-            let status = inb(BMR_STATUS);
+/// Splits `buffer` into PRD entries that never cross a 64 KiB boundary.
+fn build_prdt(channel: usize, buffer: u32, bytes: u32) -> Result<(), BlockError> {
+    let table = unsafe { &mut (*(&raw mut PRDT))[channel].0 };
 
-            if status & 0x02 != 0 {
-                panic!("DMA error occurred");
-            }
+    let mut address = buffer;
+    let mut left = bytes;
+    let mut i = 0;
 
-            if (status & 0x04) == 0 {
-                break;
-            }
+    while left > 0 {
+        if i == PRDT_ENTRIES {
+            return Err(BlockError::OutOfRange);
         }
 
-        let mut command = inb(BMR_COMMAND);
-        command &= !0x01;
+        let boundary = (address & !0xFFFF) + 0x10000;
+        let size = core::cmp::min(left, boundary - address);
 
-        outb(BMR_COMMAND, command);
+        table[i] = PrdtEntry {
+            buffer_phys: address,
+            // a size of 0 means 64 KiB
+            transfer_size: size as u16,
+            flags: if size == left { PRDT_LAST } else { 0 },
+        };
 
-        outb(BMR_STATUS, 0x04 | 0x02);
+        address += size;
+        left -= size;
+        i += 1;
     }
-}
 
-pub fn write<T>(lba: u64, sectors: u8, disk: u8, buffer: *const T) {
-    unsafe {
-
-        outb(BMR_COMMAND, 0);
+    Ok(())
+}
 
-        PRDT.buffer_phys = buffer as u32;
-        PRDT.transfer_size = 512 * sectors as u16;
-        PRDT.flags = 0x8000;
+fn transfer(lba: u64, sectors: u32, drive: u8, buffer: u32, write: bool) -> Result<(), BlockError> {
+    let d = disk::drive(drive)?;
+    let bm = unsafe { BM_BASE };
 
-        outl(BMR_PRDT, core::ptr::addr_of!(PRDT) as u32);
+    if bm == 0 || !d.identity.dma {
+        return if write {
+            disk::write(lba, sectors, drive, buffer as *const u8)
+        } else {
+            disk::read(lba, sectors, drive, buffer as *mut u8)
+        };
+    }
 
-        outb(ATA_DISK, (disk as u64 | ((lba >> 24) & 0x0F)) as u8);
+    let bm = bm + d.channel as u16 * 8;
+    build_prdt(d.channel as usize, buffer, sectors * 512)?;
 
-        outb(ATA_SECTOR, sectors as u8);
-        outb(ATA_LBA_LOW, lba as u8);
-        outb(ATA_LBA_MID, (lba >> 8) as u8);
-        outb(ATA_LBA_HIG, (lba >> 16) as u8);
+    outb(bm + BMR_COMMAND, 0);
+    outb(bm + BMR_STATUS, BM_IRQ | BM_ERROR);
+    outl(bm + BMR_PRDT, unsafe {
+        core::ptr::addr_of!((*(&raw const PRDT))[d.channel as usize]) as u32
+    });
 
-        outb(ATA_COMMAND, ATA_WRITE_DMA);
+    let ext = disk::setup(&d, lba, sectors)?;
+    let command = match (write, ext) {
+        (false, false) => ATA_READ_DMA,
+        (false, true) => ATA_READ_DMA_EXT,
+        (true, false) => ATA_WRITE_DMA,
+        (true, true) => ATA_WRITE_DMA_EXT,
+    };
 
-        outb(BMR_COMMAND, 0x1);
+    outb(d.io() + disk::REG_COMMAND, command);
+    outb(bm + BMR_COMMAND, BM_START | if write { 0 } else { BM_READ });
 
-        loop {
-            let status = inb(BMR_STATUS);
+    let mut result = Err(BlockError::Timeout);
 
-            if status & 0x02 != 0 {
-                panic!("DMA error occurred");
-            }
+    for _ in 0..TIMEOUT {
+        let status = inb(bm + BMR_STATUS);
 
-            if (status & 0x04) == 0 {
-                break;
-            }
+        if status & BM_ERROR != 0 {
+            result = Err(BlockError::DeviceFault);
+            break;
         }
 
-        let mut command = inb(BMR_COMMAND);
-        command &= !0x01;
+        if status & BM_IRQ != 0 || status & BM_ACTIVE == 0 {
+            result = Ok(());
+            break;
+        }
+    }
 
+    outb(bm + BMR_COMMAND, 0);
+    outb(bm + BMR_STATUS, BM_IRQ | BM_ERROR);
 
-        outb(BMR_COMMAND, command);
+    if result.is_err() {
+        disk::reset(d.channel);
+        return result;
     }
+
+    disk::check(d.io())
 }
 
 pub fn init() {
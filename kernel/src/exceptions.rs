@@ -1,6 +1,7 @@
 use crate::keyboard;
-use crate::pic::PICS;
 use libk::port::{inb, outb};
+use libk::perm::{ADMIN, Access, Mode};
+use libk::io::WatchKind;
 use libk::println;
 
 use crate::composer::{COMPOSER, Window};
@@ -27,7 +28,7 @@ pub extern "x86-interrupt" fn div_error(info: StackFrame) {
     println!("DIVISION ERROR!");
 
     if info.cs == 0x1B {
-        crate::task::exit();
+        crate::task::exit(libk::task::FAULT);
     }
 }
 
@@ -35,7 +36,7 @@ pub extern "x86-interrupt" fn bounds(info: StackFrame) {
     println!("OUT OF BOUNDS! -> {:?}", info);
 
     if info.cs == 0x1B {
-        crate::task::exit();
+        crate::task::exit(libk::task::FAULT);
     }
 }
 
@@ -43,7 +44,7 @@ pub extern "x86-interrupt" fn invalid_opcode(info: StackFrame) {
     println!("IO -> {:?}", info);
 
     if info.cs == 0x1B {
-        crate::task::exit();
+        crate::task::exit(libk::task::FAULT);
     }
 }
 
@@ -51,7 +52,7 @@ pub extern "x86-interrupt" fn double_fault(info: StackFrame) {
     println!("DOUBLE FAULT!");
 
     if info.cs == 0x1B {
-        crate::task::exit();
+        crate::task::exit(libk::task::FAULT);
     }
 }
 
@@ -59,7 +60,7 @@ pub extern "x86-interrupt" fn general_protection_fault(info: InterruptStackFrame
     println!("GPF -> {:?}", info);
 
     if info.cs == 0x1B {
-        crate::task::exit();
+        crate::task::exit(libk::task::FAULT);
     }
 }
 
@@ -67,7 +68,7 @@ pub extern "x86-interrupt" fn page_fault(info: InterruptStackFrame) {
     println!("PAGE FAULT!");
 
     if info.cs == 0x1B {
-        crate::task::exit();
+        crate::task::exit(libk::task::FAULT);
     }
 }
 
@@ -77,24 +78,22 @@ pub extern "x86-interrupt" fn generic_handler() {
 
 /* SPECIFIC STUFF */
 
-pub const NET_INT: u8 = 43;
-
 pub const TIMER_INT: u8 = 32;
 
 pub const KEYBOARD_INT: u8 = 33;
 
 pub extern "x86-interrupt" fn keyboard_handler() {
     libk::disable_interrupts();
+    let kernel = crate::smp::lock_kernel();
 
     let data: u8 = inb(0x60);
 
     keyboard::keyboard_italian(data);
     libk::print!("<{}>", data);
 
-    unsafe {
-        (*(&raw mut PICS)).end_interrupt(KEYBOARD_INT);
-    }
+    crate::apic::end_interrupt(KEYBOARD_INT);
 
+    drop(kernel);
     libk::enable_interrupts();
 }
 
@@ -105,11 +104,13 @@ pub static mut MOUSE_IDX: usize = 0;
 pub extern "x86-interrupt" fn mouse_handler() {
     unsafe {
         libk::disable_interrupts();
+        let kernel = crate::smp::lock_kernel();
 
         let data = inb(0x60);
 
         if MOUSE_IDX == 0 && (data & 0b00001000) == 0 {
-            (*(&raw mut PICS)).end_interrupt(MOUSE_INT);
+            crate::apic::end_interrupt(MOUSE_INT);
+            drop(kernel);
             libk::enable_interrupts();
             return;
         }
@@ -122,7 +123,8 @@ pub extern "x86-interrupt" fn mouse_handler() {
             MOUSE_IDX = 0;
         }
 
-        (*(&raw mut PICS)).end_interrupt(MOUSE_INT);
+        crate::apic::end_interrupt(MOUSE_INT);
+        drop(kernel);
         libk::enable_interrupts();
     }
 }
@@ -133,9 +135,7 @@ pub extern "x86-interrupt" fn rtc_handler() {
     outb(0x70, 0x0C);
     let _ = inb(0x71);
 
-    unsafe {
-        (*(&raw mut PICS)).end_interrupt(RTC_INT);
-    }
+    crate::apic::end_interrupt(RTC_INT);
 }
 
 #[naked]
@@ -150,6 +150,10 @@ pub extern "C" fn syscall() {
             "call syscall_handler",
             "add esp, 16",
             "push eax",
+            // the caller's code segment, from the frame iretd returns through
+            "push dword ptr [esp + 8]",
+            "call leave_kernel",
+            "add esp, 4",
             "pop eax",
             "sti",
             "iretd",
@@ -163,6 +167,8 @@ pub static mut TEMP_FILE: crate::fs::fat16::structs::Entry = crate::fs::fat16::s
 #[inline(never)]
 #[unsafe(no_mangle)]
 pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32 {
+    let _kernel = crate::smp::lock_kernel();
+
     unsafe {
         let mut return_val = 0;
 
@@ -177,8 +183,6 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
             }
 
             2 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
@@ -187,17 +191,18 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
                 let filename =
                     core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                if let Some(entry) = (*(&raw mut crate::fs::fat16::structs::FAT16))
-                    .find_entry(&format_path_8_3(filename))
-                {
-                    (*(&raw mut crate::fs::fat16::structs::FAT16)).read(&entry, ecx as *mut u8);
-                    return_val = 1;
+                if let Err(e) = crate::fs::vfs::check(filename, Access::Read) {
+                    libk::println!("[!] {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    if let Some(entry) = fs.find_entry(&format_path_8_3(&path)) {
+                        fs.read(&entry, ecx as *mut u8);
+                        return_val = 1;
+                        fs.mark_accessed(&format_path_8_3(&path));
+                    }
                 }
             }
 
             3 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
@@ -206,17 +211,17 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
                 let filename =
                     core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                if let Some(entry) = (*(&raw mut crate::fs::fat16::structs::FAT16))
-                    .find_entry(&format_path_8_3(filename))
-                {
-                    TEMP_FILE = entry;
-                    return_val = core::ptr::addr_of!(TEMP_FILE) as u32;
+                if let Err(e) = crate::fs::vfs::check(filename, Access::Read) {
+                    libk::println!("[!] {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    if let Some(entry) = fs.find_entry(&format_path_8_3(&path)) {
+                        TEMP_FILE = entry;
+                        return_val = core::ptr::addr_of!(TEMP_FILE) as u32;
+                    }
                 }
             }
 
             4 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
@@ -225,8 +230,11 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
                 let filename =
                     core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                if let Some(entry) = (*(&raw mut crate::fs::fat16::structs::FAT16))
-                    .find_entry(&format_path_8_3(&format_path_8_3(filename)))
+                if let Err(e) = crate::fs::vfs::check(filename, Access::Read) {
+                    libk::println!("[!] {}: {}", filename, e);
+                    return_val = 699669;
+                } else if let Some(entry) = crate::fs::vfs::resolve(filename)
+                    .and_then(|(fs, path)| fs.find_entry(&format_path_8_3(&path)))
                 {
                     return_val = entry.size;
                 } else {
@@ -298,14 +306,32 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
                     args_ptr = Some(core::slice::from_raw_parts(edx as *const u32, 4));
                 }
 
-                (*(&raw mut crate::task::TASK_MANAGER))
-                    .lock()
-                    .add_user_task(ebx, args_ptr);
-                return_val = 1;
+                // a program names the file it was loaded from, a thread of one does not
+                let allowed = ecx == 0 || {
+                    let path_ref = *(ecx as *const libk::io::StringRef);
+                    let path = core::str::from_utf8_unchecked(core::slice::from_raw_parts(
+                        path_ref.address as *const u8,
+                        core::cmp::min(path_ref.length as usize, 256),
+                    ));
+
+                    match crate::fs::vfs::check(path, Access::Execute) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            libk::println!("[!] {}: {}", path, e);
+                            false
+                        }
+                    }
+                };
+
+                if allowed {
+                    return_val = (*(&raw mut crate::task::TASK_MANAGER))
+                        .lock()
+                        .add_user_task(ebx, args_ptr);
+                }
             }
 
             26 => {
-                crate::task::exit();
+                crate::task::exit(ebx);
             }
 
             27 => {
@@ -319,32 +345,34 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
             }
 
             28 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
                 }
 
-                let n_entries = (*(&raw mut crate::fs::fat16::structs::FAT16)).count_entries_in_dir(
-                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]),
-                );
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                return_val = n_entries;
+                if crate::fs::vfs::check(filename, Access::Read).is_ok() {
+                    if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                        return_val = fs.count_entries_in_dir(&path);
+                    }
+                }
             }
 
             29 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
                 }
 
-                let mut n_entries = (*(&raw mut crate::fs::fat16::structs::FAT16)).get_entries_by_id(
-                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]),
-                    ecx as u8,
-                );
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                let n_entries = crate::fs::vfs::check(filename, Access::Read)
+                    .ok()
+                    .and_then(|_| crate::fs::vfs::resolve(filename))
+                    .and_then(|(fs, path)| fs.get_entries_by_id(&path, ecx));
 
                 if n_entries.is_some() {
                     let e_size = core::mem::size_of::<crate::fs::fat16::structs::Entry>() as u32;
@@ -400,8 +428,6 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
             }
 
             38 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
@@ -410,21 +436,28 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
                 let filename =
                     core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                if let Some(_entry) = (*(&raw mut crate::fs::fat16::structs::FAT16))
-                    .find_entry(&format_path_8_3(filename))
-                {
-                    let data_ptr = *(ecx as *const (u32, u32));
-
-                    let data =
-                        core::slice::from_raw_parts(data_ptr.0 as *const u8, data_ptr.1 as usize);
-                    let _ = (*(&raw mut crate::fs::fat16::structs::FAT16))
-                        .overwrite_file(&format_path_8_3(filename), data);
+                return_val = 1;
+                if let Err(e) = crate::fs::vfs::check(filename, Access::Write) {
+                    libk::println!("[!] {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    if let Some(_entry) = fs.find_entry(&format_path_8_3(&path)) {
+                        let data_ptr = *(ecx as *const (u32, u32));
+                        let data = core::slice::from_raw_parts(
+                            data_ptr.0 as *const u8,
+                            data_ptr.1 as usize,
+                        );
+                        match fs.overwrite_file(&format_path_8_3(&path), data) {
+                            Ok(()) => {
+                                crate::fs::watch::notify(filename, WatchKind::Modify);
+                                return_val = 0;
+                            }
+                            Err(e) => libk::println!("[!] {}: {}", filename, e),
+                        }
+                    }
                 }
             }
 
             39 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
@@ -433,20 +466,28 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
                 let filename =
                     core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                if let Some(_entry) = (*(&raw mut crate::fs::fat16::structs::FAT16))
-                    .find_entry(&format_path_8_3(filename))
-                {
-                    let data_ptr = *(ecx as *const (u32, u32));
-                    let data =
-                        core::slice::from_raw_parts(data_ptr.0 as *const u8, data_ptr.1 as usize);
-                    let _ = (*(&raw mut crate::fs::fat16::structs::FAT16))
-                        .append_to_file(&format_path_8_3(filename), data);
+                return_val = 1;
+                if let Err(e) = crate::fs::vfs::check(filename, Access::Write) {
+                    libk::println!("[!] {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    if let Some(_entry) = fs.find_entry(&format_path_8_3(&path)) {
+                        let data_ptr = *(ecx as *const (u32, u32));
+                        let data = core::slice::from_raw_parts(
+                            data_ptr.0 as *const u8,
+                            data_ptr.1 as usize,
+                        );
+                        match fs.append_to_file(&format_path_8_3(&path), data) {
+                            Ok(()) => {
+                                crate::fs::watch::notify(filename, WatchKind::Modify);
+                                return_val = 0;
+                            }
+                            Err(e) => libk::println!("[!] {}: {}", filename, e),
+                        }
+                    }
                 }
             }
 
             40 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
-
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
@@ -454,7 +495,18 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
 
                 let filename = core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).create_file(&format_path_8_3(filename));
+                return_val = 1;
+                if let Err(e) = crate::fs::vfs::check_parent(filename) {
+                    libk::println!("[!] {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    match fs.create_file(&format_path_8_3(&path)) {
+                        Ok(()) => {
+                            crate::fs::watch::notify(filename, WatchKind::Create);
+                            return_val = 0;
+                        }
+                        Err(e) => libk::println!("[!] {}: {}", filename, e),
+                    }
+                }
             }
 
             41 => {
@@ -465,8 +517,246 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
             }
 
             42 => {
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).reload();
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                return_val = 1;
+                if let Err(e) = crate::fs::vfs::check_parent(filename) {
+                    libk::println!("[!] {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    match fs.create_dir(&format_path_8_3(&path)) {
+                        Ok(()) => {
+                            crate::fs::watch::notify(filename, WatchKind::Create);
+                            return_val = 0;
+                        }
+                        Err(e) => libk::println!("[!] {}: {}", filename, e),
+                    }
+                }
+            }
+
+            43 => {
+                let target: &mut [libk::io::PartitionInfo] = if ebx == 0 {
+                    &mut []
+                } else {
+                    core::slice::from_raw_parts_mut(ebx as *mut libk::io::PartitionInfo, ecx as usize)
+                };
+
+                return_val = crate::fs::partition::list(target);
+            }
+
+            44 => {
+                return_val = crate::fs::cache::sync().is_err() as u32;
+            }
+
+            45 => {
+                *(ebx as *mut libk::io::CacheStats) = (*(&raw mut crate::fs::cache::CACHE)).stats();
+            }
+
+            46 => {
+                let ok = crate::task::current_uid() == ADMIN
+                    && (*(&raw mut crate::fs::cache::CACHE)).resize(ebx as usize);
+                return_val = if ok { 0 } else { 1 };
+            }
+
+            47 => {
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                return_val = 1;
+                if let Err(e) = crate::fs::vfs::check_parent(filename) {
+                    libk::println!("[!] rm {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    match fs.remove(&format_path_8_3(&path)) {
+                        Ok(()) => {
+                            crate::fs::watch::notify(filename, WatchKind::Delete);
+                            return_val = 0;
+                        }
+                        Err(e) => libk::println!("[!] rm {}: {}", filename, e),
+                    }
+                }
+            }
+
+            48 => {
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let point =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                let ok = crate::task::current_uid() == ADMIN && crate::fs::vfs::mount_tmpfs(point, ecx);
+                return_val = if ok { 0 } else { 1 };
+            }
+
+            49 => {
+                *(ebx as *mut libk::time::DateTime) = crate::rtc::now();
+            }
+
+            50 | 51 => {
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                let uid = crate::task::current_uid();
+                return_val = 1;
+
+                if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    let path = format_path_8_3(&path);
+
+                    if let Some(entry) = fs.find_entry(&path) {
+                        // only the owner may chmod, only the admin may chown
+                        let (owner, mode, allowed) = if eax == 50 {
+                            (entry.owner(), Mode::from_octal(ecx as u8), entry.owner() == uid)
+                        } else {
+                            (ecx as u16, Mode(entry.mode()), false)
+                        };
+
+                        if (allowed || uid == ADMIN) && fs.set_permissions(&path, owner, mode).is_ok() {
+                            return_val = 0;
+                        }
+                    }
+                }
+            }
+
+            52 => {
+                let mut tasks = (*(&raw mut crate::task::TASK_MANAGER)).lock();
+
+                return_val = 1;
+                if tasks.uid() == ADMIN {
+                    tasks.set_uid(ebx as u16);
+                    return_val = 0;
+                }
+            }
+
+            53 => {
+                return_val = crate::task::current_uid() as u32;
+            }
+
+            54 => {
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                let access = match ecx {
+                    0 => Access::Read,
+                    1 => Access::Write,
+                    _ => Access::Execute,
+                };
+
+                return_val = if crate::fs::vfs::check(filename, access).is_ok() { 0 } else { 1 };
+            }
+
+            55 => {
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                // anyone may check, only the admin may repair
+                let repair = ecx != 0;
+                return_val = u32::MAX;
+
+                if !repair || crate::task::current_uid() == ADMIN {
+                    if let Some((disk, partition)) = crate::fs::vfs::device_of(filename) {
+                        if let Some(report) = crate::fs::check::run(disk, partition, repair) {
+                            return_val = report.problems();
+                        }
+                    }
+                }
+            }
+
+            56 => {
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                if crate::fs::vfs::check(filename, Access::Read).is_ok() {
+                    if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                        // mount points have no entry of their own
+                        let is_dir = path.trim_matches('/').is_empty()
+                            || fs.find_entry(&format_path_8_3(&path)).is_some_and(|e| e.is_dir());
+
+                        if is_dir {
+                            return_val = crate::fs::watch::add(filename);
+                        }
+                    }
+                }
+            }
+
+            57 => {
+                return_val = match crate::fs::watch::poll(ebx) {
+                    Some(event) => {
+                        *(ecx as *mut libk::io::WatchEvent) = event;
+                        1
+                    }
+                    None => 0,
+                };
+            }
+
+            58 => {
+                crate::fs::watch::remove(ebx);
+            }
+
+            59 => {
+                let mut fname = [0u8; 256];
+                for i in 0..(core::cmp::min(edx as usize, 256)) {
+                    fname[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let filename =
+                    core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
+
+                let name_ref = *(ecx as *const libk::io::StringRef);
+                let name = core::str::from_utf8_unchecked(core::slice::from_raw_parts(
+                    name_ref.address as *const u8,
+                    core::cmp::min(name_ref.length as usize, 12),
+                ));
 
+                return_val = 1;
+                if name.is_empty() || name.contains('/') {
+                    libk::println!("[!] rename {}: Invalid name", filename);
+                } else if let Err(e) = crate::fs::vfs::check_parent(filename) {
+                    libk::println!("[!] rename {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    let new_name = crate::fs::fat16::structs::fat_name(&format_path_8_3(name));
+
+                    match fs.rename(&format_path_8_3(&path), new_name) {
+                        Ok(()) => {
+                            crate::fs::watch::notify_rename(filename, name);
+                            return_val = 0;
+                        }
+                        Err(e) => libk::println!("[!] rename {}: {}", filename, e),
+                    }
+                }
+            }
+
+            60 => {
                 let mut fname = [0u8; 256];
                 for i in 0..(core::cmp::min(edx as usize, 256)) {
                     fname[i] = *((ebx + i as u32) as *mut u8);
@@ -475,7 +765,118 @@ pub extern "C" fn syscall_handler(eax: u32, ebx: u32, ecx: u32, edx: u32) -> u32
                 let filename =
                     core::str::from_utf8_unchecked(&fname[..core::cmp::min(edx as usize, 256)]);
 
-                (*(&raw mut crate::fs::fat16::structs::FAT16)).create_dir(&format_path_8_3(filename));
+                let request = *(ecx as *const libk::io::ReadRequest);
+                let target =
+                    core::slice::from_raw_parts_mut(request.buffer as *mut u8, request.length as usize);
+
+                return_val = u32::MAX;
+                if let Err(e) = crate::fs::vfs::check(filename, Access::Read) {
+                    libk::println!("[!] {}: {}", filename, e);
+                } else if let Some((fs, path)) = crate::fs::vfs::resolve(filename) {
+                    if let Some(entry) = fs.find_entry(&format_path_8_3(&path)) {
+                        if !entry.is_dir() {
+                            return_val = fs.read_at(&entry, request.offset, target) as u32;
+                            fs.mark_accessed(&format_path_8_3(&path));
+                        }
+                    }
+                }
+            }
+
+            61 => {
+                let args_ptr = if edx != 0 {
+                    Some(core::slice::from_raw_parts(edx as *const u32, 4))
+                } else {
+                    None
+                };
+
+                let mut tasks = (*(&raw mut crate::task::TASK_MANAGER)).lock();
+                if tasks.uid() == ADMIN {
+                    return_val = tasks.add_user_task_as(ebx, args_ptr, ecx as u16);
+                }
+            }
+
+            62 => {
+                if let Some(exit) = (*(&raw mut crate::task::TASK_MANAGER)).lock().wait() {
+                    *(ebx as *mut libk::task::Exit) = exit;
+                    return_val = 1;
+                }
+            }
+
+            63 => {
+                let mut tasks = (*(&raw mut crate::task::TASK_MANAGER)).lock();
+
+                return_val = 1;
+                if tasks.uid() == ADMIN {
+                    tasks.set_reaper();
+                    return_val = 0;
+                }
+            }
+
+            64 => {
+                let mut key = [0u8; 64];
+                for i in 0..(core::cmp::min(edx as usize, 64)) {
+                    key[i] = *((ebx + i as u32) as *mut u8);
+                }
+
+                let key = core::str::from_utf8_unchecked(&key[..core::cmp::min(edx as usize, 64)]);
+                let value = if key.is_empty() {
+                    Some(crate::cmdline::line())
+                } else {
+                    crate::cmdline::get(key)
+                };
+
+                return_val = u32::MAX;
+                if let Some(value) = value {
+                    let target = core::slice::from_raw_parts_mut(ecx as *mut u8, libk::cmdline::MAX_LEN);
+                    target[..value.len()].copy_from_slice(value.as_bytes());
+                    return_val = value.len() as u32;
+                }
+            }
+
+            65 => {
+                let display = &mut *(&raw mut crate::composer::DISPLAY_SERVER);
+
+                return_val = 1;
+                if crate::task::current_uid() != ADMIN {
+                    libk::println!("[x] Video mode: only the admin can change it");
+                } else if let Err(err) = display.set_mode(ebx as u16, ecx as u16, edx as u8) {
+                    libk::println!("[x] Video mode {}x{}x{}: {}", ebx, ecx, edx, err);
+                } else {
+                    return_val = 0;
+                }
+            }
+
+            66 => {
+                return_val = 1;
+                if let Some(acpi) = crate::acpi::get() {
+                    *(ebx as *mut libk::acpi::Acpi) = *acpi;
+                    return_val = 0;
+                }
+            }
+
+            67 => {
+                return_val = 1;
+                if crate::task::current_uid() == ADMIN {
+                    crate::power::shutdown();
+                }
+            }
+
+            68 => {
+                return_val = 1;
+                if crate::task::current_uid() == ADMIN {
+                    crate::power::reboot();
+                }
+            }
+
+            69 => {
+                let data = core::slice::from_raw_parts(ebx as *const u8, edx as usize);
+                crate::serial::write(data);
+                return_val = edx;
+            }
+
+            70 => {
+                let buffer = core::slice::from_raw_parts_mut(ebx as *mut u8, edx as usize);
+                return_val = crate::serial::read(buffer) as u32;
             }
 
             100 => loop {},
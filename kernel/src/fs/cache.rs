@@ -0,0 +1,377 @@
+use crate::block::{self, BlockError};
+use alloc::vec::Vec;
+use libk::hashmap::HashMap;
+use libk::io::CacheStats;
+
+/// Default number of 512-byte sectors kept in memory (512 KiB).
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Dirty sectors are written back after this many timer ticks (~5 s at 18.2 Hz).
+const WRITEBACK_TICKS: u64 = 91;
+
+/// Longest transfer the cache makes (64 KiB), also the size of the bounce
+/// buffer `sync` merges neighbouring dirty sectors in.
+const MAX_RUN: usize = 128;
+
+/// Largest cache syscall 46 can ask for (64 MiB).
+const MAX_CAPACITY: usize = 131072;
+
+const SECTOR: usize = 512;
+
+#[derive(Copy, Clone, Debug)]
+struct Slot {
+    drive: u8,
+    lba: u64,
+    used: u64,
+    dirty: bool,
+    valid: bool,
+}
+
+const NULL_SLOT: Slot = Slot {
+    drive: 0,
+    lba: 0,
+    used: 0,
+    dirty: false,
+    valid: false,
+};
+
+pub struct BlockCache {
+    slots: Vec<Slot>,
+    index: HashMap<(u8, u64), usize>,
+    pool: u32,
+    bounce: u32,
+    clock: u64,
+    last_sync: u64,
+    hits: u64,
+    misses: u64,
+    writebacks: u64,
+}
+
+pub static mut CACHE: BlockCache = BlockCache {
+    slots: Vec::new(),
+    index: HashMap::empty(),
+    pool: 0,
+    bounce: 0,
+    clock: 0,
+    last_sync: 0,
+    hits: 0,
+    misses: 0,
+    writebacks: 0,
+};
+
+impl BlockCache {
+    pub fn init(&mut self, capacity: usize) {
+        if self.bounce == 0 {
+            self.bounce = unsafe {
+                (*(&raw mut crate::pmm::PADDR))
+                    .malloc((MAX_RUN * SECTOR) as u32)
+                    .unwrap_or(0)
+            };
+        }
+
+        self.resize(capacity);
+    }
+
+    /// Writes back everything and reallocates the pool. A capacity of 0 disables caching.
+    /// Nothing changes if a dirty sector cannot be written back, it would be lost.
+    pub fn resize(&mut self, capacity: usize) -> bool {
+        let Some(bytes) = capacity.checked_mul(SECTOR).filter(|_| capacity <= MAX_CAPACITY) else {
+            libk::println!("[x] A {} sector cache is too large", capacity);
+            return false;
+        };
+
+        if self.sync().is_err() {
+            libk::println!("[x] Cache not resized, dirty sectors could not be written back");
+            return false;
+        }
+
+        let pool = if capacity == 0 {
+            None
+        } else {
+            unsafe { (*(&raw mut crate::pmm::PADDR)).malloc(bytes as u32) }
+        };
+
+        if capacity != 0 && pool.is_none() {
+            libk::println!("[x] Not enough memory for a {} sector cache", capacity);
+            return false;
+        }
+
+        unsafe {
+            (*(&raw mut crate::pmm::PADDR)).dealloc(self.pool);
+        }
+
+        self.pool = pool.unwrap_or(0);
+        self.slots.clear();
+        self.slots.resize(capacity, NULL_SLOT);
+        self.index = HashMap::with_capacity(core::cmp::max(capacity, 16));
+
+        true
+    }
+
+    fn data(&self, slot: usize) -> *mut u8 {
+        (self.pool as usize + slot * SECTOR) as *mut u8
+    }
+
+    fn lookup(&mut self, drive: u8, lba: u64) -> Option<usize> {
+        let slot = *self.index.get(&(drive, lba))?;
+
+        self.clock += 1;
+        self.slots[slot].used = self.clock;
+
+        Some(slot)
+    }
+
+    /// Picks a free slot or evicts the least recently used one, writing it back if dirty.
+    /// A dirty one that cannot be written back stays cached and the error is returned.
+    fn claim(&mut self, drive: u8, lba: u64) -> Result<usize, BlockError> {
+        let slot = match self.slots.iter().position(|s| !s.valid) {
+            Some(free) => free,
+            None => {
+                let (victim, _) = self
+                    .slots
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, s)| s.used)
+                    .unwrap();
+
+                let old = self.slots[victim];
+                if old.dirty {
+                    report(block::write(old.lba, 1, old.drive, self.data(victim)))?;
+                    self.writebacks += 1;
+                }
+
+                self.index.remove(&(old.drive, old.lba));
+                victim
+            }
+        };
+
+        self.clock += 1;
+        self.slots[slot] = Slot {
+            drive,
+            lba,
+            used: self.clock,
+            dirty: false,
+            valid: true,
+        };
+        self.index.insert((drive, lba), slot);
+
+        Ok(slot)
+    }
+
+    /// Sectors that cannot be read are handed back zeroed, and the first error is returned.
+    pub fn read(&mut self, lba: u64, sectors: u8, drive: u8, target: *mut u8) -> Result<(), BlockError> {
+        if self.slots.is_empty() {
+            return report(block::read(lba, sectors as u32, drive, target));
+        }
+
+        let sectors = sectors as usize;
+        let mut result = Ok(());
+        let mut i = 0;
+
+        while i < sectors {
+            if let Some(slot) = self.lookup(drive, lba + i as u64) {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(self.data(slot), target.add(i * SECTOR), SECTOR);
+                }
+                self.hits += 1;
+                i += 1;
+                continue;
+            }
+
+            // fetch the whole run of missing sectors with a single transfer
+            let mut run = 1;
+            while i + run < sectors
+                && run < MAX_RUN
+                && self.index.get(&(drive, lba + (i + run) as u64)).is_none()
+            {
+                run += 1;
+            }
+
+            let dest = unsafe { target.add(i * SECTOR) };
+            self.misses += run as u64;
+
+            // failed sectors are handed back zeroed and never cached
+            if let Err(e) = report(block::read(lba + i as u64, run as u32, drive, dest)) {
+                unsafe { core::ptr::write_bytes(dest, 0, run * SECTOR) };
+                result = result.and(Err(e));
+                i += run;
+                continue;
+            }
+
+            // the data is read either way, a sector without a slot is just not cached
+            for j in 0..run {
+                if let Ok(slot) = self.claim(drive, lba + (i + j) as u64) {
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(dest.add(j * SECTOR), self.data(slot), SECTOR);
+                    }
+                }
+            }
+
+            i += run;
+        }
+
+        result
+    }
+
+    /// Sectors without a slot to go in are written through, and the first error is returned.
+    pub fn write(&mut self, lba: u64, sectors: u8, drive: u8, buffer: *const u8) -> Result<(), BlockError> {
+        if self.slots.is_empty() {
+            return report(block::write(lba, sectors as u32, drive, buffer));
+        }
+
+        let mut result = Ok(());
+
+        for i in 0..sectors as usize {
+            let sector = lba + i as u64;
+            let data = unsafe { buffer.add(i * SECTOR) };
+
+            let slot = match self.lookup(drive, sector) {
+                Some(slot) => slot,
+                None => match self.claim(drive, sector) {
+                    Ok(slot) => slot,
+                    Err(_) => {
+                        result = result.and(report(block::write(sector, 1, drive, data)));
+                        continue;
+                    }
+                },
+            };
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(data, self.data(slot), SECTOR);
+            }
+            self.slots[slot].dirty = true;
+        }
+
+        result
+    }
+
+    /// Writes every dirty sector to disk, merging neighbouring sectors into one transfer.
+    /// Sectors that fail stay dirty for the next sync, and the first error is returned.
+    pub fn sync(&mut self) -> Result<(), BlockError> {
+        let mut dirty: Vec<usize> = (0..self.slots.len())
+            .filter(|&i| self.slots[i].valid && self.slots[i].dirty)
+            .collect();
+
+        dirty.sort_by_key(|&i| (self.slots[i].drive, self.slots[i].lba));
+
+        let mut result = Ok(());
+        let mut i = 0;
+        while i < dirty.len() {
+            let first = self.slots[dirty[i]];
+            let mut run = 1;
+
+            while i + run < dirty.len()
+                && run < MAX_RUN
+                && self.bounce != 0
+                && self.slots[dirty[i + run]].drive == first.drive
+                && self.slots[dirty[i + run]].lba == first.lba + run as u64
+            {
+                run += 1;
+            }
+
+            let written = if run == 1 {
+                report(block::write(first.lba, 1, first.drive, self.data(dirty[i])))
+            } else {
+                for j in 0..run {
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(
+                            self.data(dirty[i + j]),
+                            (self.bounce as *mut u8).add(j * SECTOR),
+                            SECTOR,
+                        );
+                    }
+                }
+
+                report(block::write(first.lba, run as u32, first.drive, self.bounce as *const u8))
+            };
+
+            if written.is_ok() {
+                for j in 0..run {
+                    self.slots[dirty[i + j]].dirty = false;
+                }
+                self.writebacks += run as u64;
+            }
+
+            result = result.and(written);
+            i += run;
+        }
+
+        result
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            writebacks: self.writebacks,
+            cached: self.slots.iter().filter(|s| s.valid).count() as u32,
+            dirty: self.slots.iter().filter(|s| s.valid && s.dirty).count() as u32,
+            capacity: self.slots.len() as u32,
+        }
+    }
+}
+
+/// Logs a failed transfer and passes the result on.
+fn report(result: Result<(), BlockError>) -> Result<(), BlockError> {
+    if let Err(e) = result {
+        libk::println!("[x] Disk error: {:?}", e);
+    }
+
+    result
+}
+
+pub fn read<T>(lba: u64, sectors: u8, drive: u8, target: *mut T) -> Result<(), BlockError> {
+    unsafe { (*(&raw mut CACHE)).read(lba, sectors, drive, target as *mut u8) }
+}
+
+pub fn write<T>(lba: u64, sectors: u8, drive: u8, buffer: *const T) -> Result<(), BlockError> {
+    unsafe { (*(&raw mut CACHE)).write(lba, sectors, drive, buffer as *const u8) }
+}
+
+pub fn sync() -> Result<(), BlockError> {
+    unsafe { (*(&raw mut CACHE)).sync() }
+}
+
+/// A drive seen through the cache, so the `fat` crate sees the same data as everyone else.
+pub struct CacheDevice(pub u8);
+
+impl fat::BlockDevice for CacheDevice {
+    fn read(&self, lba: u64, buffer: &mut [u8]) -> Result<(), fat::Error> {
+        for (i, run) in buffer.chunks_mut(MAX_RUN * SECTOR).enumerate() {
+            read(lba + (i * MAX_RUN) as u64, (run.len() / SECTOR) as u8, self.0, run.as_mut_ptr())
+                .map_err(|_| fat::Error::Io)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buffer: &[u8]) -> Result<(), fat::Error> {
+        for (i, run) in buffer.chunks(MAX_RUN * SECTOR).enumerate() {
+            write(lba + (i * MAX_RUN) as u64, (run.len() / SECTOR) as u8, self.0, run.as_ptr())
+                .map_err(|_| fat::Error::Io)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Kernel task that periodically flushes dirty sectors.
+pub fn writeback() {
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+
+            let ticks = crate::task::TICKS;
+            if ticks - CACHE.last_sync >= WRITEBACK_TICKS {
+                libk::disable_interrupts();
+                {
+                    let _kernel = crate::smp::lock_kernel();
+                    // failed sectors stay dirty and are tried again next time
+                    let _ = (*(&raw mut CACHE)).sync();
+                    CACHE.last_sync = ticks;
+                }
+                libk::enable_interrupts();
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::fs::cache::{self, CacheDevice};
+use crate::fs::partition;
+use fat::Report;
+
+/// Checks a partition, printing every problem found.
+pub fn run(disk: u8, index: u8, repair: bool) -> Option<Report> {
+    let (drive, p) = partition::find(disk, index)?;
+
+    match fat::check(&CacheDevice(drive), p.start, repair) {
+        Ok(report) => {
+            for message in report.messages.iter() {
+                libk::println!("[!] fsck: {}", message);
+            }
+
+            libk::println!(
+                "[+] fsck disk {} partition {}: {} files, {} directories, {} problems{}",
+                disk,
+                index,
+                report.files,
+                report.directories,
+                report.problems(),
+                if report.repaired { ", repaired" } else { "" }
+            );
+
+            if report.repaired && cache::sync().is_err() {
+                libk::println!("[x] fsck disk {} partition {}: repairs not written back", disk, index);
+            }
+
+            Some(report)
+        }
+        Err(e) => {
+            libk::println!("[x] fsck disk {} partition {}: {}", disk, index, e);
+            None
+        }
+    }
+}
+
+/// Repairs the volume if it was not cleanly unmounted, then marks it as in use.
+pub fn on_mount(drive: u8, lba: u64) {
+    let disk = &CacheDevice(drive);
+
+    if fat::is_clean(disk, lba) == Ok(false) {
+        libk::println!("[!] Volume at LBA {} was not cleanly unmounted, checking it", lba);
+
+        match fat::check(disk, lba, true) {
+            Ok(report) => {
+                for message in report.messages.iter() {
+                    libk::println!("[!] fsck: {}", message);
+                }
+            }
+            Err(e) => libk::println!("[x] fsck: {}", e),
+        }
+    }
+
+    if fat::set_clean(disk, lba, false).is_err() || cache::sync().is_err() {
+        libk::println!("[x] Volume at LBA {} could not be marked in use", lba);
+    }
+}
+
+/// Marks the volume as cleanly unmounted, so the next mount skips the check.
+pub fn on_unmount(drive: u8, lba: u64) {
+    if let Err(e) = fat::set_clean(&CacheDevice(drive), lba, true) {
+        libk::println!("[x] Volume at LBA {}: {}", lba, e);
+    }
+}
@@ -1,5 +1,30 @@
+pub mod cache;
+pub mod check;
 pub mod fat16;
+pub mod partition;
+pub mod tmpfs;
+pub mod vfs;
+pub mod watch;
 
-pub trait  Fs {
-    fn check_disk(disk: u8) -> bool;
-}
\ No newline at end of file
+use fat16::structs::Entry;
+use libk::perm::Mode;
+
+/// Operations the syscalls perform on a mounted filesystem. Paths are relative to the
+/// mount point, with the last component already in 8.3 form.
+pub trait Fs {
+    fn find_entry(&self, path: &str) -> Option<Entry>;
+    fn read(&self, entry: &Entry, target: *mut u8);
+    /// Reads part of a file from `offset`, returning how many bytes were read.
+    fn read_at(&self, entry: &Entry, offset: u32, target: &mut [u8]) -> usize;
+    fn count_entries_in_dir(&self, path: &str) -> u32;
+    fn get_entries_by_id(&self, path: &str, index: u32) -> Option<Entry>;
+    fn create_file(&mut self, path: &str) -> Result<(), &'static str>;
+    fn create_dir(&mut self, path: &str) -> Result<(), &'static str>;
+    fn overwrite_file(&mut self, path: &str, data: &[u8]) -> Result<(), &'static str>;
+    fn append_to_file(&mut self, path: &str, data: &[u8]) -> Result<(), &'static str>;
+    fn remove(&mut self, path: &str) -> Result<(), &'static str>;
+    /// Gives an entry a new name in the directory it is already in.
+    fn rename(&mut self, path: &str, name: [u8; 11]) -> Result<(), &'static str>;
+    fn mark_accessed(&mut self, _path: &str) {}
+    fn set_permissions(&mut self, path: &str, owner: u16, mode: Mode) -> Result<(), &'static str>;
+}
@@ -0,0 +1,416 @@
+use crate::block;
+use alloc::vec;
+use alloc::vec::Vec;
+use libk::hash::crc32;
+use libk::io::{FsKind, PartitionInfo};
+
+pub static mut DISKS: Vec<Disk> = Vec::new();
+
+const MBR_SIGNATURE: u16 = 0xAA55;
+const MBR_EMPTY: u8 = 0x00;
+const MBR_EXTENDED_CHS: u8 = 0x05;
+const MBR_EXTENDED_LBA: u8 = 0x0F;
+const MBR_EXTENDED_LINUX: u8 = 0x85;
+const MBR_PROTECTIVE: u8 = 0xEE;
+const MBR_EFI: u8 = 0xEF;
+
+const GPT_SIGNATURE: [u8; 8] = *b"EFI PART";
+const GPT_MAX_ENTRIES: u32 = 128;
+/// Size of a revision 1.0 header, the part its CRC covers.
+const GPT_HEADER_SIZE: u32 = 92;
+/// Largest entry array read, the usual one is 16 KiB.
+const GPT_MAX_ARRAY: u64 = 1024 * 1024;
+
+/* GUIDs as they are stored on disk (mixed endian) */
+const GUID_UNUSED: [u8; 16] = [0; 16];
+const GUID_EFI_SYSTEM: [u8; 16] = [
+    0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
+];
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct MbrEntry {
+    status: u8,
+    chs_first: [u8; 3],
+    kind: u8,
+    chs_last: [u8; 3],
+    lba_first: u32,
+    sectors: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct Mbr {
+    bootstrap: [u8; 440],
+    disk_id: u32,
+    reserved: u16,
+    entries: [MbrEntry; 4],
+    signature: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct GptHeader {
+    signature: [u8; 8],
+    revision: u32,
+    header_size: u32,
+    header_crc: u32,
+    reserved: u32,
+    current_lba: u64,
+    backup_lba: u64,
+    first_usable_lba: u64,
+    last_usable_lba: u64,
+    disk_guid: [u8; 16],
+    entries_lba: u64,
+    entries_count: u32,
+    entry_size: u32,
+    entries_crc: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct GptEntry {
+    type_guid: [u8; 16],
+    unique_guid: [u8; 16],
+    lba_first: u64,
+    lba_last: u64,
+    attributes: u64,
+    name: [u16; 36],
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Partition {
+    pub index: u8,
+    pub start: u64,
+    pub sectors: u64,
+    pub kind: FsKind,
+    pub bootable: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Disk {
+    pub id: u8,
+    pub drive: u8,
+    pub sectors: u64,
+    pub partitions: Vec<Partition>,
+}
+
+impl Disk {
+    pub fn name(&self) -> [u8; 3] {
+        [b'H', b'D', b'A' + self.id]
+    }
+}
+
+pub fn init() {
+    for drive in 0..block::count() {
+        let disk = scan(drive, drive);
+
+        libk::println!(
+            "[+] Disk {} ({} partitions)",
+            core::str::from_utf8(&disk.name()).unwrap_or("?"),
+            disk.partitions.len()
+        );
+
+        unsafe {
+            (*(&raw mut DISKS)).push(disk);
+        }
+    }
+}
+
+pub fn scan(id: u8, drive: u8) -> Disk {
+    let mut disk = Disk {
+        id,
+        drive,
+        sectors: block::sectors(drive).unwrap_or(0),
+        partitions: Vec::new(),
+    };
+
+    let mut sector = [0u8; 512];
+    if block::read(0, 1, drive, sector.as_mut_ptr()).is_err() {
+        return disk;
+    }
+    let mbr = unsafe { *(sector.as_ptr() as *const Mbr) };
+
+    let kind = probe(&sector);
+    if kind != FsKind::Unknown {
+        // superfloppy: the whole disk is a single filesystem
+        disk.partitions.push(Partition {
+            index: 1,
+            start: 0,
+            sectors: disk.sectors,
+            kind,
+            bootable: false,
+        });
+        return disk;
+    }
+
+    if mbr.signature != MBR_SIGNATURE {
+        return disk;
+    }
+
+    let entries = mbr.entries;
+
+    if entries.iter().any(|e| e.kind == MBR_PROTECTIVE) {
+        parse_gpt(&mut disk);
+    } else {
+        parse_mbr(&mut disk, &entries);
+    }
+
+    disk
+}
+
+fn parse_mbr(disk: &mut Disk, entries: &[MbrEntry; 4]) {
+    for entry in entries.iter() {
+        match entry.kind {
+            MBR_EMPTY => {}
+
+            MBR_EXTENDED_CHS | MBR_EXTENDED_LBA | MBR_EXTENDED_LINUX => {
+                parse_ebr(disk, entry.lba_first as u64);
+            }
+
+            kind => {
+                add_partition(
+                    disk,
+                    entry.lba_first as u64,
+                    entry.sectors as u64,
+                    entry.status & 0x80 != 0,
+                    kind == MBR_EFI,
+                );
+            }
+        }
+    }
+}
+
+fn parse_ebr(disk: &mut Disk, extended_start: u64) {
+    let mut ebr_lba = extended_start;
+    let mut sector = [0u8; 512];
+
+    // logical partitions are a linked list of EBRs; cap the walk in case of loops
+    for _ in 0..64 {
+        let ok = block::read(ebr_lba, 1, disk.drive, sector.as_mut_ptr()).is_ok();
+        let ebr = unsafe { *(sector.as_ptr() as *const Mbr) };
+
+        if !ok || ebr.signature != MBR_SIGNATURE {
+            return;
+        }
+
+        let entries = ebr.entries;
+
+        if entries[0].kind != MBR_EMPTY {
+            add_partition(
+                disk,
+                ebr_lba + entries[0].lba_first as u64,
+                entries[0].sectors as u64,
+                entries[0].status & 0x80 != 0,
+                false,
+            );
+        }
+
+        if entries[1].kind == MBR_EMPTY || entries[1].lba_first == 0 {
+            return;
+        }
+
+        ebr_lba = extended_start + entries[1].lba_first as u64;
+    }
+}
+
+/// Reads the primary GPT, or the backup at the end of the disk if the
+/// primary fails its checksums.
+fn parse_gpt(disk: &mut Disk) {
+    let gpt = read_gpt(disk.drive, 1).or_else(|| {
+        libk::println!("[x] Disk {}: primary GPT is damaged, trying the backup", disk.id);
+        disk.sectors.checked_sub(1).and_then(|lba| read_gpt(disk.drive, lba))
+    });
+
+    let Some((header, array)) = gpt else {
+        libk::println!("[x] Disk {}: no valid GPT", disk.id);
+        return;
+    };
+
+    if disk.sectors == 0 {
+        disk.sectors = core::cmp::max(header.current_lba, header.backup_lba) + 1;
+    }
+
+    let entry_size = header.entry_size as usize;
+    let count = core::cmp::min(header.entries_count, GPT_MAX_ENTRIES) as usize;
+
+    for i in 0..count {
+        let entry = unsafe { *(array.as_ptr().add(i * entry_size) as *const GptEntry) };
+        let (first, last) = (entry.lba_first, entry.lba_last);
+
+        if entry.type_guid == GUID_UNUSED {
+            continue;
+        }
+
+        if last < first {
+            libk::println!("[x] Disk {}: GPT entry {} ends before it starts", disk.id, i);
+            continue;
+        }
+
+        add_partition(
+            disk,
+            first,
+            last - first + 1,
+            entry.attributes & 0x04 != 0,
+            entry.type_guid == GUID_EFI_SYSTEM,
+        );
+    }
+}
+
+/// The GPT header at `lba` and its entry array, if both match their CRC32.
+fn read_gpt(drive: u8, lba: u64) -> Option<(GptHeader, Vec<u8>)> {
+    let mut sector = [0u8; 512];
+    block::read(lba, 1, drive, sector.as_mut_ptr()).ok()?;
+    let header = unsafe { *(sector.as_ptr() as *const GptHeader) };
+
+    let size = header.header_size;
+    if header.signature != GPT_SIGNATURE || !(GPT_HEADER_SIZE..=512).contains(&size) {
+        return None;
+    }
+
+    // the header CRC is taken with its own field zeroed
+    sector[16..20].fill(0);
+    if crc32(&sector[..size as usize]) != header.header_crc {
+        return None;
+    }
+
+    let entry_size = header.entry_size as u64;
+    let bytes = header.entries_count as u64 * entry_size;
+    if entry_size < 128 || bytes > GPT_MAX_ARRAY {
+        return None;
+    }
+
+    let mut array = vec![0u8; bytes.div_ceil(512) as usize * 512];
+    for (i, chunk) in array.chunks_mut(512).enumerate() {
+        block::read(header.entries_lba + i as u64, 1, drive, chunk.as_mut_ptr()).ok()?;
+    }
+
+    if crc32(&array[..bytes as usize]) != header.entries_crc {
+        return None;
+    }
+
+    Some((header, array))
+}
+
+fn add_partition(disk: &mut Disk, start: u64, sectors: u64, bootable: bool, efi: bool) {
+    let mut sector = [0u8; 512];
+    let _ = block::read(start, 1, disk.drive, sector.as_mut_ptr());
+
+    let kind = match probe(&sector) {
+        FsKind::Unknown if efi => FsKind::Efi,
+        kind => kind,
+    };
+
+    disk.partitions.push(Partition {
+        index: disk.partitions.len() as u8 + 1,
+        start,
+        sectors,
+        kind,
+        bootable,
+    });
+}
+
+/// Looks at the first sector of a volume and guesses what lives there.
+pub fn probe(sector: &[u8; 512]) -> FsKind {
+    if &sector[3..11] == b"NTFS    " {
+        return FsKind::Ntfs;
+    }
+
+    if &sector[3..11] == b"EXFAT   " {
+        return FsKind::ExFat;
+    }
+
+    if sector[510] != 0x55 || sector[511] != 0xAA || (sector[0] != 0xEB && sector[0] != 0xE9) {
+        return FsKind::Unknown;
+    }
+
+    let bytes_per_sector = u16::from_le_bytes([sector[11], sector[12]]) as u32;
+    let sectors_per_cluster = sector[13] as u32;
+    let reserved = u16::from_le_bytes([sector[14], sector[15]]) as u32;
+    let fat_count = sector[16] as u32;
+    let root_entries = u16::from_le_bytes([sector[17], sector[18]]) as u32;
+    let small_total = u16::from_le_bytes([sector[19], sector[20]]) as u32;
+    let small_fat = u16::from_le_bytes([sector[22], sector[23]]) as u32;
+    let large_total = u32::from_le_bytes([sector[32], sector[33], sector[34], sector[35]]);
+    let large_fat = u32::from_le_bytes([sector[36], sector[37], sector[38], sector[39]]);
+
+    if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+        || !sectors_per_cluster.is_power_of_two()
+        || !(1..=2).contains(&fat_count)
+    {
+        return FsKind::Unknown;
+    }
+
+    let total = if small_total != 0 { small_total } else { large_total };
+    let fat_size = if small_fat != 0 { small_fat } else { large_fat };
+    let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+    let data = total.saturating_sub(reserved + fat_count * fat_size + root_sectors);
+    let clusters = data / sectors_per_cluster;
+
+    if clusters < 4085 {
+        FsKind::Fat12
+    } else if clusters < 65525 {
+        FsKind::Fat16
+    } else {
+        FsKind::Fat32
+    }
+}
+
+pub fn find(disk: u8, index: u8) -> Option<(u8, Partition)> {
+    unsafe {
+        for d in (*(&raw mut DISKS)).iter() {
+            if d.id != disk {
+                continue;
+            }
+
+            for p in d.partitions.iter() {
+                if p.index == index {
+                    return Some((d.drive, *p));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Fills `target` with one record per disk (index 0) followed by its partitions
+/// and returns how many records exist in total.
+pub fn list(target: &mut [PartitionInfo]) -> u32 {
+    let mut count = 0;
+
+    let mut push = |info: PartitionInfo| {
+        if count < target.len() {
+            target[count] = info;
+        }
+        count += 1;
+    };
+
+    unsafe {
+        for d in (*(&raw mut DISKS)).iter() {
+            push(PartitionInfo {
+                disk: d.id,
+                index: 0,
+                kind: FsKind::Unknown,
+                bootable: false,
+                start: 0,
+                sectors: d.sectors,
+                mount: [0; 8],
+            });
+
+            for p in d.partitions.iter() {
+                push(PartitionInfo {
+                    disk: d.id,
+                    index: p.index,
+                    kind: p.kind,
+                    bootable: p.bootable,
+                    start: p.start,
+                    sectors: p.sectors,
+                    mount: crate::fs::vfs::mount_point(d.id, p.index),
+                });
+            }
+        }
+    }
+
+    count as u32
+}
@@ -0,0 +1,372 @@
+use crate::fs::Fs;
+use crate::fs::fat16::structs::{Entry, fat_name};
+use alloc::vec::Vec;
+use libk::perm::{ADMIN, Mode};
+use libk::time::DateTime;
+
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+
+const ROOT: u32 = 0;
+
+/// Data is kept in whole pages handed out by the physical allocator.
+pub const PAGE: u32 = 4096;
+
+pub const DEFAULT_LIMIT: u32 = 16 * 1024 * 1024;
+pub const DEFAULT_NODES: usize = 4096;
+
+struct Node {
+    name: [u8; 11],
+    dir: bool,
+    parent: u32,
+    children: Vec<u32>,
+    data: u32,
+    size: u32,
+    capacity: u32,
+    created: DateTime,
+    modified: DateTime,
+    accessed: DateTime,
+    owner: u16,
+    mode: Mode,
+}
+
+/// A RAM backed filesystem. Entries it hands out carry the node id in place of
+/// the first cluster, so `read` can find the file again.
+pub struct TmpFs {
+    nodes: Vec<Option<Node>>,
+    used: u32,
+    limit: u32,
+    max_nodes: usize,
+}
+
+impl TmpFs {
+    pub fn new(limit: u32, max_nodes: usize) -> TmpFs {
+        let now = crate::rtc::now();
+        let mut nodes = Vec::new();
+        nodes.push(Some(Node {
+            name: [32; 11],
+            dir: true,
+            parent: ROOT,
+            children: Vec::new(),
+            data: 0,
+            size: 0,
+            capacity: 0,
+            created: now,
+            modified: now,
+            accessed: now,
+            owner: ADMIN,
+            mode: Mode::from_octal(0o77),
+        }));
+
+        TmpFs {
+            nodes,
+            used: 0,
+            limit,
+            max_nodes,
+        }
+    }
+
+    fn node(&self, id: u32) -> Option<&Node> {
+        self.nodes.get(id as usize)?.as_ref()
+    }
+
+    fn node_mut(&mut self, id: u32) -> Option<&mut Node> {
+        self.nodes.get_mut(id as usize)?.as_mut()
+    }
+
+    fn child(&self, dir: u32, name: &[u8; 11]) -> Option<u32> {
+        let node = self.node(dir)?;
+
+        node.children
+            .iter()
+            .copied()
+            .find(|c| self.node(*c).is_some_and(|n| n.name == *name))
+    }
+
+    fn lookup(&self, path: &str) -> Option<u32> {
+        let mut current = ROOT;
+
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            if !self.node(current)?.dir {
+                return None;
+            }
+
+            current = self.child(current, &fat_name(part))?;
+        }
+
+        Some(current)
+    }
+
+    /// Parent directory id and the name of the last component.
+    fn split(&self, path: &str) -> Option<(u32, [u8; 11])> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = match path.rfind('/') {
+            Some(pos) => (&path[..pos], &path[pos + 1..]),
+            None => ("", path),
+        };
+
+        if name.is_empty() {
+            return None;
+        }
+
+        let parent = self.lookup(parent)?;
+        if !self.node(parent)?.dir {
+            return None;
+        }
+
+        Some((parent, fat_name(name)))
+    }
+
+    fn entry(&self, id: u32) -> Option<Entry> {
+        let node = self.node(id)?;
+        let attributes = if node.dir { ATTR_DIRECTORY } else { ATTR_ARCHIVE };
+
+        let mut entry = Entry::new(node.name, attributes, id, node.size);
+        entry.stamp_created(crate::rtc::fat_timestamp(&node.created));
+        entry.stamp_modified(crate::rtc::fat_timestamp(&node.modified));
+        entry.stamp_accessed(crate::rtc::fat_timestamp(&node.accessed));
+        entry.set_permissions(node.owner, node.mode.0);
+
+        Some(entry)
+    }
+
+    fn create(&mut self, path: &str, dir: bool) -> Result<u32, &'static str> {
+        let (parent, name) = self.split(path).ok_or("No such directory")?;
+
+        if self.child(parent, &name).is_some() {
+            return Err("File exists");
+        }
+
+        let live = self.nodes.iter().filter(|n| n.is_some()).count();
+        if live >= self.max_nodes {
+            return Err("Too many files");
+        }
+
+        let now = crate::rtc::now();
+        let node = Node {
+            name,
+            dir,
+            parent,
+            children: Vec::new(),
+            data: 0,
+            size: 0,
+            capacity: 0,
+            created: now,
+            modified: now,
+            accessed: now,
+            owner: crate::task::current_uid(),
+            mode: Mode::DEFAULT,
+        };
+
+        let id = match self.nodes.iter().position(|n| n.is_none()) {
+            Some(free) => {
+                self.nodes[free] = Some(node);
+                free as u32
+            }
+            None => {
+                self.nodes.push(Some(node));
+                (self.nodes.len() - 1) as u32
+            }
+        };
+
+        if let Some(p) = self.node_mut(parent) {
+            p.children.push(id);
+        }
+
+        Ok(id)
+    }
+
+    /// Makes room for `size` bytes, moving the data to a bigger allocation if needed.
+    fn reserve(&mut self, id: u32, size: u32) -> Result<(), &'static str> {
+        let (data, old_size, capacity) = {
+            let node = self.node(id).ok_or("No such file")?;
+            (node.data, node.size, node.capacity)
+        };
+
+        if size <= capacity {
+            return Ok(());
+        }
+
+        let new_capacity = core::cmp::max(size.div_ceil(PAGE) * PAGE, capacity * 2);
+
+        if self.used - capacity + new_capacity > self.limit {
+            return Err("No space left on tmpfs");
+        }
+
+        let new_data = unsafe { (*(&raw mut crate::pmm::PADDR)).malloc(new_capacity) }
+            .ok_or("Out of memory")?;
+
+        unsafe {
+            if data != 0 {
+                core::ptr::copy_nonoverlapping(data as *const u8, new_data as *mut u8, old_size as usize);
+                (*(&raw mut crate::pmm::PADDR)).dealloc(data);
+            }
+        }
+
+        self.used = self.used - capacity + new_capacity;
+
+        let node = self.node_mut(id).ok_or("No such file")?;
+        node.data = new_data;
+        node.capacity = new_capacity;
+
+        Ok(())
+    }
+
+    fn write_at(&mut self, path: &str, data: &[u8], append: bool) -> Result<(), &'static str> {
+        let id = self.lookup(path).ok_or("No such file")?;
+
+        if self.node(id).ok_or("No such file")?.dir {
+            return Err("Is a directory");
+        }
+
+        let offset = if append { self.node(id).map_or(0, |n| n.size) } else { 0 };
+        let size = offset
+            .checked_add(data.len() as u32)
+            .ok_or("File too large")?;
+
+        self.reserve(id, size)?;
+
+        let node = self.node_mut(id).ok_or("No such file")?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                (node.data + offset) as *mut u8,
+                data.len(),
+            );
+        }
+        node.size = size;
+        node.modified = crate::rtc::now();
+        node.accessed = node.modified;
+
+        Ok(())
+    }
+}
+
+impl Fs for TmpFs {
+    fn find_entry(&self, path: &str) -> Option<Entry> {
+        self.entry(self.lookup(path)?)
+    }
+
+    fn read(&self, entry: &Entry, target: *mut u8) {
+        if let Some(node) = self.node(entry.cluster()) {
+            if !node.dir && node.data != 0 {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(node.data as *const u8, target, node.size as usize);
+                }
+            }
+        }
+    }
+
+    fn read_at(&self, entry: &Entry, offset: u32, target: &mut [u8]) -> usize {
+        let Some(node) = self.node(entry.cluster()) else {
+            return 0;
+        };
+
+        if node.dir || node.data == 0 || offset >= node.size {
+            return 0;
+        }
+
+        let count = core::cmp::min((node.size - offset) as usize, target.len());
+        unsafe {
+            core::ptr::copy_nonoverlapping((node.data + offset) as *const u8, target.as_mut_ptr(), count);
+        }
+
+        count
+    }
+
+    fn count_entries_in_dir(&self, path: &str) -> u32 {
+        self.lookup(path)
+            .and_then(|id| self.node(id))
+            .filter(|n| n.dir)
+            .map_or(0, |n| n.children.len() as u32)
+    }
+
+    fn get_entries_by_id(&self, path: &str, index: u32) -> Option<Entry> {
+        let dir = self.node(self.lookup(path)?)?;
+
+        if !dir.dir {
+            return None;
+        }
+
+        self.entry(*dir.children.get(index as usize)?)
+    }
+
+    fn create_file(&mut self, path: &str) -> Result<(), &'static str> {
+        self.create(path, false).map(|_| ())
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<(), &'static str> {
+        self.create(path, true).map(|_| ())
+    }
+
+    fn overwrite_file(&mut self, path: &str, data: &[u8]) -> Result<(), &'static str> {
+        self.write_at(path, data, false)
+    }
+
+    fn append_to_file(&mut self, path: &str, data: &[u8]) -> Result<(), &'static str> {
+        self.write_at(path, data, true)
+    }
+
+    fn set_permissions(&mut self, path: &str, owner: u16, mode: Mode) -> Result<(), &'static str> {
+        let id = self.lookup(path).ok_or("No such file or directory")?;
+        let node = self.node_mut(id).ok_or("No such file or directory")?;
+
+        node.owner = owner;
+        node.mode = mode;
+
+        Ok(())
+    }
+
+    fn mark_accessed(&mut self, path: &str) {
+        if let Some(id) = self.lookup(path) {
+            if let Some(node) = self.node_mut(id) {
+                node.accessed = crate::rtc::now();
+            }
+        }
+    }
+
+    fn rename(&mut self, path: &str, name: [u8; 11]) -> Result<(), &'static str> {
+        let id = self.lookup(path).ok_or("No such file or directory")?;
+
+        if id == ROOT {
+            return Err("Cannot rename the mount point");
+        }
+
+        let parent = self.node(id).ok_or("No such file or directory")?.parent;
+        if self.child(parent, &name).is_some() {
+            return Err("File exists");
+        }
+
+        let node = self.node_mut(id).ok_or("No such file or directory")?;
+        node.name = name;
+        node.modified = crate::rtc::now();
+
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), &'static str> {
+        let id = self.lookup(path).ok_or("No such file or directory")?;
+
+        if id == ROOT {
+            return Err("Cannot remove the mount point");
+        }
+
+        let node = self.nodes[id as usize].take().ok_or("No such file or directory")?;
+
+        if node.dir && !node.children.is_empty() {
+            self.nodes[id as usize] = Some(node);
+            return Err("Directory not empty");
+        }
+
+        if node.data != 0 {
+            unsafe { (*(&raw mut crate::pmm::PADDR)).dealloc(node.data) };
+            self.used -= node.capacity;
+        }
+
+        if let Some(parent) = self.node_mut(node.parent) {
+            parent.children.retain(|c| *c != id);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use crate::fs::Fs;
+use crate::fs::check;
+use crate::fs::fat16::structs::Fat16;
+use crate::fs::partition;
+use crate::fs::tmpfs::{self, TmpFs};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use libk::io::FsKind;
+use libk::perm::{ADMIN, Access, Mode};
+
+pub struct Mount {
+    pub point: String,
+    pub disk: u8,
+    pub partition: u8,
+    pub fs: Box<dyn Fs>,
+}
+
+/// Disk id used by mounts that are not backed by a block device.
+pub const NO_DISK: u8 = 0xFF;
+
+/// Temporary files live in RAM unless something else is mounted here.
+pub const TEMP_DIR: &str = "/USER/TEMPS";
+
+pub static mut MOUNTS: Vec<Mount> = Vec::new();
+
+pub fn init() {
+    unsafe {
+        let disks = (*(&raw mut partition::DISKS)).clone();
+
+        let root = disks.iter().find(|d| d.id == 0).and_then(|d| {
+            d.partitions
+                .iter()
+                .filter(|p| p.kind == FsKind::Fat16)
+                .max_by_key(|p| p.bootable)
+        });
+
+        if let Some(p) = root {
+            mount("/", 0, p.index);
+        } else {
+            libk::println!("[x] No FAT16 partition found on the boot disk");
+        }
+
+        for d in disks.iter() {
+            for p in d.partitions.iter() {
+                if p.kind != FsKind::Fat16 || is_mounted(d.id, p.index) {
+                    continue;
+                }
+
+                let name = d.name();
+                let point = format!(
+                    "/{}{}",
+                    core::str::from_utf8_unchecked(&name),
+                    p.index
+                );
+                mount(&point, d.id, p.index);
+            }
+        }
+    }
+
+    mount_tmpfs(TEMP_DIR, tmpfs::DEFAULT_LIMIT);
+}
+
+pub fn mount(point: &str, disk: u8, index: u8) -> bool {
+    let Some((drive, p)) = partition::find(disk, index) else {
+        return false;
+    };
+
+    if p.kind != FsKind::Fat16 {
+        return false;
+    }
+
+    check::on_mount(drive, p.start);
+
+    let fs = match Fat16::open(drive, p.start) {
+        Ok(fs) => fs,
+        Err(e) => {
+            libk::println!("[x] Disk {} partition {}: {}", disk, index, e);
+            return false;
+        }
+    };
+
+    libk::println!("[+] Mounted disk {} partition {} at {}", disk, index, point);
+
+    mount_fs(point, disk, index, Box::new(fs));
+
+    true
+}
+
+/// Mounts an empty RAM filesystem that can hold up to `limit` bytes of file data.
+pub fn mount_tmpfs(point: &str, limit: u32) -> bool {
+    let point = point.trim_end_matches('/');
+
+    unsafe {
+        if (*(&raw mut MOUNTS)).iter().any(|m| m.point.eq_ignore_ascii_case(point)) {
+            return false;
+        }
+    }
+
+    mount_fs(point, NO_DISK, 0, Box::new(TmpFs::new(limit, tmpfs::DEFAULT_NODES)));
+
+    libk::println!("[+] Mounted tmpfs ({} KiB) at {}", limit / 1024, point);
+
+    true
+}
+
+pub fn mount_fs(point: &str, disk: u8, partition: u8, fs: Box<dyn Fs>) {
+    unsafe {
+        (*(&raw mut MOUNTS)).push(Mount {
+            point: String::from(point.trim_end_matches('/')),
+            disk,
+            partition,
+            fs,
+        });
+    }
+}
+
+/// Unmounts everything before powering off, leaving the FAT volumes marked
+/// clean and the cache written back.
+pub fn unmount_all() {
+    unsafe {
+        for m in (*(&raw mut MOUNTS)).drain(..) {
+            if m.disk == NO_DISK {
+                continue;
+            }
+
+            if let Some((drive, p)) = partition::find(m.disk, m.partition) {
+                check::on_unmount(drive, p.start);
+            }
+        }
+    }
+
+    if crate::fs::cache::sync().is_err() {
+        libk::println!("[x] Some cached sectors could not be written back");
+    }
+}
+
+pub fn is_mounted(disk: u8, index: u8) -> bool {
+    unsafe {
+        (*(&raw mut MOUNTS))
+            .iter()
+            .any(|m| m.disk == disk && m.partition == index)
+    }
+}
+
+/// Name of the mount point of a partition, padded with zeroes, or all zeroes if unmounted.
+pub fn mount_point(disk: u8, index: u8) -> [u8; 8] {
+    let mut name = [0u8; 8];
+
+    unsafe {
+        for m in (*(&raw mut MOUNTS)).iter() {
+            if m.disk == disk && m.partition == index {
+                let point = if m.point.is_empty() { "/" } else { &m.point };
+                let len = core::cmp::min(point.len(), name.len());
+                name[..len].copy_from_slice(&point.as_bytes()[..len]);
+            }
+        }
+    }
+
+    name
+}
+
+/// Finds the filesystem that owns `path` and returns it together with the path
+/// relative to its mount point.
+pub fn resolve(path: &str) -> Option<(&'static mut dyn Fs, String)> {
+    let relative = path.trim_start_matches('/');
+
+    unsafe {
+        let m = &mut (*(&raw mut MOUNTS))[find(path)?];
+
+        if m.point.is_empty() {
+            return Some((m.fs.as_mut(), String::from(path)));
+        }
+
+        let point_len = m.point.trim_start_matches('/').len();
+        Some((m.fs.as_mut(), format!("/{}", relative[point_len..].trim_start_matches('/'))))
+    }
+}
+
+/// Disk and partition of the filesystem holding `path`, if it lives on a disk.
+pub fn device_of(path: &str) -> Option<(u8, u8)> {
+    unsafe {
+        let m = &(*(&raw const MOUNTS))[find(path)?];
+
+        if m.disk == NO_DISK {
+            return None;
+        }
+
+        Some((m.disk, m.partition))
+    }
+}
+
+/// Index of the mount with the longest mount point that contains `path`.
+fn find(path: &str) -> Option<usize> {
+    let relative = path.trim_start_matches('/');
+    let mut best: Option<usize> = None;
+
+    unsafe {
+        let mounts = &(*(&raw const MOUNTS));
+
+        for (i, m) in mounts.iter().enumerate() {
+            let point = m.point.trim_start_matches('/');
+
+            let matches = point.is_empty()
+                || (relative.len() >= point.len()
+                    && relative.as_bytes()[..point.len()].eq_ignore_ascii_case(point.as_bytes())
+                    && (relative.len() == point.len()
+                        || relative.as_bytes()[point.len()] == b'/'));
+
+            if matches && best.is_none_or(|b| mounts[b].point.len() < m.point.len()) {
+                best = Some(i);
+            }
+        }
+    }
+
+    best
+}
+
+pub const DENIED: &str = "Permission denied";
+
+/// Everything under `/SYS` of the root filesystem is writable by the admin only,
+/// whatever its entry says. The directory is named the way FAT looks it up, so
+/// `/sys` or `/SYS     ` are the same place.
+pub fn is_system(path: &str) -> bool {
+    let Some(m) = find(path) else {
+        return false;
+    };
+
+    if unsafe { !(*(&raw const MOUNTS))[m].point.is_empty() } {
+        return false;
+    }
+
+    let first = path.trim_start_matches('/').split('/').next().unwrap_or("");
+    fat::short_name(first).is_ok_and(|name| &name == b"SYS        ")
+}
+
+fn parent(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(pos) => &path[..pos],
+    }
+}
+
+/// Checks that the current task may access an existing file or directory.
+pub fn check(path: &str, access: Access) -> Result<(), &'static str> {
+    let uid = crate::task::current_uid();
+
+    if uid == ADMIN {
+        return Ok(());
+    }
+
+    if access == Access::Write && is_system(path) {
+        return Err(DENIED);
+    }
+
+    let entry = resolve(path)
+        .and_then(|(fs, relative)| fs.find_entry(&crate::exceptions::format_path_8_3(&relative)));
+
+    match entry {
+        Some(e) if !Mode(e.mode()).allows(e.owner() == uid, access) => Err(DENIED),
+        _ => Ok(()),
+    }
+}
+
+/// Creating or removing a name needs write access to the directory holding it.
+pub fn check_parent(path: &str) -> Result<(), &'static str> {
+    if is_system(path) && crate::task::current_uid() != ADMIN {
+        return Err(DENIED);
+    }
+
+    check(parent(path), Access::Write)
+}
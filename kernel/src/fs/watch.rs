@@ -0,0 +1,147 @@
+use crate::fs::fat16::structs::fat_name;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use libk::io::{WatchEvent, WatchKind};
+
+/// Events a watch holds before it collapses them into a single overflow.
+const QUEUE_LIMIT: usize = 64;
+
+struct Watch {
+    id: u32,
+    /// Task slot of the owner, None once the task has been reaped.
+    task: Option<usize>,
+    dir: String,
+    queue: VecDeque<WatchEvent>,
+}
+
+static mut WATCHES: Vec<Watch> = Vec::new();
+static mut NEXT_ID: u32 = 1;
+
+/// Compares two directory paths component by component, ignoring case and stray slashes.
+fn same_dir(a: &str, b: &str) -> bool {
+    let mut a = a.split('/').filter(|p| !p.is_empty());
+    let mut b = b.split('/').filter(|p| !p.is_empty());
+
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(x), Some(y)) if x.eq_ignore_ascii_case(y) => continue,
+            _ => return false,
+        }
+    }
+}
+
+/// Directory holding `path` and the 8.3 name of its last component.
+fn split(path: &str) -> (&str, [u8; 11]) {
+    let path = path.trim_end_matches('/');
+
+    let (dir, name) = match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("/", path),
+    };
+
+    let formatted = crate::exceptions::format_path_8_3(name);
+    (dir, fat_name(&formatted))
+}
+
+/// Drops the watches of tasks that have exited.
+fn prune() {
+    unsafe {
+        (*(&raw mut WATCHES)).retain(|w| w.task.is_some());
+    }
+}
+
+/// Starts watching `dir` for the current task and returns the watch id.
+pub fn add(dir: &str) -> u32 {
+    prune();
+
+    let Some(task) = crate::task::current_task() else {
+        return 0;
+    };
+
+    unsafe {
+        let id = NEXT_ID;
+        NEXT_ID = NEXT_ID.wrapping_add(1).max(1);
+
+        (*(&raw mut WATCHES)).push(Watch {
+            id,
+            task: Some(task),
+            dir: String::from(dir),
+            queue: VecDeque::new(),
+        });
+
+        id
+    }
+}
+
+pub fn remove(id: u32) -> bool {
+    let task = crate::task::current_task();
+
+    unsafe {
+        let watches = &mut (*(&raw mut WATCHES));
+        let before = watches.len();
+        watches.retain(|w| !(w.id == id && w.task == task));
+
+        watches.len() != before
+    }
+}
+
+/// Takes the oldest pending event of a watch owned by the current task.
+pub fn poll(id: u32) -> Option<WatchEvent> {
+    let task = crate::task::current_task();
+
+    unsafe {
+        (*(&raw mut WATCHES))
+            .iter_mut()
+            .find(|w| w.id == id && w.task == task)?
+            .queue
+            .pop_front()
+    }
+}
+
+/// Called from the scheduler, so it only marks the watches and leaves freeing
+/// them to the next syscall.
+pub fn task_exited(task: usize) {
+    unsafe {
+        for w in (*(&raw mut WATCHES)).iter_mut() {
+            if w.task == Some(task) {
+                w.task = None;
+            }
+        }
+    }
+}
+
+fn push(dir: &str, event: WatchEvent) {
+    unsafe {
+        for w in (*(&raw mut WATCHES)).iter_mut() {
+            if w.task.is_none() || !same_dir(&w.dir, dir) {
+                continue;
+            }
+
+            if w.queue.len() >= QUEUE_LIMIT {
+                // the reader has to list the directory again anyway
+                w.queue.clear();
+                w.queue.push_back(WatchEvent::new(WatchKind::Overflow, [32; 11]));
+            } else if w.queue.back().is_none_or(|last| last.kind != WatchKind::Overflow) {
+                w.queue.push_back(event);
+            }
+        }
+    }
+}
+
+/// Tells everyone watching the directory holding `path` that it changed.
+pub fn notify(path: &str, kind: WatchKind) {
+    let (dir, name) = split(path);
+    push(dir, WatchEvent::new(kind, name));
+}
+
+pub fn notify_rename(path: &str, new_name: &str) {
+    let (dir, old_name) = split(path);
+    let (_, name) = split(new_name);
+
+    let mut event = WatchEvent::new(WatchKind::Rename, name);
+    event.old_name = old_name;
+    push(dir, event);
+}
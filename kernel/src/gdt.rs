@@ -0,0 +1,80 @@
+use core::arch::asm;
+use core::mem::size_of;
+use core::ptr::addr_of;
+
+use crate::smp::MAX_CPUS;
+use crate::tss::TaskStateSegment;
+
+/// The layout stage 2 uses, set up again here since a Multiboot loader leaves
+/// its own GDT behind, followed by a TSS for each processor.
+static mut GDT: [u64; 5 + MAX_CPUS] = {
+    let mut gdt = [0; 5 + MAX_CPUS];
+    gdt[1] = 0x00CF_9A00_0000_FFFF; // kernel code, 0x08
+    gdt[2] = 0x00CF_9200_0000_FFFF; // kernel data, 0x10
+    gdt[3] = 0x00CF_FA00_0000_FFFF; // user code, 0x18
+    gdt[4] = 0x00CF_F200_0000_FFFF; // user data, 0x20
+    gdt
+};
+
+/// Selector of the first processor's TSS, the others follow.
+pub const TSS_SELECTOR: u16 = 0x28;
+
+pub static mut TSS: [TaskStateSegment; MAX_CPUS] = unsafe { core::mem::zeroed() };
+
+#[repr(C, packed)]
+struct Descriptor {
+    size: u16,
+    offset: u32,
+}
+
+pub fn init() {
+    unsafe {
+        let limit = (size_of::<TaskStateSegment>() - 1) as u64;
+
+        for cpu in 0..MAX_CPUS {
+            let base = addr_of!(TSS[cpu]) as u64;
+
+            GDT[5 + cpu] = (limit & 0xFFFF)
+                | ((base & 0xFF_FFFF) << 16)
+                | (0x89 << 40)
+                | (((limit >> 16) & 0xF) << 48)
+                | (((base >> 24) & 0xFF) << 56);
+        }
+    }
+
+    load(0);
+}
+
+/// Loads the GDT on the processor running this, with the TSS of `cpu`.
+pub fn load(cpu: usize) {
+    unsafe {
+        let descriptor = Descriptor {
+            size: (size_of::<[u64; 5 + MAX_CPUS]>() - 1) as u16,
+            offset: addr_of!(GDT) as u32,
+        };
+
+        asm!(
+            "lgdt [{desc}]",
+
+            // reload cs with a far return
+            "push 0x08",
+            "lea {tmp}, [2f]",
+            "push {tmp}",
+            "retf",
+            "2:",
+
+            "mov {tmp:x}, 0x10",
+            "mov ds, {tmp:x}",
+            "mov es, {tmp:x}",
+            "mov fs, {tmp:x}",
+            "mov gs, {tmp:x}",
+            "mov ss, {tmp:x}",
+
+            "ltr {tss:x}",
+
+            desc = in(reg) &descriptor,
+            tss = in(reg) TSS_SELECTOR + cpu as u16 * 8,
+            tmp = out(reg) _,
+        );
+    }
+}
@@ -6,6 +6,28 @@ use libk::println;
 const MIN_FREE_SEGMENT_SIZE: usize = 64;
 const USED_SEGMENT_MAGIC: u32 = 0xBAF10500;
 
+pub const SIZE: usize = 0x10_0000;
+
+unsafe extern "C" {
+    static KERNEL_END: u8;
+}
+
+/// The heap goes on the first page after the kernel and the modules loaded
+/// with it, as reported in `BOOTINFO`.
+pub fn base() -> u32 {
+    let info = unsafe { crate::BOOTINFO };
+    let (kernel, modules) = (info.kernel, info.modules);
+
+    let end = kernel
+        .iter()
+        .chain(modules.iter())
+        .filter(|r| r.size != 0)
+        .map(|r| r.base + r.size)
+        .fold(core::ptr::addr_of!(KERNEL_END) as u32, core::cmp::max);
+
+    (end + 0xFFF) & !0xFFF
+}
+
 #[repr(C, packed)]
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub struct FreeSegment {
@@ -78,10 +100,10 @@ impl Allocator {
         }
     }
 
-    pub fn init(&self) {
+    pub fn init(&self, base: u32) {
         unsafe {
-            let segment_size: usize = 0x10_0000 - core::mem::size_of::<FreeSegment>();
-            let segment = 0x30_0000 as *mut FreeSegment;
+            let segment_size: usize = SIZE - core::mem::size_of::<FreeSegment>();
+            let segment = base as *mut FreeSegment;
             *segment = FreeSegment {
                 size: segment_size,
                 next_segment: core::ptr::null_mut(),
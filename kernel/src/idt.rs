@@ -58,6 +58,14 @@ impl Idt {
         self.entries[int].set_ring_3(handler);
     }
 
+    /// Whether `int` still has the catch-all handler `init` put there.
+    pub fn is_free(&self, int: usize) -> bool {
+        let entry = self.entries[int];
+        let offset = entry.offset_low as u32 | (entry.offset_high as u32) << 16;
+
+        offset == exceptions::generic_handler as u32
+    }
+
     pub fn load(&self) {
         let idt_descriptor = Descriptor {
             size: (256 * size_of::<Entry>() - 1) as u16,
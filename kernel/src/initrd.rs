@@ -0,0 +1,69 @@
+//! The ustar archive loaded with the kernel as its first module, unpacked
+//! into a tmpfs at `/INITRD` so its files are there without any disk driver.
+
+use crate::fs::Fs;
+use crate::fs::tmpfs::{self, TmpFs};
+use crate::fs::vfs;
+use alloc::boxed::Box;
+use libk::tar::{self, Kind};
+
+pub const MOUNT_POINT: &str = "/INITRD";
+
+pub fn init() {
+    let modules = unsafe { (*(&raw const crate::BOOTINFO)).modules };
+    let Some(module) = modules.iter().find(|m| m.size != 0) else {
+        return;
+    };
+
+    let archive = unsafe { core::slice::from_raw_parts(module.base as *const u8, module.size as usize) };
+
+    // tmpfs hands out whole pages per file
+    let limit = tar::members(archive)
+        .filter_map(Result::ok)
+        .map(|(member, _)| member.size.div_ceil(tmpfs::PAGE) * tmpfs::PAGE)
+        .sum();
+
+    let mut fs = TmpFs::new(limit, tmpfs::DEFAULT_NODES);
+    let mut unpacked = 0;
+
+    for member in tar::members(archive) {
+        let (member, data) = match member {
+            Ok(member) => member,
+            Err(e) => {
+                libk::println!("[x] Initrd: {}", e);
+                break;
+            }
+        };
+
+        // `tar -C dir .` starts with the directory itself
+        if member.name == "." {
+            continue;
+        }
+
+        let Some(path) = tar::target_path("/", &member.name) else {
+            libk::println!("[x] Initrd: {}: Name does not fit 8.3", member.name);
+            continue;
+        };
+
+        // parents may only appear implicitly in the archive
+        for (pos, _) in path.match_indices('/').skip(1) {
+            let _ = fs.create_dir(&path[..pos]);
+        }
+
+        let result = match member.kind {
+            // a later member can name a directory already made for its children
+            Kind::Dir if fs.find_entry(&path).is_some() => Ok(()),
+            Kind::Dir => fs.create_dir(&path),
+            Kind::File => fs.create_file(&path).and_then(|_| fs.overwrite_file(&path, data)),
+            Kind::Other => Err("Unsupported member type"),
+        };
+
+        match result {
+            Ok(()) => unpacked += 1,
+            Err(e) => libk::println!("[x] Initrd: {}: {}", member.name, e),
+        }
+    }
+
+    vfs::mount_fs(MOUNT_POINT, vfs::NO_DISK, 0, Box::new(fs));
+    libk::println!("[+] Unpacked {} initrd entries at {}", unpacked, MOUNT_POINT);
+}
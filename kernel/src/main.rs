@@ -7,6 +7,11 @@ extern crate alloc;
 
 mod ac97;
 mod acpi;
+mod ahci;
+mod apic;
+mod bga;
+mod block;
+mod cmdline;
 mod composer;
 mod disk;
 mod display;
@@ -14,6 +19,7 @@ mod dma;
 mod elf;
 mod exceptions;
 mod idt;
+mod initrd;
 mod heap;
 mod keyboard;
 mod mouse;
@@ -21,13 +27,20 @@ mod net;
 mod pci;
 mod pic;
 mod pmm;
+mod rtc;
+mod serial;
+mod smp;
 mod task;
 mod tss;
+mod virtio;
 mod fs;
+mod gdt;
+mod multiboot;
+mod power;
 
 use libk;
 
-use core::arch::asm;
+use core::arch::{asm, naked_asm};
 use core::panic::PanicInfo;
 use idt::IDT;
 use pic::PICS;
@@ -37,36 +50,110 @@ static ALLOC: heap::Allocator = heap::Allocator::new();
 
 pub static mut BOOTINFO: libk::boot::BootInfo = libk::boot::BOOTINFO_NULL;
 
+/// Stack of the first processor until it runs tasks. Being in the BSS, it is
+/// wherever the kernel was loaded, however big that is.
+#[repr(C, align(16))]
+struct BootStack([u8; 0x1_0000]);
+
+static mut BOOT_STACK: BootStack = BootStack([0; 0x1_0000]);
+
+/// Entered from stage 3 or a Multiboot loader, which leave the boot info in
+/// ebx and tell themselves apart by eax.
+#[naked]
 #[unsafe(no_mangle)]
 #[unsafe(link_section = ".start")]
 pub extern "C" fn _start() -> ! {
+    unsafe {
+        naked_asm!(
+            "lea esp, [{stack} + {size}]",
+            "push ebx",
+            "push eax",
+            "call {main}",
+            stack = sym BOOT_STACK,
+            size = const core::mem::size_of::<BootStack>(),
+            main = sym kernel_main,
+        );
+    }
+}
 
-    ALLOC.init();
-
+extern "C" fn kernel_main(magic: u32, info: u32) -> ! {
     unsafe {
-        BOOTINFO = args();
+        BOOTINFO = args(magic, info);
     }
 
-    tss_flush();
+    ALLOC.init(heap::base());
+    gdt::init();
+
+    // safe mode skips the network, the APICs and the other processors, the
+    // serial console has no graphics
+    let safe = cmdline::flag("safe");
+    let serial_console = serial::is_console();
+
+    libk::serial::set_quiet(cmdline::flag("quiet"));
+    libk::println!("[!] Command line: {}", cmdline::line());
+
+    acpi::init();
 
     unsafe {
-        dma::init();
+        // drivers allocate their command memory from it
         (*(&raw mut pmm::PADDR)).init();
-        (*(&raw mut composer::DISPLAY_SERVER)).init();
+        dma::init();
+        disk::init();
+        ahci::init();
+        virtio::init();
+        (*(&raw mut fs::cache::CACHE)).init(
+            cmdline::number("cache").map_or(fs::cache::DEFAULT_CAPACITY, |n| n as usize),
+        );
+
+        // a Multiboot loader can leave the card in text mode
+        bga::init();
+        if BOOTINFO.mode.framebuffer == 0 && !serial_console {
+            if let Err(err) = bga::set_mode(1024, 768, 32) {
+                libk::println!("[x] No framebuffer: {}", err);
+            }
+        }
+        let display = &mut *(&raw mut composer::DISPLAY_SERVER);
+        if let Err(err) = display.init() {
+            libk::println!("[x] Display: {}", err);
+        }
+
+        // stage 2 only sees the disk sector, /SYS/CMDLINE.TXT can still ask
+        if let Some((width, height)) = cmdline::video().filter(|_| !serial_console) {
+            if (width as u64, height as u64) != (display.width, display.height) {
+                if let Err(err) = display.set_mode(width, height, display.depth as u8) {
+                    libk::println!("[x] Video mode {}x{}: {}", width, height, err);
+                }
+            }
+        }
     }
 
+    fs::partition::init();
+    fs::vfs::init();
+    initrd::init();
+
     libk::println!("[!] Kernel reached and args loaded");
 
     unsafe {
         (*(&raw mut task::TASK_MANAGER)).lock().init();
         (*(&raw mut task::TASK_MANAGER))
             .lock()
-            .add_user_task(test as u32, None);
+            .add_task(fs::cache::writeback as u32, None);
+        (*(&raw mut task::TASK_MANAGER))
+            .lock()
+            .add_user_task(start_init as u32, None);
 
         idt();
-        mouse::init();
+        if serial_console {
+            serial::init();
+        } else {
+            mouse::init();
+        }
+
+        if !safe && cmdline::get("net") != Some("none") {
+            (*(&raw mut net::rtl8139::RTL8139)).init();
+        }
 
-        (*(&raw mut net::rtl8139::RTL8139)).init();
+        smp::init();
 
         libk::println!("[-] Kernel ended");
 
@@ -78,7 +165,21 @@ pub extern "C" fn _start() -> ! {
     }
 }
 
-fn test() -> ! {
+/// Starts the first user process (`init=` on the command line, the serial
+/// console with `console=serial`), falling back to the login screen on images
+/// built without init.
+fn start_init() -> ! {
+    let default = if serial::is_console() { "USER/CONSOLE.ELF" } else { "USER/INIT.ELF" };
+    let init = libk::cmdline::get("init").unwrap_or_else(|| alloc::string::String::from(default));
+
+    if libk::io::exists(&init) {
+        if libk::elf::load_elf(&init, None).is_ok() {
+            libk::syscall::exit();
+        }
+
+        libk::println!("[x] Could not start {}", init);
+    }
+
     let _ = libk::elf::load_elf("USER/LOGIN.ELF", None);
     libk::syscall::exit();
 }
@@ -97,38 +198,47 @@ fn idt() {
             exceptions::MOUSE_INT as usize,
             exceptions::mouse_handler as u32,
         );
-        (*(&raw mut IDT)).add(exceptions::NET_INT as usize, net::rtl8139::net as u32);
+        (*(&raw mut IDT)).add(serial::SERIAL_INT as usize, serial::handler as u32);
+        (*(&raw mut IDT)).add(apic::SPURIOUS as usize, apic::spurious as u32);
+        (*(&raw mut IDT)).add(smp::RESCHEDULE as usize, task::rescheduled as u32);
+        (*(&raw mut IDT)).add(smp::TLB_SHOOTDOWN as usize, smp::tlb_shootdown as u32);
+        (*(&raw mut IDT)).add(smp::HALT as usize, smp::halted as u32);
         (*(&raw mut IDT)).add_ring_3(0x80, exceptions::syscall as u32);
         (*(&raw mut IDT)).load();
         (*(&raw mut PICS)).init();
+
+        // the local APIC timer takes over from the PIT on IRQ 0
+        if !apic::init() {
+            apic::enable_irq(0, apic::Bus::Isa);
+        }
+        apic::enable_irq(1, apic::Bus::Isa);
+        apic::enable_irq(12, apic::Bus::Isa);
+
+        ahci::install_irq();
+        virtio::install_irq();
     }
 }
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    libk::serial::set_quiet(false);
     libk::println!("PANIC\n {}", info);
     loop {}
 }
 
-fn args() -> libk::boot::BootInfo {
-    let bx: u16;
-    unsafe {
-        asm!( "mov {0:x}, bx" , out(reg) bx );
+fn args(magic: u32, info: u32) -> libk::boot::BootInfo {
+    match magic {
+        multiboot::MAGIC1 => multiboot::boot_info1(info),
+        multiboot::MAGIC2 => multiboot::boot_info2(info),
+        // stage 3 passes the real mode address of stage 2's copy
+        _ => unsafe { *((info & 0xFFFF) as *const libk::boot::BootInfo) },
     }
-    let info = bx as *const libk::boot::BootInfo;
-    unsafe { *info }
 }
 
 pub fn set_tss(esp: u32) {
     unsafe {
-        let tss = &mut *(BOOTINFO.tss as u32 as *mut tss::TaskStateSegment);
+        let tss = &mut (*(&raw mut gdt::TSS))[smp::cpu()];
         tss.esp0 = esp;
         tss.ss0 = 0x10;
     }
 }
-
-fn tss_flush() {
-    unsafe {
-        asm!("mov ax, 0x28", "ltr ax",);
-    }
-}
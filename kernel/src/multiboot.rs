@@ -0,0 +1,237 @@
+//! Booting from a Multiboot loader instead of stage 3: GRUB through the
+//! Multiboot2 header, `qemu -kernel` through the original Multiboot one. The
+//! information they pass is turned into a `BootInfo`.
+
+use core::arch::global_asm;
+use core::ptr::{addr_of, read_unaligned};
+
+use libk::boot::{BOOTINFO_NULL, BootInfo, MemoryMapEntry, Range, Rsdp};
+
+/// What the loaders leave in eax.
+pub const MAGIC1: u32 = 0x2BAD_B002;
+pub const MAGIC2: u32 = 0x36D7_6289;
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 768;
+const DEPTH: u32 = 32;
+
+// Both headers ask for a linear framebuffer, which QEMU ignores.
+global_asm!(
+    ".section .multiboot, \"a\"",
+
+    ".align 4",
+    "multiboot1_header:",
+    ".long 0x1BADB002",
+    ".long 0x6", // memory map, video mode
+    ".long -(0x1BADB002 + 0x6)",
+    ".long 0, 0, 0, 0, 0",
+    ".long 0", // linear framebuffer
+    ".long {width}, {height}, {depth}",
+
+    ".align 8",
+    "multiboot2_header:",
+    ".long 0xE85250D6",
+    ".long 0", // i386
+    ".long multiboot2_end - multiboot2_header",
+    ".long -(0xE85250D6 + (multiboot2_end - multiboot2_header))",
+
+    // framebuffer, optional
+    ".short 5, 1",
+    ".long 20",
+    ".long {width}, {height}, {depth}",
+
+    ".align 8",
+    ".short 0, 0",
+    ".long 8",
+    "multiboot2_end:",
+
+    width = const WIDTH,
+    height = const HEIGHT,
+    depth = const DEPTH,
+);
+
+unsafe extern "C" {
+    static KERNEL_START: u8;
+    static KERNEL_END: u8;
+}
+
+/// Reads the Multiboot2 information at `addr`.
+pub fn boot_info2(addr: u32) -> BootInfo {
+    let mut info = BOOTINFO_NULL;
+
+    let total = read32(addr);
+    let mut tag = addr + 8;
+    let mut modules = 0;
+
+    while tag + 8 <= addr + total {
+        let kind = read32(tag);
+        let size = read32(tag + 4);
+
+        match kind {
+            0 => break,
+            1 => set_cmdline(&mut info, tag + 8),
+            3 => {
+                add_module(&mut info, modules, read32(tag + 8), read32(tag + 12));
+                modules += 1;
+            }
+            6 => {
+                let entry_size = read32(tag + 8);
+                let mut entry = tag + 16;
+                let mut i = 0;
+
+                while entry_size != 0 && entry + entry_size <= tag + size {
+                    add_mmap(&mut info, i, entry);
+                    entry += entry_size;
+                    i += 1;
+                }
+            }
+            8 => set_framebuffer(&mut info, tag + 8),
+            // the old RSDP tag stops after the RSDT address
+            14 => unsafe {
+                core::ptr::copy_nonoverlapping(
+                    (tag + 8) as *const u8,
+                    &raw mut info.rsdp as *mut u8,
+                    20,
+                );
+            },
+            15 => {
+                info.rsdp = unsafe { read_unaligned((tag + 8) as *const Rsdp) };
+            }
+            _ => {}
+        }
+
+        tag += (size + 7) & !7;
+    }
+
+    finish(&mut info);
+    info
+}
+
+/// Reads the original Multiboot information at `addr`.
+pub fn boot_info1(addr: u32) -> BootInfo {
+    let mut info = BOOTINFO_NULL;
+    let flags = read32(addr);
+
+    if flags & (1 << 2) != 0 {
+        set_cmdline(&mut info, read32(addr + 16));
+    }
+
+    if flags & (1 << 3) != 0 {
+        let count = read32(addr + 20);
+        let list = read32(addr + 24);
+
+        for i in 0..count as usize {
+            let module = list + i as u32 * 16;
+            add_module(&mut info, i, read32(module), read32(module + 4));
+        }
+    }
+
+    if flags & (1 << 6) != 0 {
+        let length = read32(addr + 44);
+        let start = read32(addr + 48);
+
+        // every entry is preceded by its size, which does not count itself
+        let mut entry = start;
+        let mut i = 0;
+        while entry + 4 <= start + length {
+            add_mmap(&mut info, i, entry + 4);
+            entry += read32(entry) + 4;
+            i += 1;
+        }
+    }
+
+    if flags & (1 << 12) != 0 {
+        set_framebuffer(&mut info, addr + 88);
+    }
+
+    finish(&mut info);
+    info
+}
+
+fn set_cmdline(info: &mut BootInfo, addr: u32) {
+    let mut cmdline = [0; libk::cmdline::MAX_LEN];
+
+    // the last byte stays 0
+    for (i, byte) in cmdline.iter_mut().take(libk::cmdline::MAX_LEN - 1).enumerate() {
+        *byte = unsafe { *((addr + i as u32) as *const u8) };
+        if *byte == 0 {
+            break;
+        }
+    }
+
+    info.cmdline = cmdline;
+}
+
+fn add_module(info: &mut BootInfo, i: usize, start: u32, end: u32) {
+    if i < info.modules.len() && end > start {
+        info.modules[i] = Range {
+            base: start,
+            size: end - start,
+        };
+    }
+}
+
+/// Both versions start entries like E820 does: base, length and type.
+fn add_mmap(info: &mut BootInfo, i: usize, addr: u32) {
+    if i < info.mmap.entries.len() {
+        info.mmap.entries[i] = MemoryMapEntry {
+            base: read64(addr),
+            length: read64(addr + 8),
+            memory_type: read32(addr + 16),
+            reserved_acpi: 0,
+        };
+    }
+}
+
+/// Both versions describe the framebuffer the same way, starting at `addr`.
+fn set_framebuffer(info: &mut BootInfo, addr: u32) {
+    let base = read64(addr);
+    let kind = unsafe { *((addr + 21) as *const u8) };
+
+    // 1 is direct RGB, the others are palettes and text mode
+    if kind != 1 || base > u32::MAX as u64 {
+        return;
+    }
+
+    info.mode.framebuffer = base as u32;
+    info.mode.pitch = read32(addr + 8) as u16;
+    info.mode.width = read32(addr + 12) as u16;
+    info.mode.height = read32(addr + 16) as u16;
+    info.mode.bpp = unsafe { *((addr + 20) as *const u8) };
+}
+
+fn finish(info: &mut BootInfo) {
+    let start = addr_of!(KERNEL_START) as u32;
+    let end = addr_of!(KERNEL_END) as u32;
+    info.kernel[0] = Range {
+        base: start,
+        size: end - start,
+    };
+
+    if info.rsdp.signature != *b"RSD PTR " {
+        info.rsdp = find_rsdp();
+    }
+}
+
+/// The BIOS area search stage 2 does, for loaders without an ACPI tag.
+fn find_rsdp() -> Rsdp {
+    let mut addr = 0xE0000;
+
+    while addr < 0x10_0000 {
+        let rsdp = unsafe { read_unaligned(addr as *const Rsdp) };
+        if rsdp.signature == *b"RSD PTR " {
+            return rsdp;
+        }
+        addr += 16;
+    }
+
+    BOOTINFO_NULL.rsdp
+}
+
+fn read32(addr: u32) -> u32 {
+    unsafe { read_unaligned(addr as *const u32) }
+}
+
+fn read64(addr: u32) -> u64 {
+    unsafe { read_unaligned(addr as *const u64) }
+}
@@ -28,6 +28,8 @@ const RTL_ROK: u16 = 0x01;
 const RTL_TOK: u16 = 0x04;
 
 static mut MMIO: u32 = 0;
+/// PCI interrupt line, delivered on vector 32 + IRQ.
+static mut IRQ: u8 = 0;
 static mut RX_BUFFER: u32 = 0;
 static mut RX_OFFSET: u32 = 0;
 static mut TX_BUFFERS: [u32; 4] = [0; 4];
@@ -92,6 +94,16 @@ impl Rtl8139Driver {
 
             pci_dev.enable_bus_mastering();
 
+            match crate::apic::pci_irq(&pci_dev) {
+                Some(irq) if (*(&raw const crate::idt::IDT)).is_free(32 + irq as usize) => {
+                    IRQ = irq;
+                    (*(&raw mut crate::idt::IDT)).add(32 + irq as usize, net as u32);
+                    crate::apic::enable_irq(irq, crate::apic::Bus::Pci);
+                }
+                Some(irq) => libk::println!("[x] rtl8139: IRQ {} is taken, nothing will be received", irq),
+                None => libk::println!("[x] rtl8139: no IRQ routed, nothing will be received"),
+            }
+
             write_8(self.mmio + RTL_CR, RTL_RESET);
             while (read_8(self.mmio + RTL_CR) & RTL_RESET) != 0 {}
 
@@ -198,11 +210,13 @@ const UDP: u8 = 17;
 const TCP: u8 = 6;
 
 pub extern "x86-interrupt" fn net() {
+    let _kernel = crate::smp::lock_kernel();
+
     unsafe {
         let isr = read_16(MMIO + RTL_ISR);
         write_16(MMIO + RTL_ISR, isr);
 
-        (*(&raw mut crate::pic::PICS)).end_interrupt(43);
+        crate::apic::end_interrupt(32 + IRQ);
 
         if isr & RTL_ROK != 0 {
             let packet = core::ptr::read((RX_BUFFER + RX_OFFSET) as *const Packet);
@@ -1,9 +1,24 @@
+use alloc::vec::Vec;
 use libk::port::{inl, outl};
 use libk::println;
 
 const PCI_CONFIG_ADDRESS: u32 = 0xCF8;
 const PCI_CONFIG_DATA: u32 = 0xCFC;
 
+/// Vendor and device ID of the ICH9 LPC bridge at 00:1F.0 on Q35 boards.
+const ICH9_LPC: u32 = 0x2918_8086;
+const ICH9_RCBA: u8 = 0xF0;
+/// Offsets of the D31IR..D25IR interrupt route registers from the RCBA.
+const ICH9_ROUTES: [(u8, u32); 7] = [
+    (31, 0x3140),
+    (30, 0x3142),
+    (29, 0x3144),
+    (28, 0x3146),
+    (27, 0x3148),
+    (26, 0x314C),
+    (25, 0x3150),
+];
+
 #[derive(Debug, Copy, Clone)]
 pub struct PciDevice {
     class: u32,
@@ -65,6 +80,67 @@ pub fn find_device(v_id: u32, d_id: u32) -> Option<PciDevice> {
     None
 }
 
+pub fn find_devices(v_id: u32, d_id: u32) -> Vec<PciDevice> {
+    let mut found = Vec::new();
+
+    for bus in 0..=255 {
+        for device in 0..32 {
+            for function in 0..8 {
+                let id = pci_read(bus, device, function, 0);
+
+                if id & 0xFFFF == v_id && id >> 16 == d_id {
+                    let class_subclass = pci_read(bus, device, function, 8);
+
+                    found.push(PciDevice {
+                        class: (class_subclass >> 24) & 0xFF,
+                        subclass: (class_subclass >> 16) & 0xFF,
+                        vendor_id: v_id,
+                        device_id: d_id,
+                        bus,
+                        device,
+                        function,
+                    });
+                }
+            }
+        }
+    }
+
+    found
+}
+
+/// Finds the first function with the given class, subclass and programming interface.
+pub fn find_class(class: u32, subclass: u32, prog_if: u32) -> Option<PciDevice> {
+    for bus in 0..=255 {
+        for device in 0..32 {
+            for function in 0..8 {
+                let vendor_id = pci_read(bus, device, function, 0) & 0xFFFF;
+
+                if vendor_id == 0xFFFF {
+                    continue;
+                }
+
+                let class_subclass = pci_read(bus, device, function, 8);
+
+                if (class_subclass >> 24) & 0xFF == class
+                    && (class_subclass >> 16) & 0xFF == subclass
+                    && (class_subclass >> 8) & 0xFF == prog_if
+                {
+                    return Some(PciDevice {
+                        class,
+                        subclass,
+                        vendor_id,
+                        device_id: pci_read(bus, device, function, 0) >> 16,
+                        bus,
+                        device,
+                        function,
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
 pub fn list_devices() {
     for bus in 0..=255 {
         for device in 0..32 {
@@ -100,6 +176,71 @@ impl PciDevice {
         (value & 0xFF) as u8
     }
 
+    pub fn read_config(&self, offset: u8) -> u32 {
+        self.read_config_register(offset as u32)
+    }
+
+    /// Walks the capability list and returns the config offset of every capability with `id`.
+    pub fn capabilities(&self, id: u8) -> Vec<u8> {
+        let mut found = Vec::new();
+
+        // bit 4 of the status register says whether there is a list at all
+        if (self.read_config(0x04) >> 16) & 0x10 == 0 {
+            return found;
+        }
+
+        let mut offset = (self.read_config(0x34) & 0xFC) as u8;
+
+        for _ in 0..48 {
+            if offset == 0 {
+                break;
+            }
+
+            let header = self.read_config(offset);
+            if header as u8 == id {
+                found.push(offset);
+            }
+
+            offset = ((header >> 8) & 0xFC) as u8;
+        }
+
+        found
+    }
+
+    pub fn interrupt_line(&self) -> u8 {
+        Self::get_pci_irq(self.bus, self.device, self.function)
+    }
+
+    /// INTA..INTD as 1..4, 0 when the function has no interrupt pin.
+    pub fn interrupt_pin(&self) -> u8 {
+        (self.read_config(0x3C) >> 8) as u8
+    }
+
+    /// The PIRQ (0 = A .. 7 = H) an ICH9 chipset routes this device's pin to.
+    /// Devices 25-31 are routed by the DnIR registers; the slots below them
+    /// are swizzled onto PIRQ E-H, which is what the firmware's _PRT reports.
+    /// Devices behind bridges are not handled.
+    pub fn ich9_pirq(&self) -> Option<u8> {
+        let pin = self.interrupt_pin();
+        if self.bus != 0 || pin == 0 || pin > 4 || pci_read(0, 31, 0, 0x00) != ICH9_LPC {
+            return None;
+        }
+
+        let intx = pin as u32 - 1;
+
+        let Some(&(_, offset)) = ICH9_ROUTES.iter().find(|(slot, _)| *slot == self.device) else {
+            return Some(((self.device as u32 + intx) % 4 + 4) as u8);
+        };
+
+        let rcba = pci_read(0, 31, 0, ICH9_RCBA);
+        if rcba & 1 == 0 {
+            return None;
+        }
+
+        let routes = libk::mmio::read_32((rcba & !0x3FFF) + (offset & !3)) >> ((offset & 2) * 8);
+        Some(((routes >> (intx * 4)) & 0x7) as u8)
+    }
+
     pub fn get_bar(&self, bar_index: u8) -> Option<u32> {
         if bar_index > 5 {
             return None;
@@ -82,10 +82,6 @@ impl Pics {
         wait();
         self.master.write_data(mask1);
         self.slave.write_data(mask2);
-
-        self.master.unmask_irq(1);
-        self.slave.unmask_irq(3);
-        self.slave.unmask_irq(4);
     }
 
     pub fn handles_interrupt(&self, interrupt: u8) -> bool {
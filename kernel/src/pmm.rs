@@ -9,6 +9,8 @@ pub struct Section {
 pub struct PMM {
     pub sections: Vec<Section>,
     pub ram_size: u32,
+    /// Nothing below this is handed out: the kernel, its modules and its heap.
+    pub start: u32,
 }
 
 const INITIAL_CAPACITY: usize = 2048;
@@ -16,6 +18,7 @@ const INITIAL_CAPACITY: usize = 2048;
 pub static mut PADDR: PMM = PMM {
     sections: Vec::new(),
     ram_size: 0,
+    start: 0,
 };
 
 impl PMM {
@@ -23,16 +26,26 @@ impl PMM {
         let info = unsafe { crate::BOOTINFO };
 
         self.ram_size = info.get_mmap(0x10_0000 as u64).length as u32;
+        self.start = crate::heap::base() + crate::heap::SIZE as u32;
 
         self.sections.push(Section {
-            base: 0xA0_0000,
+            base: self.start,
             size: 0,
         });
+
+        let kernel = info.kernel;
+        let modules = info.modules;
+        for range in kernel.iter().chain(modules.iter()).filter(|r| r.size != 0) {
+            self.sections.push(Section {
+                base: range.base,
+                size: range.size,
+            });
+        }
     }
 
     pub fn malloc(&mut self, size: u32) -> Option<u32> {
         self.sections.sort_by_key(|s| s.base);
-        let mut candidate = 0xA0_0000;
+        let mut candidate = self.start;
 
         for section in &self.sections {
             let candidate_aligned = self.align_up(candidate);
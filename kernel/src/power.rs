@@ -0,0 +1,172 @@
+//! Powering off and restarting. Both stop the other processors and unmount
+//! the filesystems first, then try what ACPI describes before falling back to
+//! emulator ports and the 8042.
+
+use core::arch::asm;
+
+use libk::acpi::{FADT_RESET_SUPPORTED, Fadt, SPACE_IO, SPACE_MEMORY};
+use libk::port::{inb, inw, outb, outw};
+use libk::println;
+
+const SCI_EN: u16 = 1 << 0;
+const SLP_EN: u16 = 1 << 13;
+const SLP_TYP: u16 = 0x7 << 10;
+
+/// QEMU, then Bochs and older QEMU, then VirtualBox.
+const EMULATOR_SHUTDOWN: [(u16, u16); 3] = [(0x604, 0x2000), (0xB004, 0x2000), (0x4004, 0x3400)];
+
+const PS2_STATUS: u16 = 0x64;
+const PS2_RESET: u8 = 0xFE;
+
+pub fn shutdown() -> ! {
+    libk::disable_interrupts();
+    crate::smp::halt_others();
+    crate::fs::vfs::unmount_all();
+    println!("[!] Powering off");
+
+    if let Some(fadt) = crate::acpi::get().and_then(|a| a.fadt) {
+        match s5(fadt.dsdt) {
+            Some((a, b)) => {
+                enable_acpi(&fadt);
+                sleep(fadt.pm1a_control, a);
+                sleep(fadt.pm1b_control, b);
+            }
+            None => println!("[x] No _S5_ in the DSDT"),
+        }
+    }
+
+    for (port, value) in EMULATOR_SHUTDOWN {
+        outw(port, value);
+    }
+
+    println!("[x] Could not power off");
+    halt()
+}
+
+pub fn reboot() -> ! {
+    libk::disable_interrupts();
+    crate::smp::halt_others();
+    crate::fs::vfs::unmount_all();
+    println!("[!] Restarting");
+
+    if let Some(fadt) = crate::acpi::get().and_then(|a| a.fadt) {
+        let reset = fadt.reset;
+        let address = reset.address;
+
+        if fadt.flags & FADT_RESET_SUPPORTED != 0 {
+            match reset.space {
+                SPACE_IO => outb(address as u16, fadt.reset_value),
+                SPACE_MEMORY if address <= u32::MAX as u64 => unsafe {
+                    core::ptr::write_volatile(address as u32 as *mut u8, fadt.reset_value)
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // pulse the reset line through the keyboard controller
+    for _ in 0..0x10000 {
+        if inb(PS2_STATUS) & 0x02 == 0 {
+            break;
+        }
+    }
+    outb(PS2_STATUS, PS2_RESET);
+
+    // an empty IDT turns the next interrupt into a triple fault
+    let idt = [0u16; 3];
+    unsafe { asm!("lidt [{}]", "int3", in(reg) &idt) };
+
+    halt()
+}
+
+/// Switches from SMM to ACPI mode if the firmware has not yet, otherwise
+/// the PM1 control block ignores us.
+fn enable_acpi(fadt: &Fadt) {
+    let control = fadt.pm1a_control as u16;
+    if inw(control) & SCI_EN != 0 || fadt.smi_command == 0 || fadt.acpi_enable == 0 {
+        return;
+    }
+
+    outb(fadt.smi_command as u16, fadt.acpi_enable);
+    for _ in 0..1_000_000 {
+        if inw(control) & SCI_EN != 0 {
+            break;
+        }
+    }
+}
+
+fn sleep(control: u32, sleep_type: u8) {
+    if control == 0 {
+        return;
+    }
+
+    let control = control as u16;
+    let value = inw(control) & !SLP_TYP;
+    outw(control, value | ((sleep_type as u16) << 10) | SLP_EN);
+}
+
+/// The S5 sleep types for PM1a and PM1b. Without an AML interpreter this
+/// looks for the bytes of `Name (_S5_, Package () { a, b, ... })`.
+fn s5(dsdt: u64) -> Option<(u8, u8)> {
+    if dsdt == 0 || dsdt > u32::MAX as u64 {
+        return None;
+    }
+
+    let dsdt = dsdt as u32;
+    let header = unsafe { core::slice::from_raw_parts(dsdt as *const u8, 8) };
+    if header[..4] != *b"DSDT" {
+        return None;
+    }
+
+    let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if length < 36 {
+        return None;
+    }
+
+    let aml =
+        unsafe { core::slice::from_raw_parts((dsdt + 36) as *const u8, length as usize - 36) };
+
+    let at = aml.windows(4).position(|w| w == b"_S5_")?;
+
+    // NameOp, with or without the root prefix
+    let named = match at {
+        0 => false,
+        1 => aml[0] == 0x08,
+        _ => aml[at - 1] == 0x08 || (aml[at - 1] == b'\\' && aml[at - 2] == 0x08),
+    };
+    if !named || *aml.get(at + 4)? != 0x12 {
+        return None;
+    }
+
+    // PackageOp, then PkgLength, whose top bits count its extra bytes, then
+    // the number of elements
+    let mut i = at + 5;
+    i += (*aml.get(i)? >> 6) as usize + 1;
+    i += 1;
+
+    let a = element(aml, &mut i)?;
+    let b = element(aml, &mut i)?;
+
+    Some((a, b))
+}
+
+fn element(aml: &[u8], i: &mut usize) -> Option<u8> {
+    let value = match *aml.get(*i)? {
+        // BytePrefix
+        0x0A => {
+            *i += 1;
+            *aml.get(*i)?
+        }
+        // ZeroOp, OneOp and small values are the byte itself
+        byte => byte,
+    };
+
+    *i += 1;
+    Some(value)
+}
+
+fn halt() -> ! {
+    loop {
+        unsafe { asm!("cli", "hlt") };
+    }
+}
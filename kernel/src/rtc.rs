@@ -1,7 +1,5 @@
 use libk::port::{inb, outb};
-use core::arch::asm;
-
-pub static mut CPU_CLOCK: u64 = 0;
+use libk::time::DateTime;
 
 const SECONDS: u8 = 0x00;
 const MINUTES: u8 = 0x02;
@@ -9,31 +7,108 @@ const HOURS: u8 = 0x04;
 const DAY: u8 = 0x07;
 const MONTH: u8 = 0x08;
 const YEAR: u8 = 0x09;
+const STATUS_A: u8 = 0x0A;
+const STATUS_B: u8 = 0x0B;
 
-fn read_rtc(reg: u8) -> u8 {
-    while updating() {}
+/// Not standardised, but where nearly every PC keeps it. The FADT can say otherwise.
+pub static mut CENTURY: u8 = 0x32;
+
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+const MODE_24H: u8 = 0x02;
+const MODE_BINARY: u8 = 0x04;
+const HOUR_PM: u8 = 0x80;
 
+/// Used when there is no century register or it holds garbage.
+const DEFAULT_CENTURY: u16 = 20;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct Raw {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+fn read_register(reg: u8) -> u8 {
     outb(0x70, reg);
-    let v = inb(0x71);
+    inb(0x71)
+}
 
-    return bcd_to_binary(v);
+fn updating() -> bool {
+    read_register(STATUS_A) & UPDATE_IN_PROGRESS != 0
 }
 
 fn bcd_to_binary(bcd: u8) -> u8 {
     ((bcd / 16) * 10) + (bcd % 16)
 }
 
-fn updating() -> bool {
-    outb(0x70, 0x0A);
-    (inb(0x71) & 0x80) != 0
+fn read_raw() -> Raw {
+    while updating() {}
+
+    let century = unsafe { CENTURY };
+
+    Raw {
+        second: read_register(SECONDS),
+        minute: read_register(MINUTES),
+        hour: read_register(HOURS),
+        day: read_register(DAY),
+        month: read_register(MONTH),
+        year: read_register(YEAR),
+        century: if century != 0 { read_register(century) } else { 0 },
+    }
 }
 
-pub fn get_date() {
+/// Current wall clock time. The registers are read until two passes agree, so an
+/// update landing halfway through cannot tear the result.
+pub fn now() -> DateTime {
+    let mut raw = read_raw();
+
+    loop {
+        let again = read_raw();
+        if again == raw {
+            break;
+        }
+        raw = again;
+    }
+
+    let status = read_register(STATUS_B);
+    let binary = status & MODE_BINARY != 0;
+    let convert = |v: u8| if binary { v } else { bcd_to_binary(v) };
 
-    let s = read_rtc(SECONDS);
-    let m = read_rtc(MINUTES);
-    let h = read_rtc(HOURS);
+    let pm = raw.hour & HOUR_PM != 0;
+    let mut hour = convert(raw.hour & !HOUR_PM);
 
-    libk::println!("{}:{}:{}", h, m, s);
+    if status & MODE_24H == 0 {
+        // 12-hour clocks count 12, 1, ... 11
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
 
-}
\ No newline at end of file
+    let century = match convert(raw.century) as u16 {
+        c @ 19..=21 => c,
+        _ => DEFAULT_CENTURY,
+    };
+
+    DateTime {
+        year: century * 100 + convert(raw.year) as u16,
+        month: convert(raw.month),
+        day: convert(raw.day),
+        hour,
+        minute: convert(raw.minute),
+        second: convert(raw.second),
+    }
+}
+
+/// `time` in the packed form FAT directory entries store.
+pub fn fat_timestamp(time: &DateTime) -> fat::Timestamp {
+    fat::Timestamp {
+        date: time.fat_date(),
+        time: time.fat_time(),
+        tenths: (time.second % 2) * 100,
+    }
+}
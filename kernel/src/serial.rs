@@ -0,0 +1,86 @@
+//! Input from the first serial port, kept until the serial console reads it.
+//! The kernel log still goes straight to the port through `libk::println`.
+
+use libk::port::{inb, outb};
+
+const COM1: u16 = 0x3F8;
+const INTERRUPT_ENABLE: u16 = COM1 + 1;
+const MODEM_CONTROL: u16 = COM1 + 4;
+const LINE_STATUS: u16 = COM1 + 5;
+
+const DATA_READY: u8 = 0x01;
+const TRANSMIT_EMPTY: u8 = 0x20;
+
+pub const SERIAL_INT: u8 = 36;
+
+/// Received bytes nobody has read yet. When it is full the oldest go.
+const BUFFER: usize = 1024;
+
+static mut INPUT: [u8; BUFFER] = [0; BUFFER];
+static mut HEAD: usize = 0;
+static mut LENGTH: usize = 0;
+
+/// Interrupts on received data. OUT2 connects the UART to its IRQ line.
+pub fn init() {
+    outb(INTERRUPT_ENABLE, 0x01);
+    outb(MODEM_CONTROL, 0x0B);
+
+    crate::apic::enable_irq(4, crate::apic::Bus::Isa);
+}
+
+pub extern "x86-interrupt" fn handler() {
+    libk::disable_interrupts();
+    let kernel = crate::smp::lock_kernel();
+
+    while inb(LINE_STATUS) & DATA_READY != 0 {
+        push(inb(COM1));
+    }
+
+    crate::apic::end_interrupt(SERIAL_INT);
+
+    drop(kernel);
+    libk::enable_interrupts();
+}
+
+fn push(byte: u8) {
+    unsafe {
+        if LENGTH == BUFFER {
+            HEAD = (HEAD + 1) % BUFFER;
+            LENGTH -= 1;
+        }
+
+        (*(&raw mut INPUT))[(HEAD + LENGTH) % BUFFER] = byte;
+        LENGTH += 1;
+    }
+}
+
+/// Takes up to `buffer.len()` received bytes without waiting, returning how
+/// many there were.
+pub fn read(buffer: &mut [u8]) -> usize {
+    unsafe {
+        let count = core::cmp::min(buffer.len(), LENGTH);
+
+        for byte in buffer[..count].iter_mut() {
+            *byte = (*(&raw const INPUT))[HEAD];
+            HEAD = (HEAD + 1) % BUFFER;
+        }
+        LENGTH -= count;
+
+        count
+    }
+}
+
+/// Writes `data`, waiting for the port to take each byte.
+pub fn write(data: &[u8]) {
+    for &byte in data {
+        while inb(LINE_STATUS) & TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        outb(COM1, byte);
+    }
+}
+
+/// `console=serial`: no graphics, and the serial console instead of init.
+pub fn is_console() -> bool {
+    crate::cmdline::get("console") == Some("serial")
+}
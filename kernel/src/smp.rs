@@ -0,0 +1,445 @@
+//! Starting the other processors the MADT lists and what they share. Each
+//! gets its own TSS, kernel stack and run queue in the task manager, but the
+//! rest of the kernel was written for one processor, so syscalls and
+//! interrupt handlers take the kernel lock and only run on one at a time.
+//! A task holding it is not preempted, so it stays with that task. It is
+//! never held in user mode: syscalls let go of it before returning there.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use libk::println;
+
+pub const MAX_CPUS: usize = libk::acpi::MAX_CPUS;
+
+/// Sends a processor back into the scheduler, to pick up work it was given.
+pub const RESCHEDULE: u8 = 0xF0;
+/// Has a processor drop its cached page translations, see `shootdown`.
+pub const TLB_SHOOTDOWN: u8 = 0xF1;
+/// Stops a processor for good, see `halt_others`.
+pub const HALT: u8 = 0xF2;
+
+/// Real mode page the application processors start in, as a SIPI vector.
+const TRAMPOLINE: u32 = 0x8000;
+const STACK_SIZE: u32 = 64 * 1024;
+
+/// Processors up and taking timer interrupts, the bootstrap one included.
+/// They come up in order, so these are also the first unused index.
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+/// Index the processor being started takes.
+static mut STARTING: usize = 0;
+/// Local APIC ids by processor index, for IPIs.
+static mut APIC_IDS: [u32; MAX_CPUS] = [0; MAX_CPUS];
+
+/// Processor holding the kernel lock plus one, 0 while nobody is.
+static KERNEL_OWNER: AtomicUsize = AtomicUsize::new(0);
+static mut KERNEL_DEPTH: usize = 0;
+
+/// Processors, one bit each, that have yet to flush for a shootdown.
+static FLUSH_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Set once the machine is going down, and how many processors have stopped.
+static HALTING: AtomicBool = AtomicBool::new(false);
+static HALTED: AtomicUsize = AtomicUsize::new(0);
+
+const MEMORY_USABLE: u32 = 1;
+
+unsafe extern "C" {
+    static ap_trampoline: u8;
+    static ap_trampoline_end: u8;
+    static ap_stack: u8;
+    static ap_entry: u8;
+}
+
+// Copied to TRAMPOLINE and entered in real mode at its start. It loads a
+// flat GDT of its own, enters protected mode and calls `ap_entry` on
+// `ap_stack`, both filled in for each processor.
+global_asm!(
+    ".global ap_trampoline",
+    ".global ap_trampoline_end",
+    ".global ap_stack",
+    ".global ap_entry",
+    ".code16",
+    "ap_trampoline:",
+    "cli",
+    "cld",
+    "mov ax, cs",
+    "mov ds, ax",
+    ".byte 0x66",
+    "lgdt [ap_gdt_pointer_offset]",
+    "mov eax, cr0",
+    "or eax, 1",
+    "mov cr0, eax",
+    // ljmp 0x08, protected, with a 32 bit offset
+    ".byte 0x66, 0xEA",
+    ".long {base} + ap_protected - ap_trampoline",
+    ".word 0x08",
+    ".code32",
+    "ap_protected:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov ss, ax",
+    "mov esp, [{base} + ap_stack_offset]",
+    "mov eax, [{base} + ap_entry_offset]",
+    "call eax",
+    ".balign 8",
+    "ap_gdt:",
+    ".quad 0",
+    ".quad 0x00CF9A000000FFFF",
+    ".quad 0x00CF92000000FFFF",
+    "ap_gdt_pointer:",
+    ".word 23",
+    ".long {base} + ap_gdt - ap_trampoline",
+    "ap_stack:",
+    ".long 0",
+    "ap_entry:",
+    ".long 0",
+    "ap_trampoline_end:",
+    ".set ap_gdt_pointer_offset, ap_gdt_pointer - ap_trampoline",
+    ".set ap_stack_offset, ap_stack - ap_trampoline",
+    ".set ap_entry_offset, ap_entry - ap_trampoline",
+    base = const TRAMPOLINE,
+);
+
+/// Starts every processor the MADT lists as usable, one at a time. Needs the
+/// local APIC, so does nothing with `noapic`, and `nosmp` keeps to one.
+pub fn init() {
+    if !unsafe { crate::apic::ENABLED } || crate::cmdline::flag("nosmp") {
+        return;
+    }
+
+    let Some(madt) = crate::acpi::get().and_then(|a| a.madt) else {
+        return;
+    };
+
+    if !trampoline_free() {
+        println!("[x] SMP: {:#X} is not free memory, staying on one processor", TRAMPOLINE);
+        return;
+    }
+
+    let bsp = crate::apic::lapic_id();
+    unsafe { APIC_IDS[0] = bsp };
+
+    copy_trampoline();
+
+    // xAPIC IPIs can only address ids up to 0xFE
+    let cpus = madt.cpus[..madt.cpu_count]
+        .iter()
+        .filter(|c| (c.enabled || c.online_capable) && c.apic_id != bsp && c.apic_id < 0xFF);
+
+    for cpu in cpus {
+        let index = count();
+        if index >= MAX_CPUS {
+            break;
+        }
+
+        let Some(stack) = (unsafe { (*(&raw mut crate::pmm::PADDR)).malloc(STACK_SIZE) }) else {
+            println!("[x] SMP: no memory for a stack");
+            break;
+        };
+
+        unsafe {
+            STARTING = index;
+            APIC_IDS[index] = cpu.apic_id;
+            (*(&raw mut crate::task::TASK_MANAGER)).lock().add_idle(index);
+
+            write_trampoline(&raw const ap_stack, stack + STACK_SIZE);
+            write_trampoline(&raw const ap_entry, ap_main as u32);
+        }
+
+        // a processor that never shows up could still read the next one's
+        // stack, so stop at the first
+        if !start(cpu.apic_id, index) {
+            println!("[x] SMP: processor {} did not start", cpu.apic_id);
+            break;
+        }
+    }
+
+    println!("[+] SMP: {} processors online", count());
+
+    // a shootdown nobody answers would otherwise only show once a mapping changes
+    let _kernel = lock_kernel();
+    if !shootdown() {
+        println!("[x] SMP: TLB shootdown went unanswered");
+    }
+}
+
+/// INIT, then up to two SIPIs, as the MultiProcessor Specification has it.
+fn start(apic_id: u32, index: usize) -> bool {
+    crate::apic::send_init(apic_id);
+    crate::apic::delay(10_000);
+
+    for _ in 0..2 {
+        crate::apic::send_startup(apic_id, (TRAMPOLINE >> 12) as u8);
+
+        for _ in 0..10 {
+            crate::apic::delay(1_000);
+            if count() > index {
+                return true;
+            }
+        }
+    }
+
+    // slow ones get another 100 ms
+    for _ in 0..100 {
+        crate::apic::delay(1_000);
+        if count() > index {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn trampoline_length() -> u32 {
+    unsafe { (&raw const ap_trampoline_end).offset_from(&raw const ap_trampoline) as u32 }
+}
+
+/// Whether the trampoline can go to TRAMPOLINE: usable RAM in the memory map
+/// that nothing was loaded to. The PMM starts well above it, so it never
+/// hands it out.
+fn trampoline_free() -> bool {
+    let info = unsafe { crate::BOOTINFO };
+    let start = TRAMPOLINE as u64;
+    let end = start + trampoline_length() as u64;
+
+    let mmap = info.mmap;
+    let usable = mmap.entries.iter().any(|e| {
+        let (base, length, memory_type) = (e.base, e.length, e.memory_type);
+        memory_type == MEMORY_USABLE && base <= start && base + length >= end
+    });
+
+    let kernel = info.kernel;
+    let modules = info.modules;
+    let taken = kernel.iter().chain(modules.iter()).any(|r| {
+        let (base, size) = (r.base as u64, r.size as u64);
+        size != 0 && base < end && start < base + size
+    });
+
+    usable && !taken
+}
+
+fn copy_trampoline() {
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            &raw const ap_trampoline,
+            TRAMPOLINE as *mut u8,
+            trampoline_length() as usize,
+        );
+    }
+}
+
+/// Writes the copy of a trampoline variable.
+unsafe fn write_trampoline(variable: *const u8, value: u32) {
+    unsafe {
+        let offset = variable.offset_from(&raw const ap_trampoline) as u32;
+        core::ptr::write_volatile((TRAMPOLINE + offset) as *mut u32, value);
+    }
+}
+
+extern "C" fn ap_main() -> ! {
+    let index = unsafe { STARTING };
+
+    crate::gdt::load(index);
+    unsafe { (*(&raw const crate::idt::IDT)).load() };
+
+    if let Some(madt) = crate::acpi::get().and_then(|a| a.madt) {
+        crate::apic::init_local(&madt);
+    }
+    crate::apic::start_timer();
+
+    println!("[+] SMP: processor {} up as {}", crate::apic::lapic_id(), index);
+    ONLINE.fetch_add(1, Ordering::SeqCst);
+
+    // the first tick leaves this for the idle task
+    libk::enable_interrupts();
+    loop {
+        unsafe { asm!("hlt") };
+    }
+}
+
+/// Index of the processor running this, read from the TSS it loaded.
+pub fn cpu() -> usize {
+    let selector: u16;
+    unsafe { asm!("str {:x}", out(reg) selector) };
+
+    ((selector.saturating_sub(crate::gdt::TSS_SELECTOR) / 8) as usize).min(MAX_CPUS - 1)
+}
+
+pub fn count() -> usize {
+    ONLINE.load(Ordering::SeqCst)
+}
+
+pub fn reschedule(cpu: usize) {
+    if cpu < count() {
+        crate::apic::send_ipi(unsafe { APIC_IDS[cpu] }, RESCHEDULE);
+    }
+}
+
+/// Flushes the TLB here and on every other processor and returns once they
+/// all have, false if one did not within 100 ms. Needs the kernel lock, so
+/// only one runs at a time, and must not be called with the task manager
+/// locked: processors waiting for either of those have interrupts off, and
+/// only the ones spinning in `lock_kernel` answer without the IPI.
+pub fn shootdown() -> bool {
+    flush_tlb();
+
+    let me = cpu();
+    let others = (0..count()).filter(|&c| c != me).fold(0, |mask, c| mask | 1 << c);
+    if others == 0 {
+        return true;
+    }
+
+    FLUSH_PENDING.fetch_or(others, Ordering::SeqCst);
+    for c in (0..count()).filter(|&c| c != me) {
+        crate::apic::send_ipi(unsafe { APIC_IDS[c] }, TLB_SHOOTDOWN);
+    }
+
+    for _ in 0..1_000 {
+        if FLUSH_PENDING.load(Ordering::SeqCst) & others == 0 {
+            return true;
+        }
+        crate::apic::delay(100);
+    }
+
+    false
+}
+
+pub extern "x86-interrupt" fn tlb_shootdown() {
+    flush_pending();
+    crate::apic::end_interrupt(TLB_SHOOTDOWN);
+}
+
+/// Answers a shootdown this processor has been asked for, if any.
+fn flush_pending() {
+    let me = 1 << cpu();
+
+    if FLUSH_PENDING.load(Ordering::Acquire) & me != 0 {
+        flush_tlb();
+        FLUSH_PENDING.fetch_and(!me, Ordering::Release);
+    }
+}
+
+/// Stops every other processor, so nothing touches the disks while they are
+/// unmounted. Returns once they all have, or after 100 ms.
+pub fn halt_others() {
+    let me = cpu();
+    let others = count() - 1;
+    if others == 0 {
+        return;
+    }
+
+    HALTING.store(true, Ordering::SeqCst);
+    for c in (0..count()).filter(|&c| c != me) {
+        crate::apic::send_ipi(unsafe { APIC_IDS[c] }, HALT);
+    }
+
+    for _ in 0..1_000 {
+        if HALTED.load(Ordering::SeqCst) >= others {
+            return;
+        }
+        crate::apic::delay(100);
+    }
+
+    println!("[x] SMP: {} processors did not stop", others - HALTED.load(Ordering::SeqCst));
+}
+
+pub extern "x86-interrupt" fn halted() {
+    park()
+}
+
+fn park() -> ! {
+    HALTED.fetch_add(1, Ordering::SeqCst);
+    loop {
+        unsafe { asm!("cli", "hlt") };
+    }
+}
+
+/// Reloading cr3 drops every translation that is not global. Without paging
+/// nothing is cached.
+fn flush_tlb() {
+    unsafe {
+        let cr0: u32;
+        asm!("mov {}, cr0", out(reg) cr0);
+
+        if cr0 & 1 << 31 != 0 {
+            asm!("mov {0}, cr3", "mov cr3, {0}", out(reg) _);
+        }
+    }
+}
+
+/// Held for as long as it lives, see `lock_kernel`.
+pub struct KernelLock;
+
+/// Waits until no other processor is in the kernel proper. The processor
+/// holding it can take it again: the scheduler leaves the task that took it
+/// running until it lets go, so that can only be the same task or an
+/// interrupt handler on top of it.
+pub fn lock_kernel() -> KernelLock {
+    let me = cpu() + 1;
+
+    if KERNEL_OWNER.load(Ordering::Acquire) != me {
+        while KERNEL_OWNER
+            .compare_exchange(0, me, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // the holder may be waiting on this one for a shootdown or halt
+            flush_pending();
+            if HALTING.load(Ordering::Relaxed) {
+                park();
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    unsafe { KERNEL_DEPTH += 1 };
+    KernelLock
+}
+
+/// Whether the task running on this processor holds the kernel lock.
+pub fn holds_kernel() -> bool {
+    KERNEL_OWNER.load(Ordering::Acquire) == cpu() + 1
+}
+
+/// Takes the kernel lock only if nobody, this processor included, has it.
+pub fn try_lock_kernel() -> Option<KernelLock> {
+    KERNEL_OWNER
+        .compare_exchange(0, cpu() + 1, Ordering::Acquire, Ordering::Relaxed)
+        .ok()?;
+
+    unsafe { KERNEL_DEPTH = 1 };
+    Some(KernelLock)
+}
+
+/// Lets go of the kernel lock however deep this processor holds it, for a
+/// task leaving the kernel without returning through its guards.
+pub fn release_kernel() {
+    if holds_kernel() {
+        unsafe { KERNEL_DEPTH = 0 };
+        KERNEL_OWNER.store(0, Ordering::Release);
+    }
+}
+
+/// Called on the way out of a syscall with the code segment it returns to.
+/// User code never holds the kernel lock, so going back to it lets go of
+/// whatever this processor has left, not only what the guards dropped.
+#[unsafe(no_mangle)]
+pub extern "C" fn leave_kernel(cs: u32) {
+    if cs & 3 == 3 {
+        release_kernel();
+    }
+}
+
+impl Drop for KernelLock {
+    fn drop(&mut self) {
+        unsafe {
+            KERNEL_DEPTH -= 1;
+            if KERNEL_DEPTH == 0 {
+                KERNEL_OWNER.store(0, Ordering::Release);
+            }
+        }
+    }
+}
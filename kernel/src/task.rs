@@ -1,14 +1,28 @@
 use core::arch::{asm, naked_asm};
+use libk::task::Exit;
+
+use crate::smp::MAX_CPUS;
 
 const STACK_SIZE: u32 = 64 * 1024;
 const MAX_TASKS: u32 = 125;
 
+pub static mut TICKS: u64 = 0;
+
 #[derive(Copy, Clone, Debug)]
 pub struct Task {
     pub kernel_stack: u32,
     pub stack: u32,
     pub cpu_state_ptr: u32,
     pub state: TaskState,
+    pub uid: u16,
+    pub pid: u32,
+    /// Task that started this one, or the reaper once that has exited.
+    pub parent: u32,
+    pub status: u32,
+    /// Processor whose run queue it is on.
+    pub cpu: usize,
+    /// An idle task, never moved to another processor.
+    pub pinned: bool,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -36,13 +50,27 @@ pub struct CPUState {
     ss: u32,
 }
 
+impl CPUState {
+    pub fn user_mode(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
 static NULL_TASK: Task = Task {
     stack: 0,
     kernel_stack: 0,
     cpu_state_ptr: 0,
     state: TaskState::Null,
+    uid: libk::perm::ADMIN,
+    pid: 0,
+    parent: 0,
+    status: 0,
+    cpu: 0,
+    pinned: false,
 };
 
+const NO_EXIT: Option<Exit> = None;
+
 impl Task {
     pub fn init(&mut self, entry_point: u32, args: Option<&[u32]>) {
         self.state = TaskState::Ready;
@@ -115,78 +143,266 @@ impl Task {
 pub struct TaskManager {
     pub tasks: [Task; MAX_TASKS as usize],
     task_count: u32,
-    current_task: i8,
+    /// Slot each processor is running, -1 before its first tick.
+    current_task: [i8; MAX_CPUS],
+    next_pid: u32,
+    /// Pid orphans are handed to, 0 while nobody has asked for them.
+    reaper: u32,
+    /// Exits not yet collected by the parent, the oldest overwritten when full.
+    exits: [Option<Exit>; MAX_TASKS as usize],
+    next_exit: usize,
 }
 
 pub static mut TASK_MANAGER: libk::mutex::Mutex<TaskManager> =
     libk::mutex::Mutex::new(TaskManager {
         tasks: [NULL_TASK; MAX_TASKS as usize],
         task_count: 0,
-        current_task: -1,
+        current_task: [-1; MAX_CPUS],
+        next_pid: 1,
+        reaper: 0,
+        exits: [NO_EXIT; MAX_TASKS as usize],
+        next_exit: 0,
     });
 
 impl TaskManager {
     pub fn init(&mut self) {
-        self.add_task(idle as u32, None);
+        self.add_idle(0);
+    }
+
+    /// Gives `cpu` the task it runs when its run queue is empty.
+    pub fn add_idle(&mut self, cpu: usize) {
+        let free_slot = self.get_free_slot();
+        self.tasks[free_slot].init(idle as u32, None);
+        self.tasks[free_slot].uid = libk::perm::ADMIN;
+        self.tasks[free_slot].pinned = true;
+        self.start(free_slot, cpu);
+    }
+
+    pub fn add_task(&mut self, entry_point: u32, args: Option<&[u32]>) -> u32 {
+        if self.task_count >= MAX_TASKS {
+            return 0;
+        }
+
+        let cpu = self.least_loaded();
+        let free_slot = self.get_free_slot();
+        self.tasks[free_slot].init(entry_point, args);
+        self.tasks[free_slot].uid = libk::perm::ADMIN;
+        self.start(free_slot, cpu)
+    }
+
+    /// Starts a user task running as the same user as the current one.
+    pub fn add_user_task(&mut self, entry_point: u32, args: Option<&[u32]>) -> u32 {
+        let uid = self.uid();
+        self.add_user_task_as(entry_point, args, uid)
+    }
+
+    /// Returns the pid of the new task, 0 if there is no room for it.
+    pub fn add_user_task_as(&mut self, entry_point: u32, args: Option<&[u32]>, uid: u16) -> u32 {
+        if self.task_count >= MAX_TASKS {
+            return 0;
+        }
+
+        let cpu = self.least_loaded();
+        let free_slot = self.get_free_slot();
+        self.tasks[free_slot].init_u(entry_point, args);
+        self.tasks[free_slot].uid = uid;
+        self.start(free_slot, cpu)
+    }
+
+    fn start(&mut self, slot: usize, cpu: usize) -> u32 {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+
+        self.tasks[slot].pid = pid;
+        self.tasks[slot].parent = self.pid();
+        self.tasks[slot].status = 0;
+        self.tasks[slot].cpu = cpu;
+        self.task_count += 1;
+
+        // an idle processor would otherwise only notice on its next tick
+        if cpu != crate::smp::cpu() && self.is_idle(cpu) {
+            crate::smp::reschedule(cpu);
+        }
+
+        pid
+    }
+
+    /// Slot of the task this processor is running, None before its first tick.
+    pub fn current(&self) -> Option<usize> {
+        let current = self.current_task[crate::smp::cpu()];
+
+        if current < 0 { None } else { Some(current as usize) }
+    }
+
+    /// Pid of the current task, 0 before the scheduler has started.
+    pub fn pid(&self) -> u32 {
+        self.current().map_or(0, |t| self.tasks[t].pid)
     }
 
-    pub fn add_task(&mut self, entry_point: u32, args: Option<&[u32]>) {
-        if self.task_count < MAX_TASKS {
-            let free_slot = self.get_free_slot();
-            self.tasks[free_slot].init(entry_point, args);
-            self.task_count += 1;
+    /// Makes the current task the one orphans and their exits are handed to.
+    pub fn set_reaper(&mut self) {
+        self.reaper = self.pid();
+    }
+
+    /// Takes the exit of one of the current task's children, if any has exited.
+    pub fn wait(&mut self) -> Option<Exit> {
+        let pid = self.pid();
+
+        let record = self
+            .exits
+            .iter_mut()
+            .find(|e| e.is_some_and(|e| e.parent == pid))?;
+        record.take()
+    }
+
+    fn is_alive(&self, pid: u32) -> bool {
+        self.tasks
+            .iter()
+            .any(|t| t.pid == pid && t.state == TaskState::Ready)
+    }
+
+    /// Leaves the exit for the parent and hands the children to the reaper.
+    fn reap(&mut self, slot: usize) {
+        let task = self.tasks[slot];
+
+        let parent = if self.is_alive(task.parent) { task.parent } else { self.reaper };
+        if parent != 0 && parent != task.pid {
+            self.exits[self.next_exit] = Some(Exit {
+                pid: task.pid,
+                parent,
+                status: task.status,
+            });
+            self.next_exit = (self.next_exit + 1) % self.exits.len();
+        }
+
+        if self.reaper == task.pid {
+            self.reaper = 0;
+        }
+
+        for child in self.tasks.iter_mut().filter(|t| t.parent == task.pid) {
+            child.parent = self.reaper;
+        }
+        for exit in self.exits.iter_mut().flatten().filter(|e| e.parent == task.pid) {
+            exit.parent = self.reaper;
         }
     }
 
-    pub fn add_user_task(&mut self, entry_point: u32, args: Option<&[u32]>) {
-        if self.task_count < MAX_TASKS {
-            let free_slot = self.get_free_slot();
-            self.tasks[free_slot].init_u(entry_point, args);
-            self.task_count += 1;
+    pub fn uid(&self) -> u16 {
+        self.current().map_or(libk::perm::ADMIN, |t| self.tasks[t].uid)
+    }
+
+    pub fn set_uid(&mut self, uid: u16) {
+        if let Some(t) = self.current() {
+            self.tasks[t].uid = uid;
         }
     }
 
     pub fn schedule(&mut self, cpu_state: *mut CPUState) -> (*mut CPUState, u32) {
-        if self.current_task >= 0 {
-            self.tasks[self.current_task as usize].cpu_state_ptr = cpu_state as u32;
-
-            if self.tasks[self.current_task as usize].state == TaskState::Zombie {
-                unsafe {
-                    (*(&raw mut crate::pmm::PADDR))
-                        .dealloc(self.tasks[self.current_task as usize].stack);
-                    (*(&raw mut crate::pmm::PADDR))
-                        .dealloc(self.tasks[self.current_task as usize].kernel_stack);
-                }
-
-                self.tasks[self.current_task as usize] = NULL_TASK;
-                self.task_count -= 1;
-            }
+        let cpu = crate::smp::cpu();
+
+        if let Some(t) = self.current() {
+            self.tasks[t].cpu_state_ptr = cpu_state as u32;
         }
 
-        self.current_task = self.get_next_task();
-        if self.current_task < 0 {
-            return (cpu_state, 0);
+        // freeing stacks needs the kernel lock, whoever has it can wait a tick
+        if let Some(_kernel) = crate::smp::try_lock_kernel() {
+            self.free_zombies();
         }
+        self.balance(cpu);
+
+        self.current_task[cpu] = self.get_next_task(cpu);
+        let Some(t) = self.current() else {
+            return (cpu_state, 0);
+        };
 
         (
-            self.tasks[self.current_task as usize].cpu_state_ptr as *mut CPUState,
-            self.tasks[self.current_task as usize].kernel_stack,
+            self.tasks[t].cpu_state_ptr as *mut CPUState,
+            self.tasks[t].kernel_stack,
         )
     }
 
-    pub fn get_next_task(&self) -> i8 {
-        let mut i = self.current_task + 1;
-        while i < MAX_TASKS as i8 {
-            let running = self.tasks[i as usize].state == TaskState::Ready;
+    /// Frees the tasks that have exited once no processor is still on their
+    /// stacks, which for the one that just exited is after it switched away.
+    fn free_zombies(&mut self) {
+        for slot in 0..MAX_TASKS as usize {
+            if self.tasks[slot].state != TaskState::Zombie || self.is_running(slot) {
+                continue;
+            }
+
+            self.reap(slot);
 
-            if running {
-                return i;
+            unsafe {
+                (*(&raw mut crate::pmm::PADDR)).dealloc(self.tasks[slot].stack);
+                (*(&raw mut crate::pmm::PADDR)).dealloc(self.tasks[slot].kernel_stack);
             }
 
-            i = (i + 1) % MAX_TASKS as i8;
+            self.tasks[slot] = NULL_TASK;
+            self.task_count -= 1;
+
+            crate::fs::watch::task_exited(slot);
         }
+    }
+
+    /// Next ready task on the run queue of `cpu`, round robin.
+    pub fn get_next_task(&self, cpu: usize) -> i8 {
+        let current = self.current_task[cpu] as isize;
 
-        -1
+        (1..=MAX_TASKS as isize)
+            .map(|n| ((current + n) % MAX_TASKS as isize) as usize)
+            .find(|&i| self.tasks[i].state == TaskState::Ready && self.tasks[i].cpu == cpu)
+            .map_or(-1, |i| i as i8)
+    }
+
+    /// Takes a task from the busiest run queue when `cpu` only has its idle
+    /// task, leaving the one running there alone.
+    fn balance(&mut self, cpu: usize) {
+        let count = crate::smp::count();
+        if count < 2 || self.load(cpu) > 0 {
+            return;
+        }
+
+        let Some(busiest) = (0..count).max_by_key(|&c| self.load(c)) else {
+            return;
+        };
+        if self.load(busiest) < 2 {
+            return;
+        }
+
+        let movable = (0..MAX_TASKS as usize).find(|&slot| {
+            let task = &self.tasks[slot];
+            task.cpu == busiest
+                && task.state == TaskState::Ready
+                && !task.pinned
+                && !self.is_running(slot)
+        });
+
+        if let Some(slot) = movable {
+            self.tasks[slot].cpu = cpu;
+        }
+    }
+
+    /// Ready tasks on the run queue of `cpu`, not counting its idle task.
+    fn load(&self, cpu: usize) -> usize {
+        self.tasks
+            .iter()
+            .filter(|t| t.cpu == cpu && t.state == TaskState::Ready && !t.pinned)
+            .count()
+    }
+
+    fn least_loaded(&self) -> usize {
+        (0..crate::smp::count())
+            .min_by_key(|&c| self.load(c))
+            .unwrap_or(0)
+    }
+
+    fn is_running(&self, slot: usize) -> bool {
+        self.current_task.contains(&(slot as i8))
+    }
+
+    fn is_idle(&self, cpu: usize) -> bool {
+        let current = self.current_task[cpu];
+
+        current >= 0 && self.tasks[current as usize].pinned
     }
 
     fn get_free_slot(&self) -> usize {
@@ -200,64 +416,113 @@ impl TaskManager {
     }
 }
 
+/// User the calling task runs as; kernel tasks are always the admin.
+pub fn current_uid() -> u16 {
+    unsafe { (*(&raw mut TASK_MANAGER)).lock().uid() }
+}
+
+/// Slot of the calling task, None before the scheduler has started.
+pub fn current_task() -> Option<usize> {
+    unsafe { (*(&raw mut TASK_MANAGER)).lock().current() }
+}
+
 fn idle() {
     loop {
         unsafe { asm!("hlt") };
     }
 }
 
-pub fn exit() {
+pub fn exit(status: u32) {
     unsafe {
-        let t = (*(&raw mut TASK_MANAGER)).lock().current_task as usize;
+        let Some(t) = (*(&raw mut TASK_MANAGER)).lock().current() else {
+            return;
+        };
         (*(&raw mut TASK_MANAGER)).lock().tasks[t].state = TaskState::Zombie;
+        (*(&raw mut TASK_MANAGER)).lock().tasks[t].status = status;
 
+        // the syscall or fault that got here never returns to let go of it
+        crate::smp::release_kernel();
         asm!("int 0x20");
     }
 }
 
-#[naked]
-pub extern "C" fn timer() {
-    unsafe {
-        naked_asm!(
-            "cli",
-            "push eax",
-            "push ebx",
-            "push ecx",
-            "push edx",
-            "push esi",
-            "push edi",
-            "push ebp",
-            "push esp",
-            "call switch",
-            "mov esp, eax",
-            "pop ebp",
-            "pop edi",
-            "pop esi",
-            "pop edx",
-            "pop ecx",
-            "pop ebx",
-            "pop eax",
-            "sti",
-            "iretd",
-        );
-    }
+/// Saves the registers, has `$switch` pick the stack to go on with and
+/// returns on that one.
+macro_rules! switch_entry {
+    ($name:ident, $switch:literal) => {
+        #[naked]
+        pub extern "C" fn $name() {
+            unsafe {
+                naked_asm!(
+                    "cli",
+                    "push eax",
+                    "push ebx",
+                    "push ecx",
+                    "push edx",
+                    "push esi",
+                    "push edi",
+                    "push ebp",
+                    "push esp",
+                    concat!("call ", $switch),
+                    "mov esp, eax",
+                    "pop ebp",
+                    "pop edi",
+                    "pop esi",
+                    "pop edx",
+                    "pop ecx",
+                    "pop ebx",
+                    "pop eax",
+                    "sti",
+                    "iretd",
+                );
+            }
+        }
+    };
 }
 
+switch_entry!(timer, "switch");
+switch_entry!(rescheduled, "switch_rescheduled");
+
 #[unsafe(no_mangle)]
 pub extern "C" fn switch(esp: u32) -> u32 {
+    // every processor has a timer, the first one keeps the time
+    if crate::smp::cpu() == 0 {
+        unsafe { TICKS += 1 };
+    }
+
+    let esp = switch_task(esp);
+    crate::apic::end_interrupt(crate::exceptions::TIMER_INT);
+    esp
+}
+
+/// Entered through `smp::RESCHEDULE`, sent when this processor was given work.
+#[unsafe(no_mangle)]
+pub extern "C" fn switch_rescheduled(esp: u32) -> u32 {
+    let esp = switch_task(esp);
+    crate::apic::end_interrupt(crate::smp::RESCHEDULE);
+    esp
+}
+
+/// Stack of the task to run next. A task holding the kernel lock keeps
+/// running until it lets go of it, unless it was interrupted in user mode,
+/// where it cannot be holding it any more.
+fn switch_task(esp: u32) -> u32 {
+    if crate::smp::holds_kernel() {
+        if !unsafe { (*(esp as *const CPUState)).user_mode() } {
+            return esp;
+        }
+        crate::smp::release_kernel();
+    }
+
     unsafe {
-        let new_esp = (*(&raw mut TASK_MANAGER))
+        let (new_esp, k_stack) = (*(&raw mut TASK_MANAGER))
             .lock()
             .schedule(esp as *mut CPUState);
-        let k_stack = new_esp.1;
-        let new_esp = new_esp.0 as u32;
 
         if k_stack != 0 {
             crate::set_tss(k_stack);
         }
 
-        (*(&raw mut crate::pic::PICS)).end_interrupt(crate::exceptions::TIMER_INT);
-
-        new_esp
+        new_esp as u32
     }
 }
@@ -0,0 +1,526 @@
+use crate::block::{BlockDevice, BlockError};
+use crate::pci::{self, PciDevice};
+use alloc::boxed::Box;
+use core::sync::atomic::{Ordering, fence};
+use libk::port::{inb, inl, inw, outb, outl, outw};
+
+const VENDOR: u32 = 0x1AF4;
+const DEVICE_BLK_TRANSITIONAL: u32 = 0x1001;
+const DEVICE_BLK_MODERN: u32 = 0x1042;
+
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 128;
+
+/* legacy transport, I/O BAR 0 */
+const LEGACY_DEVICE_FEATURES: u16 = 0x00;
+const LEGACY_DRIVER_FEATURES: u16 = 0x04;
+const LEGACY_QUEUE_PFN: u16 = 0x08;
+const LEGACY_QUEUE_SIZE: u16 = 0x0C;
+const LEGACY_QUEUE_SELECT: u16 = 0x0E;
+const LEGACY_QUEUE_NOTIFY: u16 = 0x10;
+const LEGACY_STATUS: u16 = 0x12;
+const LEGACY_ISR: u16 = 0x13;
+const LEGACY_CONFIG: u16 = 0x14;
+
+/* modern transport, vendor specific PCI capabilities */
+const CAP_VENDOR: u8 = 0x09;
+const CAP_COMMON: u8 = 1;
+const CAP_NOTIFY: u8 = 2;
+const CAP_ISR: u8 = 3;
+const CAP_DEVICE: u8 = 4;
+
+const COMMON_DEVICE_FEATURE_SELECT: u32 = 0x00;
+const COMMON_DRIVER_FEATURE_SELECT: u32 = 0x08;
+const COMMON_DRIVER_FEATURE: u32 = 0x0C;
+const COMMON_STATUS: u32 = 0x14;
+const COMMON_QUEUE_SELECT: u32 = 0x16;
+const COMMON_QUEUE_SIZE: u32 = 0x18;
+const COMMON_QUEUE_ENABLE: u32 = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: u32 = 0x1E;
+const COMMON_QUEUE_DESC: u32 = 0x20;
+const COMMON_QUEUE_DRIVER: u32 = 0x28;
+const COMMON_QUEUE_DEVICE: u32 = 0x30;
+
+/// VIRTIO_F_VERSION_1, bit 32 of the feature set.
+const FEATURE_VERSION_1: u32 = 1 << 0;
+
+const DESC_NEXT: u16 = 1;
+const DESC_WRITE: u16 = 2;
+
+const REQ_IN: u32 = 0;
+const REQ_OUT: u32 = 1;
+const REQ_FLUSH: u32 = 4;
+
+const REQ_STATUS_OK: u8 = 0;
+
+/// Queues bigger than this are shrunk on the modern transport; legacy devices dictate the size.
+const MAX_QUEUE: u16 = 256;
+/// Largest single request; bigger transfers are split.
+const MAX_SECTORS: u32 = 256;
+
+const TIMEOUT: u32 = 10_000_000;
+/// How many interrupts a request sleeps through before it goes back to polling.
+const IRQ_WAITS: u32 = 1000;
+
+/// Only the first device gets the interrupt, any others are polled.
+static mut IRQ_DEVICE: Option<(PciDevice, Transport)> = None;
+static mut IRQ_LINE: u8 = 0xFF;
+static mut IRQ_ENABLED: bool = false;
+/// The processor the IOAPIC delivers the interrupt to.
+static mut IRQ_CPU: usize = 0;
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+struct Descriptor {
+    address: u64,
+    length: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+struct RequestHeader {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+#[derive(Copy, Clone)]
+enum Transport {
+    Legacy { io: u16 },
+    Modern { common: u32, notify: u32, isr: u32, device: u32 },
+}
+
+impl Transport {
+    fn status(&self) -> u8 {
+        match self {
+            Transport::Legacy { io } => inb(io + LEGACY_STATUS),
+            Transport::Modern { common, .. } => mmio_read8(common + COMMON_STATUS),
+        }
+    }
+
+    fn set_status(&self, status: u8) {
+        match self {
+            Transport::Legacy { io } => outb(io + LEGACY_STATUS, status),
+            Transport::Modern { common, .. } => mmio_write8(common + COMMON_STATUS, status),
+        }
+    }
+
+    fn capacity(&self) -> u64 {
+        match self {
+            Transport::Legacy { io } => {
+                inl(io + LEGACY_CONFIG) as u64 | (inl(io + LEGACY_CONFIG + 4) as u64) << 32
+            }
+            Transport::Modern { device, .. } => {
+                mmio_read32(*device) as u64 | (mmio_read32(device + 4) as u64) << 32
+            }
+        }
+    }
+
+    /// Reading the ISR status acknowledges the interrupt.
+    fn acknowledge(&self) -> u8 {
+        match self {
+            Transport::Legacy { io } => inb(io + LEGACY_ISR),
+            Transport::Modern { isr, .. } => mmio_read8(*isr),
+        }
+    }
+
+    fn notify(&self, queue: u16) {
+        match self {
+            Transport::Legacy { io } => outw(io + LEGACY_QUEUE_NOTIFY, queue),
+            Transport::Modern { notify, .. } => mmio_write16(*notify, queue),
+        }
+    }
+}
+
+fn mmio_read8(address: u32) -> u8 {
+    unsafe { core::ptr::read_volatile(address as *const u8) }
+}
+
+fn mmio_read16(address: u32) -> u16 {
+    unsafe { core::ptr::read_volatile(address as *const u16) }
+}
+
+fn mmio_read32(address: u32) -> u32 {
+    unsafe { core::ptr::read_volatile(address as *const u32) }
+}
+
+fn mmio_write8(address: u32, value: u8) {
+    unsafe { core::ptr::write_volatile(address as *mut u8, value) }
+}
+
+fn mmio_write16(address: u32, value: u16) {
+    unsafe { core::ptr::write_volatile(address as *mut u16, value) }
+}
+
+fn mmio_write32(address: u32, value: u32) {
+    unsafe { core::ptr::write_volatile(address as *mut u32, value) }
+}
+
+fn mmio_write64(address: u32, value: u64) {
+    mmio_write32(address, value as u32);
+    mmio_write32(address + 4, (value >> 32) as u32);
+}
+
+/// A split virtqueue: descriptor table, available ring and used ring, plus a
+/// scratch page holding the request header and status byte.
+struct Queue {
+    size: u16,
+    desc: u32,
+    avail: u32,
+    used: u32,
+    scratch: u32,
+    last_used: u16,
+}
+
+impl Queue {
+    /// Legacy devices expect the used ring on the page after the available ring.
+    fn allocate(size: u16) -> Option<Queue> {
+        let n = size as u32;
+        let avail_end = 16 * n + 6 + 2 * n;
+        let used_start = (avail_end + 0xFFF) & !0xFFF;
+        let used_end = used_start + 6 + 8 * n;
+        let total = ((used_end + 0xFFF) & !0xFFF) + 4096;
+
+        let Some(base) = (unsafe { (*(&raw mut crate::pmm::PADDR)).malloc(total) }) else {
+            libk::println!("[x] virtio-blk: no memory for a {} entry queue", size);
+            return None;
+        };
+
+        unsafe {
+            core::ptr::write_bytes(base as *mut u8, 0, total as usize);
+        }
+
+        Some(Queue {
+            size,
+            desc: base,
+            avail: base + 16 * n,
+            used: base + used_start,
+            scratch: base + total - 4096,
+            last_used: 0,
+        })
+    }
+
+    fn set_descriptor(&self, index: u16, address: u32, length: u32, flags: u16, next: u16) {
+        unsafe {
+            *((self.desc as *mut Descriptor).add(index as usize)) = Descriptor {
+                address: address as u64,
+                length,
+                flags,
+                next,
+            };
+        }
+    }
+
+    /// Publishes the chain starting at `head` and returns once the device has
+    /// consumed it, sleeping on the interrupt in between if `wait_irq`.
+    fn submit(&mut self, transport: &Transport, head: u16, wait_irq: bool) -> Result<(), BlockError> {
+        let avail_idx = mmio_read16(self.avail + 2);
+        let slot = self.avail + 4 + 2 * (avail_idx % self.size) as u32;
+
+        mmio_write16(slot, head);
+        fence(Ordering::SeqCst);
+        mmio_write16(self.avail + 2, avail_idx.wrapping_add(1));
+        fence(Ordering::SeqCst);
+
+        transport.notify(0);
+
+        for i in 0..TIMEOUT {
+            let used_idx = mmio_read16(self.used + 2);
+
+            if used_idx != self.last_used {
+                self.last_used = used_idx;
+                return Ok(());
+            }
+
+            if wait_irq && i < IRQ_WAITS {
+                crate::block::wait_irq();
+            }
+        }
+
+        Err(BlockError::Timeout)
+    }
+}
+
+pub struct VirtioBlk {
+    transport: Transport,
+    queue: Queue,
+    capacity: u64,
+    /// Whether this is the device the interrupt is installed for.
+    irq: bool,
+}
+
+impl VirtioBlk {
+    fn request(&mut self, kind: u32, lba: u64, sectors: u32, buffer: u32) -> Result<(), BlockError> {
+        let header = self.queue.scratch;
+        let status = self.queue.scratch + core::mem::size_of::<RequestHeader>() as u32;
+
+        unsafe {
+            *(header as *mut RequestHeader) = RequestHeader {
+                kind,
+                reserved: 0,
+                sector: lba,
+            };
+            *(status as *mut u8) = 0xFF;
+        }
+
+        self.queue.set_descriptor(0, header, 16, DESC_NEXT, 1);
+
+        if sectors == 0 {
+            self.queue.set_descriptor(1, status, 1, DESC_WRITE, 0);
+        } else {
+            let data_flags = if kind == REQ_IN { DESC_WRITE } else { 0 };
+            self.queue.set_descriptor(1, buffer, sectors * 512, data_flags | DESC_NEXT, 2);
+            self.queue.set_descriptor(2, status, 1, DESC_WRITE, 0);
+        }
+
+        let wait_irq = self.irq && unsafe { IRQ_ENABLED } && crate::block::can_wait_irq(unsafe { IRQ_CPU });
+        self.queue.submit(&self.transport, 0, wait_irq)?;
+
+        match unsafe { *(status as *const u8) } {
+            REQ_STATUS_OK => Ok(()),
+            code => Err(BlockError::Error(code)),
+        }
+    }
+
+    fn transfer(&mut self, lba: u64, sectors: u32, buffer: u32, write: bool) -> Result<(), BlockError> {
+        if lba + sectors as u64 > self.capacity {
+            return Err(BlockError::OutOfRange);
+        }
+
+        let kind = if write { REQ_OUT } else { REQ_IN };
+        let mut done = 0;
+
+        while done < sectors {
+            let count = core::cmp::min(sectors - done, MAX_SECTORS);
+            self.request(kind, lba + done as u64, count, buffer + done * 512)?;
+            done += count;
+        }
+
+        if write {
+            // devices without VIRTIO_BLK_F_FLUSH reject this, which is harmless
+            let _ = self.request(REQ_FLUSH, 0, 0, 0);
+        }
+
+        Ok(())
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn driver(&self) -> &'static str {
+        "virtio-blk"
+    }
+
+    fn model(&self) -> &str {
+        match self.transport {
+            Transport::Legacy { .. } => "legacy",
+            Transport::Modern { .. } => "modern",
+        }
+    }
+
+    fn sectors(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read(&mut self, lba: u64, sectors: u32, target: *mut u8) -> Result<(), BlockError> {
+        self.transfer(lba, sectors, target as u32, false)
+    }
+
+    fn write(&mut self, lba: u64, sectors: u32, buffer: *const u8) -> Result<(), BlockError> {
+        self.transfer(lba, sectors, buffer as u32, true)
+    }
+}
+
+/// Address of a region described by a virtio capability, if its BAR is reachable without paging.
+fn region(dev: &PciDevice, cap: u8) -> Option<u32> {
+    let bar = dev.read_config(cap + 4) as u8;
+    let offset = dev.read_config(cap + 8);
+
+    if bar > 5 {
+        return None;
+    }
+
+    let low = dev.read_config(0x10 + bar * 4);
+    if low & 1 != 0 {
+        return None;
+    }
+
+    // 64-bit BARs mapped above 4 GiB are out of reach
+    if (low >> 1) & 3 == 2 && bar < 5 && dev.read_config(0x10 + (bar + 1) * 4) != 0 {
+        return None;
+    }
+
+    Some((low & !0xF) + offset)
+}
+
+fn modern_transport(dev: &PciDevice) -> Option<(Transport, Queue)> {
+    let mut common = None;
+    let mut notify = None;
+    let mut isr = None;
+    let mut device = None;
+    let mut multiplier = 0;
+
+    for cap in dev.capabilities(CAP_VENDOR) {
+        let kind = (dev.read_config(cap) >> 24) as u8;
+
+        match kind {
+            CAP_COMMON if common.is_none() => common = region(dev, cap),
+            CAP_NOTIFY if notify.is_none() => {
+                notify = region(dev, cap);
+                multiplier = dev.read_config(cap + 16);
+            }
+            CAP_ISR if isr.is_none() => isr = region(dev, cap),
+            CAP_DEVICE if device.is_none() => device = region(dev, cap),
+            _ => {}
+        }
+    }
+
+    let (common, notify, isr, device) = (common?, notify?, isr?, device?);
+
+    let status = |s: u8| mmio_write8(common + COMMON_STATUS, s);
+
+    status(0);
+    status(STATUS_ACKNOWLEDGE);
+    status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+    // no optional features, just VERSION_1 which the modern interface requires
+    mmio_write32(common + COMMON_DEVICE_FEATURE_SELECT, 0);
+    mmio_write32(common + COMMON_DRIVER_FEATURE_SELECT, 0);
+    mmio_write32(common + COMMON_DRIVER_FEATURE, 0);
+    mmio_write32(common + COMMON_DRIVER_FEATURE_SELECT, 1);
+    mmio_write32(common + COMMON_DRIVER_FEATURE, FEATURE_VERSION_1);
+
+    status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+    if mmio_read8(common + COMMON_STATUS) & STATUS_FEATURES_OK == 0 {
+        status(STATUS_FAILED);
+        return None;
+    }
+
+    mmio_write16(common + COMMON_QUEUE_SELECT, 0);
+    let size = core::cmp::min(mmio_read16(common + COMMON_QUEUE_SIZE), MAX_QUEUE);
+    if size < 3 {
+        status(STATUS_FAILED);
+        return None;
+    }
+
+    let queue = Queue::allocate(size)?;
+    mmio_write16(common + COMMON_QUEUE_SIZE, size);
+    mmio_write64(common + COMMON_QUEUE_DESC, queue.desc as u64);
+    mmio_write64(common + COMMON_QUEUE_DRIVER, queue.avail as u64);
+    mmio_write64(common + COMMON_QUEUE_DEVICE, queue.used as u64);
+    mmio_write16(common + COMMON_QUEUE_ENABLE, 1);
+
+    let notify_off = mmio_read16(common + COMMON_QUEUE_NOTIFY_OFF) as u32;
+
+    let transport = Transport::Modern {
+        common,
+        notify: notify + notify_off * multiplier,
+        isr,
+        device,
+    };
+
+    Some((transport, queue))
+}
+
+fn legacy_transport(dev: &PciDevice) -> Option<(Transport, Queue)> {
+    let bar = dev.read_config(0x10);
+    if bar & 1 == 0 {
+        return None;
+    }
+
+    let io = (bar & !0x3) as u16;
+
+    outb(io + LEGACY_STATUS, 0);
+    outb(io + LEGACY_STATUS, STATUS_ACKNOWLEDGE);
+    outb(io + LEGACY_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+    let _ = inl(io + LEGACY_DEVICE_FEATURES);
+    outl(io + LEGACY_DRIVER_FEATURES, 0);
+
+    outw(io + LEGACY_QUEUE_SELECT, 0);
+    let size = inw(io + LEGACY_QUEUE_SIZE);
+    if size < 3 {
+        outb(io + LEGACY_STATUS, STATUS_FAILED);
+        return None;
+    }
+
+    let queue = Queue::allocate(size)?;
+    outl(io + LEGACY_QUEUE_PFN, queue.desc / 4096);
+
+    Some((Transport::Legacy { io }, queue))
+}
+
+fn probe(dev: &PciDevice) -> Option<VirtioBlk> {
+    dev.enable_bus_mastering();
+
+    let (transport, queue) = modern_transport(dev).or_else(|| legacy_transport(dev))?;
+
+    transport.set_status(transport.status() | STATUS_DRIVER_OK);
+
+    Some(VirtioBlk {
+        capacity: transport.capacity(),
+        transport,
+        queue,
+        irq: false,
+    })
+}
+
+pub fn init() {
+    let mut devices = pci::find_devices(VENDOR, DEVICE_BLK_MODERN);
+    devices.extend(pci::find_devices(VENDOR, DEVICE_BLK_TRANSITIONAL));
+
+    for dev in devices.iter() {
+        match probe(dev) {
+            Some(mut blk) => {
+                unsafe {
+                    if (*(&raw const IRQ_DEVICE)).is_none() {
+                        IRQ_DEVICE = Some((*dev, blk.transport));
+                        blk.irq = true;
+                    }
+                }
+
+                crate::block::register(Box::new(blk));
+            }
+            None => libk::println!("[x] virtio-blk device could not be initialised"),
+        }
+    }
+}
+
+/// Routes the first device's interrupt to its PCI IRQ unless that is already
+/// taken by another driver, in which case its requests keep being polled.
+pub fn install_irq() {
+    unsafe {
+        let Some((dev, _)) = *(&raw const IRQ_DEVICE) else {
+            return;
+        };
+        let Some(line) = crate::apic::pci_irq(&dev) else {
+            return;
+        };
+
+        let vector = 32 + line as usize;
+        if !(*(&raw const crate::idt::IDT)).is_free(vector) {
+            return;
+        }
+
+        IRQ_LINE = line;
+        (*(&raw mut crate::idt::IDT)).add(vector, irq as u32);
+        crate::apic::enable_irq(line, crate::apic::Bus::Pci);
+
+        IRQ_CPU = crate::smp::cpu();
+        IRQ_ENABLED = true;
+    }
+}
+
+pub extern "x86-interrupt" fn irq() {
+    unsafe {
+        if let Some((_, transport)) = *(&raw const IRQ_DEVICE) {
+            transport.acknowledge();
+        }
+
+        crate::apic::end_interrupt(32 + IRQ_LINE);
+    }
+}
@@ -1059,6 +1059,9 @@ pub fn resize_handler(id: u32, w: u32, h: u32, buffer: u32) -> ! {
             syscall::exit();
         }
 
+        // the screen itself may have changed
+        (*(&raw mut SCREEN)).init();
+
         for i in 0..(*(&raw mut crate::widgets::WINDOWS)).len() {
             if crate::widgets::WINDOWS[i].id == id as u16 {
                 let old_buffer = crate::widgets::WINDOWS[i].buffer;
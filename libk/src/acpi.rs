@@ -0,0 +1,181 @@
+//! What the kernel found in the ACPI tables at boot. Programs get a copy
+//! through `info`, the kernel keeps its own for the drivers.
+
+pub const MAX_TABLES: usize = 32;
+pub const MAX_CPUS: usize = 16;
+pub const MAX_IOAPICS: usize = 4;
+pub const MAX_OVERRIDES: usize = 16;
+pub const MAX_NMIS: usize = 8;
+
+/// Address spaces of a `GenericAddress`.
+pub const SPACE_MEMORY: u8 = 0;
+pub const SPACE_IO: u8 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Acpi {
+    pub revision: u8,
+    pub oem: [u8; 6],
+    /// Tables were listed by the XSDT rather than the RSDT.
+    pub xsdt: bool,
+    /// Signatures of every table with a valid checksum.
+    pub tables: [[u8; 4]; MAX_TABLES],
+    pub table_count: usize,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    pub lapic_address: u64,
+    /// There are 8259 PICs to mask before using the APICs.
+    pub legacy_pic: bool,
+    pub cpus: [Cpu; MAX_CPUS],
+    pub cpu_count: usize,
+    pub ioapics: [IoApic; MAX_IOAPICS],
+    pub ioapic_count: usize,
+    pub overrides: [Override; MAX_OVERRIDES],
+    pub override_count: usize,
+    pub nmis: [Nmi; MAX_NMIS],
+    pub nmi_count: usize,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Cpu {
+    pub processor_id: u32,
+    pub apic_id: u32,
+    /// Disabled, but firmware allows starting it anyway.
+    pub online_capable: bool,
+    pub enabled: bool,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    /// First global system interrupt it handles.
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ wired to a different global system interrupt, or with a
+/// polarity and trigger mode other than the ISA default.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Override {
+    pub irq: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+/// Which LINT pin of a local APIC the NMI is wired to, 0xFF for all of them.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Nmi {
+    pub processor_id: u8,
+    pub flags: u16,
+    pub lint: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub dsdt: u64,
+    pub sci: u16,
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event: u32,
+    pub pm1b_event: u32,
+    pub pm1a_control: u32,
+    pub pm1b_control: u32,
+    pub pm_timer: u32,
+    /// CMOS index of the century, 0 if there is none.
+    pub century: u8,
+    pub boot_flags: u16,
+    pub flags: u32,
+    /// Only there if `flags` has `FADT_RESET_SUPPORTED`.
+    pub reset: GenericAddress,
+    pub reset_value: u8,
+}
+
+pub const FADT_RESET_SUPPORTED: u32 = 1 << 10;
+
+/// Where a register is and how to reach it, as laid out in the tables.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub address: u64,
+    pub number: u8,
+    pub comparators: u8,
+    pub counter_64: bool,
+    pub legacy_replacement: bool,
+    pub vendor: u16,
+    /// Smallest periodic tick without lost interrupts, in counter ticks.
+    pub min_tick: u16,
+}
+
+pub const ACPI_NULL: Acpi = Acpi {
+    revision: 0,
+    oem: [0; 6],
+    xsdt: false,
+    tables: [[0; 4]; MAX_TABLES],
+    table_count: 0,
+    madt: None,
+    fadt: None,
+    hpet: None,
+};
+
+pub const MADT_NULL: Madt = Madt {
+    lapic_address: 0,
+    legacy_pic: false,
+    cpus: [Cpu {
+        processor_id: 0,
+        apic_id: 0,
+        online_capable: false,
+        enabled: false,
+    }; MAX_CPUS],
+    cpu_count: 0,
+    ioapics: [IoApic {
+        id: 0,
+        address: 0,
+        gsi_base: 0,
+    }; MAX_IOAPICS],
+    ioapic_count: 0,
+    overrides: [Override {
+        irq: 0,
+        gsi: 0,
+        flags: 0,
+    }; MAX_OVERRIDES],
+    override_count: 0,
+    nmis: [Nmi {
+        processor_id: 0,
+        flags: 0,
+        lint: 0,
+    }; MAX_NMIS],
+    nmi_count: 0,
+};
+
+/// The tables the kernel parsed, None if the firmware has no valid RSDP.
+pub fn info() -> Option<Acpi> {
+    let mut acpi = ACPI_NULL;
+
+    if crate::syscall::syscall(66, &raw mut acpi as u32, 0, 0) != 0 {
+        return None;
+    }
+
+    Some(acpi)
+}
@@ -1,11 +1,32 @@
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
 pub struct BootInfo {
+    /// Ranges the kernel was loaded to, unused entries have a size of 0.
+    /// Kept first so stage 3 can fill it in without the rest of the layout.
+    pub kernel: [Range; KERNEL_RANGES],
     pub mmap: MemoryMap,
     pub rsdp: Rsdp,
     pub tss: u16,
     pub vbe: VbeInfoBlock,
     pub mode: VbeModeInfoBlock,
+    /// Kernel command line, zero padded.
+    pub cmdline: [u8; crate::cmdline::MAX_LEN],
+    /// Modules loaded by a Multiboot loader, unused entries have a size of 0.
+    pub modules: [Range; MODULES],
+}
+
+// stage 3 writes these fields at fixed offsets without the rest of the layout
+const _: () = assert!(core::mem::offset_of!(BootInfo, cmdline) == 1637);
+const _: () = assert!(core::mem::offset_of!(BootInfo, modules) == 1893);
+
+pub const KERNEL_RANGES: usize = 8;
+pub const MODULES: usize = 8;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Range {
+    pub base: u32,
+    pub size: u32,
 }
 
 #[repr(C, packed)]
@@ -43,33 +64,11 @@ pub struct Rsdp {
     pub oem_id: [u8; 6],
     pub revision: u8,
     pub rsdt_address: u32,
-}
-
-#[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
-pub struct RsdtHeader {
-    signature: [u8; 4],
-    length: u32,
-    revision: u8,
-    checksum: u8,
-    oem_id: [u8; 6],
-    oem_table_id: [u8; 8],
-    oem_revision: u32,
-    creator_id: u32,
-    creator_revision: u32,
-}
-
-#[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
-pub struct Rsdt {
-    header: RsdtHeader,
-    ptr: [u32; 10],
-}
-
-#[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
-pub struct GenericTable {
-    signature: [u8; 4],
+    // ACPI 2.0 and later, only valid if `revision` is at least 2
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    pub reserved: [u8; 3],
 }
 
 #[repr(C, packed)]
@@ -123,20 +122,6 @@ pub struct VbeModeInfoBlock {
 }
 
 impl BootInfo {
-    pub fn get_acpi(&self) {
-        unsafe {
-            let rsdt_ptr = self.rsdp.rsdt_address as *const Rsdt;
-            let rsdt = rsdt_ptr.read();
-
-            let entries = (rsdt.header.length as usize - core::mem::size_of::<RsdtHeader>()) / 4;
-
-            for i in 0..entries {
-                let entry = *(rsdt.ptr[i] as *const GenericTable);
-                let signature = core::str::from_utf8_unchecked(&entry.signature);
-            }
-        }
-    }
-
     pub fn get_mmap(&self, start: u64) -> MemoryMapEntry {
         for i in 0..32 {
             if self.mmap.entries[i].base == start {
@@ -149,13 +134,18 @@ impl BootInfo {
 }
 
 pub const BOOTINFO_NULL: BootInfo = BootInfo {
+    kernel: [RANGE_NULL; KERNEL_RANGES],
     mmap: MMAP_NULL,
     rsdp: RSDP_NULL,
     vbe: VBEINFO_NULL,
     mode: VBEBLOCK_NULL,
     tss: 0,
+    cmdline: [0; crate::cmdline::MAX_LEN],
+    modules: [RANGE_NULL; MODULES],
 };
 
+const RANGE_NULL: Range = Range { base: 0, size: 0 };
+
 const VBEINFO_NULL: VbeInfoBlock = VbeInfoBlock {
     signature: [0; 4],
     version: 0,
@@ -183,6 +173,10 @@ const RSDP_NULL: Rsdp = Rsdp {
     oem_id: [0; 6],
     revision: 0,
     rsdt_address: 0,
+    length: 0,
+    xsdt_address: 0,
+    extended_checksum: 0,
+    reserved: [0; 3],
 };
 
 const VBEBLOCK_NULL: VbeModeInfoBlock = VbeModeInfoBlock {
@@ -0,0 +1,58 @@
+//! Kernel command line: whitespace separated `key=value` pairs or bare flags,
+//! set when the image is built (`cmdline.txt`) and handed over by stage 2.
+
+use alloc::string::String;
+
+/// Longest command line stage 2 passes on.
+pub const MAX_LEN: usize = 256;
+
+/// Value of `key` in `line`; bare flags have an empty value and the last
+/// occurrence wins, so a parameter can be overridden by appending it.
+pub fn find<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split_ascii_whitespace()
+        .filter_map(|param| match param.split_once('=') {
+            Some((k, v)) if k == key => Some(v),
+            None if param == key => Some(""),
+            _ => None,
+        })
+        .last()
+}
+
+/// Whether a flag is set: bare, or `on`, `yes`, `true` or `1`.
+pub fn is_set(value: Option<&str>) -> bool {
+    matches!(value, Some("" | "1" | "on" | "yes" | "true"))
+}
+
+fn query(key: &str) -> Option<String> {
+    let mut buffer = [0u8; MAX_LEN];
+
+    let length = crate::syscall::syscall(
+        64,
+        key.as_ptr() as u32,
+        buffer.as_mut_ptr() as u32,
+        key.len() as u32,
+    );
+
+    if length as usize > MAX_LEN {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&buffer[..length as usize]).into_owned())
+}
+
+/// The whole command line the kernel was booted with.
+pub fn line() -> String {
+    query("").unwrap_or_default()
+}
+
+pub fn get(key: &str) -> Option<String> {
+    if key.is_empty() {
+        return None;
+    }
+
+    query(key)
+}
+
+pub fn flag(key: &str) -> bool {
+    is_set(get(key).as_deref())
+}
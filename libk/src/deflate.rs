@@ -0,0 +1,814 @@
+//! DEFLATE (RFC 1951) with its zlib (RFC 1950) and gzip (RFC 1952) wrappers.
+//!
+//! `Decoder` takes compressed data in pieces of any size and appends what it
+//! can decode to an output buffer, so a file can be inflated while it is read.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::hash::{Adler32, Crc32};
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_SIZE: usize = 1 << 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order the code length code lengths of a dynamic block are stored in.
+const CODE_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const GZIP_EXTRA: u8 = 0x04;
+const GZIP_NAME: u8 = 0x08;
+const GZIP_COMMENT: u8 = 0x10;
+const GZIP_HEADER_CRC: u8 = 0x02;
+
+/// Framing around the compressed data.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Format {
+    Raw,
+    Zlib,
+    Gzip,
+}
+
+/// How hard `compress` tries.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Level {
+    /// Stored blocks, the data is only framed.
+    Stored,
+    /// LZ77 matches coded with the fixed Huffman tables.
+    Fixed,
+}
+
+/// Why decoding stopped: out of input, which a later `push` may fix, or bad data.
+enum Fail {
+    Input,
+    Bad(&'static str),
+}
+
+impl From<&'static str> for Fail {
+    fn from(e: &'static str) -> Fail {
+        Fail::Bad(e)
+    }
+}
+
+/// Canonical Huffman code, decoded a bit at a time.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, &'static str> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err("Over-subscribed Huffman code");
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn fixed() -> (Huffman, Huffman) {
+        let mut lengths = [0u8; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+
+        // neither table can be over-subscribed
+        let literals = Huffman::new(&lengths).unwrap_or(Huffman::EMPTY);
+        let distances = Huffman::new(&[5; 30]).unwrap_or(Huffman::EMPTY);
+        (literals, distances)
+    }
+
+    const EMPTY: Huffman = Huffman {
+        counts: [0; 16],
+        symbols: Vec::new(),
+    };
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Header,
+    Block,
+    Stored(u16),
+    Codes,
+    Trailer,
+    Done,
+}
+
+/// Streaming decompressor for one stream in the given `Format`.
+pub struct Decoder {
+    format: Format,
+    state: State,
+    last: bool,
+
+    input: Vec<u8>,
+    pos: usize,
+    bits: u32,
+    count: u32,
+
+    literals: Huffman,
+    distances: Huffman,
+
+    window: Vec<u8>,
+    head: usize,
+    filled: usize,
+
+    adler: Adler32,
+    crc: Crc32,
+    length: u32,
+}
+
+impl Decoder {
+    pub fn new(format: Format) -> Decoder {
+        Decoder {
+            format,
+            state: if format == Format::Raw { State::Block } else { State::Header },
+            last: false,
+            input: Vec::new(),
+            pos: 0,
+            bits: 0,
+            count: 0,
+            literals: Huffman::EMPTY,
+            distances: Huffman::EMPTY,
+            window: vec![0u8; WINDOW],
+            head: 0,
+            filled: 0,
+            adler: Adler32::new(),
+            crc: Crc32::new(),
+            length: 0,
+        }
+    }
+
+    /// Decodes as much of `input` as it can, appending the data to `output`.
+    /// Returns true once the stream has ended and its checksum matched.
+    pub fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<bool, &'static str> {
+        self.input.extend_from_slice(input);
+        let start = output.len();
+
+        let result = self.run(output, start);
+
+        self.input.drain(..self.pos);
+        self.pos = 0;
+
+        result.map(|_| self.state == State::Done)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    fn run(&mut self, output: &mut Vec<u8>, start: usize) -> Result<(), &'static str> {
+        loop {
+            let step = match self.state {
+                State::Header => self.atomic(Decoder::header),
+                State::Block => self.atomic(Decoder::block),
+                State::Stored(left) => self.stored(left, output),
+                State::Codes => self.codes(output),
+                State::Trailer => {
+                    self.sum(&output[start..]);
+                    self.atomic(Decoder::trailer)
+                }
+                State::Done => return Ok(()),
+            };
+
+            match step {
+                Ok(()) => {}
+                Err(Fail::Input) => {
+                    if self.state != State::Trailer {
+                        self.sum(&output[start..]);
+                    }
+                    return Ok(());
+                }
+                Err(Fail::Bad(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Runs a step that reads everything it needs before it writes, rewinding
+    /// the input if it runs out so the step can start over on the next `push`.
+    fn atomic<T>(&mut self, step: impl FnOnce(&mut Decoder) -> Result<T, Fail>) -> Result<T, Fail> {
+        let mark = (self.pos, self.bits, self.count);
+
+        let result = step(self);
+        if let Err(Fail::Input) = result {
+            (self.pos, self.bits, self.count) = mark;
+        }
+
+        result
+    }
+
+    fn sum(&mut self, data: &[u8]) {
+        match self.format {
+            Format::Raw => {}
+            Format::Zlib => self.adler.update(data),
+            Format::Gzip => self.crc.update(data),
+        }
+        self.length = self.length.wrapping_add(data.len() as u32);
+    }
+
+    fn take(&mut self, n: u32) -> Result<u32, Fail> {
+        while self.count < n {
+            let byte = *self.input.get(self.pos).ok_or(Fail::Input)?;
+            self.pos += 1;
+            self.bits |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.bits & ((1u32 << n) - 1);
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, Fail> {
+        self.take(8).map(|b| b as u8)
+    }
+
+    fn align(&mut self) {
+        let skip = self.count % 8;
+        self.bits >>= skip;
+        self.count -= skip;
+    }
+
+    fn decode(&mut self, table: Table) -> Result<u16, Fail> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for length in 1..16 {
+            code |= self.take(1)? as i32;
+
+            let huffman = match table {
+                Table::Literals => &self.literals,
+                Table::Distances => &self.distances,
+            };
+            let count = huffman.counts[length] as i32;
+            if code - count < first {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(Fail::Bad("Invalid Huffman code"))
+    }
+
+    fn emit(&mut self, byte: u8, output: &mut Vec<u8>) {
+        output.push(byte);
+        self.window[self.head] = byte;
+        self.head = (self.head + 1) % WINDOW;
+        self.filled = core::cmp::min(self.filled + 1, WINDOW);
+    }
+
+    fn header(&mut self) -> Result<(), Fail> {
+        match self.format {
+            Format::Raw => {}
+            Format::Zlib => {
+                let (cmf, flags) = (self.byte()?, self.byte()?);
+
+                if cmf & 0x0F != 8 || cmf >> 4 > 7 || (cmf as u16 * 256 + flags as u16) % 31 != 0 {
+                    return Err(Fail::Bad("Not a zlib stream"));
+                }
+                if flags & 0x20 != 0 {
+                    return Err(Fail::Bad("Preset dictionaries are not supported"));
+                }
+            }
+            Format::Gzip => {
+                if self.byte()? != 0x1F || self.byte()? != 0x8B || self.byte()? != 8 {
+                    return Err(Fail::Bad("Not a gzip stream"));
+                }
+
+                let flags = self.byte()?;
+                // modification time, extra flags and OS
+                for _ in 0..6 {
+                    self.byte()?;
+                }
+
+                if flags & GZIP_EXTRA != 0 {
+                    let length = self.take(16)?;
+                    for _ in 0..length {
+                        self.byte()?;
+                    }
+                }
+                for flag in [GZIP_NAME, GZIP_COMMENT] {
+                    if flags & flag != 0 {
+                        while self.byte()? != 0 {}
+                    }
+                }
+                if flags & GZIP_HEADER_CRC != 0 {
+                    self.take(16)?;
+                }
+            }
+        }
+
+        self.state = State::Block;
+        Ok(())
+    }
+
+    fn block(&mut self) -> Result<(), Fail> {
+        self.last = self.take(1)? == 1;
+
+        match self.take(2)? {
+            0 => {
+                self.align();
+                let length = self.take(16)?;
+                if self.take(16)? != !length & 0xFFFF {
+                    return Err(Fail::Bad("Stored block length is corrupt"));
+                }
+
+                self.state = State::Stored(length as u16);
+            }
+            1 => {
+                (self.literals, self.distances) = Huffman::fixed();
+                self.state = State::Codes;
+            }
+            2 => {
+                self.dynamic()?;
+                self.state = State::Codes;
+            }
+            _ => return Err(Fail::Bad("Invalid block type")),
+        }
+
+        Ok(())
+    }
+
+    fn dynamic(&mut self) -> Result<(), Fail> {
+        let literals = self.take(5)? as usize + 257;
+        let distances = self.take(5)? as usize + 1;
+        let codes = self.take(4)? as usize + 4;
+
+        if literals > 286 || distances > 30 {
+            return Err(Fail::Bad("Too many codes in dynamic block"));
+        }
+
+        let mut lengths = [0u8; 19];
+        for &index in &CODE_ORDER[..codes] {
+            lengths[index] = self.take(3)? as u8;
+        }
+        self.literals = Huffman::new(&lengths)?;
+
+        let mut lengths = [0u8; 286 + 30];
+        let mut index = 0;
+        while index < literals + distances {
+            let symbol = self.decode(Table::Literals)?;
+
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => {
+                    if index == 0 {
+                        return Err(Fail::Bad("Repeat with no previous length"));
+                    }
+                    (lengths[index - 1], 3 + self.take(2)? as usize)
+                }
+                17 => (0, 3 + self.take(3)? as usize),
+                _ => (0, 11 + self.take(7)? as usize),
+            };
+
+            if index + repeat > literals + distances {
+                return Err(Fail::Bad("Code lengths overflow"));
+            }
+            lengths[index..index + repeat].fill(value);
+            index += repeat;
+        }
+
+        if lengths[256] == 0 {
+            return Err(Fail::Bad("Block has no end code"));
+        }
+
+        self.literals = Huffman::new(&lengths[..literals])?;
+        self.distances = Huffman::new(&lengths[literals..literals + distances])?;
+        Ok(())
+    }
+
+    fn stored(&mut self, mut left: u16, output: &mut Vec<u8>) -> Result<(), Fail> {
+        while left > 0 {
+            // aligned, so a byte is either wholly there or not
+            match self.byte() {
+                Ok(byte) => self.emit(byte, output),
+                Err(e) => {
+                    self.state = State::Stored(left);
+                    return Err(e);
+                }
+            }
+            left -= 1;
+        }
+
+        self.state = if self.last { State::Trailer } else { State::Block };
+        Ok(())
+    }
+
+    fn codes(&mut self, output: &mut Vec<u8>) -> Result<(), Fail> {
+        while !self.atomic(|decoder| decoder.symbol(output))? {}
+
+        self.state = if self.last { State::Trailer } else { State::Block };
+        Ok(())
+    }
+
+    /// Decodes one literal or match, returning true at the end of the block.
+    fn symbol(&mut self, output: &mut Vec<u8>) -> Result<bool, Fail> {
+        let symbol = self.decode(Table::Literals)? as usize;
+
+        if symbol < 256 {
+            self.emit(symbol as u8, output);
+            return Ok(false);
+        }
+        if symbol == 256 {
+            return Ok(true);
+        }
+
+        let symbol = symbol - 257;
+        if symbol >= 29 {
+            return Err(Fail::Bad("Invalid length code"));
+        }
+        let length = LENGTH_BASE[symbol] as usize + self.take(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+        let symbol = self.decode(Table::Distances)? as usize;
+        if symbol >= 30 {
+            return Err(Fail::Bad("Invalid distance code"));
+        }
+        let distance = DIST_BASE[symbol] as usize + self.take(DIST_EXTRA[symbol] as u32)? as usize;
+
+        if distance > self.filled {
+            return Err(Fail::Bad("Distance reaches before the start"));
+        }
+
+        for _ in 0..length {
+            let byte = self.window[(self.head + WINDOW - distance) % WINDOW];
+            self.emit(byte, output);
+        }
+
+        Ok(false)
+    }
+
+    fn trailer(&mut self) -> Result<(), Fail> {
+        self.align();
+
+        match self.format {
+            Format::Raw => {}
+            Format::Zlib => {
+                let mut sum = 0u32;
+                for _ in 0..4 {
+                    sum = (sum << 8) | self.byte()? as u32;
+                }
+
+                if sum != self.adler.finish() {
+                    return Err(Fail::Bad("Adler-32 mismatch"));
+                }
+            }
+            Format::Gzip => {
+                let crc = self.take(16)? | (self.take(16)? << 16);
+                let length = self.take(16)? | (self.take(16)? << 16);
+
+                if crc != self.crc.finish() {
+                    return Err(Fail::Bad("CRC-32 mismatch"));
+                }
+                if length != self.length {
+                    return Err(Fail::Bad("Length mismatch"));
+                }
+            }
+        }
+
+        self.state = State::Done;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Table {
+    Literals,
+    Distances,
+}
+
+/// Decompresses a whole stream held in memory.
+pub fn decompress(data: &[u8], format: Format) -> Result<Vec<u8>, &'static str> {
+    let mut decoder = Decoder::new(format);
+    let mut output = Vec::new();
+
+    if decoder.push(data, &mut output)? {
+        Ok(output)
+    } else {
+        Err("Unexpected end of data")
+    }
+}
+
+struct BitWriter {
+    output: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, n: u32) {
+        self.bits |= value << self.count;
+        self.count += n;
+
+        while self.count >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes go out most significant bit first.
+    fn code(&mut self, code: u32, n: u32) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.put(0, 8 - self.count);
+        }
+    }
+
+    fn literal(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn matched(&mut self, length: usize, distance: usize) {
+        let symbol = LENGTH_BASE.iter().rposition(|&b| b as usize <= length).unwrap_or(0);
+        self.literal(257 + symbol as u32);
+        self.put((length - LENGTH_BASE[symbol] as usize) as u32, LENGTH_EXTRA[symbol] as u32);
+
+        let symbol = DIST_BASE.iter().rposition(|&b| b as usize <= distance).unwrap_or(0);
+        self.code(symbol as u32, 5);
+        self.put((distance - DIST_BASE[symbol] as usize) as u32, DIST_EXTRA[symbol] as u32);
+    }
+}
+
+fn stored(writer: &mut BitWriter, data: &[u8]) {
+    let mut chunks = data.chunks(0xFFFF).peekable();
+
+    if chunks.peek().is_none() {
+        writer.put(1, 3);
+        writer.flush();
+        writer.output.extend_from_slice(&[0, 0, 0xFF, 0xFF]);
+        return;
+    }
+
+    while let Some(chunk) = chunks.next() {
+        writer.put(chunks.peek().is_none() as u32, 3);
+        writer.flush();
+
+        let length = chunk.len() as u16;
+        writer.output.extend_from_slice(&length.to_le_bytes());
+        writer.output.extend_from_slice(&(!length).to_le_bytes());
+        writer.output.extend_from_slice(chunk);
+    }
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    (((data[i] as usize) << 10) ^ ((data[i + 1] as usize) << 5) ^ data[i + 2] as usize) % HASH_SIZE
+}
+
+/// One fixed Huffman block, with matches found through hash chains.
+fn fixed(writer: &mut BitWriter, data: &[u8]) {
+    const NONE: usize = usize::MAX;
+
+    let mut head = vec![NONE; HASH_SIZE];
+    let mut prev = vec![NONE; WINDOW];
+    let insert = |head: &mut [usize], prev: &mut [usize], i: usize| {
+        if i + 3 <= data.len() {
+            let h = hash(data, i);
+            prev[i % WINDOW] = head[h];
+            head[h] = i;
+        }
+    };
+
+    writer.put(1, 1);
+    writer.put(1, 2);
+
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best, mut distance) = (0, 0);
+
+        if i + 3 <= data.len() {
+            let limit = core::cmp::min(MAX_MATCH, data.len() - i);
+            let mut candidate = head[hash(data, i)];
+            let mut chain = MAX_CHAIN;
+
+            while candidate != NONE && chain > 0 && i - candidate <= WINDOW {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + limit])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if length > best {
+                    (best, distance) = (length, i - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+
+                candidate = prev[candidate % WINDOW];
+                chain -= 1;
+            }
+        }
+
+        if best >= 3 {
+            writer.matched(best, distance);
+            for j in i..i + best {
+                insert(&mut head, &mut prev, j);
+            }
+            i += best;
+        } else {
+            writer.literal(data[i] as u32);
+            insert(&mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+
+    writer.literal(256);
+    writer.flush();
+}
+
+/// Compresses `data` into a complete stream.
+pub fn compress(data: &[u8], format: Format, level: Level) -> Vec<u8> {
+    let mut writer = BitWriter {
+        output: Vec::new(),
+        bits: 0,
+        count: 0,
+    };
+
+    match format {
+        Format::Raw => {}
+        Format::Zlib => match level {
+            Level::Stored => writer.output.extend_from_slice(&[0x78, 0x01]),
+            Level::Fixed => writer.output.extend_from_slice(&[0x78, 0x5E]),
+        },
+        // no name or time, OS unknown
+        Format::Gzip => writer.output.extend_from_slice(&[0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF]),
+    }
+
+    match level {
+        Level::Stored => stored(&mut writer, data),
+        Level::Fixed => {
+            let header = writer.output.len();
+            fixed(&mut writer, data);
+
+            // incompressible data comes out smaller left as it is
+            let blocks = core::cmp::max(1, data.len().div_ceil(0xFFFF));
+            if writer.output.len() - header > data.len() + 5 * blocks {
+                writer.output.truncate(header);
+                stored(&mut writer, data);
+            }
+        }
+    }
+
+    match format {
+        Format::Raw => {}
+        Format::Zlib => {
+            let sum = crate::hash::adler32(data);
+            writer.output.extend_from_slice(&sum.to_be_bytes());
+        }
+        Format::Gzip => {
+            let crc = crate::hash::crc32(data);
+            writer.output.extend_from_slice(&crc.to_le_bytes());
+            writer.output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+    }
+
+    writer.output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &[u8] = b"hello hello hello hello";
+
+    /// `HELLO` as zlib and gzip write it at level 9.
+    const HELLO_ZLIB: [u8; 16] = [
+        0x78, 0xDA, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0x01, 0x68, 0x03, 0x08,
+        0xB1,
+    ];
+    const HELLO_GZIP: [u8; 28] = [
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xCB, 0x48, 0xCD, 0xC9, 0xC9,
+        0x57, 0xC8, 0x40, 0x27, 0x01, 0xE3, 0x51, 0x3D, 0x8D, 0x17, 0x00, 0x00, 0x00,
+    ];
+
+    /// `skewed(64)` deflated by zlib into a single dynamic Huffman block.
+    const SKEWED_RAW: [u8; 39] = [
+        0x25, 0x8A, 0xC1, 0x0D, 0x00, 0x40, 0x0C, 0x82, 0x66, 0x05, 0xEB, 0xFE, 0x2B, 0x5C, 0x9B,
+        0xF3, 0x61, 0x0C, 0xC8, 0xA0, 0x22, 0xA4, 0x09, 0x50, 0xE6, 0x6A, 0xB3, 0x3C, 0xAB, 0xCE,
+        0x61, 0xBE, 0xB9, 0xEB, 0x6E, 0x99, 0x6A, 0x5B, 0x1E,
+    ];
+
+    /// Letters with uneven odds and few repeats, so zlib picks dynamic codes.
+    fn skewed(n: usize) -> Vec<u8> {
+        let mut x: u32 = 1;
+        (0..n)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                b"aaaaaaaabbbbccde"[((x >> 16) % 16) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inflates_zlib() {
+        assert_eq!(decompress(&HELLO_ZLIB, Format::Zlib).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn inflates_gzip() {
+        assert_eq!(decompress(&HELLO_GZIP, Format::Gzip).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn inflates_dynamic_block() {
+        assert_eq!(SKEWED_RAW[0] >> 1 & 3, 2);
+        assert_eq!(decompress(&SKEWED_RAW, Format::Raw).unwrap(), skewed(64));
+    }
+
+    #[test]
+    fn inflates_a_byte_at_a_time() {
+        let mut decoder = Decoder::new(Format::Gzip);
+        let mut output = Vec::new();
+
+        for (i, byte) in HELLO_GZIP.iter().enumerate() {
+            let done = decoder.push(core::slice::from_ref(byte), &mut output).unwrap();
+            assert_eq!(done, i == 27);
+        }
+
+        assert_eq!(output, HELLO);
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut zlib = HELLO_ZLIB;
+        zlib[15] ^= 1;
+        assert_eq!(decompress(&zlib, Format::Zlib), Err("Adler-32 mismatch"));
+
+        let mut gzip = HELLO_GZIP;
+        gzip[20] ^= 1;
+        assert_eq!(decompress(&gzip, Format::Gzip), Err("CRC-32 mismatch"));
+    }
+
+    #[test]
+    fn rejects_truncated_streams() {
+        assert_eq!(decompress(&HELLO_ZLIB[..10], Format::Zlib), Err("Unexpected end of data"));
+    }
+
+    #[test]
+    fn round_trips_every_format_and_level() {
+        // past 0xFFFF so stored data needs more than one block
+        let mut data = skewed(70_000);
+        data.extend_from_slice(&[0u8; 1000]);
+
+        for format in [Format::Raw, Format::Zlib, Format::Gzip] {
+            for level in [Level::Stored, Level::Fixed] {
+                let packed = compress(&data, format, level);
+                assert_eq!(decompress(&packed, format).unwrap(), data, "{:?} {:?}", format, level);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_level_compresses() {
+        let data = HELLO.repeat(100);
+        assert!(compress(&data, Format::Zlib, Level::Fixed).len() < data.len() / 10);
+    }
+}
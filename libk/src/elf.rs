@@ -511,7 +511,17 @@ fn check_header(header: &Elf32Ehdr) -> bool {
     true
 }
 
-pub fn load_elf(filename: &str, args: Option<&[u32]>) -> Result<(), &'static str> {
+/// Loads a program and starts it, returning its pid.
+pub fn load_elf(filename: &str, args: Option<&[u32]>) -> Result<u32, &'static str> {
+    load_elf_as(filename, args, None)
+}
+
+/// Loads a program and starts it as another user, which only the admin may do.
+pub fn load_elf_as(filename: &str, args: Option<&[u32]>, uid: Option<u16>) -> Result<u32, &'static str> {
+    if !crate::perm::access(filename, crate::perm::Access::Execute) {
+        return Err("Permission denied");
+    }
+
     unsafe {
         REL_TABLE.base_ptr = crate::syscall::malloc(10000);
     }
@@ -522,12 +532,16 @@ pub fn load_elf(filename: &str, args: Option<&[u32]>) -> Result<(), &'static str
         crate::syscall::free(REL_TABLE.base_ptr);
     }
 
-    if entry_point != 0 {
-        crate::syscall::add_task(entry_point, args);
-        Ok(())
-    } else {
-        Err("")
+    if entry_point == 0 {
+        return Err("");
     }
+
+    let pid = match uid {
+        Some(uid) => crate::syscall::add_task_as(entry_point, args, uid),
+        None => crate::syscall::add_program(entry_point, args, filename),
+    };
+
+    if pid != 0 { Ok(pid) } else { Err("Could not start task") }
 }
 
 #[unsafe(no_mangle)]
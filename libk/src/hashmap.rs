@@ -45,6 +45,19 @@ pub struct HashMap<K, V, H = DefaultHasher> {
     hasher: H,
 }
 
+impl<K, V> HashMap<K, V, FnvHasher> {
+    /// An empty map usable in statics; buckets are allocated on the first insert.
+    pub const fn empty() -> Self {
+        HashMap {
+            buckets: Vec::new(),
+            items: 0,
+            hasher: FnvHasher {
+                state: 0xcbf29ce484222325,
+            },
+        }
+    }
+}
+
 impl<K, V, H> HashMap<K, V, H>
 where
     K: Hash + Eq,
@@ -101,6 +114,10 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
         let bucket_idx = self.bucket_for_key(key);
         self.buckets[bucket_idx].as_ref().and_then(|bucket| {
             bucket
@@ -115,6 +132,10 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
         let bucket_idx = self.bucket_for_key(key);
         if let Some(bucket) = &mut self.buckets[bucket_idx] {
             if let Some(pos) = bucket.iter().position(|(k, _)| k.borrow() == key) {
@@ -126,7 +147,7 @@ where
         None
     }
     fn resize(&mut self) {
-        let new_size = self.buckets.len() * 2;
+        let new_size = core::cmp::max(self.buckets.len() * 2, 16);
         let mut new_buckets = Vec::with_capacity(new_size);
         new_buckets.resize_with(new_size, || None);
         for bucket in self.buckets.drain(..) {
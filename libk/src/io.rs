@@ -11,18 +11,81 @@ pub struct File {
     pub write: bool,
 }
 
-pub fn make_file(fname: &str) {
+/// Creates an empty file, false if it exists or cannot be made. Why goes
+/// to the serial log.
+pub fn make_file(fname: &str) -> bool {
     let string_ptr = fname.as_ptr();
     let string_len = fname.len();
 
-    crate::syscall::syscall(40, string_ptr as u32, 0, string_len as u32);
+    crate::syscall::syscall(40, string_ptr as u32, 0, string_len as u32) == 0
 }
 
-pub fn make_dir(fname: &str) {
+/// Creates a directory, false if it exists or cannot be made.
+pub fn make_dir(fname: &str) -> bool {
     let string_ptr = fname.as_ptr();
     let string_len = fname.len();
 
-    crate::syscall::syscall(42, string_ptr as u32, 0, string_len as u32);
+    crate::syscall::syscall(42, string_ptr as u32, 0, string_len as u32) == 0
+}
+
+/// Deletes a file or an empty directory.
+pub fn remove(fname: &str) -> bool {
+    let string_ptr = fname.as_ptr();
+    let string_len = fname.len();
+
+    crate::syscall::syscall(47, string_ptr as u32, 0, string_len as u32) == 0
+}
+
+/// Checks the filesystem holding `path` and returns the number of problems found,
+/// or None if it could not be checked. Details go to the serial log.
+pub fn fsck(path: &str, repair: bool) -> Option<u32> {
+    let string_ptr = path.as_ptr();
+    let string_len = path.len();
+
+    match crate::syscall::syscall(55, string_ptr as u32, repair as u32, string_len as u32) {
+        u32::MAX => None,
+        problems => Some(problems),
+    }
+}
+
+/// Reads up to `buffer.len()` bytes starting `offset` bytes into a file, so large
+/// files can be processed a piece at a time. Returns 0 at the end of the file.
+pub fn read_at(path: &str, offset: u32, buffer: &mut [u8]) -> Option<usize> {
+    let string_ptr = path.as_ptr();
+    let string_len = path.len();
+
+    let request = ReadRequest {
+        buffer: buffer.as_mut_ptr() as u32,
+        length: buffer.len() as u32,
+        offset,
+    };
+
+    match crate::syscall::syscall(
+        60,
+        string_ptr as u32,
+        &request as *const ReadRequest as u32,
+        string_len as u32,
+    ) {
+        u32::MAX => None,
+        count => Some(count as usize),
+    }
+}
+
+/// What `read_at` hands syscall 60 through its second argument.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct ReadRequest {
+    pub buffer: u32,
+    pub length: u32,
+    pub offset: u32,
+}
+
+/// Mounts an empty in-memory filesystem at `path` holding up to `limit` bytes.
+pub fn mount_tmpfs(path: &str, limit: u32) -> bool {
+    let string_ptr = path.as_ptr();
+    let string_len = path.len();
+
+    crate::syscall::syscall(48, string_ptr as u32, limit, string_len as u32) == 0
 }
 impl File {
     pub fn new(fname: &str) -> File {
@@ -94,7 +157,8 @@ impl File {
         crate::syscall::syscall(2, string_ptr as u32, buffer, string_len as u32);
     }
 
-    pub fn write(&self, data: &[u8]) {
+    /// Replaces the contents of the file, false if that failed. Why goes to the serial log.
+    pub fn write(&self, data: &[u8]) -> bool {
         let string_ptr = self.fname.as_ptr();
         let string_len = self.fname.len();
 
@@ -105,10 +169,11 @@ impl File {
             string_ptr as u32,
             &data_ref as *const (u32, u32) as u32,
             string_len as u32,
-        );
+        ) == 0
     }
 
-    pub fn append(&self, data: &[u8]) {
+    /// Adds to the end of the file, false if that failed. Why goes to the serial log.
+    pub fn append(&self, data: &[u8]) -> bool {
         let string_ptr = self.fname.as_ptr();
         let string_len = self.fname.len();
 
@@ -119,7 +184,7 @@ impl File {
             string_ptr as u32,
             &data_ref as *const (u32, u32) as u32,
             string_len as u32,
-        );
+        ) == 0
     }
 
     pub fn close(&self) {
@@ -154,12 +219,12 @@ impl File {
 pub static mut FILE_ENTRY: Entry = Entry {
     name: [0; 11],
     attributes: 0,
-    reserved: 0,
+    mode: 0,
     created_time_tenths: 0,
     created_time: 0,
     created_date: 0,
     accessed_date: 0,
-    first_cluster_high: 0,
+    owner: 0,
     modified_time: 0,
     modified_date: 0,
     first_cluster_low: 0,
@@ -173,6 +238,10 @@ pub fn size(fname: &str) -> u32 {
     crate::syscall::syscall(4, string_ptr as u32, 0, string_len as u32)
 }
 
+pub fn exists(fname: &str) -> bool {
+    size(fname) != 699669
+}
+
 pub fn dir_entries(fname: &str) -> u32 {
     let string_ptr = fname.as_ptr();
     let string_len = fname.len();
@@ -180,12 +249,12 @@ pub fn dir_entries(fname: &str) -> u32 {
     crate::syscall::syscall(28, string_ptr as u32, 0, string_len as u32)
 }
 
-pub fn get_entry(fname: &str, index: u8) -> Option<Entry> {
+pub fn get_entry(fname: &str, index: u32) -> Option<Entry> {
     let string_ptr = fname.as_ptr();
     let string_len = fname.len();
 
     let entry_addr =
-        crate::syscall::syscall(29, string_ptr as u32, index as u32, string_len as u32);
+        crate::syscall::syscall(29, string_ptr as u32, index, string_len as u32);
 
     if entry_addr > 0 {
         let entry = unsafe { core::ptr::read(entry_addr as *const Entry) };
@@ -198,17 +267,121 @@ pub fn get_entry(fname: &str, index: u8) -> Option<Entry> {
     None
 }
 
+pub fn partitions() -> Vec<PartitionInfo> {
+    let mut list = Vec::new();
+    let total = crate::syscall::syscall(43, 0, 0, 0) as usize;
+
+    list.resize(total, NULL_PARTITION);
+    let filled = crate::syscall::syscall(43, list.as_mut_ptr() as u32, total as u32, 0) as usize;
+    list.truncate(core::cmp::min(filled, total));
+
+    list
+}
+
+/// Writes every dirty cached sector back to disk, false if some could not be.
+pub fn sync() -> bool {
+    crate::syscall::syscall(44, 0, 0, 0) == 0
+}
+
+pub fn cache_stats() -> CacheStats {
+    let mut stats = NULL_CACHE_STATS;
+    crate::syscall::syscall(45, &raw mut stats as u32, 0, 0);
+
+    stats
+}
+
+/// Resizes the kernel block cache to `sectors` entries; 0 disables it.
+pub fn set_cache_size(sectors: u32) -> bool {
+    crate::syscall::syscall(46, sectors, 0, 0) == 0
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub writebacks: u64,
+    pub cached: u32,
+    pub dirty: u32,
+    pub capacity: u32,
+}
+
+pub const NULL_CACHE_STATS: CacheStats = CacheStats {
+    hits: 0,
+    misses: 0,
+    writebacks: 0,
+    cached: 0,
+    dirty: 0,
+    capacity: 0,
+};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum FsKind {
+    Unknown,
+    Fat12,
+    Fat16,
+    Fat32,
+    ExFat,
+    Ntfs,
+    Efi,
+}
+
+impl FsKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FsKind::Unknown => "unknown",
+            FsKind::Fat12 => "fat12",
+            FsKind::Fat16 => "fat16",
+            FsKind::Fat32 => "fat32",
+            FsKind::ExFat => "exfat",
+            FsKind::Ntfs => "ntfs",
+            FsKind::Efi => "efi",
+        }
+    }
+}
+
+/// A disk (`index` 0) or one of its partitions, as reported by syscall 43.
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct PartitionInfo {
+    pub disk: u8,
+    pub index: u8,
+    pub kind: FsKind,
+    pub bootable: bool,
+    pub start: u64,
+    pub sectors: u64,
+    pub mount: [u8; 8],
+}
+
+impl PartitionInfo {
+    pub fn mount_point(&self) -> &str {
+        let len = self.mount.iter().position(|c| *c == 0).unwrap_or(self.mount.len());
+        core::str::from_utf8(&self.mount[..len]).unwrap_or("")
+    }
+}
+
+pub const NULL_PARTITION: PartitionInfo = PartitionInfo {
+    disk: 0,
+    index: 0,
+    kind: FsKind::Unknown,
+    bootable: false,
+    start: 0,
+    sectors: 0,
+    mount: [0; 8],
+};
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[repr(C, packed)]
 pub struct Entry {
     pub name: [u8; 11],
     pub attributes: u8,
-    reserved: u8,
+    mode: u8,
     created_time_tenths: u8,
     created_time: u16,
     created_date: u16,
     accessed_date: u16,
-    first_cluster_high: u16,
+    owner: u16,
     modified_time: u16,
     modified_date: u16,
     first_cluster_low: u16,
@@ -219,6 +392,26 @@ impl Entry {
     pub fn is_dir(&self) -> bool {
         self.attributes & 0x10 != 0
     }
+
+    pub fn created(&self) -> crate::time::DateTime {
+        crate::time::DateTime::from_fat(self.created_date, self.created_time)
+    }
+
+    pub fn modified(&self) -> crate::time::DateTime {
+        crate::time::DateTime::from_fat(self.modified_date, self.modified_time)
+    }
+
+    pub fn accessed(&self) -> crate::time::DateTime {
+        crate::time::DateTime::from_fat(self.accessed_date, 0)
+    }
+
+    pub fn owner(&self) -> u16 {
+        self.owner
+    }
+
+    pub fn mode(&self) -> crate::perm::Mode {
+        crate::perm::Mode(self.mode)
+    }
 }
 
 pub fn expand_path_8_3(path: &str) -> &[u8] {
@@ -257,3 +450,86 @@ pub fn expand_path_8_3(path: &str) -> &[u8] {
         &BUFFER[..idx]
     }
 }
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum WatchKind {
+    Create = 1,
+    Delete,
+    Modify,
+    Rename,
+    /// Events were lost; list the directory again.
+    Overflow,
+}
+
+/// A change to a watched directory. Names are in the 11 byte directory entry
+/// form; `old_name` is only set for renames.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct WatchEvent {
+    pub kind: WatchKind,
+    pub name: [u8; 11],
+    pub old_name: [u8; 11],
+}
+
+impl WatchEvent {
+    pub const fn new(kind: WatchKind, name: [u8; 11]) -> WatchEvent {
+        WatchEvent {
+            kind,
+            name,
+            old_name: [32; 11],
+        }
+    }
+}
+
+/// Starts watching a directory for changes. Returns None if it does not exist.
+pub fn watch(dir: &str) -> Option<u32> {
+    let string_ptr = dir.as_ptr();
+    let string_len = dir.len();
+
+    match crate::syscall::syscall(56, string_ptr as u32, 0, string_len as u32) {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+/// Next pending change of a watch, without waiting for one.
+pub fn poll_watch(id: u32) -> Option<WatchEvent> {
+    let mut event = WatchEvent::new(WatchKind::Overflow, [32; 11]);
+
+    if crate::syscall::syscall(57, id, &raw mut event as u32, 0) == 1 {
+        Some(event)
+    } else {
+        None
+    }
+}
+
+pub fn unwatch(id: u32) {
+    crate::syscall::syscall(58, id, 0, 0);
+}
+
+/// Renames a file or directory within the directory it is in.
+pub fn rename(path: &str, new_name: &str) -> bool {
+    let string_ptr = path.as_ptr();
+    let string_len = path.len();
+
+    let name_ref = StringRef {
+        address: new_name.as_ptr() as u32,
+        length: new_name.len() as u32,
+    };
+
+    crate::syscall::syscall(
+        59,
+        string_ptr as u32,
+        &name_ref as *const StringRef as u32,
+        string_len as u32,
+    ) == 0
+}
+
+/// A string passed by address, for syscalls with more than one.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct StringRef {
+    pub address: u32,
+    pub length: u32,
+}
@@ -2,7 +2,9 @@
 
 extern crate alloc;
 
+pub mod acpi;
 pub mod boot;
+pub mod cmdline;
 pub mod elf;
 pub mod hashmap;
 pub mod io;
@@ -11,11 +13,16 @@ pub mod mmio;
 pub mod mutex;
 pub mod net;
 pub mod packets;
+pub mod perm;
 pub mod port;
 pub mod rng;
 pub mod serial;
 pub mod syscall;
+pub mod tar;
+pub mod task;
+pub mod time;
 pub mod hash;
+pub mod deflate;
 
 #[inline(always)]
 pub fn disable_interrupts() {
@@ -0,0 +1,69 @@
+/// The only user allowed to write to `/SYS`, change owners or switch users.
+pub const ADMIN: u16 = 0;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read = 0,
+    Write = 1,
+    Execute = 2,
+}
+
+/// Permission bits kept in the reserved byte of a directory entry. They are
+/// stored inverted, so entries written by other tools (all zero) stay open to
+/// everyone. Bits 3 and 4 are left alone since some systems keep name case there.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Mode(pub u8);
+
+const OWNER_SHIFT: u8 = 0;
+const OTHERS_SHIFT: u8 = 5;
+
+impl Mode {
+    /// rwx for the owner, r-x for everyone else.
+    pub const DEFAULT: Mode = Mode::from_octal(0o75);
+
+    /// Two octal digits, owner then others, with r = 4, w = 2, x = 1.
+    pub const fn from_octal(octal: u8) -> Mode {
+        let owner = !(octal >> 3) & 7;
+        let others = !octal & 7;
+
+        Mode((owner << OWNER_SHIFT) | (others << OTHERS_SHIFT))
+    }
+
+    pub const fn to_octal(self) -> u8 {
+        let owner = !(self.0 >> OWNER_SHIFT) & 7;
+        let others = !(self.0 >> OTHERS_SHIFT) & 7;
+
+        (owner << 3) | others
+    }
+
+    pub fn allows(self, owner: bool, access: Access) -> bool {
+        // read, write, execute map to 4, 2, 1
+        let bit = 4 >> access as u8;
+        let octal = self.to_octal();
+
+        let granted = if owner { octal >> 3 } else { octal };
+        granted & bit != 0
+    }
+}
+
+pub fn uid() -> u16 {
+    crate::syscall::syscall(53, 0, 0, 0) as u16
+}
+
+/// Switches the calling task to another user. Only the admin may do this.
+pub fn set_uid(uid: u16) -> bool {
+    crate::syscall::syscall(52, uid as u32, 0, 0) == 0
+}
+
+pub fn chmod(path: &str, octal: u8) -> bool {
+    crate::syscall::syscall(50, path.as_ptr() as u32, octal as u32, path.len() as u32) == 0
+}
+
+pub fn chown(path: &str, uid: u16) -> bool {
+    crate::syscall::syscall(51, path.as_ptr() as u32, uid as u32, path.len() as u32) == 0
+}
+
+/// Whether the calling task may access `path` in the given way.
+pub fn access(path: &str, access: Access) -> bool {
+    crate::syscall::syscall(54, path.as_ptr() as u32, access as u32, path.len() as u32) == 0
+}
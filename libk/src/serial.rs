@@ -1,4 +1,5 @@
 use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use crate::port::{inb, outb};
 
@@ -60,8 +61,32 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Silences `print!` for the program calling it, the kernel uses this for `quiet`.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
+
+    if QUIET.load(Ordering::Relaxed) {
+        return;
+    }
+
     Terminal::new().write_fmt(args).unwrap();
 }
+
+/// Sends `data` to the serial port through the kernel, which waits for the
+/// port to take each byte.
+pub fn write(data: &[u8]) {
+    crate::syscall::syscall(69, data.as_ptr() as u32, 0, data.len() as u32);
+}
+
+/// Takes what arrived on the serial port and was not read yet, up to
+/// `buffer.len()` bytes, without waiting. Returns how many bytes it took.
+pub fn read(buffer: &mut [u8]) -> usize {
+    crate::syscall::syscall(70, buffer.as_mut_ptr() as u32, 0, buffer.len() as u32) as usize
+}
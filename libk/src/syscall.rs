@@ -2,6 +2,7 @@ use core::arch::asm;
 
 pub static mut PID: u16 = 0;
 
+#[cfg(not(test))]
 #[inline(never)]
 pub extern "C" fn syscall(index: u32, ebx: u32, ecx: u32, edx: u32) -> u32 {
     unsafe {
@@ -20,6 +21,12 @@ pub extern "C" fn syscall(index: u32, ebx: u32, ecx: u32, edx: u32) -> u32 {
     }
 }
 
+/// The host tests only cover code that never reaches the kernel.
+#[cfg(test)]
+pub extern "C" fn syscall(_index: u32, _ebx: u32, _ecx: u32, _edx: u32) -> u32 {
+    unimplemented!("no kernel to call on the build machine")
+}
+
 pub fn malloc(size: u32) -> u32 {
     let addr = syscall(5, size, 0, 0);
 
@@ -42,6 +49,12 @@ pub fn write_wid_to_screen(wid: u32) {
     syscall(9, wid, 0, 0);
 }
 
+/// Changes the screen mode where the display card allows it; open windows
+/// are fitted in and get their resize callback. Only the admin may.
+pub fn set_video_mode(width: u16, height: u16, bpp: u8) -> bool {
+    syscall(65, width as u32, height as u32, bpp as u32) == 0
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(C)]
 pub enum Items {
@@ -83,18 +96,47 @@ pub fn remove_window(id: u32) {
     syscall(24, 0, 0, 0);
 }*/
 
-pub fn add_task(base: u32, args: Option<&[u32]>) {
+/// Starts a task at `base`, returning its pid or 0.
+pub fn add_task(base: u32, args: Option<&[u32]>) -> u32 {
     let mut args_ptr = 0;
     if args.is_some() {
         args_ptr = args.unwrap().as_ptr() as u32;
     }
-    syscall(25, base, 0, args_ptr);
+    syscall(25, base, 0, args_ptr)
+}
+
+/// Like `add_task` for a program loaded from `path`, which the kernel checks
+/// the caller may execute.
+pub fn add_program(base: u32, args: Option<&[u32]>, path: &str) -> u32 {
+    let args_ptr = args.map_or(0, |a| a.as_ptr() as u32);
+    let path_ref = crate::io::StringRef {
+        address: path.as_ptr() as u32,
+        length: path.len() as u32,
+    };
+
+    syscall(25, base, &path_ref as *const crate::io::StringRef as u32, args_ptr)
+}
+
+/// Like `add_task` but running as `uid`, which only the admin may ask for.
+pub fn add_task_as(base: u32, args: Option<&[u32]>, uid: u16) -> u32 {
+    let args_ptr = args.map_or(0, |a| a.as_ptr() as u32);
+    syscall(61, base, uid as u32, args_ptr)
 }
 
 pub fn exit() -> ! {
-    syscall(26, 0, 0, 0);
+    crate::task::exit(0)
+}
+
+/// Unmounts the filesystems and powers the machine off. Only the admin may,
+/// for anyone else this returns.
+pub fn shutdown() {
+    syscall(67, 0, 0, 0);
+}
 
-    loop {}
+/// Unmounts the filesystems and restarts the machine. Only the admin may,
+/// for anyone else this returns.
+pub fn reboot() {
+    syscall(68, 0, 0, 0);
 }
 
 #[repr(C, packed)]
@@ -0,0 +1,586 @@
+//! ustar archives. Both sides work a block at a time through `io::read_at` and
+//! appends, so an archive never has to fit in memory.
+
+use crate::io::{self, File};
+use crate::time::DateTime;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+
+pub const BLOCK: usize = 512;
+
+/// File data is copied in pieces of this size.
+const CHUNK: usize = 32 * 1024;
+
+const NOT_FOUND: u32 = 699669;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    File,
+    Dir,
+    /// Links, devices and extended headers; listed but never extracted.
+    Other,
+}
+
+#[derive(Clone, Debug)]
+pub struct Member {
+    /// Path inside the archive, without a trailing slash.
+    pub name: String,
+    pub kind: Kind,
+    pub size: u32,
+    pub mode: u32,
+    pub modified: DateTime,
+    /// Where the data starts in the archive.
+    offset: u32,
+}
+
+fn octal(field: &[u8]) -> Option<u32> {
+    let mut value: u32 = 0;
+    let mut digits = false;
+
+    for &c in field {
+        match c {
+            b'0'..=b'7' => {
+                value = value.checked_mul(8)?.checked_add((c - b'0') as u32)?;
+                digits = true;
+            }
+            b' ' if !digits => continue,
+            b' ' | 0 => break,
+            _ => return None,
+        }
+    }
+
+    Some(value)
+}
+
+/// Writes `value` as zero padded octal followed by a NUL.
+fn put_octal(field: &mut [u8], value: u32) {
+    let digits = field.len() - 1;
+    let text = format!("{:0width$o}", value, width = digits);
+
+    field[..digits].copy_from_slice(&text.as_bytes()[text.len() - digits..]);
+    field[digits] = 0;
+}
+
+fn checksum(header: &[u8; BLOCK]) -> u32 {
+    // the checksum field itself counts as spaces
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u32 } else { b as u32 })
+        .sum()
+}
+
+fn text(field: &[u8]) -> &str {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..end]).unwrap_or("")
+}
+
+/// Splits a long name into the ustar prefix and name fields.
+fn split_name(name: &str) -> Result<(&str, &str), &'static str> {
+    if name.len() <= 100 {
+        return Ok(("", name));
+    }
+
+    for (pos, _) in name.match_indices('/') {
+        if pos <= 155 && name.len() - pos - 1 <= 100 {
+            return Ok((&name[..pos], &name[pos + 1..]));
+        }
+    }
+
+    Err("Name too long for a tar header")
+}
+
+fn header(name: &str, kind: Kind, size: u32, modified: &DateTime) -> Result<[u8; BLOCK], &'static str> {
+    let mut block = [0u8; BLOCK];
+    let (prefix, name) = split_name(name)?;
+
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    put_octal(&mut block[100..108], if kind == Kind::Dir { 0o755 } else { 0o644 });
+    put_octal(&mut block[108..116], 0);
+    put_octal(&mut block[116..124], 0);
+    put_octal(&mut block[124..136], size);
+    put_octal(&mut block[136..148], modified.to_unix());
+    block[156] = if kind == Kind::Dir { b'5' } else { b'0' };
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    let sum = checksum(&block);
+    put_octal(&mut block[148..155], sum);
+    block[155] = b' ';
+
+    Ok(block)
+}
+
+fn parse(block: &[u8; BLOCK], offset: u32) -> Result<Member, &'static str> {
+    if octal(&block[148..156]) != Some(checksum(block)) {
+        return Err("Bad tar header checksum");
+    }
+
+    let name = text(&block[..100]);
+    let prefix = if &block[257..262] == b"ustar" { text(&block[345..500]) } else { "" };
+
+    let mut full = String::from(prefix);
+    if !full.is_empty() {
+        full.push('/');
+    }
+    full.push_str(name);
+
+    let kind = match block[156] {
+        b'0' | 0 if full.ends_with('/') => Kind::Dir,
+        b'0' | 0 | b'7' => Kind::File,
+        b'5' => Kind::Dir,
+        _ => Kind::Other,
+    };
+
+    Ok(Member {
+        name: String::from(full.trim_end_matches('/')),
+        kind,
+        size: octal(&block[124..136]).ok_or("Bad tar size field")?,
+        mode: octal(&block[100..108]).unwrap_or(0),
+        modified: DateTime::from_unix(octal(&block[136..148]).unwrap_or(0)),
+        offset: offset + BLOCK as u32,
+    })
+}
+
+/// Walks the members of an archive on disk.
+pub struct Reader {
+    path: String,
+    size: u32,
+    offset: u32,
+    done: bool,
+}
+
+impl Reader {
+    pub fn open(path: &str) -> Option<Reader> {
+        let size = io::size(path);
+        if size == NOT_FOUND {
+            return None;
+        }
+
+        Some(Reader {
+            path: String::from(path),
+            size,
+            offset: 0,
+            done: false,
+        })
+    }
+
+    /// Reads member data from `offset` bytes into it.
+    pub fn read(&self, member: &Member, offset: u32, buffer: &mut [u8]) -> usize {
+        read_member(&self.path, member, offset, buffer)
+    }
+}
+
+fn read_member(archive: &str, member: &Member, offset: u32, buffer: &mut [u8]) -> usize {
+    if offset >= member.size {
+        return 0;
+    }
+
+    let count = core::cmp::min(buffer.len(), (member.size - offset) as usize);
+    io::read_at(archive, member.offset + offset, &mut buffer[..count]).unwrap_or(0)
+}
+
+impl Iterator for Reader {
+    type Item = Result<Member, &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.offset as usize + BLOCK > self.size as usize {
+            return None;
+        }
+
+        let mut block = [0u8; BLOCK];
+        if io::read_at(&self.path, self.offset, &mut block) != Some(BLOCK) {
+            self.done = true;
+            return Some(Err("Could not read the archive"));
+        }
+
+        // two zero blocks end the archive, one is enough to stop at
+        if block.iter().all(|&b| b == 0) {
+            self.done = true;
+            return None;
+        }
+
+        let member = match parse(&block, self.offset) {
+            Ok(m) => m,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+
+        let data = member.size.div_ceil(BLOCK as u32) * BLOCK as u32;
+        self.offset = member.offset + data;
+
+        if self.offset > self.size {
+            self.done = true;
+            return Some(Err("Archive is truncated"));
+        }
+
+        Some(Ok(member))
+    }
+}
+
+/// Walks the members of an archive held in memory, each with its data.
+pub struct Members<'a> {
+    archive: &'a [u8],
+    offset: u32,
+    done: bool,
+}
+
+pub fn members(archive: &[u8]) -> Members<'_> {
+    Members {
+        archive,
+        offset: 0,
+        done: false,
+    }
+}
+
+impl<'a> Iterator for Members<'a> {
+    type Item = Result<(Member, &'a [u8]), &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset as usize;
+        if self.done || start + BLOCK > self.archive.len() {
+            return None;
+        }
+
+        let mut block = [0u8; BLOCK];
+        block.copy_from_slice(&self.archive[start..start + BLOCK]);
+
+        if block.iter().all(|&b| b == 0) {
+            self.done = true;
+            return None;
+        }
+
+        let member = match parse(&block, self.offset) {
+            Ok(m) => m,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+
+        let Some(data) = self
+            .archive
+            .get(member.offset as usize..)
+            .and_then(|rest| rest.get(..member.size as usize))
+        else {
+            self.done = true;
+            return Some(Err("Archive is truncated"));
+        };
+
+        self.offset = member.offset + member.size.div_ceil(BLOCK as u32) * BLOCK as u32;
+        Some(Ok((member, data)))
+    }
+}
+
+/// Builds an archive by appending to a file.
+pub struct Writer {
+    archive: File,
+}
+
+impl Writer {
+    /// Starts a new, empty archive, replacing any file already at `path`.
+    pub fn create(path: &str) -> Result<Writer, &'static str> {
+        io::make_file(path);
+
+        let archive = File {
+            fname: String::from(path),
+            size: 0,
+            ptr: 0,
+            read: false,
+            write: true,
+        };
+        if !archive.write(&[]) {
+            return Err("Could not create archive");
+        }
+
+        Ok(Writer { archive })
+    }
+
+    pub fn add_dir(&mut self, name: &str, modified: &DateTime) -> Result<(), &'static str> {
+        let mut name = String::from(name.trim_end_matches('/'));
+        name.push('/');
+
+        self.append(&header(&name, Kind::Dir, 0, modified)?)
+    }
+
+    pub fn add_data(&mut self, name: &str, data: &[u8], modified: &DateTime) -> Result<(), &'static str> {
+        self.append(&header(name, Kind::File, data.len() as u32, modified)?)?;
+        self.append(data)?;
+        self.pad(data.len())
+    }
+
+    /// Copies the file at `source` into the archive a chunk at a time.
+    pub fn add_file(&mut self, name: &str, source: &str, modified: &DateTime) -> Result<(), &'static str> {
+        let size = io::size(source);
+        if size == NOT_FOUND {
+            return Err("No such file");
+        }
+
+        self.append(&header(name, Kind::File, size, modified)?)?;
+
+        let mut buffer = vec![0u8; CHUNK];
+        let mut done = 0;
+
+        while done < size {
+            let count = io::read_at(source, done, &mut buffer).ok_or("Could not read file")?;
+            if count == 0 {
+                // keep the archive well formed even if the file shrank under us
+                buffer.fill(0);
+                while done < size {
+                    let count = core::cmp::min(CHUNK as u32, size - done);
+                    self.append(&buffer[..count as usize])?;
+                    done += count;
+                }
+                return Err("File changed while archiving");
+            }
+
+            self.append(&buffer[..count])?;
+            done += count as u32;
+        }
+
+        self.pad(size as usize)
+    }
+
+    /// Adds everything below the directory `dir`, with names starting at `prefix`.
+    /// Returns how many members were added.
+    pub fn add_tree(&mut self, dir: &str, prefix: &str) -> Result<u32, &'static str> {
+        let mut added = 0;
+        let dir = dir.trim_end_matches('/');
+        let listed = if dir.is_empty() { "/" } else { dir };
+
+        for index in 0..io::dir_entries(listed) {
+            let Some(entry) = io::get_entry(listed, index) else {
+                break;
+            };
+
+            let name = display_name(&entry.name);
+            let path = format!("{}/{}", dir, name);
+            let member = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+
+            // never swallow the archive being written
+            if path.eq_ignore_ascii_case(&self.archive.fname) {
+                continue;
+            }
+
+            if entry.is_dir() {
+                self.add_dir(&member, &entry.modified())?;
+                added += 1 + self.add_tree(&path, &member)?;
+            } else {
+                self.add_file(&member, &path, &entry.modified())?;
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+
+    /// Writes the two zero blocks that end an archive.
+    pub fn finish(self) -> Result<(), &'static str> {
+        self.append(&[0u8; BLOCK * 2])
+    }
+
+    fn pad(&mut self, length: usize) -> Result<(), &'static str> {
+        let rest = length % BLOCK;
+        if rest != 0 {
+            self.append(&[0u8; BLOCK][..BLOCK - rest])?;
+        }
+        Ok(())
+    }
+
+    fn append(&self, data: &[u8]) -> Result<(), &'static str> {
+        if self.archive.append(data) { Ok(()) } else { Err("Could not write archive") }
+    }
+}
+
+/// `NAME    EXT` as `NAME.EXT`.
+fn display_name(name: &[u8; 11]) -> String {
+    let base = core::str::from_utf8(&name[..8]).unwrap_or("").trim_end();
+    let ext = core::str::from_utf8(&name[8..]).unwrap_or("").trim_end();
+
+    if ext.is_empty() { String::from(base) } else { format!("{}.{}", base, ext) }
+}
+
+/// A path component as an 8.3 name, or None if it cannot be stored as one.
+fn short_component(part: &str) -> Option<String> {
+    let (base, ext) = match part.rfind('.') {
+        Some(0) | None => (part, ""),
+        Some(dot) => (&part[..dot], &part[dot + 1..]),
+    };
+
+    let bad = |c: char| !c.is_ascii_graphic() || "\"*+,./:;<=>?[\\]|".contains(c);
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || base.contains(bad) || ext.contains(bad) {
+        return None;
+    }
+
+    let mut name = String::from(base);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(ext);
+    }
+    name.make_ascii_uppercase();
+
+    Some(name)
+}
+
+/// Where a member goes below `dir`, or None if its name does not fit.
+pub fn target_path(dir: &str, name: &str) -> Option<String> {
+    let mut path = String::from(dir.trim_end_matches('/'));
+
+    for part in name.split('/').filter(|p| !p.is_empty() && *p != ".") {
+        // `..` would let an archive write outside the target directory
+        if part == ".." {
+            return None;
+        }
+
+        path.push('/');
+        path.push_str(&short_component(part)?);
+    }
+
+    if path.len() <= dir.trim_end_matches('/').len() { None } else { Some(path) }
+}
+
+/// Archives the directory `dir` into a new archive at `archive`.
+pub fn create(archive: &str, dir: &str) -> Result<u32, &'static str> {
+    let mut writer = Writer::create(archive)?;
+    let added = writer.add_tree(dir, "");
+    // end the archive even after a failed member so it stays readable
+    writer.finish()?;
+
+    added
+}
+
+/// Unpacks `archive` below `dir`, calling `report` for every member with the
+/// path it went to or why it was skipped. Returns the number extracted.
+pub fn extract(
+    archive: &str,
+    dir: &str,
+    mut report: impl FnMut(&str, Result<&str, &'static str>),
+) -> Result<u32, &'static str> {
+    let reader = Reader::open(archive).ok_or("No such archive")?;
+    let mut buffer = vec![0u8; CHUNK];
+    let mut extracted = 0;
+
+    for member in reader {
+        let member = member?;
+
+        let Some(path) = target_path(dir, &member.name) else {
+            report(&member.name, Err("Name does not fit 8.3"));
+            continue;
+        };
+
+        // parents may only appear implicitly in the archive
+        let mut parent = 0;
+        while let Some(pos) = path[parent + 1..].find('/') {
+            parent += pos + 1;
+            if parent > dir.trim_end_matches('/').len() && !io::exists(&path[..parent]) {
+                io::make_dir(&path[..parent]);
+            }
+        }
+
+        match member.kind {
+            Kind::Other => {
+                report(&member.name, Err("Unsupported member type"));
+                continue;
+            }
+            Kind::Dir => {
+                if !io::make_dir(&path) && !io::exists(&path) {
+                    report(&member.name, Err("Could not create directory"));
+                    continue;
+                }
+            }
+            Kind::File => {
+                io::make_file(&path);
+
+                let file = File {
+                    fname: path.clone(),
+                    size: 0,
+                    ptr: 0,
+                    read: false,
+                    write: true,
+                };
+                let mut written = file.write(&[]);
+
+                let mut done = 0;
+                while written && done < member.size {
+                    let count = read_member(archive, &member, done, &mut buffer);
+                    if count == 0 {
+                        break;
+                    }
+
+                    written = file.append(&buffer[..count]);
+                    done += count as u32;
+                }
+
+                if !written || io::size(&path) != member.size {
+                    report(&member.name, Err("Could not write file"));
+                    continue;
+                }
+            }
+        }
+
+        report(&member.name, Ok(&path));
+        extracted += 1;
+    }
+
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn archive(members: &[(&str, Kind, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+
+        for &(name, kind, data) in members {
+            let block = header(name, kind, data.len() as u32, &DateTime::from_unix(0)).unwrap();
+            archive.extend_from_slice(&block);
+            archive.extend_from_slice(data);
+            archive.resize(archive.len().div_ceil(BLOCK) * BLOCK, 0);
+        }
+
+        archive.extend_from_slice(&[0u8; BLOCK * 2]);
+        archive
+    }
+
+    #[test]
+    fn walks_members_in_memory() {
+        let data = [7u8; 600];
+        let archive = archive(&[
+            ("docs/", Kind::Dir, b""),
+            ("docs/a.txt", Kind::File, &data),
+            ("b", Kind::File, b"hi"),
+        ]);
+
+        let members: Vec<_> = members(&archive).map(|m| m.unwrap()).collect();
+        let found: Vec<_> = members.iter().map(|(m, d)| (m.name.as_str(), m.kind, *d)).collect();
+
+        assert_eq!(found, [
+            ("docs", Kind::Dir, &b""[..]),
+            ("docs/a.txt", Kind::File, &data[..]),
+            ("b", Kind::File, &b"hi"[..]),
+        ]);
+    }
+
+    #[test]
+    fn reports_truncated_archives() {
+        let archive = archive(&[("a.txt", Kind::File, &[1u8; 600])]);
+
+        let mut members = members(&archive[..BLOCK * 2]);
+        assert_eq!(members.next().unwrap().err(), Some("Archive is truncated"));
+        assert!(members.next().is_none());
+    }
+
+    #[test]
+    fn maps_names_to_8_3_paths() {
+        assert_eq!(target_path("/USER/", "docs/notes.txt").as_deref(), Some("/USER/DOCS/NOTES.TXT"));
+        assert_eq!(target_path("/", "./a/b").as_deref(), Some("/A/B"));
+        assert_eq!(target_path("/USER", "../etc"), None);
+        assert_eq!(target_path("/USER", "toolongname.txt"), None);
+    }
+}
@@ -0,0 +1,51 @@
+/// Status of a task the kernel stopped after a fault.
+pub const FAULT: u32 = 0xFFFF_FFFF;
+
+/// A child that has exited, as `wait` reports it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Exit {
+    pub pid: u32,
+    pub parent: u32,
+    pub status: u32,
+}
+
+impl Exit {
+    pub fn failed(&self) -> bool {
+        self.status != 0
+    }
+}
+
+pub fn exit(status: u32) -> ! {
+    crate::syscall::syscall(26, status, 0, 0);
+
+    loop {}
+}
+
+/// Collects one exited child without blocking. Once the calling task is the
+/// reaper this includes orphans whose parent is gone.
+pub fn wait() -> Option<Exit> {
+    let mut exit = Exit {
+        pid: 0,
+        parent: 0,
+        status: 0,
+    };
+
+    if crate::syscall::syscall(62, &mut exit as *mut Exit as u32, 0, 0) == 1 {
+        Some(exit)
+    } else {
+        None
+    }
+}
+
+/// Asks for orphans to be handed to the calling task. Only the admin may.
+pub fn become_reaper() -> bool {
+    crate::syscall::syscall(63, 0, 0, 0) == 0
+}
+
+/// Gives the rest of the time slice to the other tasks.
+pub fn yield_now() {
+    unsafe {
+        core::arch::asm!("int 0x20");
+    }
+}
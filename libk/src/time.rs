@@ -0,0 +1,105 @@
+use core::fmt;
+
+/// Wall clock time as kept by the RTC, always in 24-hour binary form.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(C)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// FAT dates count years from 1980, so anything earlier is clamped.
+    pub fn fat_date(&self) -> u16 {
+        let year = self.year.saturating_sub(1980).min(127);
+
+        (year << 9) | ((self.month as u16 & 0x0F) << 5) | (self.day as u16 & 0x1F)
+    }
+
+    /// FAT times have a two second resolution.
+    pub fn fat_time(&self) -> u16 {
+        ((self.hour as u16) << 11) | ((self.minute as u16 & 0x3F) << 5) | (self.second as u16 / 2)
+    }
+
+    pub fn from_fat(date: u16, time: u16) -> DateTime {
+        DateTime {
+            year: 1980 + (date >> 9),
+            month: ((date >> 5) & 0x0F) as u8,
+            day: (date & 0x1F) as u8,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3F) as u8,
+            second: ((time & 0x1F) * 2) as u8,
+        }
+    }
+
+    /// Seconds since 1970-01-01, treating the clock as UTC.
+    pub fn to_unix(&self) -> u32 {
+        let (year, month) = if self.month <= 2 {
+            (self.year as i64 - 1, self.month as i64 + 9)
+        } else {
+            (self.year as i64, self.month as i64 - 3)
+        };
+
+        // days from the civil calendar, counting from March so leap days come last
+        let era = year.div_euclid(400);
+        let yoe = year - era * 400;
+        let doy = (153 * month + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let seconds = days * 86400
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+
+        seconds.clamp(0, u32::MAX as i64) as u32
+    }
+
+    pub fn from_unix(seconds: u32) -> DateTime {
+        let days = (seconds / 86400) as i64 + 719468;
+        let time = seconds % 86400;
+
+        let era = days.div_euclid(146097);
+        let doe = days - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.month != 0 && self.day != 0
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+pub fn now() -> DateTime {
+    let mut now = DateTime::default();
+    crate::syscall::syscall(49, &raw mut now as u32, 0, 0);
+
+    now
+}
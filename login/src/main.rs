@@ -138,37 +138,56 @@ pub fn login(w: &mut Widget, a1: u32, _a2: u32, _a3: u32) {
                 let mut p2 = false;
 
                 let f = bafioDb::load("/SYS/USERS.DB");
-                let uname = f.get("USER");
-                let psw = f.get("USERPSW");
-
-                if uname.is_some() && psw.is_some() {
-                    match uname.unwrap() {
-                        crate::bafioDb::Value::String(s) => {
-                            if username.is_some() {
-                                if s == username.unwrap() {
-                                    p1 = true;
-                                }
-                            }
+                let mut uid = None;
+                let mut session = None;
+
+                for user in f.data.iter() {
+                    let name = user.values.get("USER");
+                    let psw = user.values.get("USERPSW");
+
+                    if let Some(crate::bafioDb::Value::String(s)) = name {
+                        if username.is_some() && s == username.unwrap() {
+                            p1 = true;
                         }
-                        _ => {}
                     }
 
-                    match psw.unwrap() {
-                        crate::bafioDb::Value::String(s) => {
-                            if password.is_some() {
-                                if s == core::str::from_utf8_unchecked(&libk::hash::hash_to_hex(&libk::hash::hash_128bit(password.unwrap().as_bytes()))) {
-                                    p2 = true;
-                                }
+                    if let Some(crate::bafioDb::Value::String(s)) = psw {
+                        if password.is_some() {
+                            if s == core::str::from_utf8_unchecked(&libk::hash::hash_to_hex(&libk::hash::hash_128bit(password.unwrap().as_bytes()))) {
+                                p2 = true;
                             }
                         }
-                        _ => {}
                     }
+
+                    if p1 && p2 {
+                        uid = match user.values.get("UID") {
+                            Some(crate::bafioDb::Value::Number(n)) => Some(*n as u16),
+                            _ => None,
+                        };
+                        session = match user.values.get("SESSION") {
+                            Some(crate::bafioDb::Value::String(s)) => Some(s.clone()),
+                            _ => None,
+                        };
+                        break;
+                    }
+
+                    p1 = false;
+                    p2 = false;
                 }
 
                 if p1 && p2 {
                     libk::println!("SUCCESS");
 
-                    let _ = libk::elf::load_elf("USER/USER.ELF", None);
+                    // accounts without a UID never get admin rights
+                    if !libk::perm::set_uid(uid.unwrap_or(1000)) {
+                        libk::println!("[x] Could not switch user");
+                    }
+
+                    // the desktop unless the account names another program
+                    let session = session.as_deref().unwrap_or("USER/USER.ELF");
+                    if let Err(e) = libk::elf::load_elf(session, None) {
+                        libk::println!("[x] Could not start {}: {}", session, e);
+                    }
 
                     kui::draw::exit(w, kui::widgets::WINDOWS[0].id as u32, 0, 0);
                 } else {
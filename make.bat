@@ -13,11 +13,15 @@ cargo build --package=ide --target=bits32-I.json --release
 cargo build --package=exec --target=bits32-I.json --release
 cargo build --package=img --target=bits32-I.json --release
 cargo build --package=login --target=bits32-I.json --release
+cargo build --package=init --target=bits32-I.json --release
+cargo build --package=console --target=bits32-I.json --release
+cargo build --package=display --target=bits32-I.json --release
 
 wsl sh -c "objcopy -I elf32-i386 -O binary target/bits16/debug/bootloader build/bootloader.bin"
 wsl sh -c "objcopy -I elf32-i386 -O binary target/bits16/debug/stage2 build/stage2.bin"
 wsl sh -c "objcopy -I elf32-i386 -O binary target/bits32/debug/stage3 build/stage3.bin"
-wsl sh -c "objcopy -I elf32-i386 -O binary target/bits32/debug/kernel build/kernel.bin"
+wsl sh -c "objcopy --strip-all target/bits32/debug/kernel build/kernel.elf"
+wsl sh -c "printf 'BAFK %%010u %%010u' $(stat -c%%s build/kernel.elf) $(cksum < build/kernel.elf | cut -d' ' -f1) >> build/kernel.elf"
 
 wsl dd if=/dev/zero of=build/disk.img bs=512 count=32768
 wsl dd if=/dev/zero of=build/fat16.img bs=512 count=524288
@@ -37,7 +41,7 @@ wsl mmd -i build/fat16.img ::/user/downloads
 wsl dd if=build/bootloader.bin of=build/disk.img conv=notrunc
 wsl dd if=build/stage2.bin of=build/disk.img bs=512 seek=2048 conv=notrunc
 wsl dd if=build/stage3.bin of=build/disk.img bs=512 seek=3072 conv=notrunc
-wsl dd if=build/kernel.bin of=build/disk.img bs=512 seek=4096 conv=notrunc
+wsl dd if=cmdline.txt of=build/disk.img bs=512 seek=2047 count=1 conv=notrunc
 
 wsl mcopy -i build/fat16.img font.psf "::sys/font/default.psf"
 wsl mcopy -i build/fat16.img wallpaper.tga "::sys/bg.tga"
@@ -45,6 +49,8 @@ wsl mcopy -i build/fat16.img wallpaper.tga "::sys/bg.tga"
 wsl mcopy -i build/fat16.img icons.db "::sys/icons.db"
 wsl mcopy -i build/fat16.img exec.db "::sys/exec.db"
 wsl mcopy -i build/fat16.img users.db "::sys/users.db"
+wsl mcopy -i build/fat16.img init.db "::sys/init.db"
+wsl mcopy -i build/fat16.img build/kernel.elf "::sys/kernel.elf"
 
 wsl mcopy -i build/fat16.img icons/elf.tga "::icons/elf.tga"
 wsl mcopy -i build/fat16.img icons/file.tga "::icons/file.tga"
@@ -63,11 +69,15 @@ wsl mcopy -i build/fat16.img target/bits32-I/release/terminal "::user/desktop/cs
 wsl mcopy -i build/fat16.img target/bits32-I/release/filemanager "::user/desktop/files.elf"
 
 wsl mcopy -i build/fat16.img target/bits32-I/release/ide "::user/desktop/ide.elf"
+wsl mcopy -i build/fat16.img target/bits32-I/release/display "::user/desktop/display.elf"
 wsl mcopy -i build/fat16.img target/bits32-I/release/exec "::user/exec.elf"
 wsl mcopy -i build/fat16.img target/bits32-I/release/img "::user/img.elf"
 wsl mcopy -i build/fat16.img target/bits32-I/release/login "::user/login.elf"
+wsl mcopy -i build/fat16.img target/bits32-I/release/init "::user/init.elf"
+wsl mcopy -i build/fat16.img target/bits32-I/release/console "::user/console.elf"
 
 wsl dd if=build/fat16.img of=build/disk.img bs=512 seek=9216 conv=notrunc
+wsl sh -c "printf '\000\376\377\377\006\376\377\377\000\044\000\000\000\000\010\000' | dd of=build/disk.img bs=1 seek=446 conv=notrunc"
 
 wsl rm -rf build/fat16.img
 
@@ -14,7 +14,7 @@ pub fn read_stub() {
     let disk_setup = Dap {
         size: core::mem::size_of::<Dap>() as u8,
         zero: 0,
-        sectors: 32,
+        sectors: 64,
         offset: 0xFE00,
         segment: 0,
         lba: 3072,
@@ -36,3 +36,33 @@ pub fn read_stub() {
         );
     }
 }
+
+/// Sector holding the kernel command line, just before stage 2.
+const CMDLINE_LBA: u64 = 2047;
+
+pub fn read_cmdline(target: *mut u8) {
+    let disk_setup = Dap {
+        size: core::mem::size_of::<Dap>() as u8,
+        zero: 0,
+        sectors: 1,
+        offset: target as u16,
+        segment: 0,
+        lba: CMDLINE_LBA,
+    };
+
+    unsafe {
+        asm!(
+            "mov {1:x}, si",
+            "mov si, {0:x}",
+            "int 0x13",
+
+            "jc fail",
+            "mov si, {1:x}",
+
+            in(reg) &disk_setup as *const Dap as u16,
+            out(reg) _,
+            in("ax") 0x4200 as u16,
+            in("dx") 0x80 as u16,
+        );
+    }
+}
@@ -3,6 +3,7 @@
 
 mod disk;
 mod gdt;
+mod menu;
 mod tss;
 
 use core::fmt;
@@ -35,16 +36,34 @@ struct Rsdp {
     oem_id: [u8; 6],
     revision: u8,
     rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
 }
 
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
 struct BootInfo {
+    // filled in by stage 3
+    kernel: [Range; 8],
     mmap: MemoryMap,
     rsdp: Rsdp,
     tss: u16,
     vbe: VbeInfoBlock,
     mode: VbeModeInfoBlock,
+    cmdline: [u8; CMDLINE_LEN],
+    // only set by Multiboot loaders
+    modules: [Range; 8],
+}
+
+const CMDLINE_LEN: usize = 256;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    base: u32,
+    size: u32,
 }
 
 #[repr(C, packed)]
@@ -137,6 +156,7 @@ pub extern "C" fn fail() -> ! {
 
 static mut BOOT: BootInfo = unsafe { core::mem::zeroed() };
 static mut VBE_MODE: VbeModeInfoBlock = unsafe { core::mem::zeroed() };
+static mut SECTOR: [u8; 512] = [0; 512];
 
 fn protected_mode() {
     unsafe {
@@ -147,15 +167,21 @@ fn protected_mode() {
         BOOT.vbe = get_vbe_info();
         BOOT.tss = tss_addr;
         get_mmap();
+        get_cmdline();
+        menu::run();
 
-        let best_mode = find_vbe_mode();
+        // the serial console keeps the text mode
+        let serial = param(b"console=").is_some_and(|i| BOOT.cmdline[i..].starts_with(b"serial"));
+        if !serial {
+            let best_mode = find_vbe_mode();
 
-        asm!(
-            "int 0x10",
+            asm!(
+                "int 0x10",
 
-            in("ax") 0x4F02,
-            in("bx") best_mode
-        );
+                in("ax") 0x4F02,
+                in("bx") best_mode
+            );
+        }
 
         asm!("mov eax, cr0", "or eax, 1 << 0", "mov cr0, eax",);
 
@@ -196,6 +222,87 @@ fn get_mmap() {
     }
 }
 
+/// Copies the first line of the command line sector, zero padded.
+#[inline(never)]
+fn get_cmdline() {
+    unsafe {
+        disk::read_cmdline(&raw mut SECTOR as *mut u8);
+
+        let sector = &*(&raw const SECTOR);
+        let cmdline = &mut *(&raw mut BOOT.cmdline);
+
+        for i in 0..CMDLINE_LEN - 1 {
+            match sector[i] {
+                0 | b'\n' | b'\r' => break,
+                byte => cmdline[i] = byte,
+            }
+        }
+    }
+}
+
+/// Resolution asked for with `video=WIDTHxHEIGHT`, parsed by hand since the
+/// core string helpers do not fit in stage 2.
+fn requested_video() -> Option<(u16, u16)> {
+    let cmdline = unsafe { &*(&raw const BOOT.cmdline) };
+
+    let (width, next) = number(cmdline, param(b"video=")?);
+    let (height, _) = if next < CMDLINE_LEN && cmdline[next] == b'x' {
+        number(cmdline, next + 1)
+    } else {
+        (0, next)
+    };
+
+    if width != 0 && height != 0 { Some((width, height)) } else { None }
+}
+
+/// Where the value of the last `key` (ending in `=`) on the command line starts.
+fn param(key: &[u8]) -> Option<usize> {
+    let cmdline = unsafe { &*(&raw const BOOT.cmdline) };
+    let mut found = None;
+
+    let mut i = 0;
+    while i < CMDLINE_LEN && cmdline[i] != 0 {
+        let start = i == 0 || cmdline[i - 1] == b' ';
+        if start && cmdline[i..].starts_with(key) {
+            found = Some(i + key.len());
+        }
+        i += 1;
+    }
+
+    found
+}
+
+/// Adds a parameter at the end of the command line if it fits, the last one
+/// of a key wins.
+fn append(param: &[u8]) {
+    let cmdline = unsafe { &mut *(&raw mut BOOT.cmdline) };
+    let mut end = 0;
+    while end < CMDLINE_LEN && cmdline[end] != 0 {
+        end += 1;
+    }
+
+    let start = if end == 0 { 0 } else { end + 1 };
+    if start + param.len() >= CMDLINE_LEN {
+        return;
+    }
+
+    if end != 0 {
+        cmdline[end] = b' ';
+    }
+    cmdline[start..start + param.len()].copy_from_slice(param);
+}
+
+fn number(text: &[u8], mut i: usize) -> (u16, usize) {
+    let mut value: u16 = 0;
+
+    while i < text.len() && text[i].is_ascii_digit() {
+        value = value.wrapping_mul(10).wrapping_add((text[i] - b'0') as u16);
+        i += 1;
+    }
+
+    (value, i)
+}
+
 #[inline(never)]
 fn get_rsdp() -> Rsdp {
     let mut addr = 0xE0000 as *const u8;
@@ -218,6 +325,10 @@ fn get_rsdp() -> Rsdp {
         oem_id: [0; 6],
         revision: 0,
         rsdt_address: 0,
+        length: 0,
+        xsdt_address: 0,
+        extended_checksum: 0,
+        reserved: [0; 3],
     }
 }
 
@@ -267,52 +378,57 @@ fn get_vbe_info() -> VbeInfoBlock {
     vbe_info
 }
 
+/// Loads `mode` into VBE_MODE and tells whether the kernel can draw to it:
+/// linear, 24 or 32 bits per pixel with red, green and blue from the top.
+#[inline(never)]
+fn usable(mode: u16) -> bool {
+    load_vbe_mode(mode);
+
+    let (bpp, attributes) = unsafe { (VBE_MODE.bpp, VBE_MODE.attributes) };
+    let rgb = unsafe {
+        (
+            VBE_MODE.red_field_position,
+            VBE_MODE.green_field_position,
+            VBE_MODE.blue_field_position,
+        )
+    };
+
+    (bpp == 24 || bpp == 32) && (attributes & 0x80) != 0 && rgb == (16, 8, 0)
+}
+
 #[inline(never)]
 fn find_vbe_mode() -> u16 {
     let base_mode = unsafe { BOOT.vbe.video_ptr } as *const u16;
 
     let mut best_mode = 0x0013;
+    let (mut best_width, mut best_height) = (0, 0);
     let mut i = 0;
-    let mut mode;
+    let requested = requested_video();
 
     loop {
-        mode = unsafe { core::ptr::read_volatile(base_mode.offset(i)) };
+        let mode = unsafe { core::ptr::read_volatile(base_mode.offset(i)) };
+        i += 1;
 
         if mode == 0xFFFF {
             break;
         }
 
-        load_vbe_mode(mode);
-        let mode_width = unsafe { VBE_MODE.width };
-        let mode_height = unsafe { VBE_MODE.height };
-        let mode_bpp = unsafe { VBE_MODE.bpp };
-        let mode_red = unsafe { VBE_MODE.red_field_position };
-        let mode_green = unsafe { VBE_MODE.green_field_position };
-        let mode_blue = unsafe { VBE_MODE.blue_field_position};
-        let mode_attr = unsafe { VBE_MODE.attributes };
-
-        
-        if mode_red != 16 || mode_green != 8 || mode_blue != 0 {
-            i += 1;
+        if !usable(mode) {
             continue;
         }
 
-        load_vbe_mode(best_mode);
-        let best_mode_width = unsafe { VBE_MODE.width };
-        let best_mode_height = unsafe { VBE_MODE.height };
-
-        if (mode_width > best_mode_width
-            && mode_width <= 1024
-            && mode_height > best_mode_height
-            && mode_height <= 1024
-            && (mode_bpp == 24 || mode_bpp == 32))
-            && (mode_attr & 0x80) != 0
-        {
+        let (width, height) = unsafe { (VBE_MODE.width, VBE_MODE.height) };
+
+        if requested == Some((width, height)) {
+            save_vbe_mode(mode);
+            return mode;
+        }
+
+        if width > best_width && width <= 1024 && height > best_height && height <= 1024 {
             best_mode = mode;
+            (best_width, best_height) = (width, height);
             save_vbe_mode(best_mode);
         }
-
-        i += 1;
     }
 
     best_mode
@@ -0,0 +1,211 @@
+//! Text mode boot menu, shown for `menu=SECONDS` (3 by default, 0 skips it).
+//! The choice is added to the command line, where the rest of stage 2 and
+//! the kernel look for it.
+
+use core::arch::asm;
+
+const MAX_MODES: usize = 12;
+
+/// BIOS timer, 18.2 ticks a second.
+const TICKS: *const u32 = 0x46C as *const u32;
+
+const KEY_UP: u16 = 0x48;
+const KEY_DOWN: u16 = 0x50;
+
+#[derive(Clone, Copy)]
+struct Mode {
+    width: u16,
+    height: u16,
+}
+
+pub fn run() {
+    let seconds = match crate::param(b"menu=") {
+        Some(i) => crate::number(unsafe { &*(&raw const crate::BOOT.cmdline) }, i).0,
+        None => 3,
+    };
+    if seconds == 0 {
+        return;
+    }
+
+    let mut modes = [Mode { width: 0, height: 0 }; MAX_MODES];
+    let count = list_modes(&mut modes);
+
+    // default, the modes, safe mode, serial console
+    let items = count + 3;
+    let mut selected = 0;
+    let mut waiting = true;
+    let mut shown = None;
+
+    // the timer and the keyboard need interrupts
+    unsafe { asm!("mov ax, 0x0003", "int 0x10", "sti", out("ax") _) };
+    let start = ticks();
+
+    loop {
+        let elapsed = ((ticks().wrapping_sub(start)) * 10 / 182) as u16;
+        if waiting && elapsed >= seconds {
+            break;
+        }
+
+        let left = if waiting { seconds - elapsed } else { 0 };
+        if shown != Some((selected, left)) {
+            draw(&modes[..count], selected, left);
+            shown = Some((selected, left));
+        }
+
+        let Some(key) = key() else {
+            continue;
+        };
+        waiting = false;
+
+        match key >> 8 {
+            KEY_UP => selected = (selected + items - 1) % items,
+            KEY_DOWN => selected = (selected + 1) % items,
+            _ if key as u8 == b'\r' => break,
+            _ => {}
+        }
+    }
+
+    unsafe { asm!("cli") };
+
+    if selected == 0 {
+        return;
+    }
+
+    let mut param = [0u8; 20];
+    let len = if selected <= count {
+        let mode = modes[selected - 1];
+
+        param[..6].copy_from_slice(b"video=");
+        let mut len = write_number(&mut param, 6, mode.width);
+        param[len] = b'x';
+        len = write_number(&mut param, len + 1, mode.height);
+        len
+    } else if selected == count + 1 {
+        param[..4].copy_from_slice(b"safe");
+        4
+    } else {
+        param[..14].copy_from_slice(b"console=serial");
+        14
+    };
+
+    crate::append(&param[..len]);
+}
+
+/// Resolutions the kernel can draw to, each listed once.
+fn list_modes(modes: &mut [Mode]) -> usize {
+    let list = unsafe { crate::BOOT.vbe.video_ptr } as *const u16;
+    let mut count = 0;
+    let mut i = 0;
+
+    loop {
+        let mode = unsafe { core::ptr::read_volatile(list.offset(i)) };
+        if mode == 0xFFFF || count == modes.len() {
+            break;
+        }
+        i += 1;
+
+        if !crate::usable(mode) {
+            continue;
+        }
+
+        let found = unsafe {
+            Mode {
+                width: crate::VBE_MODE.width,
+                height: crate::VBE_MODE.height,
+            }
+        };
+        if !modes[..count]
+            .iter()
+            .any(|m| m.width == found.width && m.height == found.height)
+        {
+            modes[count] = found;
+            count += 1;
+        }
+    }
+
+    count
+}
+
+fn draw(modes: &[Mode], selected: usize, left: u16) {
+    // cursor to the top left
+    unsafe { asm!("int 0x10", in("ax") 0x0200, in("bx") 0, in("dx") 0) };
+
+    print(b"bafiOS\r\n\r\n");
+
+    for item in 0..modes.len() + 3 {
+        print(if item == selected { b" > " } else { b"   " });
+
+        if item == 0 {
+            print(b"Default video mode");
+        } else if item <= modes.len() {
+            print_number(modes[item - 1].width);
+            print(b"x");
+            print_number(modes[item - 1].height);
+        } else if item == modes.len() + 1 {
+            print(b"Safe mode, no network, APIC or extra processors");
+        } else {
+            print(b"Serial console, no graphics");
+        }
+
+        print(b"          \r\n");
+    }
+
+    print(b"\r\nUp and down to choose, enter to boot");
+    if left > 0 {
+        print(b", booting in ");
+        print_number(left);
+    }
+    print(b"          ");
+}
+
+fn key() -> Option<u16> {
+    let ready: u8;
+    unsafe {
+        asm!("int 0x16", "setnz {0}", out(reg_byte) ready, inout("ax") 0x0100u16 => _);
+    }
+
+    if ready == 0 {
+        return None;
+    }
+
+    let key: u16;
+    unsafe { asm!("int 0x16", inout("ax") 0u16 => key) };
+    Some(key)
+}
+
+fn ticks() -> u32 {
+    unsafe { core::ptr::read_volatile(TICKS) }
+}
+
+fn print(text: &[u8]) {
+    for &c in text {
+        unsafe { asm!("int 0x10", in("ax") 0x0E00 | c as u16, in("bx") 0) };
+    }
+}
+
+fn print_number(n: u16) {
+    let mut digits = [0u8; 5];
+    let len = write_number(&mut digits, 0, n);
+    print(&digits[..len]);
+}
+
+/// Writes `n` in decimal at `at`, returning the index after it.
+fn write_number(buffer: &mut [u8], at: usize, mut n: u16) -> usize {
+    let mut digits = [0u8; 5];
+    let mut count = 0;
+
+    loop {
+        digits[count] = b'0' + (n % 10) as u8;
+        count += 1;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+
+    for i in 0..count {
+        buffer[at + i] = digits[count - 1 - i];
+    }
+
+    at + count
+}
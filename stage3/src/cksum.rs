@@ -0,0 +1,41 @@
+/// POSIX `cksum`: CRC-32 with polynomial 0x04C11DB7, most significant bit
+/// first, over the data followed by its length.
+pub struct Cksum {
+    crc: u32,
+    len: u32,
+}
+
+impl Cksum {
+    pub fn new() -> Self {
+        Cksum { crc: 0, len: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.byte(byte);
+        }
+        self.len += data.len() as u32;
+    }
+
+    pub fn finish(mut self) -> u32 {
+        let mut len = self.len;
+        while len != 0 {
+            self.byte(len as u8);
+            len >>= 8;
+        }
+
+        !self.crc
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.crc ^= (byte as u32) << 24;
+
+        for _ in 0..8 {
+            self.crc = if self.crc & 0x8000_0000 != 0 {
+                (self.crc << 1) ^ 0x04C1_1DB7
+            } else {
+                self.crc << 1
+            };
+        }
+    }
+}
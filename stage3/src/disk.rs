@@ -1,18 +1,170 @@
+//! Reads the boot disk: the first SATA disk of an AHCI controller when there
+//! is one, which is where a Q35 board has it, then a disk on the primary IDE
+//! channel, then a virtio-blk disk through its legacy interface. All are
+//! polled, nothing is set up for interrupts.
+
 use core::arch::asm;
 
+const AHCI_CLASS: u32 = 0x01_06_01;
+
+const HBA_GHC: u32 = 0x04;
+const HBA_PI: u32 = 0x0C;
+const GHC_AE: u32 = 1 << 31;
+
+const PX_CLB: u32 = 0x00;
+const PX_CLBU: u32 = 0x04;
+const PX_FB: u32 = 0x08;
+const PX_FBU: u32 = 0x0C;
+const PX_IS: u32 = 0x10;
+const PX_CMD: u32 = 0x18;
+const PX_TFD: u32 = 0x20;
+const PX_SIG: u32 = 0x24;
+const PX_SSTS: u32 = 0x28;
+const PX_CI: u32 = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+const IS_TFES: u32 = 1 << 30;
+const SIG_SATA: u32 = 0x0000_0101;
+
+const ATA_READ: u8 = 0x20;
+const ATA_READ_EXT: u8 = 0x24;
+const ATA_READ_DMA_EXT: u8 = 0x25;
+
+const VIRTIO_VENDOR: u32 = 0x1AF4;
+const VIRTIO_BLK: u32 = 0x1001;
+
+const VIRTIO_QUEUE_PFN: u16 = 0x08;
+const VIRTIO_QUEUE_SIZE: u16 = 0x0C;
+const VIRTIO_QUEUE_NOTIFY: u16 = 0x10;
+const VIRTIO_STATUS: u16 = 0x12;
+/// Acknowledge, driver, then driver OK.
+const VIRTIO_READY: u8 = 1 | 2 | 4;
+
+/// Right above the scratch buffer: the AHCI command list, received FIS and
+/// the one command table, or the virtio queue with a page after it for the
+/// request header and status.
+const MEMORY: u32 = 0x3_0000;
+const CMD_LIST: u32 = MEMORY;
+const RX_FIS: u32 = MEMORY + 0x400;
+const CMD_TABLE: u32 = MEMORY + 0x800;
+const QUEUE: u32 = MEMORY;
+
+/// Registers of the AHCI port the boot disk is on, 0 if not on AHCI.
+static mut PORT: u32 = 0;
+/// I/O base of the virtio-blk disk, 0 if not on virtio.
+static mut VIRTIO: u16 = 0;
+static mut QUEUE_SIZE: u16 = 0;
+static mut USED: u32 = 0;
+
+/// Picks the controller to read the boot disk through.
+pub fn init() {
+    if let Some(address) = find(|_, class| class >> 8 == AHCI_CLASS) {
+        init_ahci(config(address, 0x24) & !0xF);
+    }
+
+    // the primary IDE channel reads 0 or floats high without a drive
+    if unsafe { PORT } != 0 || !matches!(inb(0x1F7), 0x00 | 0xFF) {
+        return;
+    }
+
+    if let Some(address) = find(|id, _| id == VIRTIO_BLK << 16 | VIRTIO_VENDOR) {
+        init_virtio((config(address, 0x10) & !0x3) as u16);
+    }
+}
+
+fn init_ahci(abar: u32) {
+    write_32(abar + HBA_GHC, read_32(abar + HBA_GHC) | GHC_AE);
+    let implemented = read_32(abar + HBA_PI);
+
+    for number in 0..32 {
+        let port = abar + 0x100 + number * 0x80;
+
+        // a disk is present and its link is up, and it is not ATAPI
+        if implemented & (1 << number) == 0
+            || read_32(port + PX_SSTS) & 0xF0F != 0x103
+            || read_32(port + PX_SIG) != SIG_SATA
+        {
+            continue;
+        }
+
+        write_32(port + PX_CMD, read_32(port + PX_CMD) & !(CMD_ST | CMD_FRE));
+        while read_32(port + PX_CMD) & (CMD_CR | CMD_FR) != 0 {}
+
+        unsafe { core::ptr::write_bytes(MEMORY as *mut u8, 0, 0x900) };
+        write_32(port + PX_CLB, CMD_LIST);
+        write_32(port + PX_CLBU, 0);
+        write_32(port + PX_FB, RX_FIS);
+        write_32(port + PX_FBU, 0);
+
+        write_32(port + PX_CMD, read_32(port + PX_CMD) | CMD_FRE);
+        write_32(port + PX_CMD, read_32(port + PX_CMD) | CMD_ST);
+
+        unsafe { PORT = port };
+        return;
+    }
+}
+
+fn init_virtio(io: u16) {
+    outb(io + VIRTIO_STATUS, 0);
+    outb(io + VIRTIO_STATUS, 1 | 2);
+
+    // a legacy queue has its used ring on the page after the available ring
+    let size = inw(io + VIRTIO_QUEUE_SIZE);
+    let used = (QUEUE + 18 * size as u32 + 6 + 0xFFF) & !0xFFF;
+    unsafe { core::ptr::write_bytes(QUEUE as *mut u8, 0, (request(used, size) + 0x1000 - QUEUE) as usize) };
+
+    outl(io + VIRTIO_QUEUE_PFN, QUEUE / 4096);
+    outb(io + VIRTIO_STATUS, VIRTIO_READY);
+
+    unsafe {
+        VIRTIO = io;
+        QUEUE_SIZE = size;
+        USED = used;
+    }
+}
+
 pub fn read<T>(lba: u64, sectors: u16, target: *mut T) {
+    if unsafe { PORT } != 0 {
+        read_ahci(lba, sectors, target as u32);
+        return;
+    }
+
+    if unsafe { VIRTIO } != 0 {
+        read_virtio(lba, sectors, target as u32);
+        return;
+    }
+
     while is_busy() {}
 
     outb(0x3f6, 0b00000010);
 
     outb(0x1F1, 0x00);
-    outb(0x1F2, sectors as u8);
-    outb(0x1F3, lba as u8);
-    outb(0x1F4, (lba >> 8) as u8);
-    outb(0x1F5, (lba >> 16) as u8);
-    outb(0x1F6, (0xE0 | ((lba >> 24) & 0x0F)) as u8);
 
-    outb(0x1F7, 0x20);
+    // LBA48 takes the high bytes first through the same registers
+    if lba + sectors as u64 > 1 << 28 {
+        outb(0x1F2, (sectors >> 8) as u8);
+        outb(0x1F3, (lba >> 24) as u8);
+        outb(0x1F4, (lba >> 32) as u8);
+        outb(0x1F5, (lba >> 40) as u8);
+        outb(0x1F2, sectors as u8);
+        outb(0x1F3, lba as u8);
+        outb(0x1F4, (lba >> 8) as u8);
+        outb(0x1F5, (lba >> 16) as u8);
+        outb(0x1F6, 0x40);
+
+        outb(0x1F7, ATA_READ_EXT);
+    } else {
+        outb(0x1F2, sectors as u8);
+        outb(0x1F3, lba as u8);
+        outb(0x1F4, (lba >> 8) as u8);
+        outb(0x1F5, (lba >> 16) as u8);
+        outb(0x1F6, (0xE0 | ((lba >> 24) & 0x0F)) as u8);
+
+        outb(0x1F7, ATA_READ);
+    }
 
     let mut sectors_left = sectors;
     let mut target_pointer = target;
@@ -40,6 +192,127 @@ pub fn read<T>(lba: u64, sectors: u16, target: *mut T) {
     reset();
 }
 
+fn read_ahci(lba: u64, sectors: u16, target: u32) {
+    let port = unsafe { PORT };
+    let fis = CMD_TABLE as *mut u8;
+
+    unsafe {
+        core::ptr::write_bytes(fis, 0, 0x80);
+
+        *fis.add(0) = 0x27;
+        *fis.add(1) = 0x80;
+        *fis.add(2) = ATA_READ_DMA_EXT;
+        for (i, at) in [4, 5, 6, 8, 9, 10].into_iter().enumerate() {
+            *fis.add(at) = (lba >> (i * 8)) as u8;
+        }
+        *fis.add(7) = 1 << 6;
+        *fis.add(12) = sectors as u8;
+        *fis.add(13) = (sectors >> 8) as u8;
+
+        // one PRD, clusters are at most 64 KiB
+        let prd = (CMD_TABLE + 0x80) as *mut u32;
+        *prd = target;
+        *prd.add(1) = 0;
+        *prd.add(3) = sectors as u32 * 512 - 1;
+
+        // five dword FIS, one PRD
+        let header = CMD_LIST as *mut u32;
+        *header = 5 | (1 << 16);
+        *header.add(1) = 0;
+        *header.add(2) = CMD_TABLE;
+        *header.add(3) = 0;
+    }
+
+    write_32(port + PX_IS, 0xFFFF_FFFF);
+    write_32(port + PX_CI, 1);
+
+    while read_32(port + PX_CI) & 1 != 0 {
+        if read_32(port + PX_IS) & IS_TFES != 0 {
+            crate::halt("Disk read failed");
+        }
+    }
+
+    if read_32(port + PX_TFD) & 1 != 0 {
+        crate::halt("Disk read failed");
+    }
+}
+
+fn read_virtio(lba: u64, sectors: u16, target: u32) {
+    let (io, size, used) = unsafe { (VIRTIO, QUEUE_SIZE as u32, USED) };
+    let header = request(used, size as u16);
+    let status = header + 16;
+
+    unsafe {
+        // request header: read, reserved, sector
+        *(header as *mut [u32; 2]) = [0, 0];
+        *((header + 8) as *mut u64) = lba;
+        *(status as *mut u8) = 0xFF;
+
+        // descriptors: header, data the device writes, status it writes
+        let desc = QUEUE as *mut [u32; 4];
+        *desc = [header, 0, 16, 1 | (1 << 16)];
+        *desc.add(1) = [target, 0, sectors as u32 * 512, 1 | 2 | (2 << 16)];
+        *desc.add(2) = [status, 0, 1, 2];
+
+        let avail = (QUEUE + 16 * size) as *mut u16;
+        let index = *avail.add(1);
+        *avail.add(2 + (index as u32 % size) as usize) = 0;
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        *avail.add(1) = index.wrapping_add(1);
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+
+        outw(io + VIRTIO_QUEUE_NOTIFY, 0);
+
+        while core::ptr::read_volatile((used + 2) as *const u16) != index.wrapping_add(1) {}
+
+        if core::ptr::read_volatile(status as *const u8) != 0 {
+            crate::halt("Disk read failed");
+        }
+    }
+}
+
+/// The page after the used ring, which holds the request header and status.
+fn request(used: u32, size: u16) -> u32 {
+    used + ((6 + 8 * size as u32 + 0xFFF) & !0xFFF)
+}
+
+/// Configuration address of the first PCI function `matches` takes by its
+/// vendor and device ID and its class, with its I/O and memory space and
+/// bus mastering turned on.
+fn find(matches: impl Fn(u32, u32) -> bool) -> Option<u32> {
+    for bus in 0..=255u32 {
+        for device in 0..32u32 {
+            for function in 0..8u32 {
+                let address = 0x8000_0000 | (bus << 16) | (device << 11) | (function << 8);
+
+                if !matches(config(address, 0x00), config(address, 0x08)) {
+                    continue;
+                }
+
+                outl(0xCF8, address | 0x04);
+                outl(0xCFC, inl(0xCFC) | 0x07);
+
+                return Some(address);
+            }
+        }
+    }
+
+    None
+}
+
+fn config(address: u32, offset: u32) -> u32 {
+    outl(0xCF8, address | offset);
+    inl(0xCFC)
+}
+
+fn read_32(address: u32) -> u32 {
+    unsafe { core::ptr::read_volatile(address as *const u32) }
+}
+
+fn write_32(address: u32, value: u32) {
+    unsafe { core::ptr::write_volatile(address as *mut u32, value) }
+}
+
 pub fn reset() {
     outb(0x3f6, 0b00000110);
     outb(0x3f6, 0b00000010);
@@ -91,3 +364,36 @@ pub fn inw(port: u16) -> u16 {
     }
     value
 }
+
+pub fn inl(port: u16) -> u32 {
+    let value: u32;
+    unsafe {
+        asm!(
+            "in eax, dx",
+            out("eax") value,
+            in("dx") port,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    value
+}
+
+pub fn outl(port: u16, value: u32) {
+    unsafe {
+        asm!(
+            "out dx, eax",
+            in("dx") port,
+            in("eax") value,
+            options(nomem, nostack, preserves_flags));
+    }
+}
+
+pub fn outw(port: u16, value: u16) {
+    unsafe {
+        asm!(
+            "out dx, ax",
+            in("dx") port,
+            in("ax") value,
+            options(nomem, nostack, preserves_flags));
+    }
+}
@@ -0,0 +1,154 @@
+const PT_LOAD: u32 = 1;
+const ET_EXEC: u16 = 2;
+const EM_386: u16 = 3;
+
+pub const MAX_SEGMENTS: usize = 8;
+
+/// The kernel has to stay out of the real mode area, where the boot stages live.
+const LOAD_START: u32 = 0x10_0000;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Header {
+    ident: [u8; 16],
+    e_type: u16,
+    machine: u16,
+    version: u32,
+    entry: u32,
+    phoff: u32,
+    shoff: u32,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ProgramHeader {
+    p_type: u32,
+    offset: u32,
+    vaddr: u32,
+    paddr: u32,
+    filesz: u32,
+    memsz: u32,
+    flags: u32,
+    align: u32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Segment {
+    pub offset: u32,
+    pub base: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+}
+
+pub struct Image {
+    pub entry: u32,
+    pub segments: [Segment; MAX_SEGMENTS],
+    pub count: usize,
+}
+
+impl Image {
+    /// Reads the headers, which have to be within `head`, the start of a file
+    /// of `size` bytes. Segments have to end below `limit`.
+    pub fn parse(head: &[u8], size: u32, limit: u32) -> Result<Image, &'static str> {
+        if head.len() < core::mem::size_of::<Header>() {
+            return Err("Kernel is too small to be an ELF");
+        }
+
+        let header = unsafe { (head.as_ptr() as *const Header).read_unaligned() };
+
+        if header.ident[0..4] != [0x7F, b'E', b'L', b'F'] {
+            return Err("Kernel is not an ELF");
+        }
+        if header.ident[4] != 1 || header.machine != EM_386 || header.e_type != ET_EXEC {
+            return Err("Kernel is not a 32 bit x86 executable");
+        }
+        if (header.phentsize as usize) < core::mem::size_of::<ProgramHeader>() {
+            return Err("Bad program header size");
+        }
+
+        let table_end = header.phoff as usize + header.phnum as usize * header.phentsize as usize;
+        if table_end > head.len() {
+            return Err("Program headers are out of reach");
+        }
+
+        let mut image = Image {
+            entry: header.entry,
+            segments: [Segment { offset: 0, base: 0, filesz: 0, memsz: 0 }; MAX_SEGMENTS],
+            count: 0,
+        };
+
+        for i in 0..header.phnum as usize {
+            let at = header.phoff as usize + i * header.phentsize as usize;
+            let ph = unsafe { (head.as_ptr().add(at) as *const ProgramHeader).read_unaligned() };
+
+            if ph.p_type != PT_LOAD || ph.memsz == 0 {
+                continue;
+            }
+            if image.count == MAX_SEGMENTS {
+                return Err("Too many segments");
+            }
+            if ph.filesz > ph.memsz || ph.offset as u64 + ph.filesz as u64 > size as u64 {
+                return Err("Segment is outside of the file");
+            }
+            if ph.paddr < LOAD_START || ph.paddr as u64 + ph.memsz as u64 > limit as u64 {
+                return Err("Segment is outside of usable memory");
+            }
+
+            image.segments[image.count] = Segment {
+                offset: ph.offset,
+                base: ph.paddr,
+                filesz: ph.filesz,
+                memsz: ph.memsz,
+            };
+            image.count += 1;
+        }
+
+        if image.count == 0 {
+            return Err("Kernel has nothing to load");
+        }
+
+        Ok(image)
+    }
+
+    /// Copies the part of every segment found in `data`, which starts at
+    /// `offset` in the file.
+    pub fn copy(&self, offset: u32, data: &[u8]) {
+        let end = offset + data.len() as u32;
+
+        for segment in &self.segments[..self.count] {
+            let from = core::cmp::max(offset, segment.offset);
+            let to = core::cmp::min(end, segment.offset + segment.filesz);
+            if from >= to {
+                continue;
+            }
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data.as_ptr().add((from - offset) as usize),
+                    (segment.base + from - segment.offset) as *mut u8,
+                    (to - from) as usize,
+                );
+            }
+        }
+    }
+
+    /// Zeroes what segments have in memory past their file contents.
+    pub fn zero_bss(&self) {
+        for segment in &self.segments[..self.count] {
+            unsafe {
+                core::ptr::write_bytes(
+                    (segment.base + segment.filesz) as *mut u8,
+                    0,
+                    (segment.memsz - segment.filesz) as usize,
+                );
+            }
+        }
+    }
+}
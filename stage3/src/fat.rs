@@ -0,0 +1,273 @@
+//! Just enough FAT16 to find a file by its path and read it, see the `fat`
+//! crate for the real thing.
+
+use crate::disk;
+
+const FAT16_TYPES: [u8; 3] = [0x04, 0x06, 0x0E];
+const GPT_PROTECTIVE: u8 = 0xEE;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// As many entries as the kernel looks at.
+const GPT_MAX_ENTRIES: u32 = 128;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+pub struct Volume {
+    fat: u64,
+    root: u64,
+    root_sectors: u32,
+    data: u64,
+    cluster_sectors: u32,
+}
+
+pub struct File {
+    cluster: u16,
+    pub size: u32,
+    dir: bool,
+}
+
+enum Search {
+    Found(File),
+    Next,
+    End,
+}
+
+impl Volume {
+    /// Opens the first FAT16 partition of the boot disk, listed in the MBR
+    /// or in a GPT.
+    pub fn open() -> Result<Volume, &'static str> {
+        let mbr = sector(0);
+        let entries = &mbr[446..510];
+
+        if entries.chunks(16).any(|e| e[4] == GPT_PROTECTIVE) {
+            return Volume::open_gpt();
+        }
+
+        let start = entries
+            .chunks(16)
+            .find(|e| FAT16_TYPES.contains(&e[4]))
+            .map(|e| u32_at(e, 8))
+            .ok_or("No FAT16 partition")?;
+
+        Volume::at(start as u64)
+    }
+
+    /// GPT types do not tell FAT16 from other FAT volumes, so every partition
+    /// is tried. The CRCs are left to the kernel, which also reads the backup.
+    fn open_gpt() -> Result<Volume, &'static str> {
+        let header = sector(1);
+        if header[..8] != GPT_SIGNATURE[..] {
+            return Err("No GPT header");
+        }
+
+        let entries = u64_at(&header, 72);
+        let count = core::cmp::min(u32_at(&header, 80), GPT_MAX_ENTRIES);
+        let size = u32_at(&header, 84);
+        if size < 128 || 512 % size != 0 {
+            return Err("Unsupported GPT entry size");
+        }
+
+        let mut data = [0u8; 512];
+        for i in 0..count {
+            let at = i as u64 * size as u64;
+            if at % 512 == 0 {
+                data = sector(entries + at / 512);
+            }
+
+            let entry = &data[(at % 512) as usize..];
+            if entry[..16].iter().all(|&b| b == 0) {
+                continue;
+            }
+
+            if let Ok(volume) = Volume::at(u64_at(entry, 32)) {
+                return Ok(volume);
+            }
+        }
+
+        Err("No FAT16 partition")
+    }
+
+    fn at(start: u64) -> Result<Volume, &'static str> {
+        let boot = sector(start);
+        // FAT32 has neither a fixed root directory nor a 16 bit FAT size
+        if boot[510..] != [0x55, 0xAA]
+            || u16_at(&boot, 11) != 512
+            || boot[13] == 0
+            || u16_at(&boot, 17) == 0
+            || u16_at(&boot, 22) == 0
+        {
+            return Err("Not a FAT16 volume");
+        }
+
+        let reserved = u16_at(&boot, 14) as u64;
+        let fats = boot[16] as u64;
+        let root_entries = u16_at(&boot, 17) as u32;
+        let fat_sectors = u16_at(&boot, 22) as u64;
+
+        let fat = start + reserved;
+        let root = fat + fats * fat_sectors;
+        let root_sectors = (root_entries * 32).div_ceil(512);
+
+        Ok(Volume {
+            fat,
+            root,
+            root_sectors,
+            data: root + root_sectors as u64,
+            cluster_sectors: boot[13] as u32,
+        })
+    }
+
+    pub fn find(&self, path: &str) -> Result<File, &'static str> {
+        let mut found: Option<File> = None;
+
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            if found.as_ref().is_some_and(|f| !f.dir) {
+                return Err("Not a directory");
+            }
+
+            found = Some(self.lookup(found.as_ref(), &short_name(part)?)?);
+        }
+
+        match found {
+            Some(file) if !file.dir => Ok(file),
+            _ => Err("Not a file"),
+        }
+    }
+
+    /// Reads `file` a cluster at a time through `buffer`, which has to hold
+    /// one, handing each piece to `f` with its offset in the file.
+    pub fn read(
+        &self,
+        file: &File,
+        buffer: *mut u8,
+        mut f: impl FnMut(u32, &[u8]) -> Result<(), &'static str>,
+    ) -> Result<(), &'static str> {
+        let cluster_bytes = self.cluster_sectors * 512;
+        let mut cluster = file.cluster;
+        let mut offset = 0;
+
+        while offset < file.size {
+            if !is_data(cluster) {
+                return Err("File is shorter than its size");
+            }
+
+            disk::read(self.cluster_lba(cluster), self.cluster_sectors as u16, buffer);
+
+            let len = core::cmp::min(cluster_bytes, file.size - offset);
+            f(offset, unsafe { core::slice::from_raw_parts(buffer, len as usize) })?;
+
+            offset += len;
+            cluster = self.next(cluster);
+        }
+
+        Ok(())
+    }
+
+    /// Looks for `name` in `dir`, the root directory if None.
+    fn lookup(&self, dir: Option<&File>, name: &[u8; 11]) -> Result<File, &'static str> {
+        match dir {
+            None => {
+                for i in 0..self.root_sectors {
+                    match search(&sector(self.root + i as u64), name) {
+                        Search::Found(file) => return Ok(file),
+                        Search::End => break,
+                        Search::Next => {}
+                    }
+                }
+            }
+
+            Some(dir) => {
+                let mut cluster = dir.cluster;
+
+                'chain: while is_data(cluster) {
+                    for i in 0..self.cluster_sectors {
+                        match search(&sector(self.cluster_lba(cluster) + i as u64), name) {
+                            Search::Found(file) => return Ok(file),
+                            Search::End => break 'chain,
+                            Search::Next => {}
+                        }
+                    }
+
+                    cluster = self.next(cluster);
+                }
+            }
+        }
+
+        Err("File not found")
+    }
+
+    fn next(&self, cluster: u16) -> u16 {
+        let at = cluster as u64 * 2;
+        u16_at(&sector(self.fat + at / 512), (at % 512) as usize)
+    }
+
+    fn cluster_lba(&self, cluster: u16) -> u64 {
+        self.data + (cluster as u64 - 2) * self.cluster_sectors as u64
+    }
+}
+
+fn search(sector: &[u8; 512], name: &[u8; 11]) -> Search {
+    for entry in sector.chunks(32) {
+        let attributes = entry[11];
+
+        match entry[0] {
+            0x00 => return Search::End,
+            0xE5 => continue,
+            _ => {}
+        }
+
+        if attributes == ATTR_LONG_NAME || attributes & ATTR_VOLUME_ID != 0 {
+            continue;
+        }
+
+        if entry[..11] == name[..] {
+            return Search::Found(File {
+                cluster: u16_at(entry, 26),
+                size: u32_at(entry, 28),
+                dir: attributes & ATTR_DIRECTORY != 0,
+            });
+        }
+    }
+
+    Search::Next
+}
+
+/// Space padded 8.3 name as stored in a directory entry, in upper case.
+fn short_name(name: &str) -> Result<[u8; 11], &'static str> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return Err("Bad file name");
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    short.make_ascii_uppercase();
+
+    Ok(short)
+}
+
+/// Clusters from 0xFFF0 on are reserved, bad or the end of a chain.
+fn is_data(cluster: u16) -> bool {
+    (2..0xFFF0).contains(&cluster)
+}
+
+fn sector(lba: u64) -> [u8; 512] {
+    let mut sector = [0u8; 512];
+    disk::read(lba, 1, sector.as_mut_ptr());
+    sector
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u32_at(bytes, at) as u64 | (u32_at(bytes, at + 4) as u64) << 32
+}
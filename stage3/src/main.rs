@@ -1,7 +1,10 @@
 #![no_std]
 #![no_main]
 
+mod cksum;
 mod disk;
+mod elf;
+mod fat;
 
 use core::arch::asm;
 use core::fmt;
@@ -23,24 +26,60 @@ pub extern "C" fn _start() -> ! {
             "mov esp, {1:e}",
 
             out(reg) _,
-            in(reg) 0x30_0000,
+            in(reg) STACK,
             out("ebx") ebx,
 
             options(nostack),
         );
     }
 
-    let target = 0x10_0000 as *mut u8;
-    disk::read(4096, 2048, target);
+    disk::init();
+
+    let volume = match fat::Volume::open() {
+        Ok(volume) => volume,
+        Err(e) => halt(e),
+    };
+
+    let limit = memory_end(ebx as u32);
+
+    let mut loaded = None;
+    for path in KERNELS {
+        match load(&volume, path, limit) {
+            Ok(image) => {
+                loaded = Some(image);
+                break;
+            }
+            Err(e) => println!("[x] {}: {}", path, e),
+        }
+    }
+
+    let Some(image) = loaded else {
+        halt("No kernel to boot");
+    };
+
+    read_cmdline(&volume, ebx as u32);
+    load_initrd(&volume, &image, limit, ebx as u32);
+
+    // the first field of the boot info, see libk::boot::BootInfo
+    let mut ranges = [Range { base: 0, size: 0 }; elf::MAX_SEGMENTS];
+    for (range, segment) in ranges.iter_mut().zip(&image.segments[..image.count]) {
+        *range = Range {
+            base: segment.base,
+            size: segment.memsz,
+        };
+    }
+    unsafe { core::ptr::write_unaligned(ebx as u32 as *mut _, ranges) };
 
     println!("[+] Jumping to kernel ...");
 
     unsafe {
+        // eax is where Multiboot loaders put their magic, see kernel _start
         asm!(
             "mov ebx, {1:e}",
             "call {0:e}",
-            in(reg) 0x10_0000,
-            in(reg) ebx,
+            in(reg) image.entry,
+            in(reg) ebx as u32,
+            in("eax") 0,
 
         );
     }
@@ -48,6 +87,222 @@ pub extern "C" fn _start() -> ! {
     loop {}
 }
 
+/// Tried in order, `make kernel` keeps the previous kernel as the second.
+const KERNELS: [&str; 2] = ["/SYS/KERNEL.ELF", "/SYS/KERNEL.OLD"];
+
+/// Appended to the ELF by the Makefile as `BAFK <size> <cksum>`, both ten
+/// digits, with the size and `cksum` of what comes before it.
+const FOOTER_SIZE: u32 = 26;
+
+/// Clusters are read through here, FAT16 ones are at most 64 KiB.
+const SCRATCH: u32 = 0x2_0000;
+
+/// Grows down towards the scratch buffer, clear of where the kernel goes.
+const STACK: u32 = 0x8_0000;
+
+const MEMORY_USABLE: u32 = 1;
+
+/// Parameters added to the command line stage 2 read from the disk, so an
+/// image can be reconfigured with `fat put`.
+const CMDLINE_FILE: &str = "/SYS/CMDLINE.TXT";
+
+/// Where the command line sits in the boot info, see libk::boot::BootInfo.
+const CMDLINE_OFFSET: u32 = 1637;
+const CMDLINE_LEN: usize = 256;
+
+/// Loaded after the kernel as its first module if it is there.
+const INITRD_FILE: &str = "/SYS/INITRD.TAR";
+
+/// Where the modules sit in the boot info, see libk::boot::BootInfo.
+const MODULES_OFFSET: u32 = 1893;
+
+/// An entry of the memory map stage 2 leaves in the boot info, right after
+/// the kernel ranges, see libk::boot::BootInfo.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct MemoryMapEntry {
+    base: u64,
+    length: u64,
+    memory_type: u32,
+    reserved_acpi: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Range {
+    base: u32,
+    size: u32,
+}
+
+/// End of the usable RAM starting at 1 MiB, which is where the kernel goes.
+fn memory_end(info: u32) -> u32 {
+    let map = (info + core::mem::size_of::<[Range; elf::MAX_SEGMENTS]>() as u32) as *const MemoryMapEntry;
+
+    for i in 0..32 {
+        let entry = unsafe { core::ptr::read_unaligned(map.add(i)) };
+        let end = entry.base + entry.length;
+
+        if entry.memory_type == MEMORY_USABLE && entry.base <= 0x10_0000 && end > 0x10_0000 {
+            return core::cmp::min(end, u32::MAX as u64) as u32;
+        }
+    }
+
+    0x10_0000
+}
+
+/// Appends the first line of `CMDLINE_FILE` to the command line. Later
+/// parameters win, so the file overrides the disk sector.
+fn read_cmdline(volume: &fat::Volume, info: u32) {
+    let Ok(file) = volume.find(CMDLINE_FILE) else {
+        return;
+    };
+
+    let cmdline = unsafe { &mut *((info + CMDLINE_OFFSET) as *mut [u8; CMDLINE_LEN]) };
+    let mut at = cmdline.iter().position(|&b| b == 0).unwrap_or(CMDLINE_LEN - 1);
+    if at > 0 && at + 1 < CMDLINE_LEN {
+        cmdline[at] = b' ';
+        at += 1;
+    }
+
+    // the last byte stays zero
+    let mut ended = false;
+    let result = volume.read(&file, SCRATCH as *mut u8, |_, data| {
+        for &byte in data {
+            ended |= matches!(byte, 0 | b'\n' | b'\r') || at + 1 >= CMDLINE_LEN;
+            if ended {
+                break;
+            }
+
+            cmdline[at] = byte;
+            at += 1;
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => println!("[+] Read {}", CMDLINE_FILE),
+        Err(e) => println!("[x] {}: {}", CMDLINE_FILE, e),
+    }
+}
+
+/// Copies `INITRD_FILE` to the first page after the kernel and records it
+/// as the first module.
+fn load_initrd(volume: &fat::Volume, image: &elf::Image, limit: u32, info: u32) {
+    let Ok(file) = volume.find(INITRD_FILE) else {
+        return;
+    };
+
+    let end = image.segments[..image.count]
+        .iter()
+        .map(|s| s.base + s.memsz)
+        .max()
+        .unwrap_or(0x10_0000);
+    let base = (end + 0xFFF) & !0xFFF;
+
+    if base.checked_add(file.size).is_none_or(|end| end > limit) {
+        println!("[x] {}: Does not fit in memory", INITRD_FILE);
+        return;
+    }
+
+    let result = volume.read(&file, SCRATCH as *mut u8, |offset, data| {
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), (base + offset) as *mut u8, data.len())
+        };
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        println!("[x] {}: {}", INITRD_FILE, e);
+        return;
+    }
+
+    let module = Range { base, size: file.size };
+    unsafe { core::ptr::write_unaligned((info + MODULES_OFFSET) as *mut Range, module) };
+
+    println!("[+] Loaded {}: {} bytes", INITRD_FILE, file.size);
+}
+
+fn load(volume: &fat::Volume, path: &str, limit: u32) -> Result<elf::Image, &'static str> {
+    let file = volume.find(path)?;
+    if file.size <= FOOTER_SIZE {
+        return Err("Kernel is empty");
+    }
+
+    let size = file.size - FOOTER_SIZE;
+    let mut image = None;
+    let mut sum = cksum::Cksum::new();
+    let mut footer = [0u8; FOOTER_SIZE as usize];
+
+    volume.read(&file, SCRATCH as *mut u8, |offset, data| {
+        let elf = &data[..size.saturating_sub(offset).min(data.len() as u32) as usize];
+
+        if image.is_none() {
+            image = Some(elf::Image::parse(elf, size, limit)?);
+        }
+        if let Some(image) = &image {
+            image.copy(offset, elf);
+        }
+        sum.update(elf);
+
+        // the footer can straddle two clusters
+        for (i, &byte) in data.iter().enumerate().skip(elf.len()) {
+            footer[(offset + i as u32 - size) as usize] = byte;
+        }
+
+        Ok(())
+    })?;
+
+    let (expected_size, expected) = parse_footer(&footer)?;
+    if expected_size != size {
+        return Err("Kernel size does not match its footer");
+    }
+
+    let found = sum.finish();
+    if found != expected {
+        println!("[x] {} checksum is {}, expected {}", path, found, expected);
+        return Err("Kernel is corrupted");
+    }
+
+    let image = image.ok_or("Kernel is empty")?;
+    image.zero_bss();
+
+    println!("[+] Loaded {}: {} bytes in {} segments", path, size, image.count as u32);
+    Ok(image)
+}
+
+/// Size and checksum of the kernel.
+fn parse_footer(footer: &[u8]) -> Result<(u32, u32), &'static str> {
+    if !footer.starts_with(b"BAFK ") {
+        return Err("No kernel footer, was it copied in without make?");
+    }
+
+    let (size, next) = number(footer, 5).ok_or("Bad kernel size")?;
+    let (sum, _) = number(footer, next + 1).ok_or("Bad kernel checksum")?;
+
+    Ok((size, sum))
+}
+
+/// Parses the decimal number at `at`, returning it and the index after it.
+fn number(bytes: &[u8], at: usize) -> Option<(u32, usize)> {
+    let mut value: u32 = 0;
+    let mut i = at;
+
+    while i < bytes.len() && bytes[i].is_ascii_digit() {
+        value = value.checked_mul(10)?.checked_add((bytes[i] - b'0') as u32)?;
+        i += 1;
+    }
+
+    if i == at { None } else { Some((value, i)) }
+}
+
+fn halt(msg: &str) -> ! {
+    println!("[x] Could not load the kernel: {}", msg);
+    loop {
+        unsafe { asm!("cli", "hlt") };
+    }
+}
+
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     println!("[x] Bootloader panicked at stage 3! x_x");
@@ -142,11 +142,23 @@ pub fn parse_command(w: &mut Widget, command: &str) {
                 let path = with_terminal(|t| t.path.clone());
                 let mut output = String::new();
 
+                let long = commands.get(1) == Some(&"-l");
+
                 let entries_count = list_entries(&path);
                 for x in 0..entries_count {
                     if let Some(entry) = libk::io::get_entry(&path, x) {
                         if let Ok(name) = core::str::from_utf8(&entry.name) {
                             output.push_str("\n ");
+                            if long {
+                                let size = entry.size;
+                                output.push_str(&format!(
+                                    "{:02o} {:>5} {} {:>8} ",
+                                    entry.mode().to_octal(),
+                                    entry.owner(),
+                                    entry.modified(),
+                                    size
+                                ));
+                            }
                             output.push_str(name.trim_end());
                         }
                     }
@@ -227,8 +239,11 @@ pub fn parse_command(w: &mut Widget, command: &str) {
                     path
                 });
 
-                libk::io::make_dir(&path);
-                append_output(l, &format!(" Created directory: {}", commands[1]));
+                if libk::io::make_dir(&path) {
+                    append_output(l, &format!(" Created directory: {}", commands[1]));
+                } else {
+                    append_output(l, &format!(" Could not create: {}", commands[1]));
+                }
             },
             
             "mkfile" => {
@@ -246,8 +261,157 @@ pub fn parse_command(w: &mut Widget, command: &str) {
                     path
                 });
 
-                libk::io::make_file(&path);
-                append_output(l, &format!(" Created file: {}", commands[1]));
+                if libk::io::make_file(&path) {
+                    append_output(l, &format!(" Created file: {}", commands[1]));
+                } else {
+                    append_output(l, &format!(" Could not create: {}", commands[1]));
+                }
+            },
+            
+            "rm" => {
+                if commands.len() <= 1 {
+                    append_output(l, " Missing file name");
+                    return;
+                }
+
+                let path = with_terminal(|t| {
+                    let mut path = t.path.clone();
+                    if !path.ends_with('/') {
+                        path.push('/');
+                    }
+                    path.push_str(commands[1]);
+                    path
+                });
+
+                if libk::io::remove(&path) {
+                    append_output(l, &format!(" Removed: {}", commands[1]));
+                } else {
+                    append_output(l, &format!(" Could not remove: {}", commands[1]));
+                }
+            },
+
+            "mv" => {
+                if commands.len() <= 2 {
+                    append_output(l, " Usage: mv FILE NEWNAME");
+                    return;
+                }
+
+                let path = with_terminal(|t| {
+                    let mut path = t.path.clone();
+                    if !path.ends_with('/') {
+                        path.push('/');
+                    }
+                    path.push_str(commands[1]);
+                    path
+                });
+
+                if libk::io::rename(&path, commands[2]) {
+                    append_output(l, &format!(" Renamed {} to {}", commands[1], commands[2]));
+                } else {
+                    append_output(l, &format!(" Could not rename: {}", commands[1]));
+                }
+            },
+            
+            "tar" => {
+                if commands.len() <= 2 || !matches!(commands[1], "c" | "t" | "x") {
+                    append_output(l, " Usage: tar c ARCHIVE DIR | tar t ARCHIVE | tar x ARCHIVE [DIR]");
+                    return;
+                }
+
+                let full_path = |name: &str| {
+                    if name.starts_with('/') {
+                        return String::from(name);
+                    }
+
+                    with_terminal(|t| {
+                        let mut path = t.path.clone();
+                        if !path.ends_with('/') {
+                            path.push('/');
+                        }
+                        path.push_str(name);
+                        path
+                    })
+                };
+                let archive = full_path(commands[2]);
+
+                match commands[1] {
+                    "c" => {
+                        let Some(dir) = commands.get(3) else {
+                            append_output(l, " Usage: tar c ARCHIVE DIR");
+                            return;
+                        };
+
+                        match libk::tar::create(&archive, &full_path(dir)) {
+                            Ok(n) => append_output(l, &format!(" Archived {} entries to {}", n, commands[2])),
+                            Err(e) => append_output(l, &format!(" tar: {}", e)),
+                        }
+                    },
+                    "t" => {
+                        let Some(reader) = libk::tar::Reader::open(&archive) else {
+                            append_output(l, &format!(" tar: No such archive: {}", commands[2]));
+                            return;
+                        };
+
+                        let mut output = String::new();
+                        for member in reader {
+                            match member {
+                                Ok(m) if m.kind == libk::tar::Kind::Dir => {
+                                    output.push_str(&format!(" {:>8}  {}  {}\n", "<DIR>", m.modified, m.name));
+                                }
+                                Ok(m) => {
+                                    output.push_str(&format!(" {:>8}  {}  {}\n", m.size, m.modified, m.name));
+                                }
+                                Err(e) => {
+                                    output.push_str(&format!(" tar: {}\n", e));
+                                    break;
+                                }
+                            }
+                        }
+
+                        append_output(l, output.trim_end());
+                    },
+                    _ => {
+                        let dir = match commands.get(3) {
+                            Some(dir) => full_path(dir),
+                            None => with_terminal(|t| t.path.clone()),
+                        };
+
+                        let mut output = String::new();
+                        let result = libk::tar::extract(&archive, &dir, |name, result| match result {
+                            Ok(path) => output.push_str(&format!(" {} -> {}\n", name, path)),
+                            Err(e) => output.push_str(&format!(" {}: {}\n", name, e)),
+                        });
+
+                        match result {
+                            Ok(n) => output.push_str(&format!(" Extracted {} entries", n)),
+                            Err(e) => output.push_str(&format!(" tar: {}", e)),
+                        }
+
+                        append_output(l, &output);
+                    },
+                }
+            },
+
+            "tmpfs" => {
+                if commands.len() <= 1 {
+                    append_output(l, " Missing mount point");
+                    return;
+                }
+
+                let limit = match commands.get(2).map(|k| k.parse::<u32>()) {
+                    None => 1024,
+                    Some(Ok(k)) => k,
+                    Some(Err(_)) => {
+                        append_output(l, " Invalid size");
+                        return;
+                    }
+                };
+
+                if libk::io::mount_tmpfs(commands[1], limit * 1024) {
+                    append_output(l, &format!(" Mounted tmpfs at {}", commands[1]));
+                } else {
+                    append_output(l, &format!(" Could not mount at {}", commands[1]));
+                }
             },
             
             "exec" => {
@@ -282,13 +446,212 @@ pub fn parse_command(w: &mut Widget, command: &str) {
                 let _ = libk::elf::load_elf(default_executor_app, Some(argv));
             },
             
+            "lsblk" => {
+                let mut output = String::new();
+
+                for p in libk::io::partitions() {
+                    let disk = (b'A' + p.disk) as char;
+                    let index = p.index;
+                    let start = p.start;
+                    let sectors = p.sectors;
+
+                    if index == 0 {
+                        output.push_str(&format!("\n HD{}", disk));
+                    } else {
+                        output.push_str(&format!(
+                            "\n  HD{}{} {} {}+{} {}",
+                            disk,
+                            index,
+                            p.kind.name(),
+                            start,
+                            sectors,
+                            p.mount_point()
+                        ));
+                    }
+                }
+
+                append_output(l, &output);
+            },
+            
+            "whoami" => {
+                append_output(l, &format!(" uid {}", libk::perm::uid()));
+            },
+            
+            "chmod" | "chown" => {
+                if commands.len() <= 2 {
+                    append_output(l, &format!(" Usage: {} VALUE FILE", commands[0]));
+                    return;
+                }
+
+                let path = with_terminal(|t| {
+                    let mut path = t.path.clone();
+                    if !path.ends_with('/') {
+                        path.push('/');
+                    }
+                    path.push_str(commands[2]);
+                    path
+                });
+
+                let ok = if commands[0] == "chmod" {
+                    u8::from_str_radix(commands[1], 8)
+                        .is_ok_and(|mode| mode <= 0o77 && libk::perm::chmod(&path, mode))
+                } else {
+                    commands[1]
+                        .parse::<u16>()
+                        .is_ok_and(|uid| libk::perm::chown(&path, uid))
+                };
+
+                if ok {
+                    append_output(l, "");
+                } else {
+                    append_output(l, &format!(" {}: Permission denied or invalid value", commands[0]));
+                }
+            },
+            
+            "fsck" => {
+                let repair = commands.contains(&"-r");
+                let path = match commands.iter().skip(1).find(|c| !c.starts_with('-')) {
+                    Some(p) => String::from(*p),
+                    None => with_terminal(|t| t.path.clone()),
+                };
+
+                match libk::io::fsck(&path, repair) {
+                    Some(0) => append_output(l, " No problems found"),
+                    Some(n) if repair => append_output(l, &format!(" Repaired {} problems", n)),
+                    Some(n) => append_output(l, &format!(" {} problems found, run fsck -r to repair", n)),
+                    None => append_output(l, " Could not check this filesystem"),
+                }
+            },
+            
+            "cmdline" => {
+                match commands.get(1) {
+                    Some(key) => match libk::cmdline::get(key) {
+                        Some(value) => append_output(l, &format!(" {}", value)),
+                        None => append_output(l, &format!(" {} is not set", key)),
+                    },
+                    None => append_output(l, &format!(" {}", libk::cmdline::line())),
+                }
+            },
+            
+            "video" => {
+                let mode: Vec<u16> = commands
+                    .get(1)
+                    .map_or(Vec::new(), |m| m.split('x').filter_map(|n| n.parse().ok()).collect());
+
+                match mode[..] {
+                    [width, height] | [width, height, _] => {
+                        let bpp = mode.get(2).map_or(32, |&b| b as u8);
+                        if libk::syscall::set_video_mode(width, height, bpp) {
+                            append_output(l, &format!(" Screen is now {}x{}x{}", width, height, bpp));
+                        } else {
+                            append_output(l, " Mode not available, or not the admin");
+                        }
+                    }
+                    _ => append_output(l, " Usage: video WIDTHxHEIGHT[xBPP]"),
+                }
+            },
+            
+            "acpi" => {
+                let Some(acpi) = libk::acpi::info() else {
+                    append_output(l, " No ACPI tables");
+                    return;
+                };
+
+                let mut text = format!(
+                    " ACPI revision {} from the {}\n tables:",
+                    acpi.revision,
+                    if acpi.xsdt { "XSDT" } else { "RSDT" }
+                );
+                for signature in &acpi.tables[..acpi.table_count] {
+                    text.push(' ');
+                    text.push_str(core::str::from_utf8(signature).unwrap_or("????"));
+                }
+
+                if let Some(madt) = acpi.madt {
+                    text.push_str(&format!("\n LAPIC at {:#x}, CPUs:", madt.lapic_address));
+                    for cpu in &madt.cpus[..madt.cpu_count] {
+                        text.push_str(&format!(" {}{}", cpu.apic_id, if cpu.enabled { "" } else { " (off)" }));
+                    }
+                    for ioapic in &madt.ioapics[..madt.ioapic_count] {
+                        text.push_str(&format!(
+                            "\n IOAPIC {} at {:#x}, GSI from {}",
+                            ioapic.id, ioapic.address, ioapic.gsi_base
+                        ));
+                    }
+                    for o in &madt.overrides[..madt.override_count] {
+                        text.push_str(&format!("\n IRQ {} -> GSI {} flags {:#x}", o.irq, o.gsi, o.flags));
+                    }
+                }
+
+                if let Some(fadt) = acpi.fadt {
+                    text.push_str(&format!(
+                        "\n SCI {}, PM1a control {:#x}, PM timer {:#x}, century register {:#x}",
+                        fadt.sci, fadt.pm1a_control, fadt.pm_timer, fadt.century
+                    ));
+                }
+
+                if let Some(hpet) = acpi.hpet {
+                    text.push_str(&format!(
+                        "\n HPET at {:#x}, {} comparators, {} bit",
+                        hpet.address,
+                        hpet.comparators,
+                        if hpet.counter_64 { 64 } else { 32 }
+                    ));
+                }
+
+                append_output(l, &text);
+            },
+            
+            "shutdown" => {
+                libk::syscall::shutdown();
+                append_output(l, " Only the admin can power off");
+            },
+
+            "reboot" => {
+                libk::syscall::reboot();
+                append_output(l, " Only the admin can restart");
+            },
+            
+            "date" => {
+                append_output(l, &format!(" {}", libk::time::now()));
+            },
+            
+            "sync" => {
+                if libk::io::sync() {
+                    append_output(l, " Cache flushed to disk");
+                } else {
+                    append_output(l, " Some sectors could not be written, see the kernel log");
+                }
+            },
+            
+            "cache" => {
+                if commands.len() > 1 {
+                    match commands[1].parse::<u32>() {
+                        Ok(size) if libk::io::set_cache_size(size) => {}
+                        _ => {
+                            append_output(l, " Invalid cache size");
+                            return;
+                        }
+                    }
+                }
+
+                let stats = libk::io::cache_stats();
+                let (hits, misses, writebacks) = (stats.hits, stats.misses, stats.writebacks);
+                let (cached, dirty, capacity) = (stats.cached, stats.dirty, stats.capacity);
+
+                append_output(l, &format!(
+                    " {}/{} sectors cached, {} dirty\n hits {} misses {} writebacks {}",
+                    cached, capacity, dirty, hits, misses, writebacks
+                ));
+            },
+            
             "clear" => {
                 l.label = String::from("\n bafiOS@guest> ");
                 l.ch_min = l.label.len() as u32;
             },
             
             "help" => {
-                let help_text = "\n Available commands:\n echo - Display text\n pwd - Print working directory\n ls - List directory contents\n cd - Change directory\n mkdir - Create directory\n mkfile - Create file\n exec - Execute program\n clear - Clear screen\n help - Show this help\n";
+                let help_text = "\n Available commands:\n echo - Display text\n pwd - Print working directory\n ls - List directory contents (ls -l for dates)\n cd - Change directory\n mkdir - Create directory\n mkfile - Create file\n rm - Remove a file or empty directory\n mv - Rename a file in its directory (mv FILE NEWNAME)\n tmpfs - Mount a RAM filesystem (tmpfs PATH [KiB])\n tar - Archives (tar c ARCHIVE DIR, tar t ARCHIVE, tar x ARCHIVE [DIR])\n exec - Execute program\n lsblk - List disks and partitions\n date - Show the date and time\n acpi - Show what the ACPI tables describe\n video - Change the screen mode (video 1280x720, 24 or 32 bpp after it)\n cmdline - Show the kernel command line (cmdline KEY for one value)\n whoami - Show the current user id\n chmod - Set permissions (chmod 75 FILE, owner then others)\n chown - Set the owner (admin only)\n sync - Flush the disk cache\n shutdown - Power off\n reboot - Restart\n fsck - Check the filesystem (fsck -r to repair)\n cache - Show cache stats (cache N to resize)\n clear - Clear screen\n help - Show this help\n";
                 append_output(l, help_text);
             },
             
@@ -308,13 +671,10 @@ fn append_output(label: &mut Label, text: &str) {
     label.ch_min = label.label.len() as u32;
 }
 
-pub fn list_entries(dir: &str) -> u8 {
+pub fn list_entries(dir: &str) -> u32 {
     let mut count = 0;
 
-    while count < 255 {
-        if libk::io::get_entry(dir, count).is_none() {
-            return count;
-        }
+    while libk::io::get_entry(dir, count).is_some() {
         count += 1;
     }
 
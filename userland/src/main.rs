@@ -50,12 +50,62 @@ pub extern "C" fn _start() -> ! {
 
     wallpaper.add(wp_i);
 
+    add_desktop_icons(&mut action_bar, h);
+    add_power_buttons(&mut action_bar, h);
+
+    init(wallpaper);
+    init(action_bar);
+
+    /*let sock = libk::net::Socket::new(68);
+    sock.send_dhcp_discover();
+    let n = sock.recv(1024);
+    sock.handle_dhcp(unsafe { &*(n.as_ptr() as *const libk::packets::DhcpPacket) });
+    let n = sock.recv(1024);
+    sock.handle_dhcp(unsafe { &*(n.as_ptr() as *const libk::packets::DhcpPacket) });
+    sock.close();*/
+
+    libk::println!("[-]");
+
+    let bar = unsafe { (*(&raw mut WINDOWS)).len() - 1 };
+    let watch = libk::io::watch(DESKTOP_DIR);
+
+    loop {
+        let mut changed = false;
+        while let Some(_event) = watch.and_then(libk::io::poll_watch) {
+            changed = true;
+        }
+
+        if changed {
+            let w = unsafe { &mut (*(&raw mut WINDOWS))[bar] };
+
+            w.children.clear();
+            add_desktop_icons(w, h);
+            add_power_buttons(w, h);
+
+            draw(w);
+            libk::syscall::syscall(41, w.id as u32, 0, 0);
+        }
+
+        for _ in 0..100_000 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+const DESKTOP_DIR: &str = "/USER/DESKTOP";
+
+/// Puts an icon on the bar for every file on the desktop.
+fn add_desktop_icons(action_bar: &mut Window, h: u32) {
     let f = bafioDb::load("/SYS/ICONS.DB");
 
-    for x in 0..list_entries("/USER/DESKTOP") {
-        let e = &libk::io::get_entry("/USER/DESKTOP", x).unwrap().name;
-        let fname = alloc::string::String::from(unsafe { core::str::from_utf8_unchecked(e) });
-        let func = alloc::string::String::from("/USER/DESKTOP/") + &fname;
+    unsafe { (*(&raw mut PROGRAMS)).lock().clear() };
+
+    for x in 0..list_entries(DESKTOP_DIR) {
+        let Some(entry) = libk::io::get_entry(DESKTOP_DIR, x) else {
+            break;
+        };
+        let fname = alloc::string::String::from(unsafe { core::str::from_utf8_unchecked(&entry.name) });
+        let func = alloc::string::String::from(DESKTOP_DIR) + "/" + &fname;
 
         unsafe {
             (*(&raw mut PROGRAMS)).lock().push(func.clone());
@@ -91,32 +141,40 @@ pub extern "C" fn _start() -> ! {
             action_bar.add(i);
         }
     }
+}
 
-    init(wallpaper);
-    init(action_bar);
-
-    /*let sock = libk::net::Socket::new(68);
-    sock.send_dhcp_discover();
-    let n = sock.recv(1024);
-    sock.handle_dhcp(unsafe { &*(n.as_ptr() as *const libk::packets::DhcpPacket) });
-    let n = sock.recv(1024);
-    sock.handle_dhcp(unsafe { &*(n.as_ptr() as *const libk::packets::DhcpPacket) });
-    sock.close();*/
+fn add_power_buttons(action_bar: &mut Window, h: u32) {
+    let button_h = kui::kui_ceil(h as f32 * 65.0 / 100.0) as u32;
+
+    let buttons: [(&str, fn(&mut Widget, u32, u32, u32)); 2] =
+        [("Restart", reboot), ("Power off", shutdown)];
+
+    for (label, event) in buttons {
+        action_bar.add(Widget::Button(
+            Button::new()
+                .label(label)
+                .width(Size::from_u32(button_h * 3))
+                .height(Size::from_u32(button_h))
+                .color(Color::rgb(78, 99, 140))
+                .event(event),
+        ));
+    }
+}
 
-    libk::println!("[-]");
+fn shutdown(_w: &mut Widget, _a1: u32, _a2: u32, _a3: u32) {
+    libk::syscall::shutdown();
+}
 
-    loop {}
+fn reboot(_w: &mut Widget, _a1: u32, _a2: u32, _a3: u32) {
+    libk::syscall::reboot();
 }
 
 pub static mut PROGRAMS: Mutex<Vec<alloc::string::String>> = Mutex::new(Vec::new());
 
-pub fn list_entries(dir: &str) -> u8 {
+pub fn list_entries(dir: &str) -> u32 {
     let mut count = 0;
 
     loop {
-        if count == 255 {
-            return count;
-        }
         let e = libk::io::get_entry(dir, count);
         if e.is_none() {
             return count;